@@ -0,0 +1,50 @@
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use tsid_bench::{LengthPrefixedEncoder, RowEncoder, read_labels_from_path};
+
+const INPUT: &str = "./assets/labels.csv.gz";
+
+fn prepare_encoded_rows(encoder: &LengthPrefixedEncoder) -> Vec<Vec<u8>> {
+    let labels = read_labels_from_path::<std::hash::DefaultHasher>(INPUT)
+        .unwrap_or_else(|err| panic!("{err}"));
+    labels
+        .label_values
+        .into_iter()
+        .map(|row| {
+            let row: Vec<(u32, String)> = row
+                .into_iter()
+                .enumerate()
+                .map(|(idx, val)| (idx as u32, val))
+                .collect();
+            let mut buffer = Vec::new();
+            encoder.encode(&mut buffer, &row);
+            buffer
+        })
+        .collect()
+}
+
+/// Measures the overhead of bounds and UTF-8 checks in `LengthPrefixedEncoder::decode`.
+fn benchmark_decode_unchecked(c: &mut Criterion) {
+    let encoder = LengthPrefixedEncoder;
+    let encoded_rows = prepare_encoded_rows(&encoder);
+
+    let mut group = c.benchmark_group("decode_length_prefixed");
+    group.bench_function("checked", |b| {
+        b.iter(|| {
+            for row in &encoded_rows {
+                black_box(encoder.decode(black_box(row)).unwrap());
+            }
+        });
+    });
+    group.bench_function("unchecked", |b| {
+        b.iter(|| {
+            for row in &encoded_rows {
+                // SAFETY: every row was produced by `LengthPrefixedEncoder::encode`.
+                black_box(unsafe { encoder.decode_unchecked(black_box(row)) });
+            }
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_decode_unchecked);
+criterion_main!(benches);
@@ -1,23 +1,93 @@
 use std::hash::Hasher;
+use std::num::NonZeroUsize;
 
+use ahash::AHasher;
 use cityhash_sys::CityHash64Hasher;
-use criterion::{Criterion, black_box, criterion_group, criterion_main};
-use tsid_bench::{open_csv_reader, read_labels_and_hash};
-use tsid_bench::ts_id_gen::{SeededHasher, TsIdGenerator};
+use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
+use fxhash::FxHasher64;
+use rand::SeedableRng;
+use rand::distr::weighted::WeightedIndex;
+use rand::distr::{Alphanumeric, SampleString};
+use rand::rngs::StdRng;
+use rand_distr::{Distribution, Zipf};
+use siphasher::sip::{SipHasher13, SipHasher24};
+use tsid_bench::read_labels_from_path;
+use tsid_bench::ts_id_gen::{
+    FramingMode, Hasher128Ext, SeededHasher, TsIdCache, TsIdGenerator, TsIdMode, compute_ts_ids,
+    compute_ts_ids_parallel, prometheus_series_hash,
+};
+use wyhash::WyHash;
+use xxhash_rust::xxh3::Xxh3;
 use xxhash_rust::xxh64::Xxh64;
 
-fn benchmark_hasher<H, F>(c: &mut Criterion, name: &str, create_hasher: F)
+/// Complete ts_id generation, names and values, over every row with
+/// [`compute_ts_ids`].
+fn benchmark_hasher<H>(c: &mut Criterion, name: &str)
 where
     H: Hasher + SeededHasher,
+{
+    let labels = read_labels_from_path::<std::hash::DefaultHasher>("./assets/unique-lables.csv.gz")
+        .unwrap_or_else(|err| panic!("{err}"));
+
+    c.bench_function(name, |b| {
+        b.iter(|| black_box(compute_ts_ids::<H>(black_box(&labels), TsIdMode::Full)));
+    });
+}
+
+fn benchmark_default_hasher(c: &mut Criterion) {
+    benchmark_hasher::<std::hash::DefaultHasher>(c, "default");
+}
+
+fn benchmark_fx_hasher(c: &mut Criterion) {
+    benchmark_hasher::<FxHasher64>(c, "fxhash");
+}
+
+fn benchmark_mur3_hasher(c: &mut Criterion) {
+    benchmark_hasher::<mur3::Hasher128>(c, "mur3");
+}
+
+/// Hashes each series like Prometheus' `labels.Hash()`, the baseline the
+/// ts_id generators are compared against.
+fn benchmark_prometheus_hash(c: &mut Criterion) {
+    let labels = read_labels_from_path::<std::hash::DefaultHasher>("./assets/unique-lables.csv.gz")
+        .unwrap_or_else(|err| panic!("{err}"));
+    let label_names: &Vec<String> = &labels.label_names;
+    let label_values: &Vec<Vec<String>> = &labels.label_values;
+
+    // Prometheus keeps label sets sorted by name, so sort the columns once
+    // rather than in every hash.
+    let mut order: Vec<usize> = (0..label_names.len()).collect();
+    order.sort_by_key(|&idx| &label_names[idx]);
+
+    c.bench_function("prometheus", |b| {
+        let mut pairs: Vec<(&str, &str)> = Vec::with_capacity(label_names.len());
+        b.iter(|| {
+            for label_value_row in label_values.iter() {
+                pairs.clear();
+                pairs.extend(
+                    order
+                        .iter()
+                        .map(|&idx| (label_names[idx].as_str(), label_value_row[idx].as_str())),
+                );
+                black_box(prometheus_series_hash(black_box(&pairs)));
+            }
+        });
+    });
+}
+
+/// Same as [`benchmark_hasher`], but builds the full 128-bit ts_id to measure
+/// the overhead over 64 bits.
+fn benchmark_hasher_128<H, F>(c: &mut Criterion, name: &str, create_hasher: F)
+where
+    H: Hasher128Ext + SeededHasher,
     F: Fn() -> H,
 {
-    // Read labels from CSV - use DefaultHasher for reading since we just need the data
-    let labels = read_labels_and_hash::<std::hash::DefaultHasher>(open_csv_reader("./assets/unique-lables.csv.gz"));
+    let labels = read_labels_from_path::<std::hash::DefaultHasher>("./assets/unique-lables.csv.gz")
+        .unwrap_or_else(|err| panic!("{err}"));
     let label_names: &Vec<String> = &labels.label_names;
     let label_values: &Vec<Vec<String>> = &labels.label_values;
 
-    // Benchmark complete tsid generation: write_label_names + write_label_values + build_ts_id
-    c.bench_function(&format!("{}", name), |b| {
+    c.bench_function(name, |b| {
         b.iter(|| {
             for label_value_row in label_values.iter() {
                 let mut generator = TsIdGenerator::new(create_hasher());
@@ -27,39 +97,404 @@ where
                 generator.write_label_values(black_box(
                     label_value_row.iter().map(|s: &String| s.as_bytes()),
                 ));
-                let tsid = black_box(generator.build_ts_id());
+                let tsid = black_box(generator.build_ts_id_128());
                 black_box(tsid);
             }
         });
     });
 }
 
-fn benchmark_default_hasher(c: &mut Criterion) {
-    benchmark_hasher::<std::hash::DefaultHasher, _>(c, "default", || {
-        std::hash::DefaultHasher::default()
+/// Same as `mur3`, but builds the full 128-bit ts_id to measure the overhead
+/// of keeping both hash halves.
+fn benchmark_mur3_128(c: &mut Criterion) {
+    benchmark_hasher_128::<mur3::Hasher128, _>(c, "mur3_128", || mur3::Hasher128::with_seed(0));
+}
+
+fn benchmark_xxh3_128(c: &mut Criterion) {
+    benchmark_hasher_128::<Xxh3, _>(c, "xxh3_128", Xxh3::default);
+}
+
+fn benchmark_xxh3_hasher(c: &mut Criterion) {
+    benchmark_hasher::<Xxh3>(c, "xxh3");
+}
+
+fn benchmark_xxh64_hasher(c: &mut Criterion) {
+    benchmark_hasher::<Xxh64>(c, "xxh64");
+}
+
+fn benchmark_cityhash_hasher(c: &mut Criterion) {
+    benchmark_hasher::<CityHash64Hasher>(c, "cityhash");
+}
+
+fn benchmark_sip13_hasher(c: &mut Criterion) {
+    benchmark_hasher::<SipHasher13>(c, "sip13");
+}
+
+fn benchmark_sip24_hasher(c: &mut Criterion) {
+    benchmark_hasher::<SipHasher24>(c, "sip24");
+}
+
+fn benchmark_ahash_hasher(c: &mut Criterion) {
+    benchmark_hasher::<AHasher>(c, "ahash");
+}
+
+fn benchmark_wyhash_hasher(c: &mut Criterion) {
+    benchmark_hasher::<WyHash>(c, "wyhash");
+}
+
+fn bench_framing<H: Hasher + SeededHasher>(
+    group: &mut criterion::BenchmarkGroup<'_, criterion::measurement::WallTime>,
+    hasher: &str,
+    label_names: &[String],
+    label_values: &[Vec<String>],
+) {
+    for (framing, name) in [
+        (FramingMode::Separator, "separator"),
+        (FramingMode::LengthPrefixed, "length_prefixed"),
+    ] {
+        group.bench_function(BenchmarkId::new(hasher, name), |b| {
+            b.iter(|| {
+                for row in label_values {
+                    let mut generator = TsIdGenerator::<H>::from_seed(0).with_framing(framing);
+                    generator
+                        .write_label_names(black_box(label_names.iter().map(|s| s.as_bytes())));
+                    generator.write_label_values(black_box(row.iter().map(|s| s.as_bytes())));
+                    black_box(generator.build_ts_id());
+                }
+            });
+        });
+    }
+}
+
+/// Overhead of length-prefixed framing over the `0xff` separator.
+fn benchmark_framing(c: &mut Criterion) {
+    let labels = read_labels_from_path::<std::hash::DefaultHasher>("./assets/unique-lables.csv.gz")
+        .unwrap_or_else(|err| panic!("{err}"));
+    let mut group = c.benchmark_group("framing");
+    bench_framing::<Xxh3>(
+        &mut group,
+        "xxh3",
+        &labels.label_names,
+        &labels.label_values,
+    );
+    bench_framing::<FxHasher64>(
+        &mut group,
+        "fxhash",
+        &labels.label_names,
+        &labels.label_values,
+    );
+    group.finish();
+}
+
+fn bench_label_order<H: Hasher + SeededHasher>(
+    group: &mut criterion::BenchmarkGroup<'_, criterion::measurement::WallTime>,
+    hasher: &str,
+    label_names: &[String],
+    label_values: &[Vec<String>],
+) {
+    group.bench_function(BenchmarkId::new(hasher, "names_then_values"), |b| {
+        b.iter(|| {
+            for row in label_values {
+                let mut generator = TsIdGenerator::<H>::from_seed(0);
+                generator.write_label_names(black_box(label_names.iter().map(|s| s.as_bytes())));
+                generator.write_label_values(black_box(row.iter().map(|s| s.as_bytes())));
+                black_box(generator.build_ts_id());
+            }
+        });
+    });
+    group.bench_function(BenchmarkId::new(hasher, "sorted_pairs"), |b| {
+        b.iter(|| {
+            for row in label_values {
+                let mut generator = TsIdGenerator::<H>::from_seed(0);
+                generator.write_sorted_label_pairs(black_box(
+                    label_names
+                        .iter()
+                        .zip(row)
+                        .map(|(name, value)| (name.as_bytes(), value.as_bytes())),
+                ));
+                black_box(generator.build_ts_id());
+            }
+        });
     });
 }
 
-fn benchmark_fx_hasher(c: &mut Criterion) {
-    benchmark_hasher::<fxhash::FxHasher64, _>(c, "fxhash", || fxhash::FxHasher64::default());
+/// Overhead of sorting `(name, value)` pairs per series over hashing the
+/// names and then the values in the given order.
+fn benchmark_label_order(c: &mut Criterion) {
+    let labels = read_labels_from_path::<std::hash::DefaultHasher>("./assets/unique-lables.csv.gz")
+        .unwrap_or_else(|err| panic!("{err}"));
+    let mut group = c.benchmark_group("label_order");
+    bench_label_order::<Xxh3>(
+        &mut group,
+        "xxh3",
+        &labels.label_names,
+        &labels.label_values,
+    );
+    bench_label_order::<FxHasher64>(
+        &mut group,
+        "fxhash",
+        &labels.label_names,
+        &labels.label_values,
+    );
+    group.finish();
 }
 
-fn benchmark_mur3_hasher(c: &mut Criterion) {
-    benchmark_hasher::<mur3::Hasher128, _>(c, "mur3", || mur3::Hasher128::with_seed(0));
+/// Hashing the values of each row from a slice with
+/// [`TsIdGenerator::write_label_values_slice`] versus an iterator with
+/// [`TsIdGenerator::write_label_values`].
+fn benchmark_write_label_values_slice(c: &mut Criterion) {
+    let labels = read_labels_from_path::<Xxh3>("./assets/unique-lables.csv.gz")
+        .unwrap_or_else(|err| panic!("{err}"));
+    let rows: Vec<Vec<&[u8]>> = labels
+        .label_values
+        .iter()
+        .map(|row| row.iter().map(String::as_bytes).collect())
+        .collect();
+
+    let mut group = c.benchmark_group("write_label_values_slice");
+    group.throughput(Throughput::Elements(rows.len() as u64));
+    group.bench_function(BenchmarkId::new("xxh3", "slice"), |b| {
+        b.iter(|| {
+            for row in &rows {
+                let mut generator = TsIdGenerator::<Xxh3>::from_seed(labels.label_name_hash);
+                generator.write_label_values_slice(black_box(row));
+                black_box(generator.build_ts_id());
+            }
+        });
+    });
+    group.bench_function(BenchmarkId::new("xxh3", "iter"), |b| {
+        b.iter(|| {
+            for row in &rows {
+                let mut generator = TsIdGenerator::<Xxh3>::from_seed(labels.label_name_hash);
+                generator.write_label_values(black_box(row).iter().copied());
+                black_box(generator.build_ts_id());
+            }
+        });
+    });
+    group.finish();
 }
 
-fn benchmark_xxh3_hasher(c: &mut Criterion) {
-    benchmark_hasher::<xxhash_rust::xxh3::Xxh3, _>(c, "xxh3", || {
-        xxhash_rust::xxh3::Xxh3::default()
+/// Batch ts_id computation over the whole dataset in each [`TsIdMode`].
+fn benchmark_compute_ts_ids(c: &mut Criterion) {
+    let labels = read_labels_from_path::<Xxh3>("./assets/unique-lables.csv.gz")
+        .unwrap_or_else(|err| panic!("{err}"));
+    let mut group = c.benchmark_group("compute_ts_ids");
+    group.throughput(Throughput::Elements(labels.label_values.len() as u64));
+    for (mode, name) in [
+        (TsIdMode::Full, "full"),
+        (TsIdMode::Seeded, "seeded"),
+        (TsIdMode::Snapshot, "snapshot"),
+    ] {
+        group.bench_function(BenchmarkId::new("xxh3", name), |b| {
+            b.iter(|| compute_ts_ids::<Xxh3>(black_box(&labels), mode));
+        });
+        group.bench_function(BenchmarkId::new("fxhash", name), |b| {
+            b.iter(|| compute_ts_ids::<FxHasher64>(black_box(&labels), mode));
+        });
+    }
+    group.finish();
+}
+
+/// Parallel batch ts_id computation over the dataset amplified 10 times, on
+/// 1, 4 and all cores.
+fn benchmark_compute_ts_ids_parallel(c: &mut Criterion) {
+    let mut labels = read_labels_from_path::<Xxh3>("./assets/unique-lables.csv.gz")
+        .unwrap_or_else(|err| panic!("{err}"));
+    let instance_idx = labels
+        .label_names
+        .iter()
+        .position(|name| name == "instance")
+        .unwrap_or(0);
+    labels.label_values = labels
+        .amplify(10, instance_idx)
+        .map(|row| {
+            row.into_iter()
+                .map(|value| String::from_utf8(value).unwrap())
+                .collect()
+        })
+        .collect();
+
+    let all_cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut thread_counts = vec![1, 4, all_cores];
+    thread_counts.sort();
+    thread_counts.dedup();
+
+    let mut group = c.benchmark_group("compute_ts_ids_parallel");
+    group.throughput(Throughput::Elements(labels.label_values.len() as u64));
+    group.sample_size(10);
+    for num_threads in thread_counts {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .unwrap();
+        group.bench_function(BenchmarkId::new("xxh3", num_threads), |b| {
+            b.iter(|| {
+                pool.install(|| {
+                    compute_ts_ids_parallel::<Xxh3>(black_box(&labels), TsIdMode::Seeded)
+                })
+            });
+        });
+    }
+    group.finish();
+}
+
+/// [`TsIdCache`] against computing every ts_id, over 10 scrapes of the 660-row
+/// dataset, each sending every series in the same order.
+///
+/// A 1000-entry cache holds all series and hits on every scrape after the
+/// first. A 100-entry cache is smaller than a scrape, so LRU evicts each series
+/// just before it comes back and never hits.
+fn benchmark_ts_id_cache(c: &mut Criterion) {
+    const SCRAPES: usize = 10;
+
+    let labels = read_labels_from_path::<Xxh3>("./assets/unique-lables.csv.gz")
+        .unwrap_or_else(|err| panic!("{err}"));
+    let rows: Vec<Vec<Vec<u8>>> = labels
+        .label_values
+        .iter()
+        .map(|row| row.iter().map(|value| value.clone().into_bytes()).collect())
+        .collect();
+    let label_name_hash = labels.label_name_hash;
+    let scrapes = || (0..SCRAPES).flat_map(|_| &rows);
+
+    let mut group = c.benchmark_group("ts_id_cache");
+    group.throughput(Throughput::Elements((rows.len() * SCRAPES) as u64));
+    group.bench_function("no_cache", |b| {
+        b.iter(|| {
+            for row in scrapes() {
+                let mut generator = TsIdGenerator::<Xxh3>::from_seed(label_name_hash);
+                generator.write_label_values(row.iter().map(Vec::as_slice));
+                black_box(generator.build_ts_id());
+            }
+        });
     });
+    for capacity in [100, 1000] {
+        let capacity = NonZeroUsize::new(capacity).unwrap();
+        let mut cache = TsIdCache::<Xxh3>::new(label_name_hash, capacity);
+        for row in scrapes() {
+            cache.get_or_compute(row);
+        }
+        println!(
+            "ts_id_cache lru/{} hit rate: {:.1}%",
+            capacity,
+            cache.hit_rate() * 100.0
+        );
+
+        group.bench_function(BenchmarkId::new("lru", capacity), |b| {
+            b.iter(|| {
+                let mut cache = TsIdCache::<Xxh3>::new(label_name_hash, capacity);
+                for row in scrapes() {
+                    black_box(cache.get_or_compute(row));
+                }
+            });
+        });
+    }
+    group.finish();
 }
 
-fn benchmark_xxh64_hasher(c: &mut Criterion) {
-    benchmark_hasher::<Xxh64, _>(c, "xxh64", || Xxh64::default());
+/// Generates one synthetic series with `label_count` labels and 8-character
+/// alphanumeric values.
+fn synthetic_series(rng: &mut StdRng, label_count: usize) -> (Vec<String>, Vec<String>) {
+    let names = (0..label_count).map(|idx| format!("label_{idx}")).collect();
+    let values = (0..label_count)
+        .map(|_| Alphanumeric.sample_string(rng, 8))
+        .collect();
+    (names, values)
 }
 
-fn benchmark_cityhash_hasher(c: &mut Criterion) {
-    benchmark_hasher::<CityHash64Hasher, _>(c, "cityhash", || CityHash64Hasher::default());
+fn bench_label_count<H: Hasher + SeededHasher + Default>(
+    group: &mut criterion::BenchmarkGroup<'_, criterion::measurement::WallTime>,
+    hasher: &str,
+    label_count: usize,
+    names: &[String],
+    values: &[String],
+) {
+    group.bench_with_input(
+        BenchmarkId::new(hasher, label_count),
+        &label_count,
+        |b, _| {
+            b.iter(|| {
+                let mut generator = TsIdGenerator::new(H::default());
+                generator.write_label_names(black_box(names.iter().map(|s| s.as_bytes())));
+                generator.write_label_values(black_box(values.iter().map(|s| s.as_bytes())));
+                black_box(generator.build_ts_id())
+            });
+        },
+    );
+}
+
+/// Ts_id generation throughput, in labels per second, for series with
+/// different numbers of labels.
+fn benchmark_label_count(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut group = c.benchmark_group("label_count");
+    for label_count in [1, 4, 8, 16, 32] {
+        let (names, values) = synthetic_series(&mut rng, label_count);
+        group.throughput(Throughput::Elements(label_count as u64));
+        bench_label_count::<Xxh3>(&mut group, "xxh3", label_count, &names, &values);
+        bench_label_count::<FxHasher64>(&mut group, "fxhash", label_count, &names, &values);
+    }
+    group.finish();
+}
+
+/// Label counts of the series in [`benchmark_prometheus_cardinality`] and the
+/// share of series having them.
+const LABEL_COUNT_MIX: [(usize, u32); 4] = [(5, 70), (10, 20), (20, 8), (40, 2)];
+
+/// Generates `series` series whose label counts follow [`LABEL_COUNT_MIX`].
+///
+/// Values are drawn from a pool of 1000 per label with a Zipf distribution,
+/// so that a few values, like a busy `job`, are shared by most series.
+fn prometheus_like_series(rng: &mut StdRng, series: usize) -> Vec<(Vec<String>, Vec<String>)> {
+    let label_counts = WeightedIndex::new(LABEL_COUNT_MIX.iter().map(|(_, share)| share)).unwrap();
+    let value_rank = Zipf::new(1000.0, 1.1).unwrap();
+    (0..series)
+        .map(|_| {
+            let (label_count, _) = LABEL_COUNT_MIX[label_counts.sample(rng)];
+            let names = (0..label_count).map(|idx| format!("label_{idx}")).collect();
+            let values = (0..label_count)
+                .map(|idx| format!("value_{idx}_{}", value_rank.sample(rng) as u64))
+                .collect();
+            (names, values)
+        })
+        .collect()
+}
+
+fn bench_prometheus_cardinality<H: Hasher + SeededHasher>(
+    group: &mut criterion::BenchmarkGroup<'_, criterion::measurement::WallTime>,
+    hasher: &str,
+    series: &[(Vec<String>, Vec<String>)],
+) {
+    group.bench_function(hasher, |b| {
+        b.iter(|| {
+            for (names, values) in series {
+                let mut generator = TsIdGenerator::<H>::from_seed(0);
+                generator.write_label_names(black_box(names.iter().map(|s| s.as_bytes())));
+                generator.write_label_values(black_box(values.iter().map(|s| s.as_bytes())));
+                black_box(generator.build_ts_id());
+            }
+        });
+    });
+}
+
+/// Ts_id generation throughput, in series per second, over a mix of label
+/// counts resembling a Prometheus deployment, where most series have a few
+/// labels and some have many.
+fn benchmark_prometheus_cardinality(c: &mut Criterion) {
+    let series = prometheus_like_series(&mut StdRng::seed_from_u64(42), 10_000);
+    let mut group = c.benchmark_group("prometheus_cardinality");
+    group.throughput(Throughput::Elements(series.len() as u64));
+    bench_prometheus_cardinality::<std::hash::DefaultHasher>(&mut group, "default", &series);
+    bench_prometheus_cardinality::<FxHasher64>(&mut group, "fxhash", &series);
+    bench_prometheus_cardinality::<mur3::Hasher128>(&mut group, "mur3", &series);
+    bench_prometheus_cardinality::<Xxh3>(&mut group, "xxh3", &series);
+    bench_prometheus_cardinality::<Xxh64>(&mut group, "xxh64", &series);
+    bench_prometheus_cardinality::<CityHash64Hasher>(&mut group, "cityhash", &series);
+    bench_prometheus_cardinality::<SipHasher13>(&mut group, "sip13", &series);
+    bench_prometheus_cardinality::<SipHasher24>(&mut group, "sip24", &series);
+    bench_prometheus_cardinality::<AHasher>(&mut group, "ahash", &series);
+    bench_prometheus_cardinality::<WyHash>(&mut group, "wyhash", &series);
+    group.finish();
 }
 
 criterion_group!(
@@ -67,8 +502,23 @@ criterion_group!(
     benchmark_default_hasher,
     benchmark_fx_hasher,
     benchmark_mur3_hasher,
+    benchmark_mur3_128,
     benchmark_xxh3_hasher,
+    benchmark_xxh3_128,
     benchmark_xxh64_hasher,
-    benchmark_cityhash_hasher
+    benchmark_cityhash_hasher,
+    benchmark_sip13_hasher,
+    benchmark_sip24_hasher,
+    benchmark_ahash_hasher,
+    benchmark_wyhash_hasher,
+    benchmark_prometheus_hash,
+    benchmark_framing,
+    benchmark_label_order,
+    benchmark_compute_ts_ids,
+    benchmark_write_label_values_slice,
+    benchmark_compute_ts_ids_parallel,
+    benchmark_ts_id_cache,
+    benchmark_label_count,
+    benchmark_prometheus_cardinality
 );
 criterion_main!(benches);
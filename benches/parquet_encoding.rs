@@ -1,41 +1,117 @@
-use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use std::hash::Hasher;
+use std::sync::Arc;
+
+use criterion::{
+    BatchSize, BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main,
+};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use tsid_bench::data_reader::{EXCLUDED_COLUMNS, read_labels_from_parquet};
+use tsid_bench::encoding::{encode_to_vec, encode_varints_batch, encode_varints_batch_scalar};
+use tsid_bench::labels_gen::{SyntheticLabelsConfig, generate_labels};
+use tsid_bench::ts_id_gen::SeededHasher;
 use tsid_bench::{
-    FlatBufferEncoder, LengthPrefixedEncoder, MemcomparableEncoder, RowEncoder, VarintEncoder,
-    encode_to_parquet, encode_to_parquet_maparray, open_csv_reader, read_labels_and_hash,
+    FlatBufferEncoder, FlatBufferEncoderThreadLocal, InternedLabels, Labels, LengthPrefixedEncoder,
+    LengthPrefixedEncoderAligned, MemcomparableEncoder, NullEncoder, ParquetWriteOptions,
+    RowEncoder, SchemaAwareEncoder, TsIdLayout, TsIdParquetOptions, VarintEncoder,
+    encode_borrowed_to_parquet, encode_to_parquet, encode_to_parquet_dict_string,
+    encode_to_parquet_maparray, encode_to_parquet_maparray_sorted, encode_to_parquet_with_dict,
+    encode_to_parquet_with_options, encode_tsids_128_to_parquet, encode_tsids_to_parquet,
+    prune_row_groups, read_labels_from_jsonl_path, read_labels_from_path, scale_and_shuffle,
+    scale_rows,
 };
+use xxhash_rust::xxh3::Xxh3;
 
 const INPUT: &str = "./assets/labels.csv.gz";
 
-fn prepare_label_data(path: &str) -> (Vec<String>, Vec<Vec<String>>) {
-    let labels = read_labels_and_hash::<std::hash::DefaultHasher>(open_csv_reader(path));
+/// Environment variable selecting a synthetic dataset instead of [`INPUT`],
+/// e.g. `TSID_BENCH_SYNTH=rows=100000,labels=12`.
+const SYNTH_ENV: &str = "TSID_BENCH_SYNTH";
+
+/// Environment variable selecting a JSON lines file instead of [`INPUT`],
+/// e.g. `TSID_BENCH_JSONL=./series.jsonl`.
+const JSONL_ENV: &str = "TSID_BENCH_JSONL";
+
+/// Environment variable selecting a parquet file instead of [`INPUT`], whose
+/// string columns are read as labels, e.g. `TSID_BENCH_PARQUET=./data.parquet`.
+const PARQUET_ENV: &str = "TSID_BENCH_PARQUET";
+
+/// Environment variable projecting the benchmark labels down to a
+/// comma-separated list of label names, e.g. `TSID_BENCH_KEEP=namespace,pod`.
+const KEEP_ENV: &str = "TSID_BENCH_KEEP";
+
+/// Seed of the row permutation used by [`benchmark_shuffled`].
+const SHUFFLE_SEED: u64 = 42;
+
+/// Loads the benchmark labels, hashing the label names with `H`.
+fn load_labels<H>() -> Labels
+where
+    H: Default + Hasher + SeededHasher,
+{
+    let labels = if let Ok(spec) = std::env::var(SYNTH_ENV) {
+        let config: SyntheticLabelsConfig = spec
+            .parse()
+            .unwrap_or_else(|err| panic!("invalid {SYNTH_ENV}: {err}"));
+        generate_labels::<H>(&config)
+    } else if let Ok(path) = std::env::var(JSONL_ENV) {
+        read_labels_from_jsonl_path::<H>(&path).unwrap_or_else(|err| panic!("{err}"))
+    } else if let Ok(path) = std::env::var(PARQUET_ENV) {
+        read_labels_from_parquet::<H>(&path, &EXCLUDED_COLUMNS)
+            .unwrap_or_else(|err| panic!("{path}: {err}"))
+    } else {
+        read_labels_from_path::<H>(INPUT).unwrap_or_else(|err| panic!("{err}"))
+    };
+
+    match std::env::var(KEEP_ENV) {
+        Ok(keep) => {
+            let keep: Vec<&str> = keep.split(',').map(str::trim).collect();
+            labels
+                .project::<H>(&keep)
+                .unwrap_or_else(|err| panic!("invalid {KEEP_ENV}: {err}"))
+        }
+        Err(_) => labels,
+    }
+}
+
+fn prepare_label_data() -> (Vec<String>, Vec<Vec<String>>) {
+    let labels = load_labels::<std::hash::DefaultHasher>();
     let label_names = labels.label_names;
     let label_values = labels.label_values.clone();
     (label_names, label_values)
 }
 
-fn scale(label_values: Vec<Vec<String>>, scale: usize) -> Vec<Vec<(u32, String)>> {
-    let rows: Vec<Vec<(u32, String)>> = label_values
-        .into_iter()
-        .map(|row| {
-            row.into_iter()
-                .enumerate()
-                .map(|(idx, val)| (idx as u32, val))
-                .collect()
-        })
-        .collect();
-    let scaled: Vec<_> = (0..scale).flat_map(|_| rows.iter().cloned()).collect();
-    scaled
+fn prepare_benchmark_input() -> Vec<Vec<(u32, String)>> {
+    let (_name, value) = prepare_label_data();
+    scale_rows(&value, 1)
 }
 
-fn prepare_benchmark_input() -> Vec<Vec<(u32, String)>> {
-    let (_name, value) = prepare_label_data(INPUT);
-    scale(value, 1)
+/// Encoders benchmarked by [`benchmark_all_encoders`].
+///
+/// Adding an encoder here is enough to include it in every encoding and
+/// decoding benchmark.
+struct BenchmarkRegistry {
+    encoders: Vec<Box<dyn RowEncoder>>,
+}
+
+impl BenchmarkRegistry {
+    fn new(label_names: Vec<String>) -> Self {
+        Self {
+            encoders: vec![
+                Box::new(LengthPrefixedEncoder),
+                Box::new(VarintEncoder),
+                Box::new(MemcomparableEncoder),
+                Box::new(FlatBufferEncoder),
+                Box::new(SchemaAwareEncoder::new(
+                    VarintEncoder,
+                    Arc::new(label_names),
+                )),
+            ],
+        }
+    }
 }
 
 /// Generic encoding benchmark for any RowEncoder implementation.
-fn benchmark_encoder<E: RowEncoder>(c: &mut Criterion, encoder: E) {
-    let rows = prepare_benchmark_input();
-    let data = encode_to_parquet(&encoder, &rows).unwrap();
+fn benchmark_encoder(c: &mut Criterion, encoder: &dyn RowEncoder, rows: &[Vec<(u32, &str)>]) {
+    let data = encode_borrowed_to_parquet(encoder, rows).unwrap();
     println!(
         "parquet_encoding_{} file size: {} bytes ({:.2} KB)",
         encoder.name(),
@@ -43,28 +119,39 @@ fn benchmark_encoder<E: RowEncoder>(c: &mut Criterion, encoder: E) {
         data.len() as f64 / 1024.0
     );
 
-    let bench_name = format!("parquet_encoding_{}", encoder.name());
-    c.bench_function(&bench_name, |b| {
+    // Throughput is measured in raw encoded row bytes, before parquet overhead,
+    // so that encoders are compared on the same axis.
+    let total_encoded_bytes: usize = encode_borrowed_rows(encoder, rows)
+        .iter()
+        .map(Vec::len)
+        .sum();
+    let mut group = c.benchmark_group("parquet_encoding");
+    group.throughput(Throughput::Bytes(total_encoded_bytes as u64));
+    group.bench_function(encoder.name(), |b| {
         b.iter(|| {
-            encode_to_parquet(&encoder, black_box(&rows)).unwrap();
+            encode_borrowed_to_parquet(encoder, black_box(rows)).unwrap();
         });
     });
+    group.finish();
 }
 
 /// Generic decoding benchmark for any RowEncoder implementation.
-fn benchmark_decoder<E: RowEncoder>(c: &mut Criterion, encoder: E, encoded_rows: &[Vec<u8>]) {
+fn benchmark_decoder(c: &mut Criterion, encoder: &dyn RowEncoder, encoded_rows: &[Vec<u8>]) {
     let bench_name = format!("decode_{}", encoder.name());
     c.bench_function(&bench_name, |b| {
         b.iter(|| {
             for row in encoded_rows {
-                black_box(encoder.decode(black_box(row)));
+                black_box(encoder.decode(black_box(row)).unwrap());
             }
         });
     });
 }
 
 /// Prepare encoded rows for decoding benchmarks.
-fn prepare_encoded_rows<E: RowEncoder>(encoder: &E, rows: &[Vec<(u32, String)>]) -> Vec<Vec<u8>> {
+fn prepare_encoded_rows<E: RowEncoder + ?Sized>(
+    encoder: &E,
+    rows: &[Vec<(u32, String)>],
+) -> Vec<Vec<u8>> {
     rows.iter()
         .map(|row| {
             let mut buffer = Vec::new();
@@ -74,28 +161,67 @@ fn prepare_encoded_rows<E: RowEncoder>(encoder: &E, rows: &[Vec<(u32, String)>])
         .collect()
 }
 
+/// Encodes rows with values borrowed from an [`InternedLabels`] pool.
+fn encode_borrowed_rows(encoder: &dyn RowEncoder, rows: &[Vec<(u32, &str)>]) -> Vec<Vec<u8>> {
+    rows.iter()
+        .map(|row| {
+            let mut buffer = Vec::new();
+            encoder.encode_borrowed(&mut buffer, row);
+            buffer
+        })
+        .collect()
+}
+
 // ============================================================================
 // Encoding Benchmarks
 // ============================================================================
 
-fn benchmark_length_prefixed(c: &mut Criterion) {
-    benchmark_encoder(c, LengthPrefixedEncoder);
-}
+/// Runs the encoding and decoding benchmarks for every registered encoder.
+fn benchmark_all_encoders(c: &mut Criterion) {
+    let labels = load_labels::<std::hash::DefaultHasher>();
+    let interned = InternedLabels::from(&labels);
+    println!(
+        "labels heap size: {} bytes, interned: {} bytes ({} distinct values)",
+        labels.heap_size(),
+        interned.heap_size(),
+        interned.pool.len()
+    );
+    drop(labels);
 
-fn benchmark_varint(c: &mut Criterion) {
-    benchmark_encoder(c, VarintEncoder);
-}
+    let rows = interned.encoder_rows();
+    let registry = BenchmarkRegistry::new(interned.names.clone());
 
-fn benchmark_memcomparable(c: &mut Criterion) {
-    benchmark_encoder(c, MemcomparableEncoder);
+    for encoder in &registry.encoders {
+        benchmark_encoder(c, encoder.as_ref(), &rows);
+        let encoded_rows = encode_borrowed_rows(encoder.as_ref(), &rows);
+        benchmark_decoder(c, encoder.as_ref(), &encoded_rows);
+    }
 }
 
-fn benchmark_flatbuffer(c: &mut Criterion) {
-    benchmark_encoder(c, FlatBufferEncoder);
+/// Writes rows that encode to nothing, measuring the Parquet writer overhead in
+/// isolation. The file size is the Parquet metadata floor.
+fn benchmark_null(c: &mut Criterion) {
+    let rows = prepare_benchmark_input();
+
+    let data = encode_to_parquet(&NullEncoder, &rows).unwrap();
+    println!(
+        "parquet_encoding_null file size: {} bytes ({:.2} KB)",
+        data.len(),
+        data.len() as f64 / 1024.0
+    );
+
+    let mut group = c.benchmark_group("parquet_encoding");
+    group.throughput(Throughput::Elements(rows.len() as u64));
+    group.bench_function("null", |b| {
+        b.iter(|| {
+            encode_to_parquet(&NullEncoder, black_box(&rows)).unwrap();
+        });
+    });
+    group.finish();
 }
 
 fn benchmark_maparray(c: &mut Criterion) {
-    let (label_names, label_values) = prepare_label_data(INPUT);
+    let (label_names, label_values) = prepare_label_data();
 
     let data = encode_to_parquet_maparray(&label_names, &label_values).unwrap();
     println!(
@@ -111,38 +237,342 @@ fn benchmark_maparray(c: &mut Criterion) {
     });
 }
 
-// ============================================================================
-// Decoding Benchmarks
-// ============================================================================
+/// Same as [`benchmark_maparray`], with rows grouped by their first label value.
+fn benchmark_maparray_sorted(c: &mut Criterion) {
+    let (label_names, label_values) = prepare_label_data();
 
-fn benchmark_decode_length_prefixed(c: &mut Criterion) {
-    let rows = prepare_benchmark_input();
-    let encoder = LengthPrefixedEncoder;
-    let encoded_rows = prepare_encoded_rows(&encoder, &rows);
-    benchmark_decoder(c, encoder, &encoded_rows);
+    let unsorted = encode_to_parquet_maparray(&label_names, &label_values).unwrap();
+    let sorted = encode_to_parquet_maparray_sorted(&label_names, &label_values).unwrap();
+    println!(
+        "parquet_encoding_maparray_sorted file size: {} bytes ({:.2} KB), unsorted: {} bytes ({:.2} KB)",
+        sorted.len(),
+        sorted.len() as f64 / 1024.0,
+        unsorted.len(),
+        unsorted.len() as f64 / 1024.0
+    );
+
+    c.bench_function("parquet_encoding_maparray_sorted", |b| {
+        b.iter(|| {
+            encode_to_parquet_maparray_sorted(black_box(&label_names), black_box(&label_values))
+                .unwrap();
+        });
+    });
 }
 
-fn benchmark_decode_varint(c: &mut Criterion) {
-    let rows = prepare_benchmark_input();
-    let encoder = VarintEncoder;
-    let encoded_rows = prepare_encoded_rows(&encoder, &rows);
-    benchmark_decoder(c, encoder, &encoded_rows);
+/// Compares one dictionary encoded column per label with the single map column
+/// of [`benchmark_maparray`].
+fn benchmark_dict_string(c: &mut Criterion) {
+    let (label_names, label_values) = prepare_label_data();
+
+    let maparray = encode_to_parquet_maparray(&label_names, &label_values).unwrap();
+    let dict_string = encode_to_parquet_dict_string(&label_names, &label_values).unwrap();
+    println!(
+        "parquet_encoding_dict_string file size: {} bytes ({:.2} KB), maparray: {} bytes ({:.2} KB)",
+        dict_string.len(),
+        dict_string.len() as f64 / 1024.0,
+        maparray.len(),
+        maparray.len() as f64 / 1024.0
+    );
+
+    c.bench_function("parquet_encoding_dict_string", |b| {
+        b.iter(|| {
+            encode_to_parquet_dict_string(black_box(&label_names), black_box(&label_values))
+                .unwrap();
+        });
+    });
 }
 
-fn benchmark_decode_flatbuffer(c: &mut Criterion) {
+/// Compares [`encode_to_parquet`] with [`encode_to_parquet_with_dict`] for the
+/// memcomparable encoder, whose keys share a predictable structure.
+fn benchmark_dictionary(c: &mut Criterion) {
     let rows = prepare_benchmark_input();
-    let encoder = FlatBufferEncoder;
-    let encoded_rows = prepare_encoded_rows(&encoder, &rows);
-    benchmark_decoder(c, encoder, &encoded_rows);
+
+    let plain = encode_to_parquet(&MemcomparableEncoder, &rows).unwrap();
+    let dict = encode_to_parquet_with_dict(&MemcomparableEncoder, &rows).unwrap();
+    println!(
+        "parquet_encoding_memcomparable_dict file size: {} bytes ({:.2} KB), default: {} bytes ({:.2} KB)",
+        dict.len(),
+        dict.len() as f64 / 1024.0,
+        plain.len(),
+        plain.len() as f64 / 1024.0
+    );
+
+    let mut group = c.benchmark_group("parquet_encoding_memcomparable_dictionary");
+    group.bench_function("default", |b| {
+        b.iter(|| {
+            encode_to_parquet(&MemcomparableEncoder, black_box(&rows)).unwrap();
+        });
+    });
+    group.bench_function("dict", |b| {
+        b.iter(|| {
+            encode_to_parquet_with_dict(&MemcomparableEncoder, black_box(&rows)).unwrap();
+        });
+    });
+    group.finish();
+}
+
+/// Encodes the varint and maparray layouts with rows in a fixed random order,
+/// so that similar rows are no longer adjacent.
+fn benchmark_shuffled(c: &mut Criterion) {
+    let mut labels = load_labels::<std::hash::DefaultHasher>();
+    let rows = scale_and_shuffle(&labels.label_values, 1, SHUFFLE_SEED);
+    labels.shuffle_rows(SHUFFLE_SEED);
+
+    let varint = encode_to_parquet(&VarintEncoder, &rows).unwrap();
+    let maparray = encode_to_parquet_maparray(&labels.label_names, &labels.label_values).unwrap();
+    for (name, data) in [("varint", &varint), ("maparray", &maparray)] {
+        println!(
+            "parquet_encoding_{}_shuffled file size: {} bytes ({:.2} KB)",
+            name,
+            data.len(),
+            data.len() as f64 / 1024.0
+        );
+    }
+
+    c.bench_function("parquet_encoding_varint_shuffled", |b| {
+        b.iter(|| {
+            encode_to_parquet(&VarintEncoder, black_box(&rows)).unwrap();
+        });
+    });
+    c.bench_function("parquet_encoding_maparray_shuffled", |b| {
+        b.iter(|| {
+            encode_to_parquet_maparray(
+                black_box(&labels.label_names),
+                black_box(&labels.label_values),
+            )
+            .unwrap();
+        });
+    });
 }
 
-fn benchmark_decode_memcomparable(c: &mut Criterion) {
+/// Compares the size of a ts_id-only column across physical layouts, for
+/// sorted and unsorted ts_id order.
+fn benchmark_tsid_layouts(c: &mut Criterion) {
+    let labels = load_labels::<Xxh3>();
+
+    for layout in [
+        TsIdLayout::UInt64,
+        TsIdLayout::UInt64Delta,
+        TsIdLayout::FixedSizeBinary,
+    ] {
+        for sorted in [false, true] {
+            let options = TsIdParquetOptions { layout, sorted };
+            let bench_name = format!(
+                "parquet_encoding_tsid_{:?}_{}",
+                layout,
+                if sorted { "sorted" } else { "unsorted" }
+            );
+            let data = encode_tsids_to_parquet::<Xxh3>(&labels, options).unwrap();
+            println!(
+                "{} file size: {} bytes ({:.2} KB)",
+                bench_name,
+                data.len(),
+                data.len() as f64 / 1024.0
+            );
+
+            c.bench_function(&bench_name, |b| {
+                b.iter(|| encode_tsids_to_parquet::<Xxh3>(black_box(&labels), options).unwrap());
+            });
+        }
+    }
+
+    for sorted in [false, true] {
+        let bench_name = format!(
+            "parquet_encoding_tsid_FixedSizeBinary128_{}",
+            if sorted { "sorted" } else { "unsorted" }
+        );
+        let data = encode_tsids_128_to_parquet::<Xxh3>(&labels, sorted).unwrap();
+        println!(
+            "{} file size: {} bytes ({:.2} KB)",
+            bench_name,
+            data.len(),
+            data.len() as f64 / 1024.0
+        );
+
+        c.bench_function(&bench_name, |b| {
+            b.iter(|| encode_tsids_128_to_parquet::<Xxh3>(black_box(&labels), sorted).unwrap());
+        });
+    }
+}
+
+/// Compares encoding all rows into one pre-sized buffer against allocating a
+/// buffer per row.
+fn benchmark_varint_encode_batch(c: &mut Criterion) {
     let rows = prepare_benchmark_input();
-    let encoder = MemcomparableEncoder;
-    let encoded_rows = prepare_encoded_rows(&encoder, &rows);
-    benchmark_decoder(c, encoder, &encoded_rows);
+
+    c.bench_function("encode_rows_varint_encode_to_vec", |b| {
+        b.iter(|| {
+            for row in black_box(&rows) {
+                black_box(encode_to_vec(&VarintEncoder, row));
+            }
+        });
+    });
+
+    // The buffer is reused across iterations, as a caller encoding batch after
+    // batch would.
+    let mut buffer = Vec::new();
+    c.bench_function("encode_rows_varint_encode_batch", |b| {
+        b.iter(|| {
+            buffer.clear();
+            let offsets = VarintEncoder::encode_batch(black_box(&rows), &mut buffer);
+            black_box(offsets);
+        });
+    });
+}
+
+/// Compares the scalar and BMI2 paths of batch varint encoding on 10,000
+/// `u32`s of every varint length.
+fn benchmark_encode_varints_batch(c: &mut Criterion) {
+    let values: Vec<u32> = (0..10_000u32)
+        .map(|idx| idx.wrapping_mul(0x9e37_79b9) >> (idx % 32))
+        .collect();
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if !is_x86_feature_detected!("bmi2") {
+        println!("encode_varints_batch: BMI2 not supported, both paths are scalar");
+    }
+
+    let mut group = c.benchmark_group("encode_varints_batch");
+    group.throughput(Throughput::Elements(values.len() as u64));
+    let mut buffer = Vec::new();
+    group.bench_function("scalar", |b| {
+        b.iter(|| {
+            buffer.clear();
+            encode_varints_batch_scalar(&mut buffer, black_box(&values));
+            black_box(&buffer);
+        });
+    });
+    group.bench_function("bmi2", |b| {
+        b.iter(|| {
+            buffer.clear();
+            encode_varints_batch(&mut buffer, black_box(&values));
+            black_box(&buffer);
+        });
+    });
+    group.finish();
+}
+
+/// Compares allocating a `FlatBufferBuilder` per row against reusing a
+/// thread-local one, on the dataset scaled 10 times.
+fn benchmark_flatbuffer_builder_reuse(c: &mut Criterion) {
+    let (_name, value) = prepare_label_data();
+    let rows = scale_rows(&value, 10);
+
+    let mut group = c.benchmark_group("encode_rows_flatbuffer_builder");
+    let encoders: [(&str, &dyn RowEncoder); 2] = [
+        ("allocating", &FlatBufferEncoder),
+        ("thread_local", &FlatBufferEncoderThreadLocal),
+    ];
+    for (name, encoder) in encoders {
+        let mut buffer = Vec::new();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for row in black_box(&rows) {
+                    buffer.clear();
+                    encoder.encode(&mut buffer, row);
+                    black_box(&buffer);
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+/// Measures the overhead of [`RowEncoder::encode_sorted`] over `encode`, on
+/// rows whose column ids are in descending order.
+fn benchmark_encode_sorted(c: &mut Criterion) {
+    let rows: Vec<Vec<(u32, String)>> = prepare_benchmark_input()
+        .into_iter()
+        .map(|mut row| {
+            row.reverse();
+            row
+        })
+        .collect();
+
+    let encoders: [&dyn RowEncoder; 2] = [&VarintEncoder, &MemcomparableEncoder];
+    for encoder in encoders {
+        let mut group = c.benchmark_group(format!("encode_rows_{}_sorted", encoder.name()));
+        let mut buffer = Vec::new();
+        group.bench_function("encode", |b| {
+            b.iter(|| {
+                for row in black_box(&rows) {
+                    buffer.clear();
+                    encoder.encode(&mut buffer, row);
+                }
+            });
+        });
+        // Sorting happens in place, so each iteration sorts a fresh copy;
+        // the copy is made outside the timed routine.
+        group.bench_function("encode_sorted", |b| {
+            b.iter_batched_ref(
+                || rows.clone(),
+                |rows| {
+                    for row in rows.iter_mut() {
+                        buffer.clear();
+                        encoder.encode_sorted(&mut buffer, row);
+                    }
+                },
+                BatchSize::LargeInput,
+            );
+        });
+        group.finish();
+    }
 }
 
+/// Compares row encoding on synthetic rows with long values, where per-value
+/// overhead matters less than copying the bytes.
+///
+/// Throughput is reported in value bytes, so encoders whose cost grows
+/// linearly with the value length show a flat throughput across lengths.
+fn benchmark_long_values(c: &mut Criterion) {
+    const NUM_ROWS: usize = 1000;
+    const NUM_LABELS: u32 = 4;
+
+    let encoders: [&dyn RowEncoder; 4] = [
+        &LengthPrefixedEncoder,
+        &VarintEncoder,
+        &MemcomparableEncoder,
+        &FlatBufferEncoder,
+    ];
+    let mut group = c.benchmark_group("encode_rows_long_values");
+    for value_len in [256, 1024, 4096] {
+        let rows: Vec<Vec<(u32, String)>> = (0..NUM_ROWS)
+            .map(|row_idx| {
+                (0..NUM_LABELS)
+                    .map(|col_id| {
+                        let prefix = format!("{}-{}-", row_idx, col_id);
+                        let value = prefix.chars().cycle().take(value_len).collect();
+                        (col_id, value)
+                    })
+                    .collect()
+            })
+            .collect();
+        let total_bytes: usize = rows.iter().flatten().map(|(_, value)| value.len()).sum();
+        group.throughput(Throughput::Bytes(total_bytes as u64));
+
+        for encoder in encoders {
+            let mut buffer = Vec::new();
+            group.bench_with_input(
+                BenchmarkId::new(encoder.name(), value_len),
+                &rows,
+                |b, rows| {
+                    b.iter(|| {
+                        for row in rows {
+                            buffer.clear();
+                            encoder.encode(&mut buffer, black_box(row));
+                        }
+                        black_box(&buffer);
+                    });
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+// ============================================================================
+// Decoding Benchmarks
+// ============================================================================
+
 fn benchmark_decode_flatbuffer_zero_copy(c: &mut Criterion) {
     let rows = prepare_benchmark_input();
     let encoder = FlatBufferEncoder;
@@ -160,19 +590,199 @@ fn benchmark_decode_flatbuffer_zero_copy(c: &mut Criterion) {
     });
 }
 
+/// Compares [`FlatBufferEncoder::decode_zero_copy`], which borrows the values
+/// from the encoded row, with [`RowEncoder::decode`], which copies each value
+/// into a `String`.
+fn benchmark_decode_flatbuffer_borrowed(c: &mut Criterion) {
+    let rows = prepare_benchmark_input();
+    let encoder = FlatBufferEncoder;
+    let encoded_rows = prepare_encoded_rows(&encoder, &rows);
+
+    let mut group = c.benchmark_group("decode_flatbuffer_values");
+    group.bench_function("zero_copy", |b| {
+        b.iter(|| {
+            for row in &encoded_rows {
+                for (column_id, value) in encoder.decode_zero_copy(black_box(row)).unwrap() {
+                    black_box((column_id, value));
+                }
+            }
+        });
+    });
+    group.bench_function("allocating", |b| {
+        b.iter(|| {
+            for row in &encoded_rows {
+                for (column_id, value) in encoder.decode(black_box(row)).unwrap() {
+                    black_box((column_id, value));
+                }
+            }
+        });
+    });
+    group.finish();
+}
+
+/// Compares decoding only the column ids of each row against a full decode.
+fn benchmark_decode_column_ids_only(c: &mut Criterion) {
+    let rows = prepare_benchmark_input();
+
+    let encoders: [&dyn RowEncoder; 2] = [&LengthPrefixedEncoder, &VarintEncoder];
+    for encoder in encoders {
+        let encoded_rows = prepare_encoded_rows(encoder, &rows);
+        let mut group = c.benchmark_group(format!("decode_{}_column_ids", encoder.name()));
+        group.bench_function("decode", |b| {
+            b.iter(|| {
+                for row in &encoded_rows {
+                    black_box(encoder.decode(black_box(row)).unwrap());
+                }
+            });
+        });
+        group.bench_function("decode_column_ids_only", |b| {
+            b.iter(|| {
+                for row in &encoded_rows {
+                    black_box(encoder.decode_column_ids_only(black_box(row)).unwrap());
+                }
+            });
+        });
+        group.finish();
+    }
+}
+
+/// XORs the little-endian 8-byte words of `bytes`, folding a tail shorter
+/// than a word in byte by byte.
+fn xor_words(bytes: &[u8]) -> u64 {
+    let words = bytes.chunks_exact(8);
+    let tail = words
+        .remainder()
+        .iter()
+        .fold(0u64, |acc, &byte| acc.rotate_left(8) ^ byte as u64);
+    words.fold(tail, |acc, word| {
+        acc ^ u64::from_le_bytes(word.try_into().unwrap())
+    })
+}
+
+/// Scans all rows of one contiguous buffer word by word.
+///
+/// Varint rows end at arbitrary offsets, so most rows start with unaligned
+/// loads and end with a byte-wise tail. Aligned rows are made of whole words
+/// starting on aligned offsets, the access pattern SIMD code relies on.
+fn benchmark_aligned_scan(c: &mut Criterion) {
+    let rows = prepare_benchmark_input();
+
+    let mut varint = Vec::new();
+    let mut offsets = vec![0];
+    for row in &rows {
+        VarintEncoder.encode(&mut varint, row);
+        offsets.push(varint.len());
+    }
+
+    let mut group = c.benchmark_group("aligned_scan");
+    group.bench_function("varint", |b| {
+        b.iter(|| {
+            offsets
+                .windows(2)
+                .fold(0, |acc, range| acc ^ xor_words(&varint[range[0]..range[1]]))
+        });
+    });
+
+    for alignment in [8, 16, 32] {
+        let encoder = LengthPrefixedEncoderAligned::new(alignment);
+        let mut aligned = Vec::new();
+        for row in &rows {
+            encoder.encode(&mut aligned, row);
+        }
+        println!(
+            "aligned_scan length_prefixed_aligned/{} size: {} bytes (varint: {} bytes)",
+            alignment,
+            aligned.len(),
+            varint.len()
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("length_prefixed_aligned", alignment),
+            &aligned,
+            |b, aligned| {
+                b.iter(|| {
+                    let mut acc = 0;
+                    let mut offset = 0;
+                    while offset < aligned.len() {
+                        let len =
+                            u32::from_le_bytes(aligned[offset..offset + 4].try_into().unwrap())
+                                as usize;
+                        let padded_len = (4 + len).next_multiple_of(alignment);
+                        acc ^= xor_words(&aligned[offset..offset + padded_len]);
+                        offset += padded_len;
+                    }
+                    black_box(acc)
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+// ============================================================================
+// Pruning Benchmarks
+// ============================================================================
+
+/// Reports how many row groups survive min/max pruning for a label prefix query,
+/// comparing sorted and unsorted input.
+fn benchmark_row_group_pruning(c: &mut Criterion) {
+    let mut labels = load_labels::<std::hash::DefaultHasher>();
+    let rows = scale_rows(&labels.label_values, 1);
+    labels.sort_rows();
+    let sorted_rows = scale_rows(&labels.label_values, 1);
+
+    let options = ParquetWriteOptions {
+        statistics: true,
+        max_row_group_size: 64,
+    };
+    // The first column is constant in the dataset, so query on the first two
+    // labels of a row from the middle of the key space.
+    let (lower, upper) =
+        MemcomparableEncoder::prefix_range(&sorted_rows[sorted_rows.len() / 2][..2]);
+
+    for (name, rows) in [("unsorted", &rows), ("sorted", &sorted_rows)] {
+        let data = encode_to_parquet_with_options(&MemcomparableEncoder, rows, &options).unwrap();
+        let total = SerializedFileReader::new(bytes::Bytes::from(data.clone()))
+            .unwrap()
+            .metadata()
+            .num_row_groups();
+        let kept = prune_row_groups(&data, &lower, &upper).unwrap().len();
+        println!(
+            "row_group_pruning_{} kept {} / {} row groups ({:.1}%)",
+            name,
+            kept,
+            total,
+            kept as f64 * 100.0 / total as f64
+        );
+
+        c.bench_function(&format!("row_group_pruning_{}", name), |b| {
+            b.iter(|| prune_row_groups(black_box(&data), &lower, &upper).unwrap());
+        });
+    }
+}
+
 criterion_group!(
     benches,
-    // Encoding benchmarks
-    benchmark_length_prefixed,
-    benchmark_varint,
-    benchmark_memcomparable,
-    benchmark_flatbuffer,
+    // Encoding and decoding benchmarks for registered encoders
+    benchmark_all_encoders,
+    benchmark_null,
     benchmark_maparray,
+    benchmark_maparray_sorted,
+    benchmark_dict_string,
+    benchmark_shuffled,
+    benchmark_dictionary,
+    benchmark_tsid_layouts,
+    benchmark_varint_encode_batch,
+    benchmark_encode_varints_batch,
+    benchmark_flatbuffer_builder_reuse,
+    benchmark_encode_sorted,
+    benchmark_long_values,
     // Decoding benchmarks
-    benchmark_decode_memcomparable,
-    benchmark_decode_length_prefixed,
-    benchmark_decode_varint,
-    benchmark_decode_flatbuffer,
     benchmark_decode_flatbuffer_zero_copy,
+    benchmark_decode_flatbuffer_borrowed,
+    benchmark_decode_column_ids_only,
+    benchmark_aligned_scan,
+    // Pruning benchmarks
+    benchmark_row_group_pruning,
 );
 criterion_main!(benches);
@@ -1,19 +1,31 @@
-use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use std::hash::Hasher;
+
+use ahash::AHasher;
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
 use fxhash::FxHasher64;
-use tsid_bench::{open_csv_reader, read_labels_and_hash};
-use tsid_bench::ts_id_gen::TsIdGenerator;
+use siphasher::sip::{SipHasher13, SipHasher24};
+use tsid_bench::read_labels_from_path;
+use tsid_bench::ts_id_gen::{SeededHasher, TsIdGenerator};
+use wyhash::WyHash;
 use xxhash_rust::xxh3::Xxh3;
 
-fn reuse_label_hash(c: &mut Criterion) {
-    let mut group = c.benchmark_group("reuse");
-    // Benchmark complete tsid generation: write_label_names + write_label_values + build_ts_id
-    group.bench_function("xx3", |b| {
-        
-        let labels = read_labels_and_hash::<Xxh3>(open_csv_reader("./assets/"));
-        let label_values: &Vec<Vec<String>> = &labels.label_values;
+/// Benchmarks ts_id generation seeded with the precomputed label name hash, so
+/// that only the label values are hashed per row.
+///
+/// `per_row` constructs a generator for every row, while `reset` resets a
+/// single generator between rows.
+fn bench_reuse<H: Default + Hasher + SeededHasher>(
+    group: &mut criterion::BenchmarkGroup<'_, criterion::measurement::WallTime>,
+    name: &str,
+) {
+    let labels =
+        read_labels_from_path::<H>("./assets/labels.csv.gz").unwrap_or_else(|err| panic!("{err}"));
+    let label_values: &Vec<Vec<String>> = &labels.label_values;
+
+    group.bench_function(BenchmarkId::new(name, "per_row"), |b| {
         b.iter(|| {
             for label_value_row in label_values.iter() {
-                let mut generator = TsIdGenerator::<Xxh3>::from_seed(labels.label_name_hash);
+                let mut generator = TsIdGenerator::<H>::from_seed(labels.label_name_hash);
                 generator.write_label_values(black_box(
                     label_value_row.iter().map(|s: &String| s.as_bytes()),
                 ));
@@ -23,24 +35,79 @@ fn reuse_label_hash(c: &mut Criterion) {
         });
     });
 
-    group.bench_function("fxhash", |b| {
-        let labels = read_labels_and_hash::<FxHasher64>(open_csv_reader("./assets/"));
-        let label_values: &Vec<Vec<String>> = &labels.label_values;
-
+    group.bench_function(BenchmarkId::new(name, "reset"), |b| {
+        let mut generator = TsIdGenerator::<H>::from_seed(labels.label_name_hash);
         b.iter(|| {
             for label_value_row in label_values.iter() {
-                let mut generator = TsIdGenerator::<FxHasher64>::from_seed(labels.label_name_hash);
+                generator.reset();
                 generator.write_label_values(black_box(
                     label_value_row.iter().map(|s: &String| s.as_bytes()),
                 ));
-                let tsid = black_box(generator.build_ts_id());
+                let tsid = black_box(generator.finish());
                 black_box(tsid);
             }
         });
     });
+}
+
+/// Compares seeding each row with the label name hash against resuming each
+/// row from a snapshot taken after hashing the label names once.
+fn bench_reuse_snapshot<H: Default + Hasher + SeededHasher + Clone>(
+    group: &mut criterion::BenchmarkGroup<'_, criterion::measurement::WallTime>,
+    name: &str,
+) {
+    let labels =
+        read_labels_from_path::<H>("./assets/labels.csv.gz").unwrap_or_else(|err| panic!("{err}"));
+    let label_values: &Vec<Vec<String>> = &labels.label_values;
+
+    group.bench_function(BenchmarkId::new(name, "seed"), |b| {
+        b.iter(|| {
+            for label_value_row in label_values.iter() {
+                let mut generator = TsIdGenerator::<H>::from_seed(labels.label_name_hash);
+                generator.write_label_values(black_box(
+                    label_value_row.iter().map(|s: &String| s.as_bytes()),
+                ));
+                black_box(generator.build_ts_id());
+            }
+        });
+    });
+
+    let mut generator = TsIdGenerator::<H>::from_seed(0);
+    generator.write_label_names(labels.label_names.iter().map(|s| s.as_bytes()));
+    let snapshot = generator.snapshot();
+    group.bench_function(BenchmarkId::new(name, "snapshot"), |b| {
+        b.iter(|| {
+            for label_value_row in label_values.iter() {
+                let mut generator = TsIdGenerator::resume(&snapshot);
+                generator.write_label_values(black_box(
+                    label_value_row.iter().map(|s: &String| s.as_bytes()),
+                ));
+                black_box(generator.build_ts_id());
+            }
+        });
+    });
+}
+
+fn reuse_snapshot(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reuse_snapshot");
+    bench_reuse_snapshot::<Xxh3>(&mut group, "xx3");
+    bench_reuse_snapshot::<FxHasher64>(&mut group, "fxhash");
+    bench_reuse_snapshot::<AHasher>(&mut group, "ahash");
+    bench_reuse_snapshot::<WyHash>(&mut group, "wyhash");
+    bench_reuse_snapshot::<SipHasher13>(&mut group, "sip13");
+    group.finish();
+}
 
+fn reuse_label_hash(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reuse");
+    bench_reuse::<Xxh3>(&mut group, "xx3");
+    bench_reuse::<FxHasher64>(&mut group, "fxhash");
+    bench_reuse::<AHasher>(&mut group, "ahash");
+    bench_reuse::<WyHash>(&mut group, "wyhash");
+    bench_reuse::<SipHasher13>(&mut group, "sip13");
+    bench_reuse::<SipHasher24>(&mut group, "sip24");
     group.finish();
 }
 
-criterion_group!(benches, reuse_label_hash);
+criterion_group!(benches, reuse_label_hash, reuse_snapshot);
 criterion_main!(benches);
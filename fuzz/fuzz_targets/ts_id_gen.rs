@@ -0,0 +1,53 @@
+//! Checks ts_id generation on arbitrary label values.
+//!
+//! Collision freedom cannot be checked exhaustively, so this asserts that the
+//! same input always yields the same ts_id and that two series differing only
+//! in their last label value get different ts_ids.
+
+#![no_main]
+
+use std::hash::Hasher;
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use tsid_bench::ts_id_gen::{SeededHasher, TsIdGenerator};
+use xxhash_rust::xxh3::Xxh3;
+use xxhash_rust::xxh64::Xxh64;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    seed: u64,
+    values: Vec<Vec<u8>>,
+    suffix_a: Vec<u8>,
+    suffix_b: Vec<u8>,
+}
+
+fn ts_id<H: Hasher + SeededHasher>(seed: u64, values: &[Vec<u8>], suffix: &[u8]) -> u64 {
+    let mut generator = TsIdGenerator::<H>::from_seed(seed);
+    generator.write_label_values(
+        values
+            .iter()
+            .map(Vec::as_slice)
+            .chain(std::iter::once(suffix)),
+    );
+    generator.build_ts_id()
+}
+
+fn check<H: Hasher + SeededHasher>(input: &Input) {
+    let a = ts_id::<H>(input.seed, &input.values, &input.suffix_a);
+    assert_eq!(
+        a,
+        ts_id::<H>(input.seed, &input.values, &input.suffix_a),
+        "ts_id is not deterministic"
+    );
+
+    if input.suffix_a != input.suffix_b {
+        let b = ts_id::<H>(input.seed, &input.values, &input.suffix_b);
+        assert_ne!(a, b, "collision between different last values");
+    }
+}
+
+fuzz_target!(|input: Input| {
+    check::<Xxh3>(&input);
+    check::<Xxh64>(&input);
+});
@@ -63,6 +63,10 @@ def parse_benchmark_output(output: str) -> tuple[list[EncodingResult], list[Deco
         r"parquet_encoding_(\w+) file size: \d+ bytes \(([\d.]+) KB\)"
     )
 
+    # Encoder benchmarks are named "parquet_encoding/XXX" (a Criterion group
+    # with a throughput annotation), the other layouts "parquet_encoding_XXX".
+    encode_prefix = re.compile(r"^parquet_encoding[_/]")
+
     # Pattern for timing - handles both formats:
     # 1. Name on separate line: "parquet_encoding_XXX\n                        time:   [...]"
     # 2. Name on same line: "parquet_encoding_XXX time:   [...]" or "decode_XXX    time:   [...]"
@@ -72,7 +76,7 @@ def parse_benchmark_output(output: str) -> tuple[list[EncodingResult], list[Deco
 
     # Combined pattern for name + time on same line
     combined_encode_pattern = re.compile(
-        r"^(parquet_encoding_\w+)\s+time:\s+\[([\d.]+)\s*(\w+)\s+([\d.]+)\s*(\w+)\s+([\d.]+)\s*(\w+)\]"
+        r"^(parquet_encoding[_/]\w+)\s+time:\s+\[([\d.]+)\s*(\w+)\s+([\d.]+)\s*(\w+)\s+([\d.]+)\s*(\w+)\]"
     )
     combined_decode_pattern = re.compile(
         r"^(decode_\w+)\s+time:\s+\[([\d.]+)\s*(\w+)\s+([\d.]+)\s*(\w+)\s+([\d.]+)\s*(\w+)\]"
@@ -99,7 +103,7 @@ def parse_benchmark_output(output: str) -> tuple[list[EncodingResult], list[Deco
             time_unit = combined_enc_match.group(5)
             time_ms = convert_to_ms(time_value, time_unit)
 
-            method_name = bench_name.replace("parquet_encoding_", "")
+            method_name = encode_prefix.sub("", bench_name)
             encoding_results[method_name] = EncodingResult(
                 name=method_name,
                 time_ms=time_ms,
@@ -124,7 +128,7 @@ def parse_benchmark_output(output: str) -> tuple[list[EncodingResult], list[Deco
             continue
 
         # Check for benchmark name on its own line (encoding)
-        if stripped.startswith("parquet_encoding_") and "file size" not in stripped and "time:" not in stripped:
+        if encode_prefix.match(stripped) and "file size" not in stripped and "time:" not in stripped:
             current_benchmark = ("encode", stripped)
             continue
 
@@ -144,7 +148,7 @@ def parse_benchmark_output(output: str) -> tuple[list[EncodingResult], list[Deco
                 bench_type, bench_name = current_benchmark
 
                 if bench_type == "encode":
-                    method_name = bench_name.replace("parquet_encoding_", "")
+                    method_name = encode_prefix.sub("", bench_name)
                     encoding_results[method_name] = EncodingResult(
                         name=method_name,
                         time_ms=time_ms,
@@ -0,0 +1,5 @@
+module github.com/v0y4g3r/tsid-benchmark/scripts/prometheus_hash_golden
+
+go 1.22
+
+require github.com/prometheus/prometheus v0.54.1
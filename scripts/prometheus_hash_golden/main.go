@@ -0,0 +1,42 @@
+// Prints Prometheus' labels.Hash() for the label sets used by the
+// prometheus_series_hash tests in src/ts_id_gen.rs.
+//
+// Run with:
+//
+//	cd scripts/prometheus_hash_golden && go mod tidy && go run .
+package main
+
+import (
+	"fmt"
+	"strings"
+
+	"github.com/prometheus/prometheus/model/labels"
+)
+
+func main() {
+	cases := []struct {
+		name string
+		lbls labels.Labels
+	}{
+		{"empty", labels.EmptyLabels()},
+		{"a=b", labels.FromStrings("a", "b")},
+		{"up", labels.FromStrings(
+			"__name__", "up",
+			"instance", "localhost:9090",
+			"job", "prometheus",
+		)},
+		{"http_requests_total", labels.FromStrings(
+			"__name__", "http_requests_total",
+			"code", "200",
+			"handler", "/api/v1/query",
+			"method", "GET",
+		)},
+		{"long", labels.FromStrings(
+			"__name__", "long",
+			"value", strings.Repeat("x", 2000),
+		)},
+	}
+	for _, c := range cases {
+		fmt.Printf("%-20s 0x%016x\n", c.name, c.lbls.Hash())
+	}
+}
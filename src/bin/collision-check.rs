@@ -0,0 +1,177 @@
+use std::hash::{DefaultHasher, Hasher};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use ahash::AHasher;
+use fxhash::FxHasher64;
+use mur3::Hasher128;
+use siphasher::sip::{SipHasher13, SipHasher24};
+use tsid_bench::collision::{
+    CollisionChecker, CollisionReport, ShardedCollisionChecker, run_collision_check_parallel,
+};
+use tsid_bench::ts_id_gen::{SeededHasher, TsIdGenerator};
+use tsid_bench::{Labels, compute_label_name_hash, read_labels_from_path};
+use wyhash::WyHash;
+use xxhash_rust::xxh3::Xxh3;
+use xxhash_rust::xxh64::Xxh64;
+
+/// Amplified rows hashed and checked at a time.
+const BATCH_SIZE: usize = 1 << 18;
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.len() < 4 || args.len() > 5 {
+        eprintln!(
+            "Usage: {} <labels_csv_path, or - for stdin> <hasher> <amp> [threads]",
+            args[0]
+        );
+        eprintln!("Hashers: default, fx, xxh3, xxh64, mur3, sip13, sip24, ahash, wyhash");
+        eprintln!("Example: {} assets/labels.csv.gz xxh3 1000 8", args[0]);
+        std::process::exit(1);
+    }
+    let path = &args[1];
+    let amp: usize = parse_arg("amp", &args[3]);
+    let threads: usize = match args.get(4) {
+        Some(arg) => parse_arg("threads", arg),
+        None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    if let Err(e) = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+    {
+        eprintln!("Error: failed to build thread pool: {}", e);
+        std::process::exit(1);
+    }
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_flag = Arc::clone(&interrupted);
+    if let Err(e) = ctrlc::set_handler(move || handler_flag.store(true, Ordering::Relaxed)) {
+        eprintln!("Error: failed to set Ctrl-C handler: {}", e);
+        std::process::exit(1);
+    }
+
+    let (label_name_hash, check_fn) = match args[2].as_str() {
+        "default" => seeded::<DefaultHasher>(),
+        "fx" | "fxhash" => seeded::<FxHasher64>(),
+        "xxh3" => seeded::<Xxh3>(),
+        "xxh64" => seeded::<Xxh64>(),
+        "mur3" => (
+            mur3_label_name_hash as LabelNameHashFn,
+            check_with::<Hasher128> as CheckFn,
+        ),
+        "sip13" => seeded::<SipHasher13>(),
+        "sip24" => seeded::<SipHasher24>(),
+        "ahash" => seeded::<AHasher>(),
+        "wyhash" => seeded::<WyHash>(),
+        other => {
+            eprintln!("Error: unknown hasher {}", other);
+            std::process::exit(1);
+        }
+    };
+    let result = check(path, amp, threads, label_name_hash, check_fn, &interrupted);
+    let (report, total) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if report.inserted < total {
+        println!("interrupted:         {} of {} rows", report.inserted, total);
+    }
+    println!("inserted:            {}", report.inserted);
+    println!("collisions:          {}", report.collisions);
+    println!("expected collisions: {:.3e}", report.expected_collisions);
+    for ts_id in &report.first_collisions {
+        println!("  {:#018x}", ts_id);
+    }
+}
+
+fn parse_arg(name: &str, arg: &str) -> usize {
+    match arg.parse() {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("Error: invalid {} {}: {}", name, arg, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+type LabelNameHashFn = fn(&[String]) -> u64;
+type CheckFn = fn(
+    &Labels,
+    usize,
+    ShardedCollisionChecker,
+    &mut dyn FnMut(&ShardedCollisionChecker) -> bool,
+) -> CollisionReport;
+
+fn seeded<H>() -> (LabelNameHashFn, CheckFn)
+where
+    H: Default + Hasher + SeededHasher,
+{
+    (compute_label_name_hash::<H>, check_with::<H>)
+}
+
+fn check_with<H>(
+    labels: &Labels,
+    amp: usize,
+    checker: ShardedCollisionChecker,
+    keep_going: &mut dyn FnMut(&ShardedCollisionChecker) -> bool,
+) -> CollisionReport
+where
+    H: Hasher + SeededHasher,
+{
+    run_collision_check_parallel::<H>(labels, amp, checker, BATCH_SIZE, keep_going)
+}
+
+/// [`Hasher128`] has no `Default`, so it hashes the label names from seed 0.
+fn mur3_label_name_hash(label_names: &[String]) -> u64 {
+    let mut generator = TsIdGenerator::<Hasher128>::from_seed(0);
+    generator.write_label_names(label_names.iter().map(String::as_bytes));
+    generator.build_ts_id()
+}
+
+/// Reads the deduplicated labels at `path` and checks the ts_ids of their rows
+/// amplified `amp` times for collisions with `run`. The label names are
+/// hashed by `label_name_hash`.
+///
+/// Stops after the current batch once `interrupted` is set. Returns the report
+/// and the number of rows it would cover if not interrupted.
+fn check(
+    path: &str,
+    amp: usize,
+    threads: usize,
+    label_name_hash: LabelNameHashFn,
+    run: CheckFn,
+    interrupted: &AtomicBool,
+) -> Result<(CollisionReport, u64), tsid_bench::Error> {
+    let mut labels = read_labels_from_path::<Xxh3>(path)?;
+    labels.label_name_hash = label_name_hash(&labels.label_names);
+    labels.dedup();
+    let total = (labels.label_values.len() * amp) as u64;
+
+    // A few shards per thread keeps them balanced.
+    let shard_bits = (threads * 4).next_power_of_two().trailing_zeros().min(16);
+    let checker = ShardedCollisionChecker::new(CollisionChecker::new(), shard_bits);
+    let start = Instant::now();
+    let mut last_progress = start;
+    let report = run(&labels, amp, checker, &mut |checker| {
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            last_progress = Instant::now();
+            let report = checker.report();
+            eprintln!(
+                "{}/{} rows, {} collisions, {:.0} rows/s",
+                report.inserted,
+                total,
+                report.collisions,
+                report.inserted as f64 / start.elapsed().as_secs_f64()
+            );
+        }
+        !interrupted.load(Ordering::Relaxed)
+    });
+    Ok((report, total))
+}
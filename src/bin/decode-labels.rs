@@ -0,0 +1,89 @@
+use std::fs::File;
+
+use arrow::array::{Array, AsArray};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use tsid_bench::{
+    ColumnIdMap, FlatBufferEncoder, LengthPrefixedEncoder, LengthPrefixedEncoderBE,
+    MemcomparableEncoder, RowEncoder, VarintEncoder,
+};
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.len() != 5 {
+        eprintln!(
+            "Usage: {} <encoder> <primary_keys.parquet> <column_ids.json> <output_csv_path>",
+            args[0]
+        );
+        eprintln!(
+            "Encoders: length_prefixed, length_prefixed_be, varint, memcomparable, flatbuffer"
+        );
+        eprintln!(
+            "Example: {} varint primary_keys.parquet column_ids.json labels.csv",
+            args[0]
+        );
+        std::process::exit(1);
+    }
+
+    let encoder: Box<dyn RowEncoder> = match args[1].as_str() {
+        "length_prefixed" => Box::new(LengthPrefixedEncoder),
+        "length_prefixed_be" => Box::new(LengthPrefixedEncoderBE),
+        "varint" => Box::new(VarintEncoder),
+        "memcomparable" => Box::new(MemcomparableEncoder),
+        "flatbuffer" => Box::new(FlatBufferEncoder),
+        other => {
+            eprintln!("Error: unknown encoder {}", other);
+            std::process::exit(1);
+        }
+    };
+
+    match decode_to_csv(encoder.as_ref(), &args[2], &args[3], &args[4]) {
+        Ok(num_rows) => {
+            println!("Successfully decoded {} rows to {}", num_rows, args[4]);
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Decode the `primary_key` column of a parquet file into a CSV file whose
+/// columns are named after the column id map, in ascending id order.
+///
+/// Labels missing from a row are written as empty values.
+fn decode_to_csv(
+    encoder: &dyn RowEncoder,
+    parquet_path: &str,
+    column_ids_path: &str,
+    output_path: &str,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let column_ids = ColumnIdMap::read_json_path(column_ids_path)?;
+    let (ids, names): (Vec<u32>, Vec<&str>) = column_ids.iter().unzip();
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(parquet_path)?)?.build()?;
+    let mut writer = csv::Writer::from_path(output_path)?;
+    writer.write_record(&names)?;
+
+    let mut num_rows = 0;
+    for batch in reader {
+        let batch = batch?;
+        let primary_keys = batch
+            .column_by_name("primary_key")
+            .ok_or("missing primary_key column")?
+            .as_binary::<i32>();
+        for idx in 0..primary_keys.len() {
+            let mut row = vec![String::new(); ids.len()];
+            for (id, value) in encoder.decode(primary_keys.value(idx))? {
+                let column = ids
+                    .binary_search(&id)
+                    .map_err(|_| format!("column id {} is not in {}", id, column_ids_path))?;
+                row[column] = value;
+            }
+            writer.write_record(&row)?;
+            num_rows += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(num_rows)
+}
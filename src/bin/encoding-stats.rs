@@ -0,0 +1,77 @@
+use std::hash::DefaultHasher;
+use std::sync::Arc;
+
+use tsid_bench::encoding::{chain, encode_to_vec};
+use tsid_bench::{
+    FlatBufferEncoder, LengthPrefixedEncoder, LengthPrefixedEncoderAligned,
+    LengthPrefixedEncoderBE, MemcomparableEncoder, MemcomparableEncoderNullable, RowEncoder,
+    SchemaAwareEncoder, VarintEncoder, compute_gzip_compression_ratio, read_labels_from_path,
+    scale_rows,
+};
+
+/// Every encoder with a distinct output, except the null encoder which has
+/// none.
+///
+/// The encoders are built afresh for each measurement, since
+/// [`SchemaAwareEncoder`] only writes its dictionary into the first row.
+fn encoders(label_names: &[String]) -> Vec<Box<dyn RowEncoder>> {
+    vec![
+        Box::new(VarintEncoder),
+        Box::new(LengthPrefixedEncoder),
+        Box::new(LengthPrefixedEncoderBE),
+        Box::new(LengthPrefixedEncoderAligned::new(8)),
+        Box::new(MemcomparableEncoder),
+        Box::new(MemcomparableEncoderNullable),
+        Box::new(FlatBufferEncoder),
+        Box::new(SchemaAwareEncoder::new(
+            VarintEncoder,
+            Arc::new(label_names.to_vec()),
+        )),
+        Box::new(chain(VarintEncoder, LengthPrefixedEncoder)),
+    ]
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.len() > 2 {
+        eprintln!("Usage: {} [labels_csv_path, or - for stdin]", args[0]);
+        eprintln!("Example: {} assets/labels.csv.gz", args[0]);
+        std::process::exit(1);
+    }
+    let path = args.get(1).map_or("./assets/labels.csv.gz", String::as_str);
+
+    let labels = match read_labels_from_path::<DefaultHasher>(path) {
+        Ok(labels) => labels,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let rows = scale_rows(&labels.label_values, 1);
+
+    let raw_sizes: Vec<usize> = encoders(&labels.label_names)
+        .iter()
+        .map(|encoder| {
+            rows.iter()
+                .map(|row| encode_to_vec(encoder.as_ref(), row).len())
+                .sum()
+        })
+        .collect();
+
+    println!("{} rows from {}", rows.len(), path);
+    println!(
+        "{:<24} {:>14} {:>10} {:>18}",
+        "encoder", "raw (bytes)", "gzip ratio", "compressed (bytes)"
+    );
+    for (encoder, raw_size) in encoders(&labels.label_names).iter().zip(raw_sizes) {
+        let ratio = compute_gzip_compression_ratio(encoder.as_ref(), &rows);
+        println!(
+            "{:<24} {:>14} {:>10.2} {:>18.0}",
+            encoder.name(),
+            raw_size,
+            ratio,
+            raw_size as f64 / ratio
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use std::hash::{DefaultHasher, Hasher};
+
+use ahash::AHasher;
+use fxhash::FxHasher64;
+use mur3::Hasher128;
+use siphasher::sip::{SipHasher13, SipHasher24};
+use tsid_bench::hash_quality::{avalanche, bit_bias};
+use tsid_bench::ts_id_gen::SeededHasher;
+use tsid_bench::{Labels, read_labels_from_path};
+use wyhash::WyHash;
+use xxhash_rust::xxh3::Xxh3;
+use xxhash_rust::xxh64::Xxh64;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.len() > 4 {
+        eprintln!(
+            "Usage: {} [labels_csv_path, or - for stdin] [amp] [samples]",
+            args[0]
+        );
+        eprintln!("Example: {} assets/labels.csv.gz 100 100000", args[0]);
+        std::process::exit(1);
+    }
+    let path = args.get(1).map_or("./assets/labels.csv.gz", String::as_str);
+    let amp = args.get(2).map_or(100, |arg| parse_arg("amp", arg));
+    let samples = args.get(3).map_or(100_000, |arg| parse_arg("samples", arg));
+
+    let mut labels = match read_labels_from_path::<Xxh3>(path) {
+        Ok(labels) => labels,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    labels.dedup();
+
+    println!(
+        "{} distinct rows from {}, amplified {} times for bit bias, {} avalanche samples",
+        labels.label_values.len(),
+        path,
+        amp,
+        samples
+    );
+    println!(
+        "{:<10} {:>14} {:>14} {:>10}",
+        "hasher", "max bit bias", "mean bit bias", "avalanche"
+    );
+    print_row::<DefaultHasher>("default", &labels, amp, samples);
+    print_row::<FxHasher64>("fx", &labels, amp, samples);
+    print_row::<Xxh3>("xxh3", &labels, amp, samples);
+    print_row::<Xxh64>("xxh64", &labels, amp, samples);
+    print_row::<Hasher128>("mur3", &labels, amp, samples);
+    print_row::<SipHasher13>("sip13", &labels, amp, samples);
+    print_row::<SipHasher24>("sip24", &labels, amp, samples);
+    print_row::<AHasher>("ahash", &labels, amp, samples);
+    print_row::<WyHash>("wyhash", &labels, amp, samples);
+}
+
+fn parse_arg(name: &str, arg: &str) -> usize {
+    match arg.parse() {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("Error: invalid {} {}: {}", name, arg, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn print_row<H>(name: &str, labels: &Labels, amp: usize, samples: usize)
+where
+    H: Hasher + SeededHasher,
+{
+    let bias = bit_bias::<H>(labels, amp);
+    let max_bias = bias.iter().copied().fold(0.0, f64::max);
+    let mean_bias = bias.iter().sum::<f64>() / bias.len() as f64;
+    println!(
+        "{:<10} {:>14.4} {:>14.4} {:>10.4}",
+        name,
+        max_bias,
+        mean_bias,
+        avalanche::<H>(labels, samples)
+    );
+}
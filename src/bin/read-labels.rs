@@ -1,22 +1,338 @@
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+
 use tsid_bench::data_reader;
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
 
-    if args.len() != 3 {
-        eprintln!("Usage: {} <input_parquet_path> <output_csv_path>", args[0]);
+    #[cfg(feature = "object-store")]
+    if args.len() == 4 && args[1] == "--store-url" {
+        read_from_store(&args[2], &args[3]);
+        return;
+    }
+
+    if args.len() == 3 && args[1] == "--stats" {
+        print_stats(&args[2]);
+        return;
+    }
+
+    let (config, paths) = match parse_config(&args[1..]) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if paths.len() != 2 {
+        eprintln!(
+            "Usage: {} [flags] <input_parquet_path_or_dir_or_prom_file> <output_csv_or_parquet_path>",
+            args[0]
+        );
+        #[cfg(feature = "object-store")]
+        eprintln!(
+            "       {} --store-url <object_store_url> <output_csv_path>",
+            args[0]
+        );
+        eprintln!(
+            "       {} --stats <labels_csv_or_prom_path, or - for a CSV on stdin>",
+            args[0]
+        );
+        eprintln!("Only --stats reads stdin; parquet input must be a file or directory.");
+        eprintln!("Flags for a single parquet file:");
+        eprintln!("  --dedup                  skip rows already written");
+        eprintln!("  --capacity <rows>        expected number of distinct rows");
+        eprintln!("  --dedup-memory <bytes>   spill distinct rows to disk beyond this size");
+        eprintln!("  --dedup-hash-only        compare row hashes instead of values");
+        eprintln!("  --spill-dir <dir>        directory of the spilled rows");
+        eprintln!("  --nulls <empty|skip-row|literal:TEXT>");
+        eprintln!("                           how null values are written");
+        eprintln!("  --limit <rows>           stop after writing this many rows");
+        eprintln!("  --sample <fraction>      keep a deterministic fraction of the rows");
+        eprintln!("  --seed <seed>            seed selecting the sample");
+        eprintln!("  --filter <column=value>  keep rows where column equals value, repeatable");
         eprintln!("Example: {} data.parquet output.csv", args[0]);
+        eprintln!(
+            "         {} --dedup --limit 1000 --sample 0.01 data.parquet output.csv",
+            args[0]
+        );
+        eprintln!(
+            "         {} --filter __name__=up --filter namespace=prod data.parquet output.csv",
+            args[0]
+        );
+        eprintln!("         {} --dedup data.parquet labels.parquet", args[0]);
+        eprintln!("         {} data/ output.csv", args[0]);
+        eprintln!("         {} metrics.prom output.csv", args[0]);
+        eprintln!("         zcat labels.csv.gz | {} --stats -", args[0]);
+        std::process::exit(1);
+    }
+
+    let input_path = paths[0];
+    let output_path = paths[1];
+    if input_path == tsid_bench::STDIN_PATH {
+        eprintln!("Error: only --stats reads stdin, pass a parquet file or directory");
+        std::process::exit(1);
+    }
+
+    let is_parquet_file = !is_prom_text(input_path) && !std::path::Path::new(input_path).is_dir();
+    if !is_parquet_file && config.is_some() {
+        eprintln!("Error: extraction flags only apply to a single parquet file");
+        std::process::exit(1);
+    }
+    let is_parquet_output =
+        data_reader::OutputFormat::from_path(output_path) == data_reader::OutputFormat::Parquet;
+    if !is_parquet_file && is_parquet_output {
+        eprintln!("Error: parquet output is only supported for a single parquet file");
         std::process::exit(1);
     }
 
-    let input_path = &args[1];
-    let output_path = &args[2];
+    if is_prom_text(input_path) {
+        let result = tsid_bench::read_labels_from_prom_path::<std::hash::DefaultHasher>(input_path)
+            .and_then(|labels| {
+                labels
+                    .write_csv_to_path(output_path)
+                    .map(|()| labels.label_values.len())
+            });
+        match result {
+            Ok(num_rows) => {
+                println!(
+                    "Successfully processed {} and wrote {} rows to {}",
+                    input_path, num_rows, output_path
+                );
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if std::path::Path::new(input_path).is_dir() {
+        match data_reader::read_parquet_directory(
+            input_path,
+            output_path,
+            &data_reader::EXCLUDED_COLUMNS,
+        ) {
+            Ok(num_rows) => {
+                println!(
+                    "Successfully processed parquet files in {} and wrote {} rows to {}",
+                    input_path, num_rows, output_path
+                );
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let mut reporter = ProgressReporter::new();
+    let result = data_reader::read_parquet_files_with_progress(
+        input_path,
+        output_path,
+        config.unwrap_or_default(),
+        |progress| reporter.report(progress),
+    );
+    reporter.finish();
+    match result {
+        Ok(summary) => {
+            println!(
+                "Successfully processed parquet file and wrote {} rows to {}",
+                summary.distinct_rows, output_path
+            );
+            println!(
+                "Scanned {} rows in {} row groups in {:.1?}",
+                summary.rows_scanned, summary.row_groups, summary.elapsed
+            );
+            if summary.spilled_runs > 0 {
+                println!(
+                    "Merged {} sorted runs spilled to disk",
+                    summary.spilled_runs
+                );
+            }
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Renders extraction progress on stderr.
+///
+/// On a terminal, a single line is redrawn in place a few times per second.
+/// Otherwise, e.g. when stderr goes to a log file, a line is printed every
+/// few seconds.
+struct ProgressReporter {
+    is_terminal: bool,
+    last_report: Instant,
+    reported: bool,
+}
+
+impl ProgressReporter {
+    const TERMINAL_INTERVAL: Duration = Duration::from_millis(200);
+    const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+    fn new() -> Self {
+        Self {
+            is_terminal: std::io::stderr().is_terminal(),
+            last_report: Instant::now(),
+            reported: false,
+        }
+    }
+
+    fn report(&mut self, progress: data_reader::Progress) {
+        let interval = if self.is_terminal {
+            Self::TERMINAL_INTERVAL
+        } else {
+            Self::LOG_INTERVAL
+        };
+        if self.last_report.elapsed() < interval {
+            return;
+        }
+        self.last_report = Instant::now();
+
+        let line = format!(
+            "row group {}/{}, {} rows scanned, {} written, {:.1}/{:.1} MiB read",
+            progress.row_group + 1,
+            progress.num_row_groups,
+            progress.rows_scanned,
+            progress.distinct_rows,
+            progress.bytes_read as f64 / (1024.0 * 1024.0),
+            progress.total_bytes as f64 / (1024.0 * 1024.0)
+        );
+        if self.is_terminal {
+            // Clear the rest of the previous line, which may have been longer
+            eprint!("\r{}\x1b[K", line);
+        } else {
+            eprintln!("{}", line);
+        }
+        self.reported = true;
+    }
+
+    /// Ends the in-place progress line, if one was drawn.
+    fn finish(&self) {
+        if self.is_terminal && self.reported {
+            eprintln!();
+        }
+    }
+}
+
+/// Splits the parquet extraction flags from the positional arguments.
+///
+/// The config is `None` if no flag was given.
+fn parse_config(
+    args: &[String],
+) -> Result<(Option<data_reader::ReadParquetConfig>, Vec<&str>), String> {
+    let mut config = None;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            paths.push(arg.as_str());
+            continue;
+        }
+        let config = config.get_or_insert_with(data_reader::ReadParquetConfig::default);
+        match arg.as_str() {
+            "--dedup" => config.dedup = true,
+            "--nulls" => config.nulls = flag_value(&mut args, arg)?,
+            "--capacity" => config.capacity_hint = Some(flag_value(&mut args, arg)?),
+            "--dedup-memory" => {
+                config.dedup = true;
+                config.dedup_memory_limit = Some(flag_value(&mut args, arg)?);
+            }
+            "--dedup-hash-only" => {
+                config.dedup = true;
+                config.dedup_hash_only = true;
+            }
+            "--spill-dir" => config.spill_dir = Some(flag_value(&mut args, arg)?),
+            "--limit" => config.limit = Some(flag_value(&mut args, arg)?),
+            "--sample" => config.sample = Some(flag_value(&mut args, arg)?),
+            "--seed" => config.sample_seed = flag_value(&mut args, arg)?,
+            "--filter" => {
+                let filter: String = flag_value(&mut args, arg)?;
+                let (column, value) = filter.split_once('=').ok_or_else(|| {
+                    format!("invalid --filter {:?}, expected column=value", filter)
+                })?;
+                config.filters.push((column.to_owned(), value.to_owned()));
+            }
+            _ => return Err(format!("unknown flag {}", arg)),
+        }
+    }
+    Ok((config, paths))
+}
+
+/// Parses the value following `flag`.
+fn flag_value<'a, T: std::str::FromStr>(
+    args: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<T, String> {
+    let value = args
+        .next()
+        .ok_or_else(|| format!("{} requires a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid {} {:?}", flag, value))
+}
+
+/// Whether `path` is a Prometheus text exposition, possibly compressed.
+fn is_prom_text(path: &str) -> bool {
+    let path = [".gz", ".zst", ".zstd"]
+        .iter()
+        .find_map(|extension| path.strip_suffix(extension))
+        .unwrap_or(path);
+    path.ends_with(".prom")
+}
+
+/// Print per-label cardinality and size statistics of a labels CSV or
+/// Prometheus text file.
+fn print_stats(path: &str) {
+    let labels = if is_prom_text(path) {
+        tsid_bench::read_labels_from_prom_path::<std::hash::DefaultHasher>(path)
+    } else {
+        tsid_bench::read_labels_from_path::<std::hash::DefaultHasher>(path)
+    };
+    match labels {
+        Ok(labels) => println!("{}", labels.stats()),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Read labels from an object store URL such as `s3://bucket/labels.parquet`.
+///
+/// Store credentials and settings are taken from environment variables, e.g.
+/// `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, `AWS_REGION` and `AWS_ENDPOINT`.
+#[cfg(feature = "object-store")]
+fn read_from_store(store_url: &str, output_path: &str) {
+    use std::sync::Arc;
+
+    use tsid_bench::store;
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start tokio runtime");
+    let result = runtime.block_on(async {
+        let url = url::Url::parse(store_url)?;
+        let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+        let (object_store, path) = object_store::parse_url_opts(&url, options)?;
+        let labels = store::read_labels_from_store::<std::hash::DefaultHasher>(
+            Arc::from(object_store),
+            &path,
+        )
+        .await?;
+        labels.write_csv_to_path(output_path)?;
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+    });
 
-    match data_reader::read_parquet_files(input_path, output_path) {
+    match result {
         Ok(()) => {
             println!(
-                "Successfully processed parquet file and wrote distinct rows to {}",
-                output_path
+                "Successfully processed {} and wrote distinct rows to {}",
+                store_url, output_path
             );
         }
         Err(e) => {
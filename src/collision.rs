@@ -0,0 +1,569 @@
+//! Counting ts_id collisions over large amplified label sets.
+//!
+//! [`CollisionChecker`] keeps counting after the first collision, so that the
+//! observed collision rate can be compared with the birthday bound of an
+//! ideal hash of the same width.
+
+use std::collections::HashSet;
+use std::hash::Hasher;
+
+use rayon::prelude::*;
+
+use crate::Labels;
+use crate::ts_id_gen::{SeededHasher, TsIdMode, compute_ts_ids, compute_ts_ids_parallel};
+
+/// Number of colliding ts_ids kept for the report by default.
+const DEFAULT_MAX_REPORTED: usize = 10;
+
+/// Streaming ts_id collision counter.
+///
+/// By default every distinct ts_id is kept in a `HashSet`, which takes about
+/// 16 bytes per ts_id. For runs that don't fit in memory,
+/// [`with_memory_limit`](CollisionChecker::with_memory_limit) caps the exact
+/// set and tracks the remaining ts_ids in a coarse bitmap only.
+#[derive(Debug, Clone)]
+pub struct CollisionChecker {
+    seen: HashSet<u64>,
+    bitmap: Option<CoarseBitmap>,
+    hash_bits: u32,
+    max_reported: usize,
+    inserted: u64,
+    collisions: u64,
+    possible_collisions: u64,
+    first_collisions: Vec<u64>,
+}
+
+/// One bit per bucket of ts_ids sharing their low bits, backing the exact set
+/// once it holds `max_exact` ts_ids.
+#[derive(Debug, Clone)]
+struct CoarseBitmap {
+    words: Vec<u64>,
+    mask: u64,
+    max_exact: usize,
+}
+
+impl CoarseBitmap {
+    /// Sets the bit of `ts_id`'s bucket, returning whether it was already set.
+    fn test_and_set(&mut self, ts_id: u64) -> bool {
+        let bucket = ts_id & self.mask;
+        let word = &mut self.words[(bucket / 64) as usize];
+        let bit = 1 << (bucket % 64);
+        let was_set = *word & bit != 0;
+        *word |= bit;
+        was_set
+    }
+}
+
+/// Collision statistics of a [`CollisionChecker`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollisionReport {
+    /// Number of ts_ids inserted, including repeated ones.
+    pub inserted: u64,
+    /// Number of inserted ts_ids equal to one inserted before.
+    pub collisions: u64,
+    /// Number of ts_ids that landed in an occupied bucket of the coarse bitmap
+    /// and could not be confirmed, see
+    /// [`CollisionChecker::with_memory_limit`]. Always 0 without a memory
+    /// limit.
+    pub possible_collisions: u64,
+    /// Collisions expected from an ideal hash of the same width, by the
+    /// birthday bound.
+    pub expected_collisions: f64,
+    /// The first colliding ts_ids, in insertion order.
+    pub first_collisions: Vec<u64>,
+}
+
+impl CollisionChecker {
+    /// Creates an exact checker of 64-bit ts_ids.
+    pub fn new() -> Self {
+        Self {
+            seen: HashSet::new(),
+            bitmap: None,
+            hash_bits: u64::BITS,
+            max_reported: DEFAULT_MAX_REPORTED,
+            inserted: 0,
+            collisions: 0,
+            possible_collisions: 0,
+            first_collisions: Vec::new(),
+        }
+    }
+
+    /// Sets the width of the ts_ids, 64 by default, used for the expected
+    /// collisions in the report.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hash_bits` is 0 or greater than 64.
+    pub fn with_hash_bits(mut self, hash_bits: u32) -> Self {
+        assert!(
+            (1..=u64::BITS).contains(&hash_bits),
+            "hash bits must be between 1 and 64, got {hash_bits}"
+        );
+        self.hash_bits = hash_bits;
+        self
+    }
+
+    /// Sets the number of colliding ts_ids kept for the report, 10 by
+    /// default.
+    pub fn with_max_reported(mut self, max_reported: usize) -> Self {
+        self.max_reported = max_reported;
+        self
+    }
+
+    /// Keeps at most `max_exact` ts_ids in the exact set, and every ts_id in
+    /// a bitmap of `2^bitmap_bits` bits indexed by its low bits.
+    ///
+    /// Once the exact set is full, a new ts_id whose bucket is empty is
+    /// certainly new. One whose bucket is occupied is counted as a
+    /// [possible collision](CollisionReport::possible_collisions), since it
+    /// may only share the bucket; with `n` ts_ids this happens for about
+    /// `n / 2^bitmap_bits` of them. Memory stays around
+    /// `16 * max_exact + 2^bitmap_bits / 8` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bitmap_bits` is less than 6 or greater than 40.
+    pub fn with_memory_limit(mut self, max_exact: usize, bitmap_bits: u32) -> Self {
+        assert!(
+            (6..=40).contains(&bitmap_bits),
+            "bitmap bits must be between 6 and 40, got {bitmap_bits}"
+        );
+        let mut bitmap = CoarseBitmap {
+            words: vec![0; 1 << (bitmap_bits - 6)],
+            mask: (1 << bitmap_bits) - 1,
+            max_exact,
+        };
+        for &ts_id in &self.seen {
+            bitmap.test_and_set(ts_id);
+        }
+        self.bitmap = Some(bitmap);
+        self
+    }
+
+    /// Records `ts_id`, returning whether it was new.
+    ///
+    /// With a memory limit, a possible collision also returns `false`.
+    pub fn insert(&mut self, ts_id: u64) -> bool {
+        self.inserted += 1;
+        let Some(bitmap) = &mut self.bitmap else {
+            return self.insert_exact(ts_id);
+        };
+
+        let bucket_occupied = bitmap.test_and_set(ts_id);
+        if self.seen.len() < bitmap.max_exact || self.seen.contains(&ts_id) {
+            return self.insert_exact(ts_id);
+        }
+        if bucket_occupied {
+            self.possible_collisions += 1;
+            return false;
+        }
+        true
+    }
+
+    fn insert_exact(&mut self, ts_id: u64) -> bool {
+        if self.seen.insert(ts_id) {
+            return true;
+        }
+        self.collisions += 1;
+        if self.first_collisions.len() < self.max_reported {
+            self.first_collisions.push(ts_id);
+        }
+        false
+    }
+
+    /// Returns the statistics of the ts_ids inserted so far.
+    pub fn report(&self) -> CollisionReport {
+        CollisionReport {
+            inserted: self.inserted,
+            collisions: self.collisions,
+            possible_collisions: self.possible_collisions,
+            expected_collisions: expected_collisions(self.inserted, self.hash_bits),
+            first_collisions: self.first_collisions.clone(),
+        }
+    }
+}
+
+impl Default for CollisionChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// [`CollisionChecker`] split into shards by the top bits of the ts_ids, so
+/// that batches are checked by one thread per shard without locking.
+///
+/// Each shard is a copy of the checker it was created from, so a memory limit
+/// applies to every shard.
+#[derive(Debug, Clone)]
+pub struct ShardedCollisionChecker {
+    shards: Vec<CollisionChecker>,
+    /// Right shift leaving the shard index of a ts_id.
+    shift: u32,
+}
+
+impl ShardedCollisionChecker {
+    /// Creates `2^shard_bits` shards, each a copy of `checker`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `checker` already holds ts_ids, or if `shard_bits` is greater
+    /// than 16 or than the hash bits of `checker`.
+    pub fn new(checker: CollisionChecker, shard_bits: u32) -> Self {
+        assert_eq!(checker.inserted, 0, "checker must be empty");
+        assert!(
+            shard_bits <= 16 && shard_bits <= checker.hash_bits,
+            "invalid number of shard bits {shard_bits}"
+        );
+        Self {
+            shift: checker.hash_bits - shard_bits,
+            shards: vec![checker; 1 << shard_bits],
+        }
+    }
+
+    /// Index of the shard of `ts_id`, from its top bits within the hash bits
+    /// of the checker. Bits above them are ignored.
+    fn shard_index(&self, ts_id: u64) -> usize {
+        let hash_bits = self.shards[0].hash_bits;
+        let ts_id = ts_id & u64::MAX.checked_shr(u64::BITS - hash_bits).unwrap_or(0);
+        ts_id.checked_shr(self.shift).unwrap_or(0) as usize
+    }
+
+    /// Records `ts_id`, returning whether it was new, like
+    /// [`CollisionChecker::insert`].
+    pub fn insert(&mut self, ts_id: u64) -> bool {
+        let shard = self.shard_index(ts_id);
+        self.shards[shard].insert(ts_id)
+    }
+
+    /// Records all `ts_ids`, checking the shards in parallel on the current
+    /// rayon pool.
+    pub fn insert_batch(&mut self, ts_ids: &[u64]) {
+        let mut per_shard = vec![Vec::new(); self.shards.len()];
+        for &ts_id in ts_ids {
+            per_shard[self.shard_index(ts_id)].push(ts_id);
+        }
+        self.shards
+            .par_iter_mut()
+            .zip(per_shard)
+            .for_each(|(shard, ts_ids)| {
+                for ts_id in ts_ids {
+                    shard.insert(ts_id);
+                }
+            });
+    }
+
+    /// Returns the statistics of all shards combined.
+    ///
+    /// The first colliding ts_ids are in insertion order within each shard,
+    /// taken from the shards in ts_id order.
+    pub fn report(&self) -> CollisionReport {
+        let first = &self.shards[0];
+        let mut checker = CollisionChecker::new()
+            .with_hash_bits(first.hash_bits)
+            .with_max_reported(first.max_reported);
+        for shard in &self.shards {
+            checker.inserted += shard.inserted;
+            checker.collisions += shard.collisions;
+            checker.possible_collisions += shard.possible_collisions;
+            let room = checker.max_reported - checker.first_collisions.len();
+            checker
+                .first_collisions
+                .extend(shard.first_collisions.iter().take(room));
+        }
+        checker.report()
+    }
+}
+
+/// Expected number of repeated values among `n` uniformly random values of
+/// `hash_bits` bits, `n` minus the expected number of distinct values.
+pub fn expected_collisions(n: u64, hash_bits: u32) -> f64 {
+    let n = n as f64;
+    let space = 2f64.powi(hash_bits as i32);
+    if n / space < 1e-3 {
+        // The exact formula cancels out to nothing in f64, use the birthday
+        // approximation instead.
+        return n * (n - 1.0).max(0.0) / (2.0 * space);
+    }
+    let distinct = -space * (n * (-1.0 / space).ln_1p()).exp_m1();
+    n - distinct
+}
+
+/// Feeds the ts_ids of `labels` amplified `amp` times to `checker` and
+/// returns its report.
+///
+/// Copies are made distinct through the `instance` label, or the first label
+/// if there is none, as in [`Labels::amplify`]. Each ts_id is seeded with the
+/// label name hash and covers the label values.
+pub fn run_collision_check<H>(
+    labels: &Labels,
+    amp: usize,
+    mut checker: CollisionChecker,
+) -> CollisionReport
+where
+    H: Hasher + SeededHasher,
+{
+    for batch in labels.amplify_batches(amp, amplified_label_index(labels), AMPLIFY_BATCH_SIZE) {
+        for ts_id in compute_ts_ids::<H>(&batch, TsIdMode::Seeded) {
+            checker.insert(ts_id);
+        }
+    }
+    checker.report()
+}
+
+/// Like [`run_collision_check`], computing the ts_ids of `batch_size`
+/// amplified rows at a time in parallel on the current rayon pool and checking
+/// them with a [`ShardedCollisionChecker`].
+///
+/// `keep_going` is called with the checker after every batch, e.g. to report
+/// progress; the check stops early once it returns `false`.
+///
+/// # Panics
+///
+/// Panics if `batch_size` is zero.
+pub fn run_collision_check_parallel<H>(
+    labels: &Labels,
+    amp: usize,
+    mut checker: ShardedCollisionChecker,
+    batch_size: usize,
+    mut keep_going: impl FnMut(&ShardedCollisionChecker) -> bool,
+) -> CollisionReport
+where
+    H: Hasher + SeededHasher,
+{
+    for batch in labels.amplify_batches(amp, amplified_label_index(labels), batch_size) {
+        checker.insert_batch(&compute_ts_ids_parallel::<H>(&batch, TsIdMode::Seeded));
+        if !keep_going(&checker) {
+            break;
+        }
+    }
+    checker.report()
+}
+
+/// Amplified rows hashed at a time by the checks over amplified labels.
+pub(crate) const AMPLIFY_BATCH_SIZE: usize = 1 << 16;
+
+/// Index of the label that makes amplified copies of a row distinct: the
+/// `instance` label, or the first label if there is none.
+pub(crate) fn amplified_label_index(labels: &Labels) -> usize {
+    labels
+        .label_names
+        .iter()
+        .position(|name| name == "instance")
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use xxhash_rust::xxh3::Xxh3;
+
+    use super::*;
+    use crate::read_labels_from_path;
+
+    /// A deliberately bad 8-bit hash: the sum of the seed and input bytes.
+    struct ByteSumHasher(u8);
+
+    impl Hasher for ByteSumHasher {
+        fn finish(&self) -> u64 {
+            self.0 as u64
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for &byte in bytes {
+                self.0 = self.0.wrapping_add(byte);
+            }
+        }
+    }
+
+    impl SeededHasher for ByteSumHasher {
+        fn from_seed(seed: u64) -> Self {
+            ByteSumHasher(seed as u8)
+        }
+    }
+
+    #[test]
+    fn counts_collisions() {
+        let mut checker = CollisionChecker::new()
+            .with_hash_bits(8)
+            .with_max_reported(3);
+        let new: Vec<bool> = (0..1000u64)
+            .map(|idx| checker.insert(idx * 37 % 256))
+            .collect();
+
+        assert!(new[..256].iter().all(|&new| new));
+        assert!(new[256..].iter().all(|&new| !new));
+        let report = checker.report();
+        assert_eq!(report.inserted, 1000);
+        assert_eq!(report.collisions, 1000 - 256);
+        assert_eq!(report.possible_collisions, 0);
+        assert_eq!(report.first_collisions, [0, 37, 74]);
+    }
+
+    #[test]
+    fn counts_repeats_of_the_same_value() {
+        let mut checker = CollisionChecker::new();
+        assert!(checker.insert(7));
+        assert!(!checker.insert(7));
+        assert!(!checker.insert(7));
+        assert!(checker.insert(8));
+        let report = checker.report();
+        assert_eq!((report.inserted, report.collisions), (4, 2));
+        assert_eq!(report.first_collisions, [7, 7]);
+    }
+
+    #[test]
+    fn expected_collisions_birthday_bound() {
+        // 256 values drawn from 256 leave 256 / e of them unseen on average.
+        let expected = expected_collisions(256, 8);
+        assert!(
+            (expected - 256.0 / std::f64::consts::E).abs() < 1.0,
+            "{expected}"
+        );
+        assert_eq!(expected_collisions(0, 8), 0.0);
+        assert_eq!(expected_collisions(1, 64), 0.0);
+        // About n^2 / 2^65 for 64-bit hashes.
+        let expected = expected_collisions(100_000_000, 64);
+        assert!((expected - 2.71e-4).abs() < 1e-6, "{expected}");
+    }
+
+    #[test]
+    fn memory_limit_exact_until_full() {
+        let mut checker = CollisionChecker::new()
+            .with_hash_bits(8)
+            .with_memory_limit(1000, 8);
+        for idx in 0..1000u64 {
+            checker.insert(idx * 37 % 256);
+        }
+        let report = checker.report();
+        assert_eq!(report.collisions, 1000 - 256);
+        assert_eq!(report.possible_collisions, 0);
+    }
+
+    #[test]
+    fn memory_limit_possible_collisions() {
+        // Only 4 ts_ids are kept exactly; the others share 64 buckets.
+        let mut checker = CollisionChecker::new().with_memory_limit(4, 6);
+        for ts_id in [1, 2, 3, 4] {
+            assert!(checker.insert(ts_id));
+        }
+        // Repeats of exact ts_ids are confirmed.
+        assert!(!checker.insert(2));
+        // New buckets are certainly new, occupied ones can't be told apart.
+        assert!(checker.insert(5));
+        assert!(!checker.insert(5));
+        assert!(!checker.insert(64 + 5));
+        assert!(!checker.insert(64 + 1));
+
+        let report = checker.report();
+        assert_eq!(report.inserted, 9);
+        assert_eq!(report.collisions, 1);
+        assert_eq!(report.possible_collisions, 3);
+        assert_eq!(report.first_collisions, [2]);
+    }
+
+    #[test]
+    fn sharded_matches_single_checker() {
+        let ts_ids: Vec<u64> = (0..5000u64)
+            .map(|idx| (idx * 37 % 1024).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+            .collect();
+        let mut single = CollisionChecker::new().with_max_reported(5000);
+        for &ts_id in &ts_ids {
+            single.insert(ts_id);
+        }
+
+        for shard_bits in [0, 1, 4] {
+            let mut sharded = ShardedCollisionChecker::new(
+                CollisionChecker::new().with_max_reported(5000),
+                shard_bits,
+            );
+            sharded.insert_batch(&ts_ids[..3000]);
+            for &ts_id in &ts_ids[3000..] {
+                sharded.insert(ts_id);
+            }
+            let (expected, mut report) = (single.report(), sharded.report());
+            assert_eq!(report.inserted, expected.inserted);
+            assert_eq!(report.collisions, 5000 - 1024);
+            assert_eq!(report.expected_collisions, expected.expected_collisions);
+            let mut expected_first = expected.first_collisions.clone();
+            expected_first.sort_unstable();
+            report.first_collisions.sort_unstable();
+            assert_eq!(report.first_collisions, expected_first, "{shard_bits} bits");
+        }
+    }
+
+    #[test]
+    fn sharded_by_top_bits() {
+        let mut sharded =
+            ShardedCollisionChecker::new(CollisionChecker::new().with_hash_bits(8), 2);
+        sharded.insert_batch(&[0x00, 0x40, 0x80, 0xc0, 0xc1, 0xc0]);
+        assert!(!sharded.insert(0x40));
+        let shards: Vec<u64> = sharded.shards.iter().map(|shard| shard.inserted).collect();
+        assert_eq!(shards, [1, 2, 1, 3]);
+        assert_eq!(sharded.report().first_collisions, [0x40, 0xc0]);
+
+        // Bits above the hash bits don't pick a shard.
+        sharded.insert(0xff00);
+        assert_eq!(sharded.shards[0].inserted, 2);
+    }
+
+    #[test]
+    fn run_collision_check_bad_hash() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        let checker = CollisionChecker::new().with_hash_bits(8);
+        let report = run_collision_check::<ByteSumHasher>(&labels, 2, checker);
+
+        let rows = labels.label_values.len() as u64 * 2;
+        let instance_idx = labels
+            .label_names
+            .iter()
+            .position(|name| name == "instance")
+            .unwrap();
+        let mut distinct = HashSet::new();
+        for row in labels.amplify(2, instance_idx) {
+            let mut hasher = ByteSumHasher::from_seed(labels.label_name_hash);
+            for value in &row {
+                hasher.write(value);
+                hasher.write_u8(0xff);
+            }
+            distinct.insert(hasher.finish());
+        }
+        assert_eq!(report.inserted, rows);
+        assert_eq!(report.collisions, rows - distinct.len() as u64);
+        assert_eq!(report.first_collisions.len(), DEFAULT_MAX_REPORTED);
+    }
+
+    #[test]
+    fn run_collision_check_parallel_matches_serial() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        let checker = CollisionChecker::new()
+            .with_hash_bits(8)
+            .with_max_reported(usize::MAX);
+        let mut expected = run_collision_check::<ByteSumHasher>(&labels, 2, checker.clone());
+        expected.first_collisions.sort_unstable();
+
+        let mut batches = 0;
+        let sharded = ShardedCollisionChecker::new(checker, 2);
+        let mut report =
+            run_collision_check_parallel::<ByteSumHasher>(&labels, 2, sharded, 5000, |_| {
+                batches += 1;
+                true
+            });
+        report.first_collisions.sort_unstable();
+        assert_eq!(report, expected);
+        assert_eq!(batches, (labels.label_values.len() * 2).div_ceil(5000));
+
+        // Stopping after the first batch.
+        let sharded = ShardedCollisionChecker::new(CollisionChecker::new(), 2);
+        let report = run_collision_check_parallel::<Xxh3>(&labels, 2, sharded, 5000, |_| false);
+        assert_eq!(report.inserted, 5000);
+    }
+
+    #[test]
+    fn run_collision_check_xxh3() {
+        let mut labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        labels.dedup();
+        let report = run_collision_check::<Xxh3>(&labels, 3, CollisionChecker::new());
+        assert_eq!(report.inserted, labels.label_values.len() as u64 * 3);
+        assert_eq!(report.collisions, 0);
+        assert!(report.first_collisions.is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+//! Stable column ids for label names.
+//!
+//! Assigning column ids by position makes the encoded output depend on the
+//! column order of the input file. A [`ColumnIdMap`] assigns them by name
+//! instead, and can be stored next to encoded data as a small JSON file.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+use crate::{Error, Labels};
+
+/// A one-to-one mapping between label names and column ids.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColumnIdMap {
+    ids: BTreeMap<String, u32>,
+    names: BTreeMap<u32, String>,
+}
+
+impl ColumnIdMap {
+    /// Assigns ids `0..` to the distinct names in sorted order, so that the
+    /// ids do not depend on the order of `names`.
+    pub fn sorted<S: AsRef<str>>(names: &[S]) -> Self {
+        let mut names: Vec<&str> = names.iter().map(AsRef::as_ref).collect();
+        names.sort_unstable();
+        names.dedup();
+        Self::from_mapping(
+            names
+                .into_iter()
+                .enumerate()
+                .map(|(id, name)| (name.to_owned(), id as u32)),
+        )
+        .unwrap()
+    }
+
+    /// Builds a map from explicit `(name, id)` pairs.
+    ///
+    /// Fails if a name or an id appears twice.
+    pub fn from_mapping(mapping: impl IntoIterator<Item = (String, u32)>) -> Result<Self, Error> {
+        let mut map = Self::default();
+        for (name, id) in mapping {
+            if map.ids.contains_key(&name) {
+                return Err(invalid(format!("label {:?} is mapped twice", name)));
+            }
+            if let Some(existing) = map.names.get(&id) {
+                return Err(invalid(format!(
+                    "id {} is used by both {:?} and {:?}",
+                    id, existing, name
+                )));
+            }
+            map.names.insert(id, name.clone());
+            map.ids.insert(name, id);
+        }
+        Ok(map)
+    }
+
+    pub fn id_of(&self, name: &str) -> Option<u32> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name_of(&self, id: u32) -> Option<&str> {
+        self.names.get(&id).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Iterates over `(id, name)` pairs in ascending id order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &str)> + '_ {
+        self.names.iter().map(|(&id, name)| (id, name.as_str()))
+    }
+
+    /// Writes the map as a JSON object from label name to id.
+    pub fn write_json<W: Write>(&self, sink: W) -> Result<(), Error> {
+        serde_json::to_writer_pretty(sink, &self.ids)
+            .map_err(|err| invalid(format!("failed to write JSON: {}", err)))
+    }
+
+    /// Reads a map written by [`ColumnIdMap::write_json`].
+    pub fn read_json<R: Read>(reader: R) -> Result<Self, Error> {
+        let ids: BTreeMap<String, u32> = serde_json::from_reader(reader)
+            .map_err(|err| invalid(format!("invalid JSON: {}", err)))?;
+        Self::from_mapping(ids)
+    }
+
+    pub fn write_json_path(&self, path: &str) -> Result<(), Error> {
+        let io_error = |source| Error::Io {
+            path: path.to_owned(),
+            source,
+        };
+        let mut file = BufWriter::new(File::create(path).map_err(io_error)?);
+        self.write_json(&mut file)
+            .map_err(|err| err.with_path(path))?;
+        file.flush().map_err(io_error)
+    }
+
+    pub fn read_json_path(path: &str) -> Result<Self, Error> {
+        let file = File::open(path).map_err(|source| Error::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        Self::read_json(BufReader::new(file)).map_err(|err| err.with_path(path))
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::ColumnIdMap {
+        path: None,
+        message,
+    }
+}
+
+impl Labels {
+    /// Pairs each value with its column id, as consumed by
+    /// [`RowEncoder::encode`](crate::RowEncoder::encode).
+    ///
+    /// Without a map, ids are the positions of the labels. With a map, ids are
+    /// looked up by label name and the pairs of each row are sorted by id, so
+    /// the result does not depend on the column order of the input.
+    ///
+    /// Fails with [`Error::UnknownLabel`] if a label name is missing from
+    /// `column_ids`.
+    pub fn to_pairs(
+        &self,
+        column_ids: Option<&ColumnIdMap>,
+    ) -> Result<Vec<Vec<(u32, String)>>, Error> {
+        let Some(column_ids) = column_ids else {
+            return Ok(crate::scale_rows(&self.label_values, 1));
+        };
+
+        let ids = self
+            .label_names
+            .iter()
+            .map(|name| {
+                column_ids
+                    .id_of(name)
+                    .ok_or_else(|| Error::UnknownLabel { name: name.clone() })
+            })
+            .collect::<Result<Vec<u32>, Error>>()?;
+        let mut order: Vec<usize> = (0..ids.len()).collect();
+        order.sort_by_key(|&idx| ids[idx]);
+
+        Ok(self
+            .label_values
+            .iter()
+            .map(|row| {
+                order
+                    .iter()
+                    .map(|&idx| (ids[idx], row[idx].clone()))
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xxhash_rust::xxh3::Xxh3;
+
+    use super::*;
+    use crate::read_labels_and_hash_str;
+
+    #[test]
+    fn stable_under_column_reordering() {
+        let a = read_labels_and_hash_str::<Xxh3>("job,instance,env\nnode,a,prod\n").unwrap();
+        let b = read_labels_and_hash_str::<Xxh3>("env,job,instance\nprod,node,a\n").unwrap();
+
+        let map_a = ColumnIdMap::sorted(&a.label_names);
+        let map_b = ColumnIdMap::sorted(&b.label_names);
+        assert_eq!(map_a, map_b);
+        assert_eq!(map_a.id_of("env"), Some(0));
+        assert_eq!(map_a.id_of("instance"), Some(1));
+        assert_eq!(map_a.name_of(2), Some("job"));
+        assert_eq!(map_a.id_of("missing"), None);
+        assert_eq!(map_a.name_of(3), None);
+
+        assert_eq!(
+            a.to_pairs(Some(&map_a)).unwrap(),
+            b.to_pairs(Some(&map_b)).unwrap()
+        );
+        assert_eq!(
+            a.to_pairs(Some(&map_a)).unwrap()[0],
+            [
+                (0, "prod".to_owned()),
+                (1, "a".to_owned()),
+                (2, "node".to_owned())
+            ]
+        );
+        assert_ne!(a.to_pairs(None).unwrap(), b.to_pairs(None).unwrap());
+
+        let partial = ColumnIdMap::sorted(&["env", "job"]);
+        assert!(matches!(
+            a.to_pairs(Some(&partial)),
+            Err(Error::UnknownLabel { name }) if name == "instance"
+        ));
+    }
+
+    #[test]
+    fn explicit_mapping() {
+        let map =
+            ColumnIdMap::from_mapping([("job".to_owned(), 7), ("instance".to_owned(), 3)]).unwrap();
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            [(3, "instance"), (7, "job")]
+        );
+
+        assert!(ColumnIdMap::from_mapping([("job".to_owned(), 1), ("job".to_owned(), 2)]).is_err());
+        assert!(ColumnIdMap::from_mapping([("job".to_owned(), 1), ("env".to_owned(), 1)]).is_err());
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let map =
+            ColumnIdMap::from_mapping([("job".to_owned(), 7), ("instance".to_owned(), 3)]).unwrap();
+        let mut buffer = Vec::new();
+        map.write_json(&mut buffer).unwrap();
+        assert_eq!(ColumnIdMap::read_json(buffer.as_slice()).unwrap(), map);
+
+        assert!(ColumnIdMap::read_json(r#"{"job": 1, "env": 1}"#.as_bytes()).is_err());
+        assert!(ColumnIdMap::read_json(r#"{"job": "one"}"#.as_bytes()).is_err());
+    }
+}
@@ -1,28 +1,1139 @@
-use std::collections::HashSet;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::fmt::Display;
 use std::fs::File;
+use std::hash::Hasher;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
-use arrow::array::{Array, LargeStringArray, StringArray};
-use arrow::datatypes::DataType;
-use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use arrow::array::temporal_conversions::as_datetime;
+use arrow::array::{
+    Array, ArrayRef, AsArray, BooleanArray, MapArray, Scalar, StringArray, StringBuilder,
+};
+use arrow::compute::kernels::cmp::eq;
+use arrow::compute::{and, cast, filter_record_batch};
+use arrow::datatypes::{
+    ArrowDictionaryKeyType, ArrowNativeType, ArrowPrimitiveType, ArrowTimestampType, DataType,
+    Field, Float32Type, Float64Type, Int8Type, Int16Type, Int32Type, Int64Type, Schema, TimeUnit,
+    TimestampMicrosecondType, TimestampMillisecondType, TimestampNanosecondType,
+    TimestampSecondType, UInt8Type, UInt16Type, UInt32Type, UInt64Type,
+};
+use arrow::error::ArrowError;
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use parquet::arrow::arrow_reader::{
+    ArrowReaderMetadata, ArrowReaderOptions, ParquetRecordBatchReaderBuilder,
+};
+use parquet::arrow::{ArrowWriter, ProjectionMask};
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
 
+use crate::ts_id_gen::{SeededHasher, Xx3TsIdGenerator};
+use crate::{
+    CsvWriteOptions, DEFAULT_ROW_GROUP_SIZE, Error, Labels, NullRepresentation,
+    compute_label_name_hash, create_compressed, default_writer_properties,
+};
+
+/// Columns that hold sample data rather than labels.
+pub const EXCLUDED_COLUMNS: [&str; 2] = ["greptime_value", "greptime_timestamp"];
+
+/// Options for [`read_parquet_files_config`].
+#[derive(Debug, Clone, Default)]
+pub struct ReadParquetConfig {
+    /// Skip rows that were already written.
+    pub dedup: bool,
+    /// Expected number of distinct rows, used to pre-allocate the set of
+    /// seen rows when `dedup` is set.
+    pub capacity_hint: Option<usize>,
+    /// Approximate memory, in bytes, that the distinct rows may take while
+    /// deduplicating.
+    ///
+    /// Once exceeded, the rows held are sorted and spilled to a temporary
+    /// file in `spill_dir`, and the spilled runs are merged at the end. The
+    /// output is then sorted by row hash rather than in first-seen order, and
+    /// `limit` caps the rows written without ending the scan early.
+    ///
+    /// With `dedup_hash_only`, only the row hashes are held and spilled, and
+    /// a second scan writes the first row of each hash, in first-seen order.
+    /// Only used when `dedup` is set.
+    pub dedup_memory_limit: Option<usize>,
+    /// Treat rows with the same 64-bit hash as duplicates without comparing
+    /// their values, so only the hashes are held in memory, or spilled with
+    /// `dedup_memory_limit`.
+    ///
+    /// Distinct rows are merged with a probability of about n² / 2^65 for n
+    /// distinct rows, e.g. 3e-4 for 100 million rows.
+    pub dedup_hash_only: bool,
+    /// Directory of the runs spilled by `dedup_memory_limit`, the system
+    /// temporary directory if `None`.
+    pub spill_dir: Option<PathBuf>,
+    /// How null values are written.
+    pub nulls: NullRepresentation,
+    /// Stop reading once this many rows were written, distinct rows if
+    /// `dedup` is set.
+    pub limit: Option<usize>,
+    /// Fraction of rows to keep, between 0 and 1.
+    ///
+    /// Rows are selected by a hash of their values, so the same rows are
+    /// kept across runs with the same `sample_seed`, and duplicates of a kept
+    /// row are kept too.
+    pub sample: Option<f64>,
+    /// Seed of the hash selecting the `sample`.
+    pub sample_seed: u64,
+    /// `(column, value)` pairs that rows must all match to be kept.
+    ///
+    /// Columns are compared as cast to strings by Arrow, which matches the
+    /// written values for string, integer and boolean columns. Excluded
+    /// columns can be filtered on too, and null values never match.
+    pub filters: Vec<(String, String)>,
+    /// Format of the output file, picked from the extension of its path if
+    /// `None`.
+    pub output_format: Option<OutputFormat>,
+    /// Compression levels of the output file, if written as CSV.
+    pub write_options: CsvWriteOptions,
+}
+
+/// File format of the labels written by [`read_parquet_files_config`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// CSV with a header row, compressed according to the extension of the
+    /// path.
+    Csv,
+    /// Parquet with one dictionary encoded `Utf8` column per label.
+    Parquet,
+}
+
+impl OutputFormat {
+    /// Parquet for paths ending with `.parquet`, CSV otherwise.
+    pub fn from_path(path: &str) -> Self {
+        if path.ends_with(".parquet") {
+            OutputFormat::Parquet
+        } else {
+            OutputFormat::Csv
+        }
+    }
+}
+
+/// Writes the label columns of the parquet file at `path` as CSV to
+/// `output_path`, compressed if the path ends with `.gz`, `.zst` or `.zstd`.
+///
+/// If the path ends with `.parquet`, the labels are written as parquet
+/// instead, with one dictionary encoded `Utf8` column per label that
+/// [`read_labels_from_parquet`] reads back.
+///
+/// `Map` columns are expanded into one CSV column per key, after the other
+/// columns, with empty values for keys missing from a row.
 pub fn read_parquet_files(
     path: &str,
-    output_csv_path: &str,
+    output_path: &str,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // Open the parquet file
-    let file = File::open(path)?;
+    read_parquet_files_config(path, output_path, ReadParquetConfig::default()).map(|_| ())
+}
+
+/// Like [`read_parquet_files`], with the compression levels taken from
+/// `options`.
+pub fn read_parquet_files_with_options(
+    path: &str,
+    output_path: &str,
+    options: &CsvWriteOptions,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let config = ReadParquetConfig {
+        write_options: options.clone(),
+        ..Default::default()
+    };
+    read_parquet_files_config(path, output_path, config).map(|_| ())
+}
+
+/// Progress of [`read_parquet_files_with_progress`], reported after each
+/// record batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// Rows read from the parquet file so far.
+    pub rows_scanned: usize,
+    /// Rows written so far; distinct rows if `dedup` is set.
+    ///
+    /// With a `dedup_memory_limit`, rows are only written once the scan ends,
+    /// and this counts the rows kept so far, some of which may turn out to be
+    /// duplicates of spilled rows.
+    pub distinct_rows: usize,
+    /// Index of the row group being read.
+    pub row_group: usize,
+    /// Number of row groups in the file.
+    pub num_row_groups: usize,
+    /// Compressed size of the row groups read so far, including the current
+    /// one.
+    pub bytes_read: u64,
+    /// Compressed size of all row groups in the file.
+    pub total_bytes: u64,
+}
+
+/// Summary of a [`read_parquet_files_config`] run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractSummary {
+    /// Rows read from the parquet file; fewer than it holds if a `limit` ended
+    /// the scan early.
+    pub rows_scanned: usize,
+    /// Rows written, not counting the header; distinct rows if `dedup` is set.
+    pub distinct_rows: usize,
+    /// Row groups read.
+    pub row_groups: usize,
+    /// Sorted runs of distinct rows spilled to disk because of a
+    /// `dedup_memory_limit`.
+    pub spilled_runs: usize,
+    pub elapsed: Duration,
+}
+
+/// Like [`read_parquet_files`], configured by `config`.
+pub fn read_parquet_files_config(
+    path: &str,
+    output_path: &str,
+    config: ReadParquetConfig,
+) -> Result<ExtractSummary, Box<dyn std::error::Error + Send + Sync>> {
+    read_parquet_files_with_progress(path, output_path, config, |_| {})
+}
 
-    // Create a parquet reader builder
-    let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+/// Like [`read_parquet_files_config`], calling `progress` after each record
+/// batch.
+pub fn read_parquet_files_with_progress(
+    path: &str,
+    output_path: &str,
+    config: ReadParquetConfig,
+    mut progress: impl FnMut(Progress),
+) -> Result<ExtractSummary, Box<dyn std::error::Error + Send + Sync>> {
+    let start = Instant::now();
+    if let Some(sample) = config.sample
+        && !(0.0..=1.0).contains(&sample)
+    {
+        return Err(format!("sample fraction {} is not between 0 and 1", sample).into());
+    }
 
-    // Get the schema
-    let schema = builder.schema().clone();
+    // Load the parquet metadata once, row groups are then read one at a time
+    // to report which one is being scanned
+    let file = File::open(path)?;
+    let metadata = ArrowReaderMetadata::load(&file, ArrowReaderOptions::default())?;
+    let row_groups = metadata.metadata().row_groups();
 
-    // Find indices of columns to exclude
-    let exclude_columns: HashSet<String> = ["greptime_value", "greptime_timestamp"]
+    // Find the label columns to keep, map columns are expanded into one
+    // column per key after the other columns
+    let (column_indices, column_names) = label_columns(metadata.schema(), &EXCLUDED_COLUMNS);
+    let (map_columns, scalar_columns): (Vec<_>, Vec<_>) = column_indices
         .into_iter()
-        .map(|s| s.to_owned())
+        .zip(column_names)
+        .partition(|(idx, _)| {
+            matches!(metadata.schema().field(*idx).data_type(), DataType::Map(..))
+        });
+    let (scalar_indices, scalar_names): (Vec<usize>, Vec<String>) =
+        scalar_columns.into_iter().unzip();
+    let map_keys = map_key_union(&file, &metadata, &map_columns)?;
+    let header: Vec<&String> = scalar_names
+        .iter()
+        .chain(map_keys.iter().flatten())
         .collect();
+
+    let mut dedup = config.dedup.then(|| Dedup::new(&config));
+    let limit = config.limit.unwrap_or(usize::MAX);
+    // Spilled rows are only written once all runs are merged, so the limit
+    // cannot end the scan early
+    let mut scan_limit = match dedup {
+        Some(Dedup::Spill(_) | Dedup::SpillHashes(_)) => usize::MAX,
+        _ => limit,
+    };
+    let mut spilled_runs = 0;
+    let filters = config
+        .filters
+        .iter()
+        .map(|(column, value)| {
+            let idx = metadata
+                .schema()
+                .index_of(column)
+                .map_err(|_| format!("filter column '{}' is not in {}", column, path))?;
+            Ok((idx, StringArray::new_scalar(value)))
+        })
+        .collect::<Result<Vec<_>, Box<dyn std::error::Error + Send + Sync>>>()?;
+
+    let mut current = Progress {
+        rows_scanned: 0,
+        distinct_rows: 0,
+        row_group: 0,
+        num_row_groups: row_groups.len(),
+        bytes_read: 0,
+        total_bytes: row_groups
+            .iter()
+            .map(|row_group| row_group.compressed_size() as u64)
+            .sum(),
+    };
+    let mut row_groups_read = 0;
+
+    // Reads the rows, passing the ones to keep to `write_row`. A hash-only
+    // dedup that spilled reads them twice
+    let mut scan = |write_row: &mut dyn FnMut(&[String]) -> Result<(), BoxError>| {
+        loop {
+            // Checking the limit before reading the next batch ends the scan early
+            for (row_group_idx, row_group) in row_groups.iter().enumerate() {
+                if current.distinct_rows >= scan_limit {
+                    break;
+                }
+                current.row_group = row_group_idx;
+                current.bytes_read += row_group.compressed_size() as u64;
+                row_groups_read += 1;
+
+                let mut reader = ParquetRecordBatchReaderBuilder::new_with_metadata(
+                    file.try_clone()?,
+                    metadata.clone(),
+                )
+                .with_row_groups(vec![row_group_idx])
+                .build()?;
+                while current.distinct_rows < scan_limit {
+                    let Some(batch_result) = reader.next() else {
+                        break;
+                    };
+                    let batch = batch_result?;
+                    let num_rows = batch.num_rows();
+                    let batch = filter_batch(batch, &filters)?;
+                    current.rows_scanned += num_rows - batch.num_rows();
+                    let mut rows = batch_values(&batch, &scalar_indices, &scalar_names)?;
+                    for ((idx, name), keys) in map_columns.iter().zip(&map_keys) {
+                        let entries = map_entries(as_map_column(batch.column(*idx), name)?, name)?;
+                        for (row, entries) in rows.iter_mut().zip(entries) {
+                            let mut entries: HashMap<String, Option<String>> =
+                                entries.into_iter().collect();
+                            // Keys missing from this row's map are empty
+                            row.extend(
+                                keys.iter()
+                                    .map(|key| entries.remove(key).unwrap_or(Some(String::new()))),
+                            );
+                        }
+                    }
+                    for row in rows {
+                        if current.distinct_rows == scan_limit {
+                            break;
+                        }
+                        current.rows_scanned += 1;
+                        let Some(row) = apply_nulls(row, &config.nulls) else {
+                            continue;
+                        };
+                        if let Some(sample) = config.sample
+                            && !in_sample(&row, sample, config.sample_seed)
+                        {
+                            continue;
+                        }
+                        match &mut dedup {
+                            None => write_row(&row)?,
+                            Some(Dedup::Rows(seen)) => {
+                                if seen.contains(&row) {
+                                    continue;
+                                }
+                                write_row(&row)?;
+                                seen.insert(row);
+                            }
+                            Some(Dedup::Hashes(seen)) => {
+                                if !seen.insert(row_hash(&row, 0)) {
+                                    continue;
+                                }
+                                write_row(&row)?;
+                            }
+                            Some(Dedup::Spill(spill)) => {
+                                if !spill.insert(row)? {
+                                    continue;
+                                }
+                            }
+                            Some(Dedup::SpillHashes(spill)) => {
+                                if !spill.insert(&row)? {
+                                    continue;
+                                }
+                            }
+                            Some(Dedup::FirstRows(first_rows)) => {
+                                if !first_rows.next_is_first()? {
+                                    continue;
+                                }
+                                write_row(&row)?;
+                            }
+                        }
+                        current.distinct_rows += 1;
+                    }
+                    progress(current);
+                }
+            }
+
+            match dedup.take() {
+                Some(Dedup::Spill(spill)) => {
+                    spilled_runs = spill.num_runs();
+                    current.distinct_rows = spill.finish(limit, write_row)?;
+                }
+                Some(Dedup::SpillHashes(spill)) => {
+                    // Scan again, writing the first row of each hash
+                    spilled_runs = spill.num_runs();
+                    let first_rows = FirstRows::new(spill.finish()?)?;
+                    spilled_runs += first_rows.num_runs();
+                    dedup = Some(Dedup::FirstRows(first_rows));
+                    scan_limit = limit;
+                    current.rows_scanned = 0;
+                    current.distinct_rows = 0;
+                    current.bytes_read = 0;
+                    row_groups_read = 0;
+                    continue;
+                }
+                _ => {}
+            }
+            return Ok::<_, BoxError>(());
+        }
+    };
+
+    let format = config
+        .output_format
+        .unwrap_or_else(|| OutputFormat::from_path(output_path));
+    match format {
+        OutputFormat::Csv => {
+            create_compressed(output_path, &config.write_options, |sink| {
+                let mut writer = csv::Writer::from_writer(sink);
+                writer.write_record(&header)?;
+                scan(&mut |row| Ok(writer.write_record(row)?))?;
+                writer.flush()?;
+                Ok::<_, BoxError>(())
+            })?;
+        }
+        OutputFormat::Parquet => {
+            let mut writer = LabelColumnsWriter::try_new(File::create(output_path)?, &header)?;
+            scan(&mut |row| writer.write_row(row))?;
+            writer.close()?;
+        }
+    }
+
+    Ok(ExtractSummary {
+        rows_scanned: current.rows_scanned,
+        distinct_rows: current.distinct_rows,
+        row_groups: row_groups_read,
+        spilled_runs,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// How [`read_parquet_files_with_progress`] skips rows already written.
+enum Dedup {
+    /// The rows already written.
+    Rows(HashSet<Vec<String>>),
+    /// The hashes of the rows already written.
+    Hashes(HashSet<u64>),
+    /// Rows are held in bounded memory and written once the scan ends.
+    Spill(SpillingDedup),
+    /// Row hashes are held in bounded memory, and the first row of each hash
+    /// is written by a second scan.
+    SpillHashes(SpillingHashDedup),
+    /// The second scan of [`Dedup::SpillHashes`].
+    FirstRows(FirstRows),
+}
+
+impl Dedup {
+    fn new(config: &ReadParquetConfig) -> Self {
+        let capacity = config.capacity_hint.unwrap_or_default();
+        let spill_dir = config.spill_dir.clone().unwrap_or_else(std::env::temp_dir);
+        match config.dedup_memory_limit {
+            Some(memory_limit) if config.dedup_hash_only => {
+                Dedup::SpillHashes(SpillingHashDedup::new(memory_limit, spill_dir))
+            }
+            Some(memory_limit) => Dedup::Spill(SpillingDedup::new(memory_limit, spill_dir)),
+            None if config.dedup_hash_only => Dedup::Hashes(HashSet::with_capacity(capacity)),
+            None => Dedup::Rows(HashSet::with_capacity(capacity)),
+        }
+    }
+}
+
+/// Deduplicates rows in bounded memory by spilling sorted runs to disk.
+///
+/// Distinct rows are held in memory, keyed by their hash, until they take
+/// more than `memory_limit` bytes. They are then sorted by hash and values and
+/// written to a temporary CSV file, with the hash as first field. Merging the
+/// runs brings equal rows next to each other, so [`SpillingDedup::finish`]
+/// writes each distinct row once, in hash order.
+struct SpillingDedup {
+    memory_limit: usize,
+    spill_dir: PathBuf,
+    /// Distinct rows held in memory, by hash.
+    rows: HashMap<u64, Vec<Vec<String>>>,
+    held_bytes: usize,
+    runs: Vec<PathBuf>,
+}
+
+impl SpillingDedup {
+    fn new(memory_limit: usize, spill_dir: PathBuf) -> Self {
+        Self {
+            memory_limit,
+            spill_dir,
+            rows: HashMap::new(),
+            held_bytes: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Holds `row` unless it was already seen since the last spill.
+    fn insert(&mut self, row: Vec<String>) -> Result<bool, BoxError> {
+        let bucket = self.rows.entry(row_hash(&row, 0)).or_default();
+        if bucket.contains(&row) {
+            return Ok(false);
+        }
+        self.held_bytes += size_of::<u64>()
+            + size_of::<Vec<String>>()
+            + row
+                .iter()
+                .map(|value| size_of::<String>() + value.len())
+                .sum::<usize>();
+        bucket.push(row);
+        if self.held_bytes > self.memory_limit {
+            self.spill()?;
+        }
+        Ok(true)
+    }
+
+    fn num_runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Takes the rows held in memory, sorted by hash and values.
+    fn take_sorted(&mut self) -> Vec<(u64, Vec<String>)> {
+        self.held_bytes = 0;
+        let mut rows: Vec<_> = self
+            .rows
+            .drain()
+            .flat_map(|(hash, bucket)| bucket.into_iter().map(move |row| (hash, row)))
+            .collect();
+        rows.sort_unstable();
+        rows
+    }
+
+    /// Writes the rows held in memory to a new run.
+    fn spill(&mut self) -> Result<(), BoxError> {
+        let path = spill_path(&self.spill_dir, "csv");
+        let file = File::options().write(true).create_new(true).open(&path)?;
+        // Registered right away so the file is removed even if writing fails
+        self.runs.push(path);
+
+        let mut writer = csv::Writer::from_writer(BufWriter::new(file));
+        for (hash, row) in self.take_sorted() {
+            writer.write_record(std::iter::once(hash.to_string()).chain(row))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the first `limit` distinct rows of all runs to `write_row`,
+    /// returning their number.
+    fn finish(
+        mut self,
+        limit: usize,
+        write_row: &mut dyn FnMut(&[String]) -> Result<(), BoxError>,
+    ) -> Result<usize, BoxError> {
+        if self.runs.is_empty() {
+            let rows = self.take_sorted();
+            for (_, row) in rows.iter().take(limit) {
+                write_row(row)?;
+            }
+            return Ok(rows.len().min(limit));
+        }
+        if !self.rows.is_empty() {
+            self.spill()?;
+        }
+
+        let mut runs = self
+            .runs
+            .iter()
+            .map(|path| {
+                csv::ReaderBuilder::new()
+                    .has_headers(false)
+                    .from_path(path)
+                    .map(csv::Reader::into_records)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let next_row = |records: &mut csv::StringRecordsIntoIter<File>| {
+            let Some(record) = records.next() else {
+                return Ok::<_, BoxError>(None);
+            };
+            let record = record?;
+            let hash = record[0].parse::<u64>()?;
+            let row: Vec<String> = record.iter().skip(1).map(str::to_owned).collect();
+            Ok(Some((hash, row)))
+        };
+
+        // The smallest row of each run, with the index of its run
+        let mut heads = BinaryHeap::new();
+        for (idx, records) in runs.iter_mut().enumerate() {
+            if let Some((hash, row)) = next_row(records)? {
+                heads.push(Reverse((hash, row, idx)));
+            }
+        }
+        let mut written = 0;
+        let mut last: Option<(u64, Vec<String>)> = None;
+        while written < limit {
+            let Some(Reverse((hash, row, idx))) = heads.pop() else {
+                break;
+            };
+            if let Some((next_hash, next_row)) = next_row(&mut runs[idx])? {
+                heads.push(Reverse((next_hash, next_row, idx)));
+            }
+            let duplicate = last
+                .as_ref()
+                .is_some_and(|(last_hash, last_row)| *last_hash == hash && *last_row == row);
+            if !duplicate {
+                write_row(&row)?;
+                written += 1;
+            }
+            last = Some((hash, row));
+        }
+        Ok(written)
+    }
+}
+
+impl Drop for SpillingDedup {
+    fn drop(&mut self) {
+        for path in &self.runs {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Returns a new path for a spilled run in `spill_dir`.
+fn spill_path(spill_dir: &Path, extension: &str) -> PathBuf {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    spill_dir.join(format!(
+        "tsid-bench-spill-{}-{}.{}",
+        std::process::id(),
+        NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        extension
+    ))
+}
+
+/// Deduplicates rows by hash in bounded memory, for `dedup_hash_only`.
+///
+/// The index of the first row of each hash is held in memory until they take
+/// more than `memory_limit` bytes. They are then spilled as a sorted run of
+/// `[hash, row index]` records. [`SpillingHashDedup::finish`] merges the runs
+/// into the indices of the rows to write, which a second scan of the same rows
+/// picks with [`FirstRows`].
+struct SpillingHashDedup {
+    memory_limit: usize,
+    /// Index of the first row of each hash inserted since the last spill.
+    first_rows: HashMap<u64, u64>,
+    runs: U64Runs<2>,
+    next_row: u64,
+}
+
+impl SpillingHashDedup {
+    fn new(memory_limit: usize, spill_dir: PathBuf) -> Self {
+        Self {
+            memory_limit,
+            first_rows: HashMap::new(),
+            runs: U64Runs::new(spill_dir),
+            next_row: 0,
+        }
+    }
+
+    /// Holds the hash of `row` unless it was already seen since the last
+    /// spill.
+    fn insert(&mut self, row: &[String]) -> Result<bool, BoxError> {
+        let row_idx = self.next_row;
+        self.next_row += 1;
+        let std::collections::hash_map::Entry::Vacant(entry) =
+            self.first_rows.entry(row_hash(row, 0))
+        else {
+            return Ok(false);
+        };
+        entry.insert(row_idx);
+        if self.first_rows.len() * size_of::<[u64; 2]>() > self.memory_limit {
+            let records = self
+                .first_rows
+                .drain()
+                .map(|(hash, row_idx)| [hash, row_idx])
+                .collect();
+            self.runs.spill(records)?;
+        }
+        Ok(true)
+    }
+
+    fn num_runs(&self) -> usize {
+        self.runs.paths.len()
+    }
+
+    /// Merges the runs into the indices of the first row of each hash, in
+    /// ascending order.
+    fn finish(self) -> Result<MergedRuns<1>, BoxError> {
+        let held = self
+            .first_rows
+            .into_iter()
+            .map(|(hash, row_idx)| [hash, row_idx])
+            .collect();
+        let mut indices = U64Runs::new(self.runs.spill_dir.clone());
+        let mut buffer = Vec::new();
+        let mut last_hash = None;
+        // Records of the same hash come in row order, the first one is kept
+        for record in self.runs.merge(held)? {
+            let [hash, row_idx] = record?;
+            if last_hash == Some(hash) {
+                continue;
+            }
+            last_hash = Some(hash);
+            buffer.push([row_idx]);
+            if buffer.len() * size_of::<u64>() > self.memory_limit {
+                indices.spill(std::mem::take(&mut buffer))?;
+            }
+        }
+        indices.merge(buffer)
+    }
+}
+
+/// Picks the rows of the second scan of a [`SpillingHashDedup`].
+struct FirstRows {
+    /// Indices of the rows to write, in ascending order.
+    indices: MergedRuns<1>,
+    next_kept: Option<u64>,
+    next_row: u64,
+}
+
+impl FirstRows {
+    fn new(mut indices: MergedRuns<1>) -> Result<Self, BoxError> {
+        let next_kept = indices.next().transpose()?.map(|[row_idx]| row_idx);
+        Ok(Self {
+            indices,
+            next_kept,
+            next_row: 0,
+        })
+    }
+
+    fn num_runs(&self) -> usize {
+        self.indices.num_runs()
+    }
+
+    /// Whether the next row scanned is the first row of its hash.
+    fn next_is_first(&mut self) -> Result<bool, BoxError> {
+        let row_idx = self.next_row;
+        self.next_row += 1;
+        if self.next_kept != Some(row_idx) {
+            return Ok(false);
+        }
+        self.next_kept = self.indices.next().transpose()?.map(|[row_idx]| row_idx);
+        Ok(true)
+    }
+}
+
+/// Sorted runs of `N` `u64`s per record, spilled to disk as little-endian
+/// integers. The files are removed on drop.
+struct U64Runs<const N: usize> {
+    spill_dir: PathBuf,
+    paths: Vec<PathBuf>,
+}
+
+impl<const N: usize> U64Runs<N> {
+    fn new(spill_dir: PathBuf) -> Self {
+        Self {
+            spill_dir,
+            paths: Vec::new(),
+        }
+    }
+
+    /// Sorts `records` and writes them to a new run.
+    fn spill(&mut self, mut records: Vec<[u64; N]>) -> Result<(), BoxError> {
+        records.sort_unstable();
+        let path = spill_path(&self.spill_dir, "bin");
+        let file = File::options().write(true).create_new(true).open(&path)?;
+        // Registered right away so the file is removed even if writing fails
+        self.paths.push(path);
+
+        let mut writer = BufWriter::new(file);
+        for value in records.iter().flatten() {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Merges the runs with the records still `held` in memory.
+    fn merge(self, mut held: Vec<[u64; N]>) -> Result<MergedRuns<N>, BoxError> {
+        held.sort_unstable();
+        let mut sources: Vec<Box<dyn Iterator<Item = Result<[u64; N], BoxError>>>> = self
+            .paths
+            .iter()
+            .map(|path| {
+                let mut reader = BufReader::new(File::open(path)?);
+                Ok(Box::new(std::iter::from_fn(move || {
+                    read_record(&mut reader).transpose()
+                })) as Box<dyn Iterator<Item = _>>)
+            })
+            .collect::<Result<_, BoxError>>()?;
+        sources.push(Box::new(held.into_iter().map(Ok)));
+
+        let mut heads = BinaryHeap::new();
+        for (idx, source) in sources.iter_mut().enumerate() {
+            if let Some(record) = source.next().transpose()? {
+                heads.push(Reverse((record, idx)));
+            }
+        }
+        Ok(MergedRuns {
+            runs: self,
+            sources,
+            heads,
+        })
+    }
+}
+
+impl<const N: usize> Drop for U64Runs<N> {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Reads the next record of a run written by [`U64Runs::spill`], or `None` at
+/// the end of the run.
+fn read_record<const N: usize>(reader: &mut impl Read) -> Result<Option<[u64; N]>, BoxError> {
+    let mut record = [0; N];
+    for (idx, value) in record.iter_mut().enumerate() {
+        let mut bytes = [0; size_of::<u64>()];
+        match reader.read_exact(&mut bytes) {
+            Err(err) if idx == 0 && err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Ok(None);
+            }
+            result => result?,
+        }
+        *value = u64::from_le_bytes(bytes);
+    }
+    Ok(Some(record))
+}
+
+/// The records of [`U64Runs`] in ascending order.
+struct MergedRuns<const N: usize> {
+    /// Kept to remove the files once the merge is dropped.
+    runs: U64Runs<N>,
+    sources: Vec<Box<dyn Iterator<Item = Result<[u64; N], BoxError>>>>,
+    /// The smallest record of each source, with the index of its source
+    heads: BinaryHeap<Reverse<([u64; N], usize)>>,
+}
+
+impl<const N: usize> MergedRuns<N> {
+    fn num_runs(&self) -> usize {
+        self.runs.paths.len()
+    }
+}
+
+impl<const N: usize> Iterator for MergedRuns<N> {
+    type Item = Result<[u64; N], BoxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((record, idx)) = self.heads.pop()?;
+        match self.sources[idx].next().transpose() {
+            Ok(Some(next)) => self.heads.push(Reverse((next, idx))),
+            Ok(None) => {}
+            Err(err) => return Some(Err(err)),
+        }
+        Some(Ok(record))
+    }
+}
+
+/// Writes rows of string labels to parquet, one dictionary encoded `Utf8`
+/// column per label.
+///
+/// Rows are buffered and written as a record batch every
+/// [`DEFAULT_ROW_GROUP_SIZE`] rows.
+struct LabelColumnsWriter<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: Arc<Schema>,
+    columns: Vec<StringBuilder>,
+    buffered: usize,
+}
+
+impl<W: Write + Send> LabelColumnsWriter<W> {
+    fn try_new(sink: W, label_names: &[&String]) -> Result<Self, ParquetError> {
+        let schema = Arc::new(Schema::new(
+            label_names
+                .iter()
+                .map(|name| Field::new(name.as_str(), DataType::Utf8, false))
+                .collect::<Vec<_>>(),
+        ));
+        let props = default_writer_properties(Compression::UNCOMPRESSED)
+            .set_dictionary_enabled(true)
+            .build();
+        Ok(Self {
+            writer: ArrowWriter::try_new(sink, schema.clone(), Some(props))?,
+            schema,
+            columns: label_names.iter().map(|_| StringBuilder::new()).collect(),
+            buffered: 0,
+        })
+    }
+
+    fn write_row(&mut self, row: &[String]) -> Result<(), BoxError> {
+        for (column, value) in self.columns.iter_mut().zip(row) {
+            column.append_value(value);
+        }
+        self.buffered += 1;
+        if self.buffered == DEFAULT_ROW_GROUP_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), BoxError> {
+        let columns: Vec<ArrayRef> = self
+            .columns
+            .iter_mut()
+            .map(|column| Arc::new(column.finish()) as ArrayRef)
+            .collect();
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.write(&batch)?;
+        self.buffered = 0;
+        Ok(())
+    }
+
+    fn close(mut self) -> Result<(), BoxError> {
+        if self.buffered > 0 {
+            self.flush()?;
+        }
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+/// Keeps the rows of `batch` whose column at each index of `filters` equals
+/// the paired value.
+fn filter_batch(
+    batch: RecordBatch,
+    filters: &[(usize, Scalar<StringArray>)],
+) -> Result<RecordBatch, ArrowError> {
+    let mut mask: Option<BooleanArray> = None;
+    for (idx, value) in filters {
+        let column = cast(batch.column(*idx), &DataType::Utf8)?;
+        let matches = eq(&column, value)?;
+        mask = Some(match mask {
+            Some(mask) => and(&mask, &matches)?,
+            None => matches,
+        });
+    }
+    match mask {
+        Some(mask) => filter_record_batch(&batch, &mask),
+        None => Ok(batch),
+    }
+}
+
+/// Hash of the values of `row`, like a ts_id seeded with `seed`.
+fn row_hash(row: &[String], seed: u64) -> u64 {
+    let mut generator = Xx3TsIdGenerator::from_seed(seed);
+    generator.write_label_values(row.iter().map(String::as_bytes));
+    generator.build_ts_id()
+}
+
+/// Whether `row` belongs to the `fraction` of rows selected by `seed`.
+fn in_sample(row: &[String], fraction: f64, seed: u64) -> bool {
+    // The top 53 bits of the hash, as a uniform f64 in [0, 1)
+    let position = (row_hash(row, seed) >> 11) as f64 / (1u64 << 53) as f64;
+    position < fraction
+}
+
+/// Reads all `.parquet` files in `dir_path` into a single CSV file.
+///
+/// Files are read in name order and must all have the same label columns; the
+/// header is written once. The output is compressed like in
+/// [`read_parquet_files`]. Returns the total number of rows written.
+pub fn read_parquet_directory(
+    dir_path: &str,
+    output_csv_path: &str,
+    exclude_columns: &[&str],
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir_path)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "parquet") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    create_compressed(output_csv_path, &CsvWriteOptions::default(), |sink| {
+        let mut writer = csv::Writer::from_writer(sink);
+        let mut header: Option<Vec<String>> = None;
+        let mut num_rows = 0;
+        for path in paths {
+            let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&path)?)?;
+            let (column_indices, column_names) = label_columns(builder.schema(), exclude_columns);
+            match &header {
+                None => {
+                    writer.write_record(&column_names)?;
+                    header = Some(column_names.clone());
+                }
+                Some(header) if *header != column_names => {
+                    return Err(format!(
+                        "{} has label columns {:?}, expected {:?}",
+                        path.display(),
+                        column_names,
+                        header
+                    )
+                    .into());
+                }
+                Some(_) => {}
+            }
+
+            for batch_result in builder.build()? {
+                let batch = batch_result?;
+                for row in batch_rows(
+                    &batch,
+                    &column_indices,
+                    &column_names,
+                    &NullRepresentation::Empty,
+                )? {
+                    writer.write_record(&row)?;
+                    num_rows += 1;
+                }
+            }
+        }
+
+        writer.flush()?;
+        Ok(num_rows)
+    })
+}
+
+/// Copies the parquet file at `input_path` to an Arrow IPC file at
+/// `output_path`, skipping the given columns.
+///
+/// Unlike CSV, the IPC file keeps the Arrow types of the columns, and can be
+/// read back with `arrow::ipc::reader::FileReader`.
+pub fn read_parquet_to_ipc(
+    input_path: &str,
+    output_path: &str,
+    exclude_columns: &[&str],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(input_path)?)?;
+    let (column_indices, _) = label_columns(builder.schema(), exclude_columns);
+    let mask = ProjectionMask::roots(builder.parquet_schema(), column_indices);
+    let reader = builder.with_projection(mask).build()?;
+
+    let output = BufWriter::new(File::create(output_path)?);
+    let mut writer = FileWriter::try_new(output, &reader.schema())?;
+    for batch in reader {
+        writer.write(&batch?)?;
+    }
+    writer.into_inner()?.flush()?;
+    Ok(())
+}
+
+/// Reads the label columns of a parquet file into memory, skipping the given
+/// columns, and hashes the label names with `H`.
+///
+/// Column selection and value conversion follow [`read_parquet_files`], without
+/// the round trip through a CSV file.
+pub fn read_labels_from_parquet<H>(
+    path: &str,
+    exclude_columns: &[&str],
+) -> Result<Labels, Box<dyn std::error::Error + Send + Sync>>
+where
+    H: Default + Hasher + SeededHasher,
+{
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?;
+    let (column_indices, label_names) = label_columns(builder.schema(), exclude_columns);
+
+    let mut label_values =
+        Vec::with_capacity(builder.metadata().file_metadata().num_rows() as usize);
+    for batch_result in builder.build()? {
+        let batch = batch_result?;
+        label_values.extend(batch_rows(
+            &batch,
+            &column_indices,
+            &label_names,
+            &NullRepresentation::Empty,
+        )?);
+    }
+
+    let label_name_hash = compute_label_name_hash::<H>(&label_names);
+
+    Ok(Labels {
+        label_names,
+        label_name_hash,
+        label_values,
+    })
+}
+
+/// Reads labels stored as a `Map` column named `map_column`, as written by
+/// [`encode_to_parquet_maparray`](crate::encode_to_parquet_maparray), and
+/// hashes the label names with `H`.
+///
+/// The label names are the sorted union of the keys of all rows, and labels
+/// missing from a row are left empty, like [`Labels::from_map_iter`].
+pub fn read_labels_from_maparray_parquet<H>(
+    path: &str,
+    map_column: &str,
+) -> Result<Labels, Box<dyn std::error::Error + Send + Sync>>
+where
+    H: Default + Hasher + SeededHasher,
+{
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?;
+    let column_idx = builder
+        .schema()
+        .index_of(map_column)
+        .map_err(|_| format!("{} has no column '{}'", path, map_column))?;
+    let mask = ProjectionMask::roots(builder.parquet_schema(), [column_idx]);
+
+    let mut rows = Vec::new();
+    for batch_result in builder.with_projection(mask).build()? {
+        let batch = batch_result?;
+        for entries in map_entries(as_map_column(batch.column(0), map_column)?, map_column)? {
+            rows.push(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, value.unwrap_or_default()))
+                    .collect::<HashMap<_, _>>(),
+            );
+        }
+    }
+    Ok(Labels::from_map_iter::<H, _>(rows.into_iter()))
+}
+
+impl Labels {
+    /// Builds labels from a map array such as the `labels` column written by
+    /// [`encode_to_parquet_maparray`](crate::encode_to_parquet_maparray),
+    /// hashing `label_names` with `H`.
+    ///
+    /// Values are placed in the order of `label_names`. Labels missing from a
+    /// map, and null values, are left empty. Fails if a map holds a key that
+    /// is not in `label_names`, or if `label_names` has duplicates.
+    pub fn from_map_array<H>(
+        array: &MapArray,
+        label_names: &[String],
+    ) -> Result<Labels, Box<dyn std::error::Error + Send + Sync>>
+    where
+        H: Default + Hasher + SeededHasher,
+    {
+        crate::check_unique_names(label_names)?;
+        let positions: HashMap<&str, usize> = label_names
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| (name.as_str(), idx))
+            .collect();
+
+        let mut label_values = Vec::with_capacity(array.len());
+        for entries in map_entries(array, "map array")? {
+            let mut row = vec![String::new(); label_names.len()];
+            for (key, value) in entries {
+                let idx = *positions
+                    .get(key.as_str())
+                    .ok_or(Error::UnknownLabel { name: key })?;
+                row[idx] = value.unwrap_or_default();
+            }
+            label_values.push(row);
+        }
+
+        Ok(Labels {
+            label_names: label_names.to_vec(),
+            label_name_hash: compute_label_name_hash::<H>(label_names),
+            label_values,
+        })
+    }
+}
+
+/// Returns the indices and names of the label columns in `schema`, skipping
+/// the given columns.
+pub(crate) fn label_columns(
+    schema: &Schema,
+    exclude_columns: &[&str],
+) -> (Vec<usize>, Vec<String>) {
+    let exclude_columns: HashSet<&str> = exclude_columns.iter().copied().collect();
     let mut column_indices_to_keep = Vec::new();
     let mut column_names = Vec::new();
 
@@ -32,82 +1143,1171 @@ pub fn read_parquet_files(
             column_names.push(field.name().clone());
         }
     }
+    (column_indices_to_keep, column_names)
+}
+
+/// Extracts the given label columns of `batch` as rows of strings.
+///
+/// See [`ValueFormatter::try_new`] for the supported column types. Null
+/// values are written as `nulls` specifies.
+pub(crate) fn batch_rows(
+    batch: &RecordBatch,
+    column_indices: &[usize],
+    column_names: &[String],
+    nulls: &NullRepresentation,
+) -> Result<Vec<Vec<String>>, Box<dyn std::error::Error + Send + Sync>> {
+    let rows = batch_values(batch, column_indices, column_names)?
+        .into_iter()
+        .filter_map(|row| apply_nulls(row, nulls))
+        .collect();
+    Ok(rows)
+}
+
+/// Extracts the given label columns of `batch` as rows of optional strings,
+/// with `None` for nulls.
+fn batch_values(
+    batch: &RecordBatch,
+    column_indices: &[usize],
+    column_names: &[String],
+) -> Result<Vec<Vec<Option<String>>>, Box<dyn std::error::Error + Send + Sync>> {
+    // Extract only the columns we want to keep, checking their types are supported
+    let columns = column_indices
+        .iter()
+        .zip(column_names)
+        .map(|(&idx, name)| ValueFormatter::try_new(batch.column(idx), name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Extract rows as vectors of strings
+    let rows = (0..batch.num_rows())
+        .map(|row_idx| columns.iter().map(|column| column.value(row_idx)).collect())
+        .collect();
+    Ok(rows)
+}
+
+/// Writes the nulls of `row` as `nulls` specifies, or returns `None` if the
+/// row is to be skipped.
+fn apply_nulls(row: Vec<Option<String>>, nulls: &NullRepresentation) -> Option<Vec<String>> {
+    row.into_iter()
+        .map(|value| match (value, nulls) {
+            (Some(value), _) => Some(value),
+            (None, NullRepresentation::Empty) => Some(String::new()),
+            (None, NullRepresentation::Literal(null)) => Some(null.clone()),
+            (None, NullRepresentation::SkipRow) => None,
+        })
+        .collect()
+}
+
+/// Boxed error returned by the label extraction helpers.
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The `(key, value)` pairs of one map, with `None` for null values.
+type MapEntries = Vec<(String, Option<String>)>;
+
+/// Returns `column` as a map array, failing with an error naming the column
+/// if it is not one.
+fn as_map_column<'a>(
+    column: &'a ArrayRef,
+    name: &str,
+) -> Result<&'a MapArray, Box<dyn std::error::Error + Send + Sync>> {
+    column.as_map_opt().ok_or_else(|| {
+        format!(
+            "Column '{}' is not a map, found type: {}",
+            name,
+            column.data_type()
+        )
+        .into()
+    })
+}
+
+/// Extracts the entries of a map array, one list per row. Null maps have no
+/// entries.
+fn map_entries(
+    map: &MapArray,
+    name: &str,
+) -> Result<Vec<MapEntries>, Box<dyn std::error::Error + Send + Sync>> {
+    let keys = ValueFormatter::try_new(map.keys(), name)?;
+    let values = ValueFormatter::try_new(map.values(), name)?;
+    let offsets = map.value_offsets();
+
+    let rows = (0..map.len())
+        .map(|row_idx| {
+            if map.is_null(row_idx) {
+                return Vec::new();
+            }
+            (offsets[row_idx] as usize..offsets[row_idx + 1] as usize)
+                .map(|entry| (keys.value(entry).unwrap_or_default(), values.value(entry)))
+                .collect()
+        })
+        .collect();
+    Ok(rows)
+}
+
+/// Reads the given map columns of a parquet file and returns the sorted
+/// union of the keys of each.
+fn map_key_union(
+    file: &File,
+    metadata: &ArrowReaderMetadata,
+    map_columns: &[(usize, String)],
+) -> Result<Vec<Vec<String>>, Box<dyn std::error::Error + Send + Sync>> {
+    if map_columns.is_empty() {
+        return Ok(Vec::new());
+    }
 
-    // Build the reader
-    let reader = builder.build()?;
+    let mask = ProjectionMask::roots(
+        metadata.metadata().file_metadata().schema_descr(),
+        map_columns.iter().map(|(idx, _)| *idx),
+    );
+    let reader =
+        ParquetRecordBatchReaderBuilder::new_with_metadata(file.try_clone()?, metadata.clone())
+            .with_projection(mask)
+            .build()?;
 
-    // Write distinct rows to CSV
-    let mut writer = csv::Writer::from_path(output_csv_path)?;
-    // Write header
-    writer.write_record(&column_names)?;
+    let mut keys = vec![BTreeSet::new(); map_columns.len()];
     for batch_result in reader {
         let batch = batch_result?;
+        // Projected columns keep their order in the file
+        for (pos, (_, name)) in map_columns.iter().enumerate() {
+            for entries in map_entries(as_map_column(batch.column(pos), name)?, name)? {
+                keys[pos].extend(entries.into_iter().map(|(key, _)| key));
+            }
+        }
+    }
+    Ok(keys
+        .into_iter()
+        .map(|keys| keys.into_iter().collect())
+        .collect())
+}
 
-        // Extract only the columns we want to keep
-        let columns: Vec<_> = column_indices_to_keep
-            .iter()
-            .map(|&idx| batch.column(idx).clone())
+/// Formats the values of a label column as strings.
+struct ValueFormatter<'a>(Box<dyn Fn(usize) -> Option<String> + 'a>);
+
+impl<'a> ValueFormatter<'a> {
+    /// Builds a formatter for `column`, failing with an error naming the
+    /// column if its type is not supported.
+    ///
+    /// Supported types are strings, integers, booleans, floats, timestamps and
+    /// dictionaries of any of these. Numbers use Rust's `Display`, which does
+    /// not depend on the locale; timestamps are formatted as RFC 3339 in UTC.
+    fn try_new(
+        column: &'a ArrayRef,
+        name: &str,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let formatter = match column.data_type() {
+            DataType::Utf8 => Self::with(column.as_string::<i32>(), |array, idx| {
+                array.value(idx).to_owned()
+            }),
+            DataType::LargeUtf8 => Self::with(column.as_string::<i64>(), |array, idx| {
+                array.value(idx).to_owned()
+            }),
+            DataType::Boolean => Self::with(column.as_boolean(), |array, idx| {
+                array.value(idx).to_string()
+            }),
+            DataType::Int8 => Self::primitive::<Int8Type>(column),
+            DataType::Int16 => Self::primitive::<Int16Type>(column),
+            DataType::Int32 => Self::primitive::<Int32Type>(column),
+            DataType::Int64 => Self::primitive::<Int64Type>(column),
+            DataType::UInt8 => Self::primitive::<UInt8Type>(column),
+            DataType::UInt16 => Self::primitive::<UInt16Type>(column),
+            DataType::UInt32 => Self::primitive::<UInt32Type>(column),
+            DataType::UInt64 => Self::primitive::<UInt64Type>(column),
+            DataType::Float32 => Self::primitive::<Float32Type>(column),
+            DataType::Float64 => Self::primitive::<Float64Type>(column),
+            DataType::Timestamp(TimeUnit::Second, _) => {
+                Self::timestamp::<TimestampSecondType>(column)
+            }
+            DataType::Timestamp(TimeUnit::Millisecond, _) => {
+                Self::timestamp::<TimestampMillisecondType>(column)
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, _) => {
+                Self::timestamp::<TimestampMicrosecondType>(column)
+            }
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                Self::timestamp::<TimestampNanosecondType>(column)
+            }
+            DataType::Dictionary(key, _) => match key.as_ref() {
+                DataType::Int8 => Self::dictionary::<Int8Type>(column, name)?,
+                DataType::Int16 => Self::dictionary::<Int16Type>(column, name)?,
+                DataType::Int32 => Self::dictionary::<Int32Type>(column, name)?,
+                DataType::Int64 => Self::dictionary::<Int64Type>(column, name)?,
+                DataType::UInt8 => Self::dictionary::<UInt8Type>(column, name)?,
+                DataType::UInt16 => Self::dictionary::<UInt16Type>(column, name)?,
+                DataType::UInt32 => Self::dictionary::<UInt32Type>(column, name)?,
+                DataType::UInt64 => Self::dictionary::<UInt64Type>(column, name)?,
+                _ => return Err(unsupported(name, column.data_type())),
+            },
+            other => return Err(unsupported(name, other)),
+        };
+        Ok(formatter)
+    }
+
+    /// Formats the non-null values of `array` with `format`.
+    fn with<A: Array>(array: &'a A, format: impl Fn(&'a A, usize) -> String + 'a) -> Self {
+        Self(Box::new(move |idx| {
+            array.is_valid(idx).then(|| format(array, idx))
+        }))
+    }
+
+    fn primitive<T: ArrowPrimitiveType>(column: &'a ArrayRef) -> Self
+    where
+        T::Native: Display,
+    {
+        Self::with(column.as_primitive::<T>(), |array, idx| {
+            array.value(idx).to_string()
+        })
+    }
+
+    /// Formats timestamps as RFC 3339 in UTC, or as the raw number if they
+    /// are out of range.
+    fn timestamp<T: ArrowTimestampType>(column: &'a ArrayRef) -> Self {
+        Self::with(column.as_primitive::<T>(), |array, idx| {
+            let value = array.value(idx);
+            as_datetime::<T>(value)
+                .map_or_else(|| value.to_string(), |time| time.and_utc().to_rfc3339())
+        })
+    }
+
+    /// Formats the dictionary values each key points to.
+    fn dictionary<K: ArrowDictionaryKeyType>(
+        column: &'a ArrayRef,
+        name: &str,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let dictionary = column.as_dictionary::<K>();
+        let values = Self::try_new(dictionary.values(), name)?;
+        let keys = dictionary.keys();
+        Ok(Self(Box::new(move |idx| {
+            keys.is_valid(idx)
+                .then(|| keys.value(idx).as_usize())
+                .and_then(|key| values.value(key))
+        })))
+    }
+
+    /// Returns the value at `idx`, or `None` if it is null.
+    fn value(&self, idx: usize) -> Option<String> {
+        (self.0)(idx)
+    }
+}
+
+fn unsupported(name: &str, data_type: &DataType) -> Box<dyn std::error::Error + Send + Sync> {
+    format!(
+        "Column '{}' has unsupported type {} for label values",
+        name, data_type
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::sync::Arc;
+
+    use arrow::array::{
+        BooleanArray, DictionaryArray, Float64Array, Int8Array, Int64Array, LargeStringArray,
+        ListArray, MapBuilder, StringArray, StringBuilder, TimestampMillisecondArray, UInt8Array,
+        UInt64Array,
+    };
+    use arrow::datatypes::Field;
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::properties::WriterProperties;
+    use xxhash_rust::xxh3::Xxh3;
+
+    use super::*;
+    use crate::read_labels_and_hash_str;
+
+    fn write_labels_parquet(path: &std::path::Path, jobs: &[&str], values: &[f64]) {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("job", DataType::Utf8, true),
+            Field::new("greptime_value", DataType::Float64, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(jobs.to_vec())),
+                Arc::new(Float64Array::from(values.to_vec())),
+            ],
+        )
+        .unwrap();
+        let mut writer = ArrowWriter::try_new(File::create(path).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn parquet_ipc_roundtrip() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("job", DataType::Utf8, true),
+            Field::new("shard", DataType::Int64, false),
+            Field::new(
+                "env",
+                DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)),
+                true,
+            ),
+            Field::new(
+                "greptime_timestamp",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new("greptime_value", DataType::Float64, false),
+        ]));
+        let env: DictionaryArray<Int32Type> =
+            vec![Some("prod"), None, Some("dev")].into_iter().collect();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![Some("node"), Some("api"), None])),
+                Arc::new(Int64Array::from(vec![1, 2, 3])),
+                Arc::new(env),
+                Arc::new(TimestampMillisecondArray::from(vec![0, 1000, 2000])),
+                Arc::new(Float64Array::from(vec![1.0, 2.0, 3.0])),
+            ],
+        )
+        .unwrap();
+
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("tsid-bench-ipc-{}.parquet", std::process::id()));
+        let ipc = dir.join(format!("tsid-bench-ipc-{}.arrow", std::process::id()));
+        let output = dir.join(format!("tsid-bench-ipc-out-{}.parquet", std::process::id()));
+        let mut writer = ArrowWriter::try_new(File::create(&input).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        read_parquet_to_ipc(
+            input.to_str().unwrap(),
+            ipc.to_str().unwrap(),
+            &EXCLUDED_COLUMNS,
+        )
+        .unwrap();
+
+        let reader =
+            arrow::ipc::reader::FileReader::try_new(File::open(&ipc).unwrap(), None).unwrap();
+        let ipc_schema = reader.schema();
+        let batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        let mut writer =
+            ArrowWriter::try_new(File::create(&output).unwrap(), ipc_schema.clone(), None).unwrap();
+        for batch in &batches {
+            writer.write(batch).unwrap();
+        }
+        writer.close().unwrap();
+
+        let builder =
+            ParquetRecordBatchReaderBuilder::try_new(File::open(&output).unwrap()).unwrap();
+        let expected = batch.project(&[0, 1, 2]).unwrap();
+        assert_eq!(ipc_schema.fields(), expected.schema().fields());
+        assert_eq!(builder.schema().fields(), expected.schema().fields());
+        assert_eq!(builder.metadata().file_metadata().num_rows(), 3);
+        let reread: Vec<RecordBatch> = builder.build().unwrap().map(Result::unwrap).collect();
+        assert_eq!(reread, [expected]);
+
+        for path in [input, ipc, output] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn read_directory() {
+        let dir = std::env::temp_dir().join(format!("tsid-bench-dir-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        write_labels_parquet(&dir.join("b.parquet"), &["node", "api"], &[2.0, 3.0]);
+        write_labels_parquet(&dir.join("a.parquet"), &["prometheus"], &[1.0]);
+        std::fs::write(dir.join("README"), "not parquet").unwrap();
+        let output = dir.join("labels.csv");
+
+        let num_rows = read_parquet_directory(
+            dir.to_str().unwrap(),
+            output.to_str().unwrap(),
+            &EXCLUDED_COLUMNS,
+        )
+        .unwrap();
+
+        assert_eq!(num_rows, 3);
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            "job\nprometheus\nnode\napi\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_labels_roundtrip() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("job", DataType::Utf8, true),
+            Field::new("instance", DataType::LargeUtf8, true),
+            Field::new(
+                "env",
+                DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)),
+                true,
+            ),
+            Field::new("greptime_value", DataType::Float64, false),
+        ]));
+        let env: DictionaryArray<Int32Type> =
+            vec![Some("prod"), None, Some("prod")].into_iter().collect();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![Some("node"), Some("api"), None])),
+                Arc::new(LargeStringArray::from(vec!["a:9100", "b:8080", "c:9100"])),
+                Arc::new(env),
+                Arc::new(Float64Array::from(vec![1.0, 2.0, 3.0])),
+            ],
+        )
+        .unwrap();
+
+        let path =
+            std::env::temp_dir().join(format!("tsid-bench-labels-{}.parquet", std::process::id()));
+        let mut writer = ArrowWriter::try_new(File::create(&path).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let labels =
+            read_labels_from_parquet::<Xxh3>(path.to_str().unwrap(), &EXCLUDED_COLUMNS).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let expected = read_labels_and_hash_str::<Xxh3>(
+            "job,instance,env\nnode,a:9100,prod\napi,b:8080,\n,c:9100,prod\n",
+        )
+        .unwrap();
+        assert_eq!(labels.label_names, expected.label_names);
+        assert_eq!(labels.label_name_hash, expected.label_name_hash);
+        assert_eq!(labels.label_values, expected.label_values);
+    }
+
+    #[test]
+    fn read_non_string_labels() {
+        let tz_dictionary =
+            DataType::Dictionary(Box::new(DataType::UInt8), Box::new(DataType::LargeUtf8));
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("shard", DataType::Int8, true),
+            Field::new("pid", DataType::Int64, true),
+            Field::new("port", DataType::UInt64, true),
+            Field::new("canary", DataType::Boolean, true),
+            Field::new("ratio", DataType::Float64, true),
+            Field::new("zone", tz_dictionary, true),
+            Field::new(
+                "started",
+                DataType::Timestamp(TimeUnit::Millisecond, Some("+00:00".into())),
+                true,
+            ),
+        ]));
+        let zone = DictionaryArray::<UInt8Type>::try_new(
+            UInt8Array::from(vec![Some(0), Some(0), None]),
+            Arc::new(LargeStringArray::from(vec!["eu-1"])),
+        )
+        .unwrap();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int8Array::from(vec![Some(-3), Some(7), None])),
+                Arc::new(Int64Array::from(vec![Some(i64::MIN), Some(42), None])),
+                Arc::new(UInt64Array::from(vec![Some(u64::MAX), Some(9100), None])),
+                Arc::new(BooleanArray::from(vec![Some(true), Some(false), None])),
+                Arc::new(Float64Array::from(vec![Some(0.5), Some(1.0), None])),
+                Arc::new(zone),
+                Arc::new(
+                    TimestampMillisecondArray::from(vec![Some(1_700_000_000_123), Some(0), None])
+                        .with_timezone("+00:00"),
+                ),
+            ],
+        )
+        .unwrap();
+
+        // Written to parquet, so the types go through the embedded arrow schema
+        let path =
+            std::env::temp_dir().join(format!("tsid-bench-typed-{}.parquet", std::process::id()));
+        let mut writer = ArrowWriter::try_new(File::create(&path).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let labels =
+            read_labels_from_parquet::<Xxh3>(path.to_str().unwrap(), &EXCLUDED_COLUMNS).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            labels.label_names,
+            ["shard", "pid", "port", "canary", "ratio", "zone", "started"]
+        );
+        assert_eq!(
+            labels.label_values,
+            [
+                vec![
+                    "-3",
+                    "-9223372036854775808",
+                    "18446744073709551615",
+                    "true",
+                    "0.5",
+                    "eu-1",
+                    "2023-11-14T22:13:20.123+00:00"
+                ],
+                vec![
+                    "7",
+                    "42",
+                    "9100",
+                    "false",
+                    "1",
+                    "eu-1",
+                    "1970-01-01T00:00:00+00:00"
+                ],
+                vec![""; 7],
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_label_type() {
+        let field = Field::new_list_field(DataType::Int32, true);
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("job", DataType::Utf8, true),
+            Field::new("ports", DataType::List(Arc::new(field)), true),
+        ]));
+        let ports = ListArray::from_iter_primitive::<Int32Type, _, _>(vec![Some(vec![Some(9100)])]);
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(StringArray::from(vec!["node"])), Arc::new(ports)],
+        )
+        .unwrap();
+
+        let path =
+            std::env::temp_dir().join(format!("tsid-bench-list-{}.parquet", std::process::id()));
+        let mut writer = ArrowWriter::try_new(File::create(&path).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let err = read_labels_from_parquet::<Xxh3>(path.to_str().unwrap(), &EXCLUDED_COLUMNS)
+            .err()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.to_string().contains("Column 'ports'"), "{}", err);
+    }
+
+    #[test]
+    fn read_parquet_files_gzip_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("tsid-bench-gz-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("labels.parquet");
+        let jobs: Vec<String> = (0..1000).map(|idx| format!("job-{}", idx % 37)).collect();
+        let jobs: Vec<&str> = jobs.iter().map(String::as_str).collect();
+        write_labels_parquet(&input, &jobs, &vec![1.0; jobs.len()]);
+        let input = input.to_str().unwrap();
+
+        let plain = dir.join("labels.csv");
+        read_parquet_files(input, plain.to_str().unwrap()).unwrap();
+        let expected = std::fs::read_to_string(&plain).unwrap();
+
+        let mut sizes = Vec::new();
+        for gzip_level in [1, 9] {
+            let gzipped = dir.join(format!("labels-{}.csv.gz", gzip_level));
+            let gzipped = gzipped.to_str().unwrap();
+            let options = CsvWriteOptions {
+                gzip_level,
+                ..Default::default()
+            };
+            read_parquet_files_with_options(input, gzipped, &options).unwrap();
+
+            let mut actual = String::new();
+            crate::open_csv_reader(gzipped)
+                .unwrap()
+                .read_to_string(&mut actual)
+                .unwrap();
+            assert_eq!(actual, expected);
+            sizes.push(std::fs::metadata(gzipped).unwrap().len());
+        }
+        assert!(sizes[0] < expected.len() as u64);
+        assert!(sizes[1] <= sizes[0], "{:?}", sizes);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn create_compressed_finishes_on_error() {
+        let path =
+            std::env::temp_dir().join(format!("tsid-bench-partial-{}.csv.gz", std::process::id()));
+        let path = path.to_str().unwrap();
+        let result: Result<(), Box<dyn std::error::Error + Send + Sync>> =
+            create_compressed(path, &CsvWriteOptions::default(), |sink| {
+                sink.write_all(b"job\nnode\n")?;
+                Err("failed halfway".into())
+            });
+        assert_eq!(result.unwrap_err().to_string(), "failed halfway");
+
+        // The partial output is still a complete gzip stream.
+        let mut actual = String::new();
+        crate::open_csv_reader(path)
+            .unwrap()
+            .read_to_string(&mut actual)
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(actual, "job\nnode\n");
+    }
+
+    #[test]
+    fn read_parquet_files_dedup() {
+        let dir = std::env::temp_dir().join(format!("tsid-bench-dedup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("labels.parquet");
+        write_labels_parquet(
+            &input,
+            &["node", "api", "node", "db", "api", "node"],
+            &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0],
+        );
+        let input = input.to_str().unwrap();
+        let output = dir.join("labels.csv");
+        let output = output.to_str().unwrap();
+
+        let num_rows = read_parquet_files_config(input, output, ReadParquetConfig::default())
+            .unwrap()
+            .distinct_rows;
+        assert_eq!(num_rows, 6);
+
+        for capacity_hint in [None, Some(2)] {
+            let config = ReadParquetConfig {
+                dedup: true,
+                capacity_hint,
+                ..Default::default()
+            };
+            let num_rows = read_parquet_files_config(input, output, config)
+                .unwrap()
+                .distinct_rows;
+            assert_eq!(num_rows, 3);
+            assert_eq!(
+                std::fs::read_to_string(output).unwrap(),
+                "job\nnode\napi\ndb\n"
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn extract_to_parquet_matches_csv() {
+        let dir =
+            std::env::temp_dir().join(format!("tsid-bench-to-parquet-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.parquet");
+        // More rows than fit in one buffered batch of the parquet writer
+        let jobs: Vec<String> = (0..DEFAULT_ROW_GROUP_SIZE * 3)
+            .map(|i| format!("job-{}", i % (DEFAULT_ROW_GROUP_SIZE + 7)))
             .collect();
+        let jobs: Vec<&str> = jobs.iter().map(String::as_str).collect();
+        write_labels_parquet(&input, &jobs, &vec![1.0; jobs.len()]);
+        let input = input.to_str().unwrap();
+        let csv_output = dir.join("labels.csv.gz");
+        let csv_output = csv_output.to_str().unwrap();
+        let parquet_output = dir.join("labels.parquet");
+        let parquet_output = parquet_output.to_str().unwrap();
 
-        // Assert all columns are string arrays
-        for (col_idx, column) in columns.iter().enumerate() {
-            match column.data_type() {
-                DataType::Utf8 | DataType::LargeUtf8 => {
-                    // Valid string type
-                }
-                _ => {
-                    return Err(format!(
-                        "Column '{}' is not a string array, found type: {:?}",
-                        column_names[col_idx],
-                        column.data_type()
-                    )
-                    .into());
-                }
+        let config = ReadParquetConfig {
+            dedup: true,
+            ..Default::default()
+        };
+        let csv_summary = read_parquet_files_config(input, csv_output, config.clone()).unwrap();
+        let parquet_summary = read_parquet_files_config(input, parquet_output, config).unwrap();
+        assert_eq!(parquet_summary.distinct_rows, DEFAULT_ROW_GROUP_SIZE + 7);
+        assert_eq!(parquet_summary.distinct_rows, csv_summary.distinct_rows);
+
+        let from_csv = crate::read_labels_from_path::<Xxh3>(csv_output).unwrap();
+        let from_parquet = read_labels_from_parquet::<Xxh3>(parquet_output, &[]).unwrap();
+        assert_eq!(from_parquet.label_names, from_csv.label_names);
+        assert_eq!(from_parquet.label_name_hash, from_csv.label_name_hash);
+        assert_eq!(from_parquet.label_values, from_csv.label_values);
+
+        let builder =
+            ParquetRecordBatchReaderBuilder::try_new(File::open(parquet_output).unwrap()).unwrap();
+        assert_eq!(builder.schema().field(0).data_type(), &DataType::Utf8);
+        let column = builder.metadata().row_group(0).column(0);
+        assert!(column.dictionary_page_offset().is_some());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dedup_spills_to_disk() {
+        let dir = std::env::temp_dir().join(format!("tsid-bench-spill-{}", std::process::id()));
+        let spill_dir = dir.join("spill");
+        std::fs::create_dir_all(&spill_dir).unwrap();
+        let input = dir.join("labels.parquet");
+        let jobs: Vec<String> = (0..3000).map(|i| format!("job-{}", i % 1007)).collect();
+        let jobs: Vec<&str> = jobs.iter().map(String::as_str).collect();
+        write_labels_parquet(&input, &jobs, &vec![1.0; jobs.len()]);
+        let input = input.to_str().unwrap();
+        let output = dir.join("labels.csv");
+        let output = output.to_str().unwrap();
+
+        let in_memory = ReadParquetConfig {
+            dedup: true,
+            ..Default::default()
+        };
+        read_parquet_files_config(input, output, in_memory).unwrap();
+        let mut expected: Vec<String> = std::fs::read_to_string(output)
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect();
+        expected.sort();
+        assert_eq!(expected.len(), 1007 + 1);
+
+        for (dedup_memory_limit, dedup_hash_only) in
+            [(None, true), (Some(4096), false), (Some(4096), true)]
+        {
+            let config = ReadParquetConfig {
+                dedup: true,
+                dedup_memory_limit,
+                dedup_hash_only,
+                spill_dir: Some(spill_dir.clone()),
+                ..Default::default()
+            };
+            let summary = read_parquet_files_config(input, output, config).unwrap();
+            assert_eq!(summary.distinct_rows, 1007);
+            assert_eq!(summary.rows_scanned, 3000);
+            if dedup_memory_limit.is_some() {
+                assert!(summary.spilled_runs > 1, "{} runs", summary.spilled_runs);
             }
+            let mut actual: Vec<String> = std::fs::read_to_string(output)
+                .unwrap()
+                .lines()
+                .map(str::to_owned)
+                .collect();
+            assert_eq!(actual[0], "job");
+            actual.sort();
+            assert_eq!(actual, expected);
+            // Spilled runs are removed once merged
+            assert_eq!(std::fs::read_dir(&spill_dir).unwrap().count(), 0);
         }
 
-        // Extract rows as vectors of strings
-        let num_rows = columns[0].len();
-        for row_idx in 0..num_rows {
-            let mut row = Vec::new();
-            for column in &columns {
-                let value = match column.data_type() {
-                    DataType::Utf8 => {
-                        let string_array = column
-                            .as_any()
-                            .downcast_ref::<StringArray>()
-                            .ok_or("Failed to downcast column to StringArray")?;
-                        if string_array.is_null(row_idx) {
-                            String::new()
-                        } else {
-                            string_array.value(row_idx).to_string()
-                        }
-                    }
-                    DataType::LargeUtf8 => {
-                        let string_array = column
-                            .as_any()
-                            .downcast_ref::<LargeStringArray>()
-                            .ok_or("Failed to downcast column to LargeStringArray")?;
-                        if string_array.is_null(row_idx) {
-                            String::new()
-                        } else {
-                            string_array.value(row_idx).to_string()
-                        }
-                    }
-                    _ => {
-                        return Err(format!(
-                            "Unexpected data type when extracting string value: {:?}",
-                            column.data_type()
-                        )
-                        .into());
-                    }
-                };
-                row.push(value);
+        // Without exceeding the memory limit, rows are still sorted by hash
+        let config = ReadParquetConfig {
+            dedup: true,
+            dedup_memory_limit: Some(usize::MAX),
+            limit: Some(10),
+            ..Default::default()
+        };
+        let summary = read_parquet_files_config(input, output, config).unwrap();
+        assert_eq!((summary.distinct_rows, summary.spilled_runs), (10, 0));
+        let config = ReadParquetConfig {
+            dedup: true,
+            dedup_memory_limit: Some(4096),
+            limit: Some(10),
+            spill_dir: Some(spill_dir.clone()),
+            ..Default::default()
+        };
+        let spilled_output = dir.join("spilled.csv");
+        let spilled_output = spilled_output.to_str().unwrap();
+        let summary = read_parquet_files_config(input, spilled_output, config).unwrap();
+        assert_eq!(summary.distinct_rows, 10);
+        assert_eq!(
+            std::fs::read_to_string(spilled_output).unwrap(),
+            std::fs::read_to_string(output).unwrap()
+        );
+
+        // Spilled row hashes keep the first-seen order of the in-memory dedup
+        let hash_only = |dedup_memory_limit| ReadParquetConfig {
+            dedup: true,
+            dedup_hash_only: true,
+            dedup_memory_limit,
+            limit: Some(500),
+            spill_dir: Some(spill_dir.clone()),
+            ..Default::default()
+        };
+        read_parquet_files_config(input, output, hash_only(None)).unwrap();
+        let summary =
+            read_parquet_files_config(input, spilled_output, hash_only(Some(1024))).unwrap();
+        assert_eq!(summary.distinct_rows, 500);
+        assert!(summary.spilled_runs > 1, "{} runs", summary.spilled_runs);
+        assert_eq!(
+            std::fs::read_to_string(spilled_output).unwrap(),
+            std::fs::read_to_string(output).unwrap()
+        );
+        assert_eq!(std::fs::read_dir(&spill_dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn null_representations() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("job", DataType::Utf8, true),
+            Field::new("pid", DataType::Int64, true),
+            Field::new(
+                "env",
+                DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)),
+                true,
+            ),
+        ]));
+        let env: DictionaryArray<Int32Type> = vec![Some("prod"), Some(""), None, Some("dev")]
+            .into_iter()
+            .collect();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![
+                    Some("node"),
+                    None,
+                    Some("api"),
+                    Some(""),
+                ])),
+                Arc::new(Int64Array::from(vec![Some(1), Some(2), Some(3), None])),
+                Arc::new(env),
+            ],
+        )
+        .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("tsid-bench-nulls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("labels.parquet");
+        let mut writer = ArrowWriter::try_new(File::create(&input).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        let input = input.to_str().unwrap();
+        let output = dir.join("labels.csv");
+        let output = output.to_str().unwrap();
+
+        let extract = |nulls: NullRepresentation| {
+            let config = ReadParquetConfig {
+                nulls,
+                ..Default::default()
+            };
+            let num_rows = read_parquet_files_config(input, output, config)
+                .unwrap()
+                .distinct_rows;
+            (num_rows, std::fs::read_to_string(output).unwrap())
+        };
+        assert_eq!(
+            extract(NullRepresentation::Empty),
+            (
+                4,
+                "job,pid,env\nnode,1,prod\n,2,\napi,3,\n,,dev\n".to_owned()
+            )
+        );
+        assert_eq!(
+            extract(NullRepresentation::SkipRow),
+            (1, "job,pid,env\nnode,1,prod\n".to_owned())
+        );
+
+        // A literal keeps nulls apart from empty values through a reload.
+        let nulls = NullRepresentation::Literal("\\N".to_owned());
+        assert_eq!(
+            extract(nulls.clone()),
+            (
+                4,
+                "job,pid,env\nnode,1,prod\n\\N,2,\napi,3,\\N\n,\\N,dev\n".to_owned()
+            )
+        );
+        let labels = crate::read_labels_from_path::<Xxh3>(output).unwrap();
+        let value = |value: &str| Some(value.to_owned());
+        assert_eq!(
+            labels.to_nullable_pairs(&nulls),
+            [
+                vec![(0, value("node")), (1, value("1")), (2, value("prod"))],
+                vec![(0, None), (1, value("2")), (2, value(""))],
+                vec![(0, value("api")), (1, value("3")), (2, None)],
+                vec![(0, value("")), (1, None), (2, value("dev"))],
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_parquet_files_limit_and_sample() {
+        let dir = std::env::temp_dir().join(format!("tsid-bench-sample-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("labels.parquet");
+        let jobs: Vec<String> = (0..2000).map(|idx| format!("job-{}", idx % 1000)).collect();
+        let jobs: Vec<&str> = jobs.iter().map(String::as_str).collect();
+        write_labels_parquet(&input, &jobs, &vec![1.0; jobs.len()]);
+        let input = input.to_str().unwrap();
+        let output = dir.join("labels.csv");
+        let output = output.to_str().unwrap();
+
+        let extract = |config: ReadParquetConfig| {
+            let num_rows = read_parquet_files_config(input, output, config)
+                .unwrap()
+                .distinct_rows;
+            let csv = std::fs::read_to_string(output).unwrap();
+            assert_eq!(csv.lines().count(), num_rows + 1);
+            (num_rows, csv)
+        };
+
+        // The limit counts distinct rows when deduplicating.
+        for limit in [0, 1, 999, 1000, 1500] {
+            let (num_rows, csv) = extract(ReadParquetConfig {
+                dedup: true,
+                limit: Some(limit),
+                ..Default::default()
+            });
+            assert_eq!(num_rows, limit.min(1000));
+            assert_eq!(csv.lines().nth(1), (limit > 0).then_some("job-0"));
+        }
+        let (num_rows, _) = extract(ReadParquetConfig {
+            limit: Some(1500),
+            ..Default::default()
+        });
+        assert_eq!(num_rows, 1500);
+
+        let sample = |sample_seed| {
+            extract(ReadParquetConfig {
+                dedup: true,
+                sample: Some(0.1),
+                sample_seed,
+                ..Default::default()
+            })
+        };
+        let (num_rows, csv) = sample(7);
+        assert!((50..150).contains(&num_rows), "{}", num_rows);
+        assert_eq!(sample(7), (num_rows, csv.clone()));
+        assert_ne!(sample(8).1, csv);
+
+        assert!(
+            read_parquet_files_config(
+                input,
+                output,
+                ReadParquetConfig {
+                    sample: Some(1.5),
+                    ..Default::default()
+                }
+            )
+            .is_err()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_parquet_files_progress() {
+        let dir = std::env::temp_dir().join(format!("tsid-bench-progress-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("labels.parquet");
+        let schema = Arc::new(Schema::new(vec![Field::new("job", DataType::Utf8, true)]));
+        let jobs: Vec<String> = (0..1000).map(|idx| format!("job-{}", idx % 300)).collect();
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(StringArray::from(jobs))]).unwrap();
+        let props = WriterProperties::builder()
+            .set_max_row_group_size(100)
+            .build();
+        let mut writer =
+            ArrowWriter::try_new(File::create(&input).unwrap(), schema, Some(props)).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        let output = dir.join("labels.csv");
+
+        let config = ReadParquetConfig {
+            dedup: true,
+            ..Default::default()
+        };
+        let mut reports = Vec::new();
+        let summary = read_parquet_files_with_progress(
+            input.to_str().unwrap(),
+            output.to_str().unwrap(),
+            config,
+            |progress| reports.push(progress),
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(reports.len(), 10);
+        for (prev, next) in reports.iter().zip(&reports[1..]) {
+            assert!(next.rows_scanned > prev.rows_scanned);
+            assert!(next.distinct_rows >= prev.distinct_rows);
+            assert_eq!(next.row_group, prev.row_group + 1);
+            assert!(next.bytes_read > prev.bytes_read);
+        }
+        let last = reports.last().unwrap();
+        assert_eq!(last.num_row_groups, 10);
+        assert_eq!(last.bytes_read, last.total_bytes);
+        assert_eq!(
+            (
+                summary.rows_scanned,
+                summary.distinct_rows,
+                summary.row_groups
+            ),
+            (1000, 300, 10)
+        );
+        assert_eq!(
+            (last.rows_scanned, last.distinct_rows),
+            (summary.rows_scanned, summary.distinct_rows)
+        );
+    }
+
+    #[test]
+    fn read_maparray_roundtrip() {
+        let labels = read_labels_and_hash_str::<Xxh3>(
+            "job,instance,env\nnode,a:9100,prod\napi,b:8080,\n,c:9100,dev\n",
+        )
+        .unwrap();
+        let data =
+            crate::encode_to_parquet_maparray(&labels.label_names, &labels.label_values).unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "tsid-bench-maparray-{}.parquet",
+            std::process::id()
+        ));
+        std::fs::write(&path, data).unwrap();
+        let path = path.to_str().unwrap();
+
+        let read = read_labels_from_maparray_parquet::<Xxh3>(path, "labels").unwrap();
+        let expected = labels.project::<Xxh3>(&["env", "instance", "job"]).unwrap();
+        assert_eq!(read.label_names, expected.label_names);
+        assert_eq!(read.label_name_hash, expected.label_name_hash);
+        assert_eq!(read.label_values, expected.label_values);
+
+        assert!(read_labels_from_maparray_parquet::<Xxh3>(path, "missing").is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_parquet_files_expands_maps() {
+        let mut map_builder = MapBuilder::new(None, StringBuilder::new(), StringBuilder::new());
+        for entries in [
+            &[("job", Some("node")), ("env", Some("prod"))][..],
+            &[("instance", Some("b:8080")), ("job", None)],
+            &[],
+        ] {
+            for (key, value) in entries {
+                map_builder.keys().append_value(key);
+                map_builder.values().append_option(*value);
             }
-            writer.write_record(&row)?;
+            map_builder.append(true).unwrap();
         }
+        let labels = map_builder.finish();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("labels", labels.data_type().clone(), false),
+            Field::new("__name__", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(labels),
+                Arc::new(StringArray::from(vec!["up", "up", "down"])),
+            ],
+        )
+        .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("tsid-bench-maps-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("labels.parquet");
+        let mut writer = ArrowWriter::try_new(File::create(&input).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        let output = dir.join("labels.csv");
+
+        let config = ReadParquetConfig {
+            nulls: NullRepresentation::Literal("\\N".to_owned()),
+            ..Default::default()
+        };
+        read_parquet_files_config(input.to_str().unwrap(), output.to_str().unwrap(), config)
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            "__name__,env,instance,job\nup,prod,,node\nup,,b:8080,\\N\ndown,,,\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
-    writer.flush()?;
-    Ok(())
+    #[test]
+    fn from_map_array_roundtrip() {
+        let labels = read_labels_and_hash_str::<Xxh3>(
+            "job,instance,env\nnode,a:9100,prod\napi,b:8080,\n,c:9100,dev\n",
+        )
+        .unwrap();
+        let data =
+            crate::encode_to_parquet_maparray(&labels.label_names, &labels.label_values).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(data))
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut label_values = Vec::new();
+        for batch in reader {
+            let batch = batch.unwrap();
+            let decoded =
+                Labels::from_map_array::<Xxh3>(batch.column(0).as_map(), &labels.label_names)
+                    .unwrap();
+            assert_eq!(decoded.label_names, labels.label_names);
+            assert_eq!(decoded.label_name_hash, labels.label_name_hash);
+            label_values.extend(decoded.label_values);
+        }
+        assert_eq!(label_values, labels.label_values);
+    }
+
+    #[test]
+    fn from_map_array_unknown_key() {
+        let mut map_builder = MapBuilder::new(None, StringBuilder::new(), StringBuilder::new());
+        map_builder.keys().append_value("job");
+        map_builder.values().append_value("node");
+        map_builder.append(true).unwrap();
+        let map = map_builder.finish();
+
+        let err = Labels::from_map_array::<Xxh3>(&map, &["env".to_owned()])
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "unknown label \"job\"");
+        assert!(
+            Labels::from_map_array::<Xxh3>(&map, &["job".to_owned(), "job".to_owned()]).is_err()
+        );
+    }
+
+    #[test]
+    fn read_parquet_files_filters() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("job", DataType::Utf8, true),
+            Field::new(
+                "env",
+                DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)),
+                true,
+            ),
+            Field::new("shard", DataType::Int64, true),
+            Field::new("greptime_value", DataType::Float64, false),
+        ]));
+        let jobs: Vec<String> = (0..300).map(|idx| format!("job-{}", idx % 3)).collect();
+        let env: DictionaryArray<Int32Type> = (0..300)
+            .map(|idx| Some(if idx % 2 == 0 { "prod" } else { "dev" }))
+            .collect();
+        let shards: Vec<Option<i64>> = (0..300)
+            .map(|idx| (idx % 5 != 0).then_some(idx % 5))
+            .collect();
+        let values: Vec<f64> = (0..300).map(|idx| (idx % 4) as f64 + 0.5).collect();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(jobs)),
+                Arc::new(env),
+                Arc::new(Int64Array::from(shards)),
+                Arc::new(Float64Array::from(values)),
+            ],
+        )
+        .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("tsid-bench-filter-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("labels.parquet");
+        let mut writer = ArrowWriter::try_new(File::create(&input).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        let input = input.to_str().unwrap();
+        let output = dir.join("labels.csv");
+        let output = output.to_str().unwrap();
+
+        let extract = |filters: &[(&str, &str)]| {
+            let config = ReadParquetConfig {
+                filters: filters
+                    .iter()
+                    .map(|(column, value)| (column.to_string(), value.to_string()))
+                    .collect(),
+                ..Default::default()
+            };
+            let summary = read_parquet_files_config(input, output, config).unwrap();
+            assert_eq!(summary.rows_scanned, 300);
+            summary.distinct_rows
+        };
+        assert_eq!(extract(&[]), 300);
+        assert_eq!(extract(&[("job", "job-1")]), 100);
+        assert_eq!(extract(&[("job", "job-1"), ("env", "prod")]), 50);
+        assert_eq!(extract(&[("shard", "3")]), 60);
+        assert_eq!(extract(&[("job", "job-1"), ("env", "staging")]), 0);
+        // Excluded from the output, but still available to filter on
+        assert_eq!(extract(&[("greptime_value", "2.5")]), 75);
+        assert_eq!(extract(&[("greptime_value", "2.5"), ("env", "prod")]), 75);
+        assert!(
+            !std::fs::read_to_string(output)
+                .unwrap()
+                .contains("greptime_value")
+        );
+
+        let config = ReadParquetConfig {
+            filters: vec![("missing".to_owned(), "x".to_owned())],
+            ..Default::default()
+        };
+        assert!(read_parquet_files_config(input, output, config).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
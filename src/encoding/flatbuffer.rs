@@ -3,9 +3,11 @@
 //! Uses the FlatBuffers schema defined in `schemas/label_row.fbs`.
 //! Provides efficient zero-copy access during reads.
 
-use flatbuffers::FlatBufferBuilder;
+use std::cell::RefCell;
 
-use super::RowEncoder;
+use flatbuffers::{FlatBufferBuilder, InvalidFlatbuffer};
+
+use super::{DecodeError, RowEncoder};
 use crate::generated::{
     LabelAndColumnId, LabelAndColumnIdArgs, PrimaryKeys, PrimaryKeysArgs,
     finish_primary_keys_buffer, root_as_primary_keys,
@@ -15,60 +17,178 @@ use crate::generated::{
 #[derive(Debug, Clone, Copy, Default)]
 pub struct FlatBufferEncoder;
 
+impl FlatBufferEncoder {
+    /// Like [`decode`](RowEncoder::decode), but returns the values as slices
+    /// of `data` instead of copying them into `String`s.
+    ///
+    /// The values borrow `data`, so they cannot outlive it:
+    ///
+    /// ```compile_fail
+    /// use tsid_bench::FlatBufferEncoder;
+    /// use tsid_bench::encoding::encode_to_vec;
+    ///
+    /// let row = {
+    ///     let data = encode_to_vec(&FlatBufferEncoder, &[(0, "up".to_owned())]);
+    ///     FlatBufferEncoder.decode_zero_copy(&data).unwrap()
+    /// };
+    /// assert_eq!(row, [(0, "up")]);
+    /// ```
+    pub fn decode_zero_copy<'a>(&self, data: &'a [u8]) -> Result<Vec<(u32, &'a str)>, DecodeError> {
+        Ok(row_entries(data)?.collect())
+    }
+}
+
 impl RowEncoder for FlatBufferEncoder {
     fn name(&self) -> &'static str {
         "flatbuffer"
     }
 
+    fn format_version(&self) -> u32 {
+        1
+    }
+
     fn encode(&self, buffer: &mut Vec<u8>, row: &[(u32, String)]) {
         let mut fb_builder = FlatBufferBuilder::new();
-        let label_entries: Vec<_> = row
-            .iter()
-            .map(|(col_idx, value)| {
-                let label_value = fb_builder.create_string(value);
-                LabelAndColumnId::create(
-                    &mut fb_builder,
-                    &LabelAndColumnIdArgs {
-                        column_id: *col_idx,
-                        label_value: Some(label_value),
-                    },
-                )
-            })
-            .collect();
+        build_row(&mut fb_builder, row);
+        buffer.extend_from_slice(fb_builder.finished_data());
+    }
 
-        let label_values_vec = fb_builder.create_vector(&label_entries);
-        let primary_keys = PrimaryKeys::create(
-            &mut fb_builder,
-            &PrimaryKeysArgs {
-                label_values: Some(label_values_vec),
-            },
-        );
-        finish_primary_keys_buffer(&mut fb_builder, primary_keys);
+    fn encode_borrowed(&self, buffer: &mut Vec<u8>, row: &[(u32, &str)]) {
+        let mut fb_builder = FlatBufferBuilder::new();
+        build_row(&mut fb_builder, row);
         buffer.extend_from_slice(fb_builder.finished_data());
     }
 
-    fn decode(&self, data: &[u8]) -> Vec<(u32, String)> {
-        let primary_keys = root_as_primary_keys(data).expect("Failed to decode FlatBuffer");
-        let label_values = primary_keys
-            .label_values()
-            .expect("label_values should be present");
+    fn decode(&self, data: &[u8]) -> Result<Vec<(u32, String)>, DecodeError> {
+        decode_row(data)
+    }
+}
 
-        label_values
-            .iter()
-            .map(|entry| {
-                (
-                    entry.column_id(),
-                    entry.label_value().unwrap_or("").to_string(),
-                )
-            })
-            .collect()
+thread_local! {
+    /// Builder reused by [`FlatBufferEncoderThreadLocal`] on each thread.
+    ///
+    /// The `'static` lifetime is not a hack around the borrow checker: the
+    /// lifetime parameter of `FlatBufferBuilder` only bounds borrowed
+    /// allocators, and the default allocator owns its buffer. What must not
+    /// leak between rows is the builder *state*, so every use starts with
+    /// `reset()`, which clears the buffer while keeping its capacity. This
+    /// avoids both a fresh allocation per row and any `unsafe` transmute of
+    /// the builder's lifetime.
+    static BUILDER: RefCell<FlatBufferBuilder<'static>> =
+        RefCell::new(FlatBufferBuilder::with_capacity(1024));
+}
+
+/// FlatBuffer encoder that reuses one builder per thread instead of
+/// allocating a new one per row.
+///
+/// Produces the same bytes as [`FlatBufferEncoder`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlatBufferEncoderThreadLocal;
+
+impl RowEncoder for FlatBufferEncoderThreadLocal {
+    fn name(&self) -> &'static str {
+        "flatbuffer_thread_local"
+    }
+
+    fn format_version(&self) -> u32 {
+        1
+    }
+
+    fn encode(&self, buffer: &mut Vec<u8>, row: &[(u32, String)]) {
+        BUILDER.with(|fb_builder| {
+            let mut fb_builder = fb_builder.borrow_mut();
+            fb_builder.reset();
+            build_row(&mut fb_builder, row);
+            buffer.extend_from_slice(fb_builder.finished_data());
+        });
+    }
+
+    fn encode_borrowed(&self, buffer: &mut Vec<u8>, row: &[(u32, &str)]) {
+        BUILDER.with(|fb_builder| {
+            let mut fb_builder = fb_builder.borrow_mut();
+            fb_builder.reset();
+            build_row(&mut fb_builder, row);
+            buffer.extend_from_slice(fb_builder.finished_data());
+        });
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<(u32, String)>, DecodeError> {
+        decode_row(data)
+    }
+}
+
+/// Builds and finishes a `PrimaryKeys` table for `row` in `fb_builder`.
+fn build_row<V: AsRef<str>>(fb_builder: &mut FlatBufferBuilder, row: &[(u32, V)]) {
+    let label_entries: Vec<_> = row
+        .iter()
+        .map(|(col_idx, value)| {
+            let label_value = fb_builder.create_string(value.as_ref());
+            LabelAndColumnId::create(
+                fb_builder,
+                &LabelAndColumnIdArgs {
+                    column_id: *col_idx,
+                    label_value: Some(label_value),
+                },
+            )
+        })
+        .collect();
+
+    let label_values_vec = fb_builder.create_vector(&label_entries);
+    let primary_keys = PrimaryKeys::create(
+        fb_builder,
+        &PrimaryKeysArgs {
+            label_values: Some(label_values_vec),
+        },
+    );
+    finish_primary_keys_buffer(fb_builder, primary_keys);
+}
+
+fn decode_row(data: &[u8]) -> Result<Vec<(u32, String)>, DecodeError> {
+    let row = row_entries(data)?
+        .map(|(column_id, value)| (column_id, value.to_string()))
+        .collect();
+    Ok(row)
+}
+
+/// Iterates over the column ids and values of the row in `data`, borrowing
+/// the values from it.
+///
+/// The buffer is verified up front, so iterating cannot fail.
+fn row_entries(data: &[u8]) -> Result<impl Iterator<Item = (u32, &str)>, DecodeError> {
+    let primary_keys = root_as_primary_keys(data).map_err(|err| verify_error(data, err))?;
+    let label_values = primary_keys
+        .label_values()
+        .ok_or(DecodeError::UnexpectedEof)?;
+
+    Ok(label_values
+        .iter()
+        .map(|entry| (entry.column_id(), entry.label_value().unwrap_or(""))))
+}
+
+/// Maps a verifier error to a [`DecodeError`]: invalid strings to
+/// [`DecodeError::InvalidUtf8`], exceeded verifier limits to
+/// [`DecodeError::Overflow`] and everything else to
+/// [`DecodeError::UnexpectedEof`].
+fn verify_error(data: &[u8], err: InvalidFlatbuffer) -> DecodeError {
+    match err {
+        InvalidFlatbuffer::Utf8Error { range, .. } => {
+            match String::from_utf8(data[range].to_vec()) {
+                Err(err) => DecodeError::InvalidUtf8(err),
+                Ok(_) => unreachable!("verifier rejected a valid UTF-8 string"),
+            }
+        }
+        InvalidFlatbuffer::TooManyTables
+        | InvalidFlatbuffer::ApparentSizeTooLarge
+        | InvalidFlatbuffer::DepthLimitReached => DecodeError::Overflow,
+        _ => DecodeError::UnexpectedEof,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::encoding::tests as test_helpers;
+    use crate::encoding::{encode_to_vec, tests as test_helpers};
+    use crate::generated::root_as_primary_keys_with_opts;
 
     #[test]
     fn roundtrip() {
@@ -89,4 +209,89 @@ mod tests {
     fn roundtrip_large_col_ids() {
         test_helpers::test_roundtrip_large_col_ids(&FlatBufferEncoder);
     }
+
+    #[test]
+    fn thread_local_roundtrip() {
+        test_helpers::test_roundtrip(&FlatBufferEncoderThreadLocal);
+        test_helpers::test_roundtrip_empty(&FlatBufferEncoderThreadLocal);
+        test_helpers::test_roundtrip_special_chars(&FlatBufferEncoderThreadLocal);
+        test_helpers::test_roundtrip_large_col_ids(&FlatBufferEncoderThreadLocal);
+    }
+
+    #[test]
+    fn thread_local_matches_allocating() {
+        let rows = [
+            vec![(0, "node".to_owned()), (1, "a:9100".to_owned())],
+            vec![(2, "x".repeat(2048))],
+            vec![],
+            vec![(0, "api".to_owned())],
+        ];
+        for row in &rows {
+            assert_eq!(
+                encode_to_vec(&FlatBufferEncoderThreadLocal, row),
+                encode_to_vec(&FlatBufferEncoder, row)
+            );
+        }
+    }
+
+    #[test]
+    fn decode_zero_copy_borrows_data() {
+        let row = vec![
+            (0, "node".to_owned()),
+            (7, String::new()),
+            (u32::MAX, "ünïcödé".to_owned()),
+        ];
+        let data = encode_to_vec(&FlatBufferEncoder, &row);
+
+        let decoded = FlatBufferEncoder.decode_zero_copy(&data).unwrap();
+        let owned: Vec<(u32, String)> = decoded
+            .iter()
+            .map(|(column_id, value)| (*column_id, value.to_string()))
+            .collect();
+        assert_eq!(owned, FlatBufferEncoder.decode(&data).unwrap());
+        assert_eq!(owned, row);
+        // Every value points into `data` rather than into a copy
+        let range = data.as_ptr_range();
+        for (_, value) in &decoded {
+            assert!(range.contains(&value.as_ptr()) || value.is_empty());
+        }
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let data = encode_to_vec(&FlatBufferEncoder, &[(1, "value".to_owned())]);
+        for len in [0, 3, 4, data.len() / 2] {
+            assert_eq!(
+                FlatBufferEncoder.decode(&data[..len]),
+                Err(DecodeError::UnexpectedEof),
+                "truncated to {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn decode_invalid_utf8() {
+        let mut data = encode_to_vec(&FlatBufferEncoder, &[(1, "value".to_owned())]);
+        let value_start = data.windows(5).position(|w| w == b"value").unwrap();
+        data[value_start] = 0xff;
+        let err = FlatBufferEncoder.decode(&data).unwrap_err();
+        assert!(matches!(err, DecodeError::InvalidUtf8(_)), "{err:?}");
+        let err = FlatBufferEncoder.decode_zero_copy(&data).unwrap_err();
+        assert!(matches!(err, DecodeError::InvalidUtf8(_)), "{err:?}");
+    }
+
+    #[test]
+    fn decode_overflow() {
+        // One table per entry plus the root exceeds a limit of 4 tables, like
+        // a million entries exceed the verifier's default limit.
+        let row: Vec<(u32, String)> = (0..4).map(|idx| (idx, String::new())).collect();
+        let data = encode_to_vec(&FlatBufferEncoder, &row);
+        let options = flatbuffers::VerifierOptions {
+            max_tables: 4,
+            ..Default::default()
+        };
+        let err = root_as_primary_keys_with_opts(&options, &data).unwrap_err();
+        assert_eq!(verify_error(&data, err), DecodeError::Overflow);
+    }
 }
@@ -3,8 +3,11 @@
 //! Format: `[num_entries: u32][column_id: u32][len: u32][bytes]...`
 //!
 //! Simple and fast encoding using fixed-size 4-byte headers for all integers.
+//! [`LengthPrefixedEncoder`] writes them little-endian and
+//! [`LengthPrefixedEncoderBE`] big-endian (network byte order).
+//! [`LengthPrefixedEncoderAligned`] pads each row to a fixed alignment.
 
-use super::RowEncoder;
+use super::{DecodeError, RowEncoder};
 
 /// Length-prefixed encoder using fixed 4-byte integers.
 #[derive(Debug, Clone, Copy, Default)]
@@ -15,32 +18,230 @@ impl RowEncoder for LengthPrefixedEncoder {
         "length_prefixed"
     }
 
+    fn format_version(&self) -> u32 {
+        1
+    }
+
     fn encode(&self, buffer: &mut Vec<u8>, row: &[(u32, String)]) {
-        buffer.extend_from_slice(&(row.len() as u32).to_le_bytes());
-        for (col_id, value) in row {
-            buffer.extend_from_slice(&col_id.to_le_bytes());
-            buffer.extend_from_slice(&(value.len() as u32).to_le_bytes());
-            buffer.extend_from_slice(value.as_bytes());
-        }
+        encode_with(buffer, row, u32::to_le_bytes);
+    }
+
+    fn encode_borrowed(&self, buffer: &mut Vec<u8>, row: &[(u32, &str)]) {
+        encode_with(buffer, row, u32::to_le_bytes);
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<(u32, String)>, DecodeError> {
+        decode_with(data, u32::from_le_bytes)
+    }
+
+    fn decode_column_ids_only(&self, data: &[u8]) -> Result<Vec<u32>, DecodeError> {
+        column_ids_with(data, u32::from_le_bytes)
+    }
+}
+
+/// Length-prefixed encoder using fixed 4-byte big-endian integers, for
+/// interoperability with network protocols and Java's `DataOutput`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LengthPrefixedEncoderBE;
+
+impl RowEncoder for LengthPrefixedEncoderBE {
+    fn name(&self) -> &'static str {
+        "length_prefixed_be"
+    }
+
+    fn format_version(&self) -> u32 {
+        1
+    }
+
+    fn encode(&self, buffer: &mut Vec<u8>, row: &[(u32, String)]) {
+        encode_with(buffer, row, u32::to_be_bytes);
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<(u32, String)>, DecodeError> {
+        decode_with(data, u32::from_be_bytes)
+    }
+
+    fn decode_column_ids_only(&self, data: &[u8]) -> Result<Vec<u32>, DecodeError> {
+        column_ids_with(data, u32::from_be_bytes)
+    }
+}
+
+/// Length-prefixed encoder padding each row to a multiple of `alignment`
+/// bytes, so that rows stored back to back start on aligned offsets and can
+/// be processed in whole SIMD lanes.
+///
+/// Format: `[encoded_len: u32][length_prefixed row][zero padding]`, with the
+/// row and its length in little-endian as written by
+/// [`LengthPrefixedEncoder`]. `encoded_len` excludes the 4-byte header and
+/// the padding.
+#[derive(Debug, Clone, Copy)]
+pub struct LengthPrefixedEncoderAligned {
+    alignment: usize,
+}
+
+impl LengthPrefixedEncoderAligned {
+    /// Creates an encoder padding rows to `alignment` bytes, typically 2, 4,
+    /// 8, 16 or 32.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `alignment` is not a power of two.
+    pub fn new(alignment: usize) -> Self {
+        assert!(
+            alignment.is_power_of_two(),
+            "alignment must be a power of two, got {alignment}"
+        );
+        Self { alignment }
+    }
+
+    pub fn alignment(&self) -> usize {
+        self.alignment
+    }
+
+    /// Returns the encoded row without its header and padding.
+    fn payload<'a>(&self, data: &'a [u8]) -> Result<&'a [u8], DecodeError> {
+        let len = read_u32(data, 0, u32::from_le_bytes)? as usize;
+        data.get(4..4 + len).ok_or(DecodeError::UnexpectedEof)
+    }
+}
+
+impl RowEncoder for LengthPrefixedEncoderAligned {
+    fn name(&self) -> &'static str {
+        "length_prefixed_aligned"
+    }
+
+    fn format_version(&self) -> u32 {
+        1
+    }
+
+    /// Pads relative to the start of the row, so rows appended to a buffer
+    /// stay aligned relative to the start of the buffer.
+    fn encode(&self, buffer: &mut Vec<u8>, row: &[(u32, String)]) {
+        let start = buffer.len();
+        buffer.extend_from_slice(&[0; 4]);
+        encode_with(buffer, row, u32::to_le_bytes);
+        let encoded_len = buffer.len() - start - 4;
+        buffer[start..start + 4].copy_from_slice(&(encoded_len as u32).to_le_bytes());
+        let padded_len = (buffer.len() - start).next_multiple_of(self.alignment);
+        buffer.resize(start + padded_len, 0);
     }
 
-    fn decode(&self, data: &[u8]) -> Vec<(u32, String)> {
-        let mut result = Vec::new();
-        let mut offset = 0;
+    fn decode(&self, data: &[u8]) -> Result<Vec<(u32, String)>, DecodeError> {
+        decode_with(self.payload(data)?, u32::from_le_bytes)
+    }
+
+    fn decode_column_ids_only(&self, data: &[u8]) -> Result<Vec<u32>, DecodeError> {
+        column_ids_with(self.payload(data)?, u32::from_le_bytes)
+    }
+}
 
-        let num_entries = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+fn encode_with<V: AsRef<str>>(
+    buffer: &mut Vec<u8>,
+    row: &[(u32, V)],
+    to_bytes: fn(u32) -> [u8; 4],
+) {
+    buffer.extend_from_slice(&to_bytes(row.len() as u32));
+    for (col_id, value) in row {
+        let value = value.as_ref();
+        buffer.extend_from_slice(&to_bytes(*col_id));
+        buffer.extend_from_slice(&to_bytes(value.len() as u32));
+        buffer.extend_from_slice(value.as_bytes());
+    }
+}
+
+fn decode_with(
+    data: &[u8],
+    from_bytes: fn([u8; 4]) -> u32,
+) -> Result<Vec<(u32, String)>, DecodeError> {
+    let mut result = Vec::new();
+    let mut offset = 0;
+
+    let num_entries = read_u32(data, offset, from_bytes)? as usize;
+    offset += 4;
+
+    for _ in 0..num_entries {
+        let col_id = read_u32(data, offset, from_bytes)?;
         offset += 4;
+        let len = read_u32(data, offset, from_bytes)? as usize;
+        offset += 4;
+        let value = data
+            .get(offset..offset + len)
+            .ok_or(DecodeError::UnexpectedEof)?;
+        offset += len;
+        result.push((col_id, String::from_utf8(value.to_vec())?));
+    }
+    Ok(result)
+}
+
+/// Reads the 4-byte integer at `offset`, failing if `data` ends before it.
+fn read_u32(
+    data: &[u8],
+    offset: usize,
+    from_bytes: fn([u8; 4]) -> u32,
+) -> Result<u32, DecodeError> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or(DecodeError::UnexpectedEof)?;
+    Ok(from_bytes(bytes.try_into().unwrap()))
+}
+
+/// Reads each column id and skips the length and value bytes after it.
+fn column_ids_with(data: &[u8], from_bytes: fn([u8; 4]) -> u32) -> Result<Vec<u32>, DecodeError> {
+    let num_entries = read_u32(data, 0, from_bytes)? as usize;
+    // The count is untrusted, and every entry takes at least 8 bytes
+    let mut result = Vec::with_capacity(num_entries.min(data.len() / 8));
+    let mut offset = 4;
+    for _ in 0..num_entries {
+        result.push(read_u32(data, offset, from_bytes)?);
+        offset += 8 + read_u32(data, offset + 4, from_bytes)? as usize;
+        if offset > data.len() {
+            return Err(DecodeError::UnexpectedEof);
+        }
+    }
+    Ok(result)
+}
 
-        for _ in 0..num_entries {
-            let col_id = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
-            offset += 4;
-            let len = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
-            offset += 4;
-            let value = String::from_utf8(data[offset..offset + len].to_vec()).unwrap();
-            offset += len;
-            result.push((col_id, value));
+#[cfg(feature = "unsafe-decode")]
+impl LengthPrefixedEncoder {
+    /// Decodes a row without bounds or UTF-8 checks.
+    ///
+    /// # Safety
+    ///
+    /// `data` must be the unmodified output of a single `encode` call on a
+    /// `LengthPrefixedEncoder`. In particular every header and value must lie
+    /// within `data`, and every value must be valid UTF-8.
+    pub unsafe fn decode_unchecked(&self, data: &[u8]) -> Vec<(u32, String)> {
+        let mut ptr = data.as_ptr();
+
+        // SAFETY: the caller guarantees `data` came from `encode`, so every read
+        // below stays within `data` and the value bytes are valid UTF-8.
+        unsafe {
+            let num_entries = read_u32_le(&mut ptr) as usize;
+            let mut result = Vec::with_capacity(num_entries);
+            for _ in 0..num_entries {
+                let col_id = read_u32_le(&mut ptr);
+                let len = read_u32_le(&mut ptr) as usize;
+                let value = std::slice::from_raw_parts(ptr, len).to_vec();
+                ptr = ptr.add(len);
+                result.push((col_id, String::from_utf8_unchecked(value)));
+            }
+            result
         }
-        result
+    }
+}
+
+/// Reads a little-endian u32 and advances `ptr` past it.
+///
+/// # Safety
+///
+/// `ptr` must point to at least 4 readable bytes.
+#[cfg(feature = "unsafe-decode")]
+unsafe fn read_u32_le(ptr: &mut *const u8) -> u32 {
+    // SAFETY: upheld by the caller.
+    unsafe {
+        let value = u32::from_le(std::ptr::read_unaligned(ptr.cast::<u32>()));
+        *ptr = ptr.add(4);
+        value
     }
 }
 
@@ -68,4 +269,137 @@ mod tests {
     fn roundtrip_large_col_ids() {
         test_helpers::test_roundtrip_large_col_ids(&LengthPrefixedEncoder);
     }
+
+    #[test]
+    fn roundtrip_be() {
+        test_helpers::test_roundtrip(&LengthPrefixedEncoderBE);
+        test_helpers::test_roundtrip_empty(&LengthPrefixedEncoderBE);
+        test_helpers::test_roundtrip_special_chars(&LengthPrefixedEncoderBE);
+        test_helpers::test_roundtrip_large_col_ids(&LengthPrefixedEncoderBE);
+    }
+
+    #[test]
+    fn byte_order() {
+        let row = [(0x01020304, String::new())];
+        let le = crate::encoding::encode_to_vec(&LengthPrefixedEncoder, &row);
+        let be = crate::encoding::encode_to_vec(&LengthPrefixedEncoderBE, &row);
+
+        // Header: one entry, then the column id and an empty value.
+        assert_eq!(le[..4], [0x01, 0x00, 0x00, 0x00]);
+        assert_eq!(be[..4], [0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(le[4..8], [0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(be[4..8], [0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(le.len(), be.len());
+    }
+
+    #[test]
+    fn roundtrip_aligned() {
+        for alignment in [1, 2, 4, 8, 16, 32] {
+            let encoder = LengthPrefixedEncoderAligned::new(alignment);
+            test_helpers::test_roundtrip(&encoder);
+            test_helpers::test_roundtrip_empty(&encoder);
+            test_helpers::test_roundtrip_special_chars(&encoder);
+            test_helpers::test_roundtrip_large_col_ids(&encoder);
+        }
+    }
+
+    #[test]
+    fn aligned_padding() {
+        let encoder = LengthPrefixedEncoderAligned::new(16);
+        let row = [(1, "value".to_owned())];
+        let unaligned = crate::encoding::encode_to_vec(&LengthPrefixedEncoder, &row);
+
+        // Appending rows keeps each one on a 16-byte boundary.
+        let mut buffer = Vec::new();
+        encoder.encode(&mut buffer, &row);
+        assert_eq!(buffer.len(), 32);
+        assert_eq!(buffer[..4], (unaligned.len() as u32).to_le_bytes());
+        assert_eq!(buffer[4..4 + unaligned.len()], unaligned);
+        assert!(buffer[4 + unaligned.len()..].iter().all(|&byte| byte == 0));
+        encoder.encode(&mut buffer, &[]);
+        assert_eq!(buffer.len(), 48);
+        assert_eq!(encoder.decode(&buffer[32..]).unwrap(), []);
+        assert_eq!(encoder.decode(&buffer[..32]).unwrap(), row);
+        assert_eq!(encoder.decode_column_ids_only(&buffer).unwrap(), [1]);
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let row = [(1, "value".to_owned()), (2, String::new())];
+        let unpadded_len = crate::encoding::encode_to_vec(&LengthPrefixedEncoder, &row).len();
+        let encoders: [(&dyn RowEncoder, usize); 3] = [
+            (&LengthPrefixedEncoder, unpadded_len),
+            (&LengthPrefixedEncoderBE, unpadded_len),
+            // Only the header and the row itself are needed, not the padding.
+            (&LengthPrefixedEncoderAligned::new(8), 4 + unpadded_len),
+        ];
+        for (encoder, needed) in encoders {
+            let encoded = crate::encoding::encode_to_vec(encoder, &row);
+            for len in 0..needed {
+                assert_eq!(
+                    encoder.decode(&encoded[..len]),
+                    Err(DecodeError::UnexpectedEof),
+                    "{} truncated to {}",
+                    encoder.name(),
+                    len
+                );
+                assert_eq!(
+                    encoder.decode_column_ids_only(&encoded[..len]),
+                    Err(DecodeError::UnexpectedEof),
+                    "{} column ids truncated to {}",
+                    encoder.name(),
+                    len
+                );
+            }
+            assert_eq!(encoder.decode(&encoded[..needed]).unwrap(), row);
+        }
+    }
+
+    #[test]
+    fn decode_invalid_utf8() {
+        let encoders: [&dyn RowEncoder; 3] = [
+            &LengthPrefixedEncoder,
+            &LengthPrefixedEncoderBE,
+            &LengthPrefixedEncoderAligned::new(8),
+        ];
+        for encoder in encoders {
+            let mut encoded = crate::encoding::encode_to_vec(encoder, &[(1, "ok".to_owned())]);
+            let value_start = encoded.windows(2).position(|w| w == b"ok").unwrap();
+            encoded[value_start] = 0xff;
+            let err = encoder.decode(&encoded).unwrap_err();
+            assert!(
+                matches!(err, DecodeError::InvalidUtf8(_)),
+                "{}: {err:?}",
+                encoder.name()
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "alignment must be a power of two")]
+    fn aligned_rejects_non_power_of_two() {
+        LengthPrefixedEncoderAligned::new(12);
+    }
+
+    #[cfg(feature = "unsafe-decode")]
+    #[test]
+    fn decode_unchecked_matches_decode() {
+        let encoder = LengthPrefixedEncoder;
+        let rows: Vec<Vec<(u32, String)>> = vec![
+            vec![],
+            vec![(0, "value_0".to_owned()), (5, "".to_owned())],
+            vec![
+                (3, "unicode: 你好🌍".to_owned()),
+                (u32::MAX, "max".to_owned()),
+            ],
+        ];
+
+        for row in &rows {
+            let encoded = crate::encoding::encode_to_vec(&encoder, row);
+            // SAFETY: `encoded` was produced by `encode` above.
+            let decoded = unsafe { encoder.decode_unchecked(&encoded) };
+            assert_eq!(&decoded, row);
+            assert_eq!(decoded, encoder.decode(&encoded).unwrap());
+        }
+    }
 }
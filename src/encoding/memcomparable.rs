@@ -3,20 +3,75 @@
 //! Uses the memcomparable serialization format which produces byte sequences
 //! that can be compared lexicographically to determine ordering.
 
+use std::fmt;
+
 use memcomparable::{Deserializer, Serializer};
+use serde::de::{self, Visitor};
 use serde::{Deserialize, Serialize};
 
-use super::RowEncoder;
+use super::{DecodeError, RowEncoder};
 
 /// Memcomparable encoder for sortable binary encoding.
 #[derive(Debug, Clone, Copy, Default)]
 pub struct MemcomparableEncoder;
 
+impl MemcomparableEncoder {
+    /// Returns the `[lower, upper)` byte range covering every encoded row that
+    /// starts with the given `(column_id, value)` pairs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `prefix` is empty.
+    pub fn prefix_range(prefix: &[(u32, String)]) -> (Vec<u8>, Vec<u8>) {
+        assert!(!prefix.is_empty(), "prefix must not be empty");
+        let mut lower = Vec::new();
+        MemcomparableEncoder.encode(&mut lower, prefix);
+
+        // Every encoded string ends with a length marker, so the last byte is
+        // never 0xff and the successor is found by a simple increment.
+        let mut upper = lower.clone();
+        while let Some(last) = upper.pop() {
+            if last != u8::MAX {
+                upper.push(last + 1);
+                break;
+            }
+        }
+        (lower, upper)
+    }
+
+    /// Returns the encoding of `col_id` alone, which every encoded row whose
+    /// first column id is `col_id` starts with.
+    ///
+    /// Together with [`MemcomparableEncoder::encode_prefix_end`] this bounds
+    /// those rows in a sorted list of encoded rows, e.g. for
+    /// [`slice::partition_point`].
+    pub fn encode_prefix(col_id: u32) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        col_id.serialize(&mut Serializer::new(&mut buffer)).unwrap();
+        buffer
+    }
+
+    /// Returns the exclusive upper bound of the rows starting with `col_id`,
+    /// the encoding of `col_id + 1`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `col_id` is `u32::MAX`.
+    pub fn encode_prefix_end(col_id: u32) -> Vec<u8> {
+        let next = col_id.checked_add(1).expect("no column id after u32::MAX");
+        Self::encode_prefix(next)
+    }
+}
+
 impl RowEncoder for MemcomparableEncoder {
     fn name(&self) -> &'static str {
         "memcomparable"
     }
 
+    fn format_version(&self) -> u32 {
+        1
+    }
+
     fn encode(&self, buffer: &mut Vec<u8>, row: &[(u32, String)]) {
         let mut serializer = Serializer::new(buffer);
         for (col_id, value) in row {
@@ -25,21 +80,273 @@ impl RowEncoder for MemcomparableEncoder {
         }
     }
 
-    fn decode(&self, data: &[u8]) -> Vec<(u32, String)> {
+    fn encode_borrowed(&self, buffer: &mut Vec<u8>, row: &[(u32, &str)]) {
+        let mut serializer = Serializer::new(buffer);
+        for (col_id, value) in row {
+            col_id.serialize(&mut serializer).unwrap();
+            value.serialize(&mut serializer).unwrap();
+        }
+    }
+
+    /// Serializes entries in column id order without reordering `row`.
+    ///
+    /// Rows are usually already sorted, in which case they are serialized
+    /// directly. Otherwise only an index permutation is sorted, instead of
+    /// moving the entries and their strings around.
+    fn encode_sorted(&self, buffer: &mut Vec<u8>, row: &mut Vec<(u32, String)>) {
+        if row.is_sorted_by_key(|(col_id, _)| *col_id) {
+            self.encode(buffer, row);
+            return;
+        }
+
+        let mut order: Vec<usize> = (0..row.len()).collect();
+        order.sort_by_key(|&idx| row[idx].0);
+        let mut serializer = Serializer::new(buffer);
+        for idx in order {
+            let (col_id, value) = &row[idx];
+            col_id.serialize(&mut serializer).unwrap();
+            value.serialize(&mut serializer).unwrap();
+        }
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<(u32, String)>, DecodeError> {
+        let mut res = vec![];
+        let mut des = Deserializer::new(data);
+        while des.has_remaining() {
+            let column_id = deserialize::<u32>(&mut des)?;
+            let value = deserialize::<RawString>(&mut des)?.into_string()?;
+            res.push((column_id, value));
+        }
+        Ok(res)
+    }
+}
+
+/// Memcomparable encoder that supports null values.
+///
+/// Each value is preceded by a sentinel byte: `0x00` for null, `0x01` followed
+/// by the memcomparable string for non-null values, so nulls sort before all
+/// non-null values of the same column.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemcomparableEncoderNullable;
+
+impl MemcomparableEncoderNullable {
+    /// Decodes a row encoded with [`RowEncoder::encode_nullable`].
+    pub fn decode_nullable(&self, data: &[u8]) -> Result<Vec<(u32, Option<String>)>, DecodeError> {
         let mut res = vec![];
         let mut des = Deserializer::new(data);
         while des.has_remaining() {
-            let column_id = u32::deserialize(&mut des).unwrap();
-            let value: String = String::deserialize(&mut des).unwrap();
+            let column_id = deserialize::<u32>(&mut des)?;
+            let value = deserialize::<Option<RawString>>(&mut des)?
+                .map(RawString::into_string)
+                .transpose()?;
             res.push((column_id, value));
         }
-        res
+        Ok(res)
+    }
+}
+
+impl RowEncoder for MemcomparableEncoderNullable {
+    fn name(&self) -> &'static str {
+        "memcomparable_nullable"
+    }
+
+    fn encode(&self, buffer: &mut Vec<u8>, row: &[(u32, String)]) {
+        let mut serializer = Serializer::new(buffer);
+        for (col_id, value) in row {
+            col_id.serialize(&mut serializer).unwrap();
+            Some(value).serialize(&mut serializer).unwrap();
+        }
+    }
+
+    fn encode_nullable(&self, buffer: &mut Vec<u8>, row: &[(u32, Option<String>)]) {
+        let mut serializer = Serializer::new(buffer);
+        for (col_id, value) in row {
+            col_id.serialize(&mut serializer).unwrap();
+            value.serialize(&mut serializer).unwrap();
+        }
+    }
+
+    /// Fails with [`DecodeError::UnexpectedNull`] if the row contains a null
+    /// value, use [`MemcomparableEncoderNullable::decode_nullable`] instead.
+    fn decode(&self, data: &[u8]) -> Result<Vec<(u32, String)>, DecodeError> {
+        self.decode_nullable(data)?
+            .into_iter()
+            .map(|(col_id, value)| {
+                value
+                    .map(|value| (col_id, value))
+                    .ok_or(DecodeError::UnexpectedNull(col_id))
+            })
+            .collect()
+    }
+}
+
+/// Deserializes a `T`, reporting any failure as truncated data.
+///
+/// Strings are read as [`RawString`], so the deserializer only fails on
+/// missing or malformed bytes.
+fn deserialize<'de, T: Deserialize<'de>>(
+    des: &mut Deserializer<&'de [u8]>,
+) -> Result<T, DecodeError> {
+    T::deserialize(des).map_err(|_| DecodeError::UnexpectedEof)
+}
+
+/// A string value read as bytes, so that invalid UTF-8 is reported as
+/// [`DecodeError::InvalidUtf8`] rather than as a deserializer error.
+///
+/// Memcomparable encodes strings and byte strings identically.
+struct RawString(Vec<u8>);
+
+impl RawString {
+    fn into_string(self) -> Result<String, DecodeError> {
+        Ok(String::from_utf8(self.0)?)
+    }
+}
+
+impl<'de> Deserialize<'de> for RawString {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RawStringVisitor;
+
+        impl Visitor<'_> for RawStringVisitor {
+            type Value = RawString;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a byte string")
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<RawString, E> {
+                Ok(RawString(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<RawString, E> {
+                Ok(RawString(v))
+            }
+        }
+
+        deserializer.deserialize_byte_buf(RawStringVisitor)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::hash::DefaultHasher;
+
     use super::*;
+    use crate::read_labels_from_path;
+
+    /// Asserts that sorting the encoded rows yields the same order as sorting
+    /// the decoded rows.
+    fn assert_order_preserved(rows: &[Vec<(u32, String)>]) {
+        let encoder = MemcomparableEncoder;
+
+        let mut sorted_rows = rows.to_vec();
+        sorted_rows.sort();
+
+        let mut encoded: Vec<Vec<u8>> = rows
+            .iter()
+            .map(|row| crate::encoding::encode_to_vec(&encoder, row))
+            .collect();
+        encoded.sort();
+
+        assert_eq!(encoded.len(), sorted_rows.len());
+        for (encoded_row, expected) in encoded.iter().zip(sorted_rows.iter()) {
+            assert_eq!(&encoder.decode(encoded_row).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn sort_order_preserved_on_labels() {
+        let labels = read_labels_from_path::<DefaultHasher>("./assets/labels.csv.gz").unwrap();
+        let rows: Vec<Vec<(u32, String)>> = labels
+            .label_values
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .enumerate()
+                    .map(|(idx, val)| (idx as u32, val.clone()))
+                    .collect()
+            })
+            .collect();
+
+        assert_order_preserved(&rows);
+    }
+
+    #[test]
+    fn sort_order_preserved_on_special_chars() {
+        let values = [
+            "hello world",
+            "with\ttab",
+            "with\nnewline",
+            "unicode: 你好🌍",
+            "",
+            "\0",
+            "a",
+            "a\0",
+            "aaaaaaaa",
+            "aaaaaaaab",
+        ];
+        let rows: Vec<Vec<(u32, String)>> = [0u32, 1, 127, 128, 16384]
+            .into_iter()
+            .flat_map(|col_id| {
+                values
+                    .iter()
+                    .map(move |val| vec![(col_id, (*val).to_owned())])
+            })
+            .collect();
+
+        assert_order_preserved(&rows);
+    }
+
+    #[test]
+    fn prefix_range_bounds() {
+        let encoder = MemcomparableEncoder;
+        let (lower, upper) = MemcomparableEncoder::prefix_range(&[(0, "b".to_owned())]);
+
+        let inside =
+            crate::encoding::encode_to_vec(&encoder, &[(0, "b".to_owned()), (1, "zzz".to_owned())]);
+        let before = crate::encoding::encode_to_vec(&encoder, &[(0, "a".to_owned())]);
+        let after = crate::encoding::encode_to_vec(&encoder, &[(0, "ba".to_owned())]);
+
+        assert!(lower <= inside && inside < upper);
+        assert!(before < lower);
+        assert!(after >= upper);
+    }
+
+    #[test]
+    fn prefix_binary_search() {
+        let encoder = MemcomparableEncoder;
+        let mut encoded: Vec<Vec<u8>> = [0u32, 1, 2, 3, 255, 256]
+            .into_iter()
+            .flat_map(|col_id| {
+                ["", "a", "b\0", "\u{ff}zz"]
+                    .into_iter()
+                    .map(move |val| vec![(col_id, val.to_owned()), (col_id + 1, "next".to_owned())])
+            })
+            .map(|row| crate::encoding::encode_to_vec(&encoder, &row))
+            .collect();
+        encoded.sort();
+
+        let start_key = MemcomparableEncoder::encode_prefix(2);
+        let end_key = MemcomparableEncoder::encode_prefix_end(2);
+        let start = encoded.partition_point(|row| *row < start_key);
+        let end = encoded.partition_point(|row| *row < end_key);
+
+        let matched: Vec<_> = encoded[start..end]
+            .iter()
+            .map(|row| encoder.decode(row).unwrap())
+            .collect();
+        assert_eq!(matched.len(), 4);
+        assert!(matched.iter().all(|row| row[0].0 == 2));
+        assert!(
+            encoded[..start]
+                .iter()
+                .all(|row| encoder.decode(row).unwrap()[0].0 < 2)
+        );
+        assert!(
+            encoded[end..]
+                .iter()
+                .all(|row| encoder.decode(row).unwrap()[0].0 > 2)
+        );
+    }
 
     #[test]
     fn test_encode() {
@@ -72,4 +379,103 @@ mod tests {
     fn roundtrip_large_col_ids() {
         crate::encoding::tests::test_roundtrip_large_col_ids(&MemcomparableEncoder);
     }
+
+    fn roundtrip_nullable(row: &[(u32, Option<String>)]) {
+        let encoder = MemcomparableEncoderNullable;
+        let mut buffer = Vec::new();
+        encoder.encode_nullable(&mut buffer, row);
+        assert_eq!(encoder.decode_nullable(&buffer).unwrap(), row);
+    }
+
+    #[test]
+    fn nullable_roundtrip() {
+        crate::encoding::tests::test_roundtrip(&MemcomparableEncoderNullable);
+        crate::encoding::tests::test_roundtrip_empty(&MemcomparableEncoderNullable);
+        crate::encoding::tests::test_roundtrip_special_chars(&MemcomparableEncoderNullable);
+
+        roundtrip_nullable(&[(0, None)]);
+        roundtrip_nullable(&[(0, Some(String::new()))]);
+        roundtrip_nullable(&[
+            (0, Some("up".to_owned())),
+            (1, None),
+            (2, Some(String::new())),
+            (3, None),
+        ]);
+    }
+
+    #[test]
+    fn decode_null_as_string() {
+        let encoder = MemcomparableEncoderNullable;
+        let mut buffer = Vec::new();
+        encoder.encode_nullable(&mut buffer, &[(0, Some("up".to_owned())), (7, None)]);
+        let decoder: &dyn RowEncoder = &encoder;
+        assert_eq!(decoder.decode(&buffer), Err(DecodeError::UnexpectedNull(7)));
+        assert_eq!(
+            DecodeError::UnexpectedNull(7).to_string(),
+            "unexpected null value in column 7"
+        );
+    }
+
+    #[test]
+    fn nullable_sentinel() {
+        let encoder = MemcomparableEncoderNullable;
+        let mut null = Vec::new();
+        encoder.encode_nullable(&mut null, &[(0, None)]);
+        let mut empty = Vec::new();
+        encoder.encode_nullable(&mut empty, &[(0, Some(String::new()))]);
+
+        let mut col_id = Vec::new();
+        0u32.serialize(&mut Serializer::new(&mut col_id)).unwrap();
+
+        assert_eq!(null, [col_id.as_slice(), &[0x00]].concat());
+        assert!(empty.starts_with(&[col_id.as_slice(), &[0x01]].concat()));
+        // Nulls sort before every non-null value, including the empty string.
+        assert!(null < empty);
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let row = [(1, "value".to_owned()), (300, "a".repeat(20))];
+        let encoded = crate::encoding::encode_to_vec(&MemcomparableEncoder, &row);
+        let mut nullable = Vec::new();
+        MemcomparableEncoderNullable
+            .encode_nullable(&mut nullable, &[(1, None), (2, Some("value".to_owned()))]);
+        // Rows have no header, so truncating between entries yields a valid row.
+        let first_len = crate::encoding::encode_to_vec(&MemcomparableEncoder, &row[..1]).len();
+        let first_nullable_len = 4 + 1;
+
+        for len in (1..encoded.len()).filter(|&len| len != first_len) {
+            assert_eq!(
+                MemcomparableEncoder.decode(&encoded[..len]),
+                Err(DecodeError::UnexpectedEof),
+                "truncated to {}",
+                len
+            );
+        }
+        for len in (1..nullable.len()).filter(|&len| len != first_nullable_len) {
+            assert_eq!(
+                MemcomparableEncoderNullable.decode_nullable(&nullable[..len]),
+                Err(DecodeError::UnexpectedEof),
+                "truncated to {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn decode_invalid_utf8() {
+        let mut encoded =
+            crate::encoding::encode_to_vec(&MemcomparableEncoder, &[(1, "ok".to_owned())]);
+        let value_start = encoded.windows(2).position(|w| w == b"ok").unwrap();
+        encoded[value_start] = 0xff;
+        let err = MemcomparableEncoder.decode(&encoded).unwrap_err();
+        assert!(matches!(err, DecodeError::InvalidUtf8(_)), "{err:?}");
+
+        let mut encoded =
+            crate::encoding::encode_to_vec(&MemcomparableEncoderNullable, &[(1, "ok".to_owned())]);
+        let value_start = encoded.windows(2).position(|w| w == b"ok").unwrap();
+        encoded[value_start] = 0xff;
+        let err = MemcomparableEncoderNullable.decode(&encoded).unwrap_err();
+        assert!(matches!(err, DecodeError::InvalidUtf8(_)), "{err:?}");
+    }
 }
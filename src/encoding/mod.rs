@@ -3,15 +3,88 @@
 //! Each encoder implements the `RowEncoder` trait which provides a unified interface
 //! for encoding and decoding `(column_id, value)` pairs.
 
+use sha2::{Digest, Sha256};
+
 mod flatbuffer;
 mod length_prefixed;
 mod memcomparable;
+mod schema_aware;
 mod varint;
 
-pub use flatbuffer::FlatBufferEncoder;
-pub use length_prefixed::LengthPrefixedEncoder;
-pub use memcomparable::MemcomparableEncoder;
-pub use varint::VarintEncoder;
+pub use flatbuffer::{FlatBufferEncoder, FlatBufferEncoderThreadLocal};
+pub use length_prefixed::{
+    LengthPrefixedEncoder, LengthPrefixedEncoderAligned, LengthPrefixedEncoderBE,
+};
+pub use memcomparable::{MemcomparableEncoder, MemcomparableEncoderNullable};
+pub use schema_aware::SchemaAwareEncoder;
+pub use varint::{VarintEncoder, encode_varints_batch, encode_varints_batch_scalar};
+
+/// Errors returned by encoders that write into caller-provided memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    /// The output slice is shorter than the `needed` bytes of the encoding.
+    BufferTooSmall { needed: usize },
+}
+
+impl std::fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncodeError::BufferTooSmall { needed } => {
+                write!(f, "buffer too small, {} bytes needed", needed)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+/// Errors returned when decoding a malformed or truncated row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The data ends before the row does.
+    UnexpectedEof,
+    /// A value is not valid UTF-8.
+    InvalidUtf8(std::string::FromUtf8Error),
+    /// An integer does not fit in its type.
+    Overflow,
+    /// A column id has no label name.
+    UnknownColumn(u32),
+    /// A column holds a null value where the decoded row cannot represent
+    /// one, e.g. in [`RowEncoder::decode`] of a nullable encoder.
+    UnexpectedNull(u32),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnexpectedEof => write!(f, "unexpected end of row"),
+            DecodeError::InvalidUtf8(err) => write!(f, "invalid UTF-8 value: {}", err),
+            DecodeError::Overflow => write!(f, "integer overflow"),
+            DecodeError::UnknownColumn(col_id) => write!(f, "unknown column id {}", col_id),
+            DecodeError::UnexpectedNull(col_id) => {
+                write!(f, "unexpected null value in column {}", col_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecodeError::InvalidUtf8(err) => Some(err),
+            DecodeError::UnexpectedEof
+            | DecodeError::Overflow
+            | DecodeError::UnknownColumn(_)
+            | DecodeError::UnexpectedNull(_) => None,
+        }
+    }
+}
+
+impl From<std::string::FromUtf8Error> for DecodeError {
+    fn from(err: std::string::FromUtf8Error) -> Self {
+        DecodeError::InvalidUtf8(err)
+    }
+}
 
 /// A trait for encoding and decoding rows of `(column_id, value)` pairs.
 ///
@@ -21,29 +94,374 @@ pub trait RowEncoder {
     /// Returns the name of the encoding scheme.
     fn name(&self) -> &'static str;
 
+    /// Returns the version of the encoded format.
+    ///
+    /// Must be bumped whenever the output of [`encode`](RowEncoder::encode)
+    /// changes, so that data written by an older version is not misread.
+    fn format_version(&self) -> u32 {
+        1
+    }
+
+    /// Returns the name and format version, identifying the encoding of
+    /// stored data.
+    fn name_and_version(&self) -> (&'static str, u32) {
+        (self.name(), self.format_version())
+    }
+
+    /// Returns a compact identifier of the encoding scheme: the first 8 bytes
+    /// of the SHA-256 digest of [`name`](RowEncoder::name).
+    fn format_id(&self) -> [u8; 8] {
+        let digest = Sha256::digest(self.name().as_bytes());
+        digest[..8].try_into().unwrap()
+    }
+
     /// Encodes a row of `(column_id, value)` pairs into the buffer.
     ///
     /// The buffer is not cleared before encoding, allowing for reuse.
     /// Callers should clear the buffer if needed.
     fn encode(&self, buffer: &mut Vec<u8>, row: &[(u32, String)]);
 
+    /// Encodes a row with its entries in ascending column id order, as
+    /// required by decoders that binary search on column ids.
+    ///
+    /// The default sorts `row` in place, keeping entries with equal ids in
+    /// their original order, then calls [`encode`](RowEncoder::encode).
+    /// Implementations may instead emit entries in sorted order without
+    /// reordering `row`, so callers must not rely on its order afterwards.
+    fn encode_sorted(&self, buffer: &mut Vec<u8>, row: &mut Vec<(u32, String)>) {
+        row.sort_by_key(|(col_id, _)| *col_id);
+        self.encode(buffer, row);
+    }
+
+    /// Encodes a row whose values may be null.
+    ///
+    /// # Panics
+    ///
+    /// The default implementation panics if any value is `None`, since most
+    /// encodings cannot represent nulls.
+    fn encode_nullable(&self, buffer: &mut Vec<u8>, row: &[(u32, Option<String>)]) {
+        let row: Vec<(u32, String)> = row
+            .iter()
+            .map(|(col_id, value)| {
+                let value = value.clone().unwrap_or_else(|| {
+                    panic!("{} encoder does not support null values", self.name())
+                });
+                (*col_id, value)
+            })
+            .collect();
+        self.encode(buffer, &row);
+    }
+
+    /// Encodes a row whose values are borrowed, e.g. from the pool of
+    /// [`InternedLabels`](crate::InternedLabels), producing the same bytes as
+    /// [`encode`](RowEncoder::encode).
+    ///
+    /// The default copies the values into an owned row; encoders that only
+    /// read the value bytes override it to encode without copying.
+    fn encode_borrowed(&self, buffer: &mut Vec<u8>, row: &[(u32, &str)]) {
+        let row: Vec<(u32, String)> = row
+            .iter()
+            .map(|&(col_id, value)| (col_id, value.to_owned()))
+            .collect();
+        self.encode(buffer, &row);
+    }
+
     /// Decodes a row from the given data.
     ///
-    /// Returns a vector of `(column_id, value)` pairs.
-    fn decode(&self, data: &[u8]) -> Vec<(u32, String)>;
+    /// Returns a vector of `(column_id, value)` pairs, or an error if `data`
+    /// is not a complete row written by [`encode`](RowEncoder::encode).
+    fn decode(&self, data: &[u8]) -> Result<Vec<(u32, String)>, DecodeError>;
+
+    /// Decodes only the column ids of a row, in encoded order.
+    ///
+    /// The default decodes the full row and drops the values; encoders that
+    /// can skip over values without copying them should override it. Fails
+    /// like [`RowEncoder::decode`] if the row is truncated, but overrides need
+    /// not check that skipped values are valid UTF-8.
+    fn decode_column_ids_only(&self, data: &[u8]) -> Result<Vec<u32>, DecodeError> {
+        Ok(self
+            .decode(data)?
+            .into_iter()
+            .map(|(col_id, _)| col_id)
+            .collect())
+    }
 }
 
 /// Helper to encode a row and return as a new Vec.
-pub fn encode_to_vec<E: RowEncoder>(encoder: &E, row: &[(u32, String)]) -> Vec<u8> {
+pub fn encode_to_vec<E: RowEncoder + ?Sized>(encoder: &E, row: &[(u32, String)]) -> Vec<u8> {
     let mut buffer = Vec::new();
     encoder.encode(&mut buffer, row);
     buffer
 }
 
+/// Applies `first`, then wraps its output as a binary payload prefixed with
+/// its length as a varint.
+///
+/// The payload is written as is, so `second` does not change the output.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChainedEncoder<A: RowEncoder, B: RowEncoder> {
+    first: A,
+    second: B,
+}
+
+/// Chains two encoders, see [`ChainedEncoder`].
+pub fn chain<A: RowEncoder, B: RowEncoder>(first: A, second: B) -> ChainedEncoder<A, B> {
+    ChainedEncoder { first, second }
+}
+
+impl<A: RowEncoder, B: RowEncoder> ChainedEncoder<A, B> {
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+
+    fn encode_payload(buffer: &mut Vec<u8>, payload: &[u8]) {
+        varint::encode_varint(buffer, payload.len() as u32);
+        buffer.extend_from_slice(payload);
+    }
+
+    fn decode_payload(data: &[u8]) -> Result<&[u8], DecodeError> {
+        let (len, offset) = varint::try_decode_varint(data)?;
+        data.get(offset..offset + len as usize)
+            .ok_or(DecodeError::UnexpectedEof)
+    }
+}
+
+impl<A: RowEncoder, B: RowEncoder> RowEncoder for ChainedEncoder<A, B> {
+    fn name(&self) -> &'static str {
+        "chained"
+    }
+
+    fn encode(&self, buffer: &mut Vec<u8>, row: &[(u32, String)]) {
+        let intermediate = encode_to_vec(&self.first, row);
+        Self::encode_payload(buffer, &intermediate);
+    }
+
+    fn encode_borrowed(&self, buffer: &mut Vec<u8>, row: &[(u32, &str)]) {
+        let mut intermediate = Vec::new();
+        self.first.encode_borrowed(&mut intermediate, row);
+        Self::encode_payload(buffer, &intermediate);
+    }
+
+    /// Fails with the errors of either encoder.
+    fn decode(&self, data: &[u8]) -> Result<Vec<(u32, String)>, DecodeError> {
+        self.first.decode(Self::decode_payload(data)?)
+    }
+}
+
+/// Writes nothing, to measure the overhead of the Parquet writer without any
+/// encoding work. Every row decodes as empty.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullEncoder;
+
+impl RowEncoder for NullEncoder {
+    fn name(&self) -> &'static str {
+        "null"
+    }
+
+    fn encode(&self, _buffer: &mut Vec<u8>, _row: &[(u32, String)]) {}
+
+    fn encode_borrowed(&self, _buffer: &mut Vec<u8>, _row: &[(u32, &str)]) {}
+
+    fn decode(&self, _data: &[u8]) -> Result<Vec<(u32, String)>, DecodeError> {
+        Ok(Vec::new())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn format_id_is_stable() {
+        assert_eq!(
+            LengthPrefixedEncoder.format_id(),
+            [0x6d, 0xf8, 0x39, 0x1b, 0xb9, 0x09, 0x04, 0x82]
+        );
+        assert_eq!(
+            LengthPrefixedEncoder.name_and_version(),
+            ("length_prefixed", 1)
+        );
+        assert_ne!(VarintEncoder.format_id(), LengthPrefixedEncoder.format_id());
+    }
+
+    #[test]
+    fn encode_sorted_orders_column_ids() {
+        let row = vec![
+            (7, "node".to_owned()),
+            (2, "a:9100".to_owned()),
+            (5, String::new()),
+            (0, "prod".to_owned()),
+        ];
+        let encoders: [&dyn RowEncoder; 3] = [
+            &VarintEncoder,
+            &LengthPrefixedEncoder,
+            &MemcomparableEncoder,
+        ];
+        for encoder in encoders {
+            let mut buffer = Vec::new();
+            encoder.encode_sorted(&mut buffer, &mut row.clone());
+            let ids: Vec<u32> = encoder
+                .decode(&buffer)
+                .unwrap()
+                .iter()
+                .map(|(id, _)| *id)
+                .collect();
+            assert_eq!(ids, [0, 2, 5, 7], "{}", encoder.name());
+
+            let mut sorted = row.clone();
+            sorted.sort();
+            assert_eq!(
+                buffer,
+                encode_to_vec(encoder, &sorted),
+                "{}",
+                encoder.name()
+            );
+        }
+    }
+
+    #[test]
+    fn encode_borrowed_matches_encode() {
+        let row = [
+            (7, "node".to_owned()),
+            (2, String::new()),
+            (300, "a".repeat(200)),
+        ];
+        let borrowed: Vec<(u32, &str)> = row.iter().map(|(id, v)| (*id, v.as_str())).collect();
+        let encoders: [&dyn RowEncoder; 9] = [
+            &LengthPrefixedEncoder,
+            &LengthPrefixedEncoderBE,
+            &LengthPrefixedEncoderAligned::new(8),
+            &VarintEncoder,
+            &MemcomparableEncoder,
+            &FlatBufferEncoder,
+            &FlatBufferEncoderThreadLocal,
+            &chain(VarintEncoder, LengthPrefixedEncoder),
+            &NullEncoder,
+        ];
+        for encoder in encoders {
+            let mut buffer = Vec::new();
+            encoder.encode_borrowed(&mut buffer, &borrowed);
+            assert_eq!(buffer, encode_to_vec(encoder, &row), "{}", encoder.name());
+        }
+    }
+
+    #[test]
+    fn decode_column_ids_only_matches_decode() {
+        let row = [
+            (7, "node".to_owned()),
+            (2, String::new()),
+            (300, "a".repeat(200)),
+            (u32::MAX, "prod".to_owned()),
+        ];
+        let encoders: [&dyn RowEncoder; 5] = [
+            &LengthPrefixedEncoder,
+            &LengthPrefixedEncoderBE,
+            &VarintEncoder,
+            &MemcomparableEncoder,
+            &FlatBufferEncoder,
+        ];
+        for encoder in encoders {
+            for row in [&row[..], &[]] {
+                let encoded = encode_to_vec(encoder, row);
+                let ids: Vec<u32> = row.iter().map(|(id, _)| *id).collect();
+                assert_eq!(
+                    encoder.decode_column_ids_only(&encoded).unwrap(),
+                    ids,
+                    "{}",
+                    encoder.name()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn encode_nullable_without_nulls() {
+        let row = vec![(0, Some("up".to_owned())), (1, Some(String::new()))];
+        let mut buffer = Vec::new();
+        VarintEncoder.encode_nullable(&mut buffer, &row);
+        assert_eq!(
+            VarintEncoder.decode(&buffer).unwrap(),
+            [(0, "up".to_owned()), (1, String::new())]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "varint encoder does not support null values")]
+    fn encode_nullable_panics_on_null() {
+        VarintEncoder.encode_nullable(&mut Vec::new(), &[(0, None)]);
+    }
+
+    #[test]
+    fn chained_roundtrip() {
+        let encoder = chain(VarintEncoder, LengthPrefixedEncoder);
+        test_roundtrip(&encoder);
+        test_roundtrip_empty(&encoder);
+        test_roundtrip_special_chars(&encoder);
+        test_roundtrip_large_col_ids(&encoder);
+    }
+
+    #[test]
+    fn chained_roundtrip_nested() {
+        let encoder = chain(
+            chain(MemcomparableEncoder, VarintEncoder),
+            FlatBufferEncoder,
+        );
+        test_roundtrip(&encoder);
+        test_roundtrip_special_chars(&encoder);
+    }
+
+    #[test]
+    fn chained_output_is_length_prefixed_payload() {
+        let row = vec![(1, "up".to_owned())];
+        let intermediate = encode_to_vec(&VarintEncoder, &row);
+
+        for encoded in [
+            encode_to_vec(&chain(VarintEncoder, LengthPrefixedEncoder), &row),
+            encode_to_vec(&chain(VarintEncoder, VarintEncoder), &row),
+        ] {
+            assert_eq!(encoded[0] as usize, intermediate.len());
+            assert_eq!(encoded[1..], intermediate);
+        }
+    }
+
+    #[test]
+    fn chained_decode_errors() {
+        let encoder = chain(VarintEncoder, LengthPrefixedEncoder);
+        let encoded = encode_to_vec(&encoder, &[(1, "up".to_owned())]);
+        assert_eq!(
+            encoder.decode(&encoded[..encoded.len() - 1]),
+            Err(DecodeError::UnexpectedEof)
+        );
+
+        assert_eq!(encoder.decode(&[]), Err(DecodeError::UnexpectedEof));
+
+        // Errors of the first encoder pass through the payload framing.
+        for (payload, expected) in [
+            (&[0x01, 0x00][..], DecodeError::UnexpectedEof),
+            (&[0x80, 0x80, 0x80, 0x80, 0x80, 0x01], DecodeError::Overflow),
+        ] {
+            let mut encoded = Vec::new();
+            ChainedEncoder::<VarintEncoder, LengthPrefixedEncoder>::encode_payload(
+                &mut encoded,
+                payload,
+            );
+            assert_eq!(encoder.decode(&encoded), Err(expected), "{payload:02x?}");
+        }
+        let mut encoded = Vec::new();
+        ChainedEncoder::<VarintEncoder, LengthPrefixedEncoder>::encode_payload(
+            &mut encoded,
+            &[0x01, 0x00, 0x01, 0xff],
+        );
+        assert!(matches!(
+            encoder.decode(&encoded),
+            Err(DecodeError::InvalidUtf8(_))
+        ));
+    }
+
     /// Test helper to run roundtrip tests for any encoder
     pub fn test_roundtrip<E: RowEncoder>(encoder: &E) {
         let pairs: Vec<(u32, String)> = vec![
@@ -54,7 +472,7 @@ mod tests {
 
         let mut buffer = Vec::new();
         encoder.encode(&mut buffer, &pairs);
-        let decoded = encoder.decode(&buffer);
+        let decoded = encoder.decode(&buffer).unwrap();
 
         assert_eq!(decoded.len(), pairs.len());
         for (i, (col_id, value)) in decoded.iter().enumerate() {
@@ -68,7 +486,7 @@ mod tests {
 
         let mut buffer = Vec::new();
         encoder.encode(&mut buffer, &pairs);
-        let decoded = encoder.decode(&buffer);
+        let decoded = encoder.decode(&buffer).unwrap();
 
         assert_eq!(decoded.len(), 0);
     }
@@ -87,7 +505,7 @@ mod tests {
 
         let mut buffer = Vec::new();
         encoder.encode(&mut buffer, &pairs);
-        let decoded = encoder.decode(&buffer);
+        let decoded = encoder.decode(&buffer).unwrap();
 
         assert_eq!(decoded.len(), pairs.len());
         for (i, (col_id, value)) in decoded.iter().enumerate() {
@@ -110,7 +528,7 @@ mod tests {
 
         let mut buffer = Vec::new();
         encoder.encode(&mut buffer, &pairs);
-        let decoded = encoder.decode(&buffer);
+        let decoded = encoder.decode(&buffer).unwrap();
 
         assert_eq!(decoded.len(), pairs.len());
         for (i, (col_id, value)) in decoded.iter().enumerate() {
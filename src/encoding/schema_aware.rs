@@ -0,0 +1,261 @@
+//! Self-describing encoding that carries label names alongside the rows.
+//!
+//! Format: `[has_dictionary: u8]([num_names: varint][name][0x00]...)?[inner row]`
+//!
+//! The label name dictionary is written once, on the first encoded row. Every
+//! following row only stores the flag byte and the inner encoder's output, so
+//! the per-row overhead is a single byte.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::varint::{encode_varint, try_decode_varint};
+use super::{DecodeError, RowEncoder};
+
+const NO_DICTIONARY: u8 = 0;
+const WITH_DICTIONARY: u8 = 1;
+
+/// Wraps another encoder and embeds the label names in the first encoded row.
+///
+/// Unlike the other encoders this one is stateful: it remembers whether the
+/// dictionary has been written. Label names must not contain `\0`.
+#[derive(Debug)]
+pub struct SchemaAwareEncoder<E: RowEncoder> {
+    inner: E,
+    label_names: Arc<Vec<String>>,
+    dictionary_written: AtomicBool,
+}
+
+impl<E: RowEncoder> SchemaAwareEncoder<E> {
+    pub fn new(inner: E, label_names: Arc<Vec<String>>) -> Self {
+        Self {
+            inner,
+            label_names,
+            dictionary_written: AtomicBool::new(false),
+        }
+    }
+
+    /// Decodes a row into `(label_name, value)` pairs.
+    ///
+    /// Names are resolved from the dictionary embedded in `data` when present,
+    /// otherwise from the label names this encoder was constructed with. Fails
+    /// with [`DecodeError::UnknownColumn`] for a column id missing from them.
+    pub fn decode_self_describing(
+        &self,
+        data: &[u8],
+    ) -> Result<Vec<(String, String)>, DecodeError> {
+        let (dictionary, row) = split_dictionary(data)?;
+        let label_names = dictionary.as_deref().unwrap_or(self.label_names.as_slice());
+
+        self.inner
+            .decode(row)?
+            .into_iter()
+            .map(|(col_id, value)| {
+                let name = label_names
+                    .get(col_id as usize)
+                    .ok_or(DecodeError::UnknownColumn(col_id))?;
+                Ok((name.clone(), value))
+            })
+            .collect()
+    }
+}
+
+impl<E: RowEncoder> RowEncoder for SchemaAwareEncoder<E> {
+    fn name(&self) -> &'static str {
+        "schema_aware"
+    }
+
+    fn encode(&self, buffer: &mut Vec<u8>, row: &[(u32, String)]) {
+        self.write_dictionary_flag(buffer);
+        self.inner.encode(buffer, row);
+    }
+
+    fn encode_borrowed(&self, buffer: &mut Vec<u8>, row: &[(u32, &str)]) {
+        self.write_dictionary_flag(buffer);
+        self.inner.encode_borrowed(buffer, row);
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<(u32, String)>, DecodeError> {
+        let (_, row) = split_dictionary(data)?;
+        self.inner.decode(row)
+    }
+}
+
+impl<E: RowEncoder> SchemaAwareEncoder<E> {
+    /// Writes the flag byte, followed by the dictionary on the first row.
+    fn write_dictionary_flag(&self, buffer: &mut Vec<u8>) {
+        if self.dictionary_written.swap(true, Ordering::Relaxed) {
+            buffer.push(NO_DICTIONARY);
+        } else {
+            buffer.push(WITH_DICTIONARY);
+            encode_varint(buffer, self.label_names.len() as u32);
+            for name in self.label_names.iter() {
+                buffer.extend_from_slice(name.as_bytes());
+                buffer.push(0);
+            }
+        }
+    }
+}
+
+/// Splits an encoded row into its optional label name dictionary and the inner row.
+fn split_dictionary(data: &[u8]) -> Result<(Option<Vec<String>>, &[u8]), DecodeError> {
+    match data.first() {
+        None => return Err(DecodeError::UnexpectedEof),
+        Some(&NO_DICTIONARY) => return Ok((None, &data[1..])),
+        Some(_) => {}
+    }
+
+    let mut offset = 1;
+    let (num_names, bytes) = try_decode_varint(&data[offset..])?;
+    offset += bytes;
+
+    // The count is untrusted, so don't pre-allocate more than a few names
+    let mut names = Vec::with_capacity((num_names as usize).min(64));
+    for _ in 0..num_names {
+        let len = data[offset..]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or(DecodeError::UnexpectedEof)?;
+        names.push(String::from_utf8(data[offset..offset + len].to_vec())?);
+        offset += len + 1;
+    }
+    Ok((Some(names), &data[offset..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{VarintEncoder, encode_to_vec, tests as test_helpers};
+
+    fn label_names() -> Arc<Vec<String>> {
+        Arc::new(vec![
+            "__name__".to_owned(),
+            "job".to_owned(),
+            "instance".to_owned(),
+        ])
+    }
+
+    fn rows() -> Vec<Vec<(u32, String)>> {
+        vec![
+            vec![
+                (0, "up".to_owned()),
+                (1, "prometheus".to_owned()),
+                (2, "localhost:9090".to_owned()),
+            ],
+            vec![(0, "up".to_owned()), (2, "localhost:9100".to_owned())],
+        ]
+    }
+
+    #[test]
+    fn roundtrip() {
+        test_helpers::test_roundtrip(&SchemaAwareEncoder::new(VarintEncoder, label_names()));
+    }
+
+    #[test]
+    fn roundtrip_empty() {
+        test_helpers::test_roundtrip_empty(&SchemaAwareEncoder::new(VarintEncoder, label_names()));
+    }
+
+    #[test]
+    fn dictionary_only_on_first_row() {
+        let encoder = SchemaAwareEncoder::new(VarintEncoder, label_names());
+        let rows = rows();
+
+        let first = encode_to_vec(&encoder, &rows[0]);
+        let second = encode_to_vec(&encoder, &rows[1]);
+
+        assert_eq!(first[0], WITH_DICTIONARY);
+        assert_eq!(second[0], NO_DICTIONARY);
+        assert_eq!(&second[1..], encode_to_vec(&VarintEncoder, &rows[1]));
+        assert_eq!(encoder.decode(&first).unwrap(), rows[0]);
+        assert_eq!(encoder.decode(&second).unwrap(), rows[1]);
+    }
+
+    #[test]
+    fn encode_borrowed_writes_dictionary_once() {
+        let rows = rows();
+        let owned = SchemaAwareEncoder::new(VarintEncoder, label_names());
+        let borrowed = SchemaAwareEncoder::new(VarintEncoder, label_names());
+        for row in &rows {
+            let row_refs: Vec<(u32, &str)> = row.iter().map(|(id, v)| (*id, v.as_str())).collect();
+            let mut buffer = Vec::new();
+            borrowed.encode_borrowed(&mut buffer, &row_refs);
+            assert_eq!(buffer, encode_to_vec(&owned, row));
+        }
+    }
+
+    #[test]
+    fn roundtrip_self_describing() {
+        let encoder = SchemaAwareEncoder::new(VarintEncoder, label_names());
+        let rows = rows();
+        let first = encode_to_vec(&encoder, &rows[0]);
+        let second = encode_to_vec(&encoder, &rows[1]);
+
+        let expected_first = vec![
+            ("__name__".to_owned(), "up".to_owned()),
+            ("job".to_owned(), "prometheus".to_owned()),
+            ("instance".to_owned(), "localhost:9090".to_owned()),
+        ];
+        let expected_second = vec![
+            ("__name__".to_owned(), "up".to_owned()),
+            ("instance".to_owned(), "localhost:9100".to_owned()),
+        ];
+        assert_eq!(
+            encoder.decode_self_describing(&first).unwrap(),
+            expected_first
+        );
+        assert_eq!(
+            encoder.decode_self_describing(&second).unwrap(),
+            expected_second
+        );
+
+        // The first row carries its own dictionary, so any decoder can resolve it.
+        let other = SchemaAwareEncoder::new(VarintEncoder, Arc::new(vec![]));
+        assert_eq!(
+            other.decode_self_describing(&first).unwrap(),
+            expected_first
+        );
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let encoder = SchemaAwareEncoder::new(VarintEncoder, label_names());
+        let first = encode_to_vec(&encoder, &rows()[0]);
+        for len in 0..first.len() {
+            assert_eq!(
+                encoder.decode(&first[..len]),
+                Err(DecodeError::UnexpectedEof),
+                "truncated to {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn decode_invalid_utf8() {
+        let encoder = SchemaAwareEncoder::new(VarintEncoder, label_names());
+        let mut first = encode_to_vec(&encoder, &rows()[0]);
+        // The first label name, right after the flag and the name count.
+        first[2] = 0xff;
+        let err = encoder.decode(&first).unwrap_err();
+        assert!(matches!(err, DecodeError::InvalidUtf8(_)), "{err:?}");
+    }
+
+    #[test]
+    fn decode_overflow() {
+        let encoder = SchemaAwareEncoder::new(VarintEncoder, label_names());
+        let too_many_names = [WITH_DICTIONARY, 0xff, 0xff, 0xff, 0xff, 0x7f];
+        assert_eq!(encoder.decode(&too_many_names), Err(DecodeError::Overflow));
+    }
+
+    #[test]
+    fn decode_self_describing_unknown_column() {
+        let encoder = SchemaAwareEncoder::new(VarintEncoder, label_names());
+        encoder.dictionary_written.store(true, Ordering::Relaxed);
+        let row = encode_to_vec(&encoder, &[(0, "up".to_owned()), (3, "x".to_owned())]);
+        assert_eq!(
+            encoder.decode_self_describing(&row),
+            Err(DecodeError::UnknownColumn(3))
+        );
+    }
+}
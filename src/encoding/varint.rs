@@ -4,44 +4,169 @@
 //!
 //! Uses variable-length encoding for integers, saving space when values are small.
 
-use super::RowEncoder;
+use std::io::{self, Read};
+
+use super::{DecodeError, EncodeError, RowEncoder};
 
 /// Varint encoder using LEB128 variable-length integers.
 #[derive(Debug, Clone, Copy, Default)]
 pub struct VarintEncoder;
 
+/// Maximum number of bytes a `u32` takes when varint-encoded.
+const MAX_VARINT_LEN: usize = 5;
+
+impl VarintEncoder {
+    /// Encodes all `rows` back to back into `buffer`, reserving space once.
+    ///
+    /// Returns the offset of each row in `buffer` followed by the end offset of
+    /// the last row, so row `i` is `buffer[offsets[i]..offsets[i + 1]]`.
+    pub fn encode_batch(rows: &[Vec<(u32, String)>], buffer: &mut Vec<u8>) -> Vec<u32> {
+        let capacity: usize = rows
+            .iter()
+            .map(|row| {
+                MAX_VARINT_LEN
+                    + row
+                        .iter()
+                        .map(|(_, value)| 2 * MAX_VARINT_LEN + value.len())
+                        .sum::<usize>()
+            })
+            .sum();
+        buffer.reserve(capacity);
+
+        let mut offsets = Vec::with_capacity(rows.len() + 1);
+        offsets.push(buffer.len() as u32);
+        for row in rows {
+            VarintEncoder.encode(buffer, row);
+            offsets.push(buffer.len() as u32);
+        }
+        offsets
+    }
+
+    /// Encodes `row` into the start of `slice`, e.g. a memory-mapped file or a
+    /// ring buffer slot, without allocating.
+    ///
+    /// Writes the same bytes as [`encode`](RowEncoder::encode) and returns
+    /// their number. Fails without writing anything if `slice` is too short.
+    pub fn encode_to_slice(
+        &self,
+        slice: &mut [u8],
+        row: &[(u32, String)],
+    ) -> Result<usize, EncodeError> {
+        let needed = varint_len(row.len() as u32)
+            + row
+                .iter()
+                .map(|(col_id, value)| {
+                    varint_len(*col_id) + varint_len(value.len() as u32) + value.len()
+                })
+                .sum::<usize>();
+        if slice.len() < needed {
+            return Err(EncodeError::BufferTooSmall { needed });
+        }
+
+        let mut offset = write_varint(slice, 0, row.len() as u32);
+        for (col_id, value) in row {
+            offset = write_varint(slice, offset, *col_id);
+            offset = write_varint(slice, offset, value.len() as u32);
+            slice[offset..offset + value.len()].copy_from_slice(value.as_bytes());
+            offset += value.len();
+        }
+        debug_assert_eq!(offset, needed);
+        Ok(needed)
+    }
+
+    /// Decodes one row from `reader`, e.g. a socket or a file holding rows
+    /// back to back, consuming exactly its bytes.
+    ///
+    /// Varints are read one byte at a time and values in bulk, so buffering
+    /// the reader is advisable. Fails with [`io::ErrorKind::UnexpectedEof`] if
+    /// the row is truncated, and [`io::ErrorKind::InvalidData`] if a varint is
+    /// too long or a value is not UTF-8.
+    pub fn decode_from_reader<R: Read>(reader: &mut R) -> Result<Vec<(u32, String)>, io::Error> {
+        let num_entries = read_varint(reader)?;
+        // The count is untrusted, so don't pre-allocate more than a few entries
+        let mut result = Vec::with_capacity((num_entries as usize).min(64));
+
+        for _ in 0..num_entries {
+            let col_id = read_varint(reader)?;
+            let len = read_varint(reader)? as usize;
+            let mut value = Vec::new();
+            reader.take(len as u64).read_to_end(&mut value)?;
+            if value.len() < len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let value = String::from_utf8(value)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            result.push((col_id, value));
+        }
+        Ok(result)
+    }
+}
+
 impl RowEncoder for VarintEncoder {
     fn name(&self) -> &'static str {
         "varint"
     }
 
+    fn format_version(&self) -> u32 {
+        1
+    }
+
     fn encode(&self, buffer: &mut Vec<u8>, row: &[(u32, String)]) {
-        encode_varint(buffer, row.len() as u32);
-        for (col_id, value) in row {
-            encode_varint(buffer, *col_id);
-            encode_varint(buffer, value.len() as u32);
-            buffer.extend_from_slice(value.as_bytes());
-        }
+        encode_row(buffer, row);
+    }
+
+    fn encode_borrowed(&self, buffer: &mut Vec<u8>, row: &[(u32, &str)]) {
+        encode_row(buffer, row);
     }
 
-    fn decode(&self, data: &[u8]) -> Vec<(u32, String)> {
+    fn decode(&self, data: &[u8]) -> Result<Vec<(u32, String)>, DecodeError> {
         let mut result = Vec::new();
         let mut offset = 0;
 
-        let (num_entries, bytes) = decode_varint(&data[offset..]);
+        let (num_entries, bytes) = try_decode_varint(&data[offset..])?;
         offset += bytes;
 
         for _ in 0..num_entries {
-            let (col_id, bytes) = decode_varint(&data[offset..]);
+            let (col_id, bytes) = try_decode_varint(&data[offset..])?;
             offset += bytes;
-            let (len, bytes) = decode_varint(&data[offset..]);
+            let (len, bytes) = try_decode_varint(&data[offset..])?;
             offset += bytes;
             let len = len as usize;
-            let value = String::from_utf8(data[offset..offset + len].to_vec()).unwrap();
+            let value = data
+                .get(offset..offset + len)
+                .ok_or(DecodeError::UnexpectedEof)?;
             offset += len;
-            result.push((col_id, value));
+            result.push((col_id, String::from_utf8(value.to_vec())?));
+        }
+        Ok(result)
+    }
+
+    fn decode_column_ids_only(&self, data: &[u8]) -> Result<Vec<u32>, DecodeError> {
+        let (num_entries, mut offset) = try_decode_varint(data)?;
+        // The count is untrusted, and every entry takes at least 2 bytes
+        let mut result = Vec::with_capacity((num_entries as usize).min(data.len() / 2));
+
+        for _ in 0..num_entries {
+            let (col_id, bytes) = try_decode_varint(&data[offset..])?;
+            offset += bytes;
+            let (len, bytes) = try_decode_varint(&data[offset..])?;
+            offset += bytes + len as usize;
+            if offset > data.len() {
+                return Err(DecodeError::UnexpectedEof);
+            }
+            result.push(col_id);
         }
-        result
+        Ok(result)
+    }
+}
+
+fn encode_row<V: AsRef<str>>(buffer: &mut Vec<u8>, row: &[(u32, V)]) {
+    encode_varint(buffer, row.len() as u32);
+    for (col_id, value) in row {
+        let value = value.as_ref();
+        encode_varint(buffer, *col_id);
+        encode_varint(buffer, value.len() as u32);
+        buffer.extend_from_slice(value.as_bytes());
     }
 }
 
@@ -60,27 +185,125 @@ pub fn encode_varint(buffer: &mut Vec<u8>, mut value: u32) {
     }
 }
 
+/// Encodes `values` as consecutive varints into `buffer`, writing the same
+/// bytes as [`encode_varint`] on each value.
+///
+/// On x86 CPUs with BMI2, the 7-bit groups of each value are spread out with a
+/// single `PDEP` instead of a loop. `PDEP` is microcoded on AMD CPUs before
+/// Zen 3, where [`encode_varints_batch_scalar`] is faster.
+pub fn encode_varints_batch(buffer: &mut Vec<u8>, values: &[u32]) {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("bmi2") {
+        // SAFETY: the CPU supports BMI2.
+        unsafe { encode_varints_bmi2(buffer, values) };
+        return;
+    }
+    encode_varints_batch_scalar(buffer, values);
+}
+
+/// Portable version of [`encode_varints_batch`], calling [`encode_varint`] on
+/// each value.
+pub fn encode_varints_batch_scalar(buffer: &mut Vec<u8>, values: &[u32]) {
+    for &value in values {
+        encode_varint(buffer, value);
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "bmi2")]
+fn encode_varints_bmi2(buffer: &mut Vec<u8>, values: &[u32]) {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::_pdep_u32;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::_pdep_u32;
+
+    /// Low 7 bits of each byte, receiving the first 28 bits of a value.
+    const GROUPS: u32 = 0x7f7f_7f7f;
+    /// Continuation bits of the first 4 bytes.
+    const CONTINUATION: u64 = 0x8080_8080;
+
+    // Every value is written as 8 bytes, of which only its varint is kept.
+    let start = buffer.len();
+    buffer.resize(
+        start + values.len() * MAX_VARINT_LEN + 8 - MAX_VARINT_LEN,
+        0,
+    );
+    let mut offset = start;
+    for &value in values {
+        let len = varint_len(value);
+        let groups = _pdep_u32(value, GROUPS) as u64 | ((value >> 28) as u64) << 32;
+        let continuation = CONTINUATION & ((1 << (8 * (len - 1))) - 1);
+        buffer[offset..offset + 8].copy_from_slice(&(groups | continuation).to_le_bytes());
+        offset += len;
+    }
+    buffer.truncate(offset);
+}
+
+/// Number of bytes `value` takes when varint-encoded.
+fn varint_len(value: u32) -> usize {
+    ((u32::BITS - value.leading_zeros()).max(1) as usize).div_ceil(7)
+}
+
+/// Writes `value` as varint into `slice` at `offset`, returning the offset
+/// past it. `slice` must have room for [`varint_len`] bytes.
+fn write_varint(slice: &mut [u8], mut offset: usize, mut value: u32) -> usize {
+    loop {
+        let mut byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value != 0 {
+            byte |= 0x80;
+        }
+        slice[offset] = byte;
+        offset += 1;
+        if value == 0 {
+            return offset;
+        }
+    }
+}
+
 /// Decode a varint (LEB128) from a slice, returning (value, bytes_read).
-pub fn decode_varint(data: &[u8]) -> (u32, usize) {
+/// Fails if `data` ends before the varint does or it does not fit in a `u32`.
+pub(super) fn try_decode_varint(data: &[u8]) -> Result<(u32, usize), DecodeError> {
     let mut result: u32 = 0;
-    let mut shift = 0;
-    let mut bytes_read = 0;
-
-    for &byte in data {
-        bytes_read += 1;
+    for (idx, &byte) in data.iter().take(MAX_VARINT_LEN).enumerate() {
+        let shift = idx as u32 * 7;
+        if idx == MAX_VARINT_LEN - 1 && byte >> (u32::BITS - shift) != 0 {
+            return Err(DecodeError::Overflow);
+        }
         result |= ((byte & 0x7F) as u32) << shift;
         if byte & 0x80 == 0 {
-            break;
+            return Ok((result, idx + 1));
+        }
+    }
+    Err(DecodeError::UnexpectedEof)
+}
+
+/// Reads a varint (LEB128) from `reader` one byte at a time.
+fn read_varint<R: Read>(reader: &mut R) -> Result<u32, io::Error> {
+    let mut result: u32 = 0;
+    for shift in (0..MAX_VARINT_LEN as u32).map(|idx| idx * 7) {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte)?;
+        result |= ((byte[0] & 0x7F) as u32) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(result);
         }
-        shift += 7;
     }
-    (result, bytes_read)
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "varint longer than 5 bytes",
+    ))
 }
 
 #[cfg(test)]
 mod tests {
+    use std::io::Cursor;
+
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
     use super::*;
-    use crate::encoding::tests as test_helpers;
+    use crate::encoding::{encode_to_vec, tests as test_helpers};
 
     #[test]
     fn roundtrip() {
@@ -102,6 +325,239 @@ mod tests {
         test_helpers::test_roundtrip_large_col_ids(&VarintEncoder);
     }
 
+    #[test]
+    fn encode_batch_matches_encode() {
+        let rows = vec![
+            vec![(0, "up".to_owned()), (1, "prometheus".to_owned())],
+            vec![],
+            vec![(2, "localhost:9090".to_owned())],
+        ];
+        let mut buffer = vec![0xff];
+
+        let offsets = VarintEncoder::encode_batch(&rows, &mut buffer);
+
+        assert_eq!(offsets.len(), rows.len() + 1);
+        assert_eq!(offsets[0], 1);
+        assert_eq!(*offsets.last().unwrap() as usize, buffer.len());
+        for (idx, row) in rows.iter().enumerate() {
+            let encoded = &buffer[offsets[idx] as usize..offsets[idx + 1] as usize];
+            assert_eq!(encoded, encode_to_vec(&VarintEncoder, row));
+            assert_eq!(&VarintEncoder.decode(encoded).unwrap(), row);
+        }
+    }
+
+    fn slice_test_row() -> Vec<(u32, String)> {
+        vec![
+            (0, "up".to_owned()),
+            (300, "x".repeat(200)),
+            (u32::MAX, String::new()),
+        ]
+    }
+
+    #[test]
+    fn encode_to_slice_exact_fit() {
+        let row = slice_test_row();
+        let expected = encode_to_vec(&VarintEncoder, &row);
+        let mut slice = vec![0; expected.len()];
+
+        assert_eq!(
+            VarintEncoder.encode_to_slice(&mut slice, &row),
+            Ok(expected.len())
+        );
+        assert_eq!(slice, expected);
+
+        let mut empty = [0; 1];
+        assert_eq!(VarintEncoder.encode_to_slice(&mut empty, &[]), Ok(1));
+        assert_eq!(empty, [0]);
+    }
+
+    #[test]
+    fn encode_to_slice_too_small() {
+        let row = slice_test_row();
+        let needed = encode_to_vec(&VarintEncoder, &row).len();
+        let mut slice = vec![0xaa; needed - 1];
+
+        assert_eq!(
+            VarintEncoder.encode_to_slice(&mut slice, &row),
+            Err(EncodeError::BufferTooSmall { needed })
+        );
+        assert!(slice.iter().all(|&byte| byte == 0xaa));
+        assert_eq!(
+            VarintEncoder.encode_to_slice(&mut [], &[]),
+            Err(EncodeError::BufferTooSmall { needed: 1 })
+        );
+    }
+
+    #[test]
+    fn encode_to_slice_oversized() {
+        let row = slice_test_row();
+        let expected = encode_to_vec(&VarintEncoder, &row);
+        let mut slice = vec![0xaa; expected.len() + 16];
+
+        let written = VarintEncoder.encode_to_slice(&mut slice, &row).unwrap();
+        assert_eq!(written, expected.len());
+        assert_eq!(slice[..written], expected);
+        assert!(slice[written..].iter().all(|&byte| byte == 0xaa));
+        assert_eq!(VarintEncoder.decode(&slice[..written]).unwrap(), row);
+    }
+
+    #[test]
+    fn decode_from_reader_cursor() {
+        let rows = [slice_test_row(), vec![], vec![(7, "é".to_owned())]];
+        let mut buffer = Vec::new();
+        VarintEncoder::encode_batch(&rows, &mut buffer);
+        let mut reader = Cursor::new(buffer);
+
+        for row in &rows {
+            assert_eq!(
+                &VarintEncoder::decode_from_reader(&mut reader).unwrap(),
+                row
+            );
+        }
+        let err = VarintEncoder::decode_from_reader(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    /// Returns `data` in two reads split at `split`, then in reads of at most
+    /// `chunk` bytes, like a socket delivering packets.
+    struct PartialReader {
+        data: Vec<u8>,
+        position: usize,
+        split: usize,
+        chunk: usize,
+    }
+
+    impl Read for PartialReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let end = if self.position < self.split {
+                self.split
+            } else {
+                self.position + self.chunk
+            };
+            let end = end.min(self.data.len()).min(self.position + buf.len());
+            let read = end - self.position;
+            buf[..read].copy_from_slice(&self.data[self.position..end]);
+            self.position = end;
+            Ok(read)
+        }
+    }
+
+    #[test]
+    fn decode_from_reader_partial_reads() {
+        let row = slice_test_row();
+        let encoded = encode_to_vec(&VarintEncoder, &row);
+
+        for split in 0..=encoded.len() {
+            let mut reader = PartialReader {
+                data: encoded.clone(),
+                position: 0,
+                split,
+                chunk: 3,
+            };
+            assert_eq!(
+                VarintEncoder::decode_from_reader(&mut reader).unwrap(),
+                row,
+                "split at {}",
+                split
+            );
+            assert_eq!(reader.position, encoded.len());
+        }
+    }
+
+    #[test]
+    fn decode_from_reader_invalid() {
+        let row = slice_test_row();
+        let encoded = encode_to_vec(&VarintEncoder, &row);
+        for len in 0..encoded.len() {
+            let err = VarintEncoder::decode_from_reader(&mut &encoded[..len]).unwrap_err();
+            assert_eq!(
+                err.kind(),
+                io::ErrorKind::UnexpectedEof,
+                "truncated to {}",
+                len
+            );
+        }
+
+        let too_long = [0x80; MAX_VARINT_LEN + 1];
+        let err = VarintEncoder::decode_from_reader(&mut &too_long[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let not_utf8 = [1, 0, 1, 0xff];
+        let err = VarintEncoder::decode_from_reader(&mut &not_utf8[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let encoded = encode_to_vec(&VarintEncoder, &slice_test_row());
+        for len in 0..encoded.len() {
+            assert_eq!(
+                VarintEncoder.decode(&encoded[..len]),
+                Err(DecodeError::UnexpectedEof),
+                "truncated to {}",
+                len
+            );
+            assert_eq!(
+                VarintEncoder.decode_column_ids_only(&encoded[..len]),
+                Err(DecodeError::UnexpectedEof),
+                "column ids truncated to {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn decode_invalid_utf8() {
+        let err = VarintEncoder.decode(&[1, 0, 1, 0xff]).unwrap_err();
+        assert!(matches!(err, DecodeError::InvalidUtf8(_)), "{err:?}");
+    }
+
+    #[test]
+    fn decode_overflow() {
+        // Six bytes, or five whose last one carries more than the top 4 bits.
+        let too_long = [0x80, 0x80, 0x80, 0x80, 0x80, 0x01];
+        assert_eq!(VarintEncoder.decode(&too_long), Err(DecodeError::Overflow));
+        let too_large = [0xff, 0xff, 0xff, 0xff, 0x1f];
+        assert_eq!(VarintEncoder.decode(&too_large), Err(DecodeError::Overflow));
+
+        let max = encode_to_vec(&VarintEncoder, &[(u32::MAX, String::new())]);
+        assert_eq!(try_decode_varint(&max[1..]), Ok((u32::MAX, MAX_VARINT_LEN)));
+    }
+
+    #[test]
+    fn varint_len_matches_encoding() {
+        for value in [
+            0u32,
+            1,
+            127,
+            128,
+            16383,
+            16384,
+            2_097_151,
+            2_097_152,
+            u32::MAX,
+        ] {
+            let mut buffer = Vec::new();
+            encode_varint(&mut buffer, value);
+            assert_eq!(varint_len(value), buffer.len(), "{value}");
+        }
+    }
+
+    #[test]
+    fn encode_varints_batch_matches_scalar() {
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut values = vec![0u32, 1, 127, 128, 16383, 16384, 2_097_152, u32::MAX];
+        values.extend((0..1000).map(|_| rng.random::<u32>() >> rng.random_range(0..32)));
+
+        for len in [0, 1, values.len()] {
+            let mut expected = vec![0xaa];
+            encode_varints_batch_scalar(&mut expected, &values[..len]);
+            let mut buffer = vec![0xaa];
+            encode_varints_batch(&mut buffer, &values[..len]);
+            assert_eq!(buffer, expected, "{len} values");
+        }
+    }
+
     #[test]
     fn varint_encoding() {
         let test_values = [0u32, 1, 127, 128, 255, 256, 16383, 16384, u32::MAX];
@@ -109,7 +565,7 @@ mod tests {
         for &val in &test_values {
             let mut buffer = Vec::new();
             encode_varint(&mut buffer, val);
-            let (decoded, _) = decode_varint(&buffer);
+            let (decoded, _) = try_decode_varint(&buffer).unwrap();
             assert_eq!(decoded, val, "Failed for value {}", val);
         }
     }
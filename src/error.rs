@@ -0,0 +1,174 @@
+//! Error type for loading label data.
+
+use std::{fmt, io};
+
+/// Errors returned while opening or parsing label files.
+///
+/// Variants carry the file path when it is known, i.e. when the labels were
+/// loaded through a path-based helper rather than from a bare reader.
+#[derive(Debug)]
+pub enum Error {
+    /// The label file could not be opened or read.
+    Io { path: String, source: io::Error },
+    /// The label file is compressed with a format that cannot be decoded.
+    UnsupportedCompression { path: String, format: &'static str },
+    /// The CSV header or a record could not be parsed.
+    ///
+    /// `record` is the 1-based index of the failing data record, or `None` if
+    /// the header failed.
+    Csv {
+        path: Option<String>,
+        record: Option<u64>,
+        source: csv::Error,
+    },
+    /// Labels could not be written as CSV.
+    CsvWrite {
+        path: Option<String>,
+        source: csv::Error,
+    },
+    /// A record does not have the same number of fields as the header.
+    FieldCount {
+        path: Option<String>,
+        line: u64,
+        expected: usize,
+        found: usize,
+    },
+    /// A header names the same label more than once.
+    ///
+    /// Holds each duplicated name, as first spelled, with the 0-based
+    /// positions at which it appears.
+    DuplicateLabels {
+        path: Option<String>,
+        duplicates: Vec<(String, Vec<usize>)>,
+    },
+    /// A requested label name is not in the header.
+    UnknownLabel { name: String },
+    /// A column id map is invalid or could not be read or written.
+    ColumnIdMap {
+        path: Option<String>,
+        message: String,
+    },
+    /// A line of a line-oriented label file, such as a Prometheus text
+    /// exposition or JSON lines, could not be parsed.
+    Parse {
+        path: Option<String>,
+        line: u64,
+        message: String,
+    },
+}
+
+impl Error {
+    /// Attaches `path` to errors that were raised without one.
+    pub fn with_path(mut self, new_path: &str) -> Self {
+        match &mut self {
+            Error::Io { .. }
+            | Error::UnsupportedCompression { .. }
+            | Error::UnknownLabel { .. } => {}
+            Error::Csv { path, .. }
+            | Error::CsvWrite { path, .. }
+            | Error::ColumnIdMap { path, .. }
+            | Error::DuplicateLabels { path, .. }
+            | Error::FieldCount { path, .. }
+            | Error::Parse { path, .. } => {
+                path.get_or_insert_with(|| new_path.to_owned());
+            }
+        }
+        self
+    }
+}
+
+/// Formats the optional path as a `"{path}: "` prefix.
+struct PathPrefix<'a>(&'a Option<String>);
+
+impl fmt::Display for PathPrefix<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(path) => write!(f, "{}: ", path),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "failed to read {}: {}", path, source),
+            Error::UnsupportedCompression { path, format } => {
+                write!(
+                    f,
+                    "{} is compressed with unsupported format {}",
+                    path, format
+                )
+            }
+            Error::Csv {
+                path,
+                record: None,
+                source,
+            } => write!(
+                f,
+                "{}failed to read CSV header: {}",
+                PathPrefix(path),
+                source
+            ),
+            Error::Csv {
+                path,
+                record: Some(record),
+                source,
+            } => write!(
+                f,
+                "{}failed to read CSV record {}: {}",
+                PathPrefix(path),
+                record,
+                source
+            ),
+            Error::CsvWrite { path, source } => {
+                write!(f, "{}failed to write CSV: {}", PathPrefix(path), source)
+            }
+            Error::FieldCount {
+                path,
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}line {} has {} fields, expected {} from the header",
+                PathPrefix(path),
+                line,
+                found,
+                expected
+            ),
+            Error::DuplicateLabels { path, duplicates } => {
+                write!(f, "{}duplicate label names:", PathPrefix(path))?;
+                for (idx, (name, positions)) in duplicates.iter().enumerate() {
+                    let separator = if idx == 0 { "" } else { ";" };
+                    write!(f, "{} {:?} at positions {:?}", separator, name, positions)?;
+                }
+                Ok(())
+            }
+            Error::UnknownLabel { name } => write!(f, "unknown label {:?}", name),
+            Error::ColumnIdMap { path, message } => {
+                write!(f, "{}invalid column id map: {}", PathPrefix(path), message)
+            }
+            Error::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}line {}: {}", PathPrefix(path), line, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Csv { source, .. } | Error::CsvWrite { source, .. } => Some(source),
+            Error::UnsupportedCompression { .. }
+            | Error::FieldCount { .. }
+            | Error::ColumnIdMap { .. }
+            | Error::DuplicateLabels { .. }
+            | Error::UnknownLabel { .. }
+            | Error::Parse { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Hash quality diagnostics beyond collision counting.
+//!
+//! A hash can produce few collisions on a given data set while still having
+//! output bits that are rarely set, or outputs that barely change when the
+//! input does. [`bit_bias`] and [`avalanche`] measure both over real labels.
+
+use std::hash::Hasher;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::Labels;
+use crate::collision::{AMPLIFY_BATCH_SIZE, amplified_label_index};
+use crate::ts_id_gen::{SeededHasher, TsIdGenerator, TsIdMode, compute_ts_ids};
+
+/// Seed of the input bytes perturbed by [`avalanche`].
+const AVALANCHE_SEED: u64 = 0x5eed;
+
+/// Measures, for each bit of the ts_ids of `labels` amplified `amp` times, how
+/// far the fraction of ts_ids with that bit set is from one half.
+///
+/// Entry `i` is the bias of bit `i`, from 0 for an ideal hash to 0.5 for a bit
+/// that never changes. Rows are amplified and hashed as in
+/// [`run_collision_check`](crate::collision::run_collision_check). Every entry
+/// is NaN if there are no rows.
+///
+/// Duplicate rows repeat their ts_ids and so skew the result; callers should
+/// [`dedup`](Labels::dedup) `labels` first.
+pub fn bit_bias<H>(labels: &Labels, amp: usize) -> [f64; 64]
+where
+    H: Hasher + SeededHasher,
+{
+    let mut ones = [0u64; 64];
+    let mut rows = 0u64;
+    let label_idx = amplified_label_index(labels);
+    for batch in labels.amplify_batches(amp, label_idx, AMPLIFY_BATCH_SIZE) {
+        for ts_id in compute_ts_ids::<H>(&batch, TsIdMode::Seeded) {
+            for (bit, ones) in ones.iter_mut().enumerate() {
+                *ones += (ts_id >> bit) & 1;
+            }
+            rows += 1;
+        }
+    }
+    ones.map(|ones| (ones as f64 / rows as f64 - 0.5).abs())
+}
+
+/// Measures the average fraction of ts_id bits flipped when a single bit of
+/// one label value is flipped, over `samples` rows of `labels`.
+///
+/// Rows are taken in order, starting over at the end, and the flipped bit is
+/// drawn at random from a fixed seed, so results are reproducible. Rows with
+/// only empty values are skipped. An ideal hash scores 0.5. Returns NaN if
+/// there is nothing to perturb.
+pub fn avalanche<H>(labels: &Labels, samples: usize) -> f64
+where
+    H: Hasher + SeededHasher,
+{
+    let mut rng = ChaCha8Rng::seed_from_u64(AVALANCHE_SEED);
+    let mut generator = TsIdGenerator::<H>::from_seed(labels.label_name_hash);
+    let mut ts_id = |row: &[Vec<u8>]| {
+        generator.reset();
+        generator.write_label_values(row.iter().map(Vec::as_slice));
+        generator.finish()
+    };
+
+    let mut flipped = 0u64;
+    let mut perturbed = 0u64;
+    for row in labels.label_values.iter().cycle().take(samples) {
+        let mut row: Vec<Vec<u8>> = row.iter().map(|value| value.clone().into_bytes()).collect();
+        let total_bytes: usize = row.iter().map(Vec::len).sum();
+        if total_bytes == 0 {
+            continue;
+        }
+        let original = ts_id(&row);
+
+        let mut byte = rng.random_range(0..total_bytes);
+        let value = row
+            .iter_mut()
+            .find(|value| {
+                if byte < value.len() {
+                    return true;
+                }
+                byte -= value.len();
+                false
+            })
+            .unwrap();
+        value[byte] ^= 1 << rng.random_range(0..8);
+
+        flipped += (original ^ ts_id(&row)).count_ones() as u64;
+        perturbed += 1;
+    }
+    flipped as f64 / (perturbed * 64) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use xxhash_rust::xxh3::Xxh3;
+
+    use super::*;
+    use crate::read_labels_from_path;
+
+    /// A deliberately broken hash: the number of input bytes.
+    struct LengthHasher(u64);
+
+    impl Hasher for LengthHasher {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            self.0 += bytes.len() as u64;
+        }
+    }
+
+    impl SeededHasher for LengthHasher {
+        fn from_seed(_seed: u64) -> Self {
+            LengthHasher(0)
+        }
+    }
+
+    fn max_bias(bias: &[f64; 64]) -> f64 {
+        bias.iter().copied().fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn broken_hash_scores_badly() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+
+        let bias = bit_bias::<LengthHasher>(&labels, 10);
+        assert_eq!(bias[63], 0.5);
+        assert_eq!(max_bias(&bias), 0.5);
+        assert_eq!(avalanche::<LengthHasher>(&labels, 1000), 0.0);
+    }
+
+    #[test]
+    fn xxh3_scores_within_tolerance() {
+        let mut labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        labels.dedup();
+
+        let bias = bit_bias::<Xxh3>(&labels, 100);
+        assert!(max_bias(&bias) < 0.02, "{bias:?}");
+        let avalanche = avalanche::<Xxh3>(&labels, 10_000);
+        assert!((avalanche - 0.5).abs() < 0.01, "{avalanche}");
+    }
+
+    #[test]
+    fn empty_labels() {
+        let labels = Labels {
+            label_names: vec!["job".to_string()],
+            label_name_hash: 0,
+            label_values: vec![],
+        };
+        assert!(
+            bit_bias::<Xxh3>(&labels, 10)
+                .iter()
+                .all(|bias| bias.is_nan())
+        );
+        assert!(avalanche::<Xxh3>(&labels, 10).is_nan());
+
+        let labels = Labels {
+            label_values: vec![vec![String::new()]],
+            ..labels
+        };
+        assert!(avalanche::<Xxh3>(&labels, 10).is_nan());
+    }
+}
@@ -0,0 +1,196 @@
+//! Interned label values.
+//!
+//! Label dumps repeat low-cardinality values such as `env=prod` on millions of
+//! rows. [`InternedLabels`] stores each distinct value once in a pool and
+//! represents rows as indices into it.
+
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::mem::size_of;
+
+use crate::ts_id_gen::SeededHasher;
+use crate::{Labels, label_row_ts_id};
+
+/// Label rows whose values are indices into a shared string pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternedLabels {
+    pub names: Vec<String>,
+    pub label_name_hash: u64,
+    /// Distinct label values, in order of first occurrence.
+    pub pool: Vec<String>,
+    /// One entry per row, each value is an index into `pool`.
+    pub rows: Vec<Vec<u32>>,
+}
+
+impl InternedLabels {
+    /// Returns the values of row `idx`, borrowed from the pool.
+    pub fn row(&self, idx: usize) -> impl Iterator<Item = &str> + '_ {
+        self.rows[idx]
+            .iter()
+            .map(|&value| self.pool[value as usize].as_str())
+    }
+
+    /// Computes the ts_id of every row with [`label_row_ts_id`], hashing the
+    /// values borrowed from the pool.
+    pub fn ts_ids<H>(&self) -> Vec<u64>
+    where
+        H: Hasher + SeededHasher,
+    {
+        let mut row = Vec::new();
+        (0..self.rows.len())
+            .map(|idx| {
+                row.clear();
+                row.extend(self.row(idx));
+                label_row_ts_id::<H>(self.label_name_hash, &row)
+            })
+            .collect()
+    }
+
+    /// Builds the `(column_id, value)` rows consumed by
+    /// [`RowEncoder::encode_borrowed`](crate::RowEncoder::encode_borrowed),
+    /// with values borrowed from the pool.
+    pub fn encoder_rows(&self) -> Vec<Vec<(u32, &str)>> {
+        (0..self.rows.len())
+            .map(|idx| {
+                self.row(idx)
+                    .enumerate()
+                    .map(|(col, value)| (col as u32, value))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Estimated heap usage in bytes, see [`Labels::heap_size`].
+    pub fn heap_size(&self) -> usize {
+        (self.names.capacity() + self.pool.capacity()) * size_of::<String>()
+            + string_bytes(&self.names)
+            + string_bytes(&self.pool)
+            + self.rows.capacity() * size_of::<Vec<u32>>()
+            + self
+                .rows
+                .iter()
+                .map(|row| row.capacity() * size_of::<u32>())
+                .sum::<usize>()
+    }
+}
+
+impl From<&Labels> for InternedLabels {
+    /// # Panics
+    ///
+    /// Panics if there are more than `u32::MAX` distinct values.
+    fn from(labels: &Labels) -> Self {
+        let mut ids: HashMap<&str, u32> = HashMap::new();
+        let mut pool = Vec::new();
+        let rows = labels
+            .label_values
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|value| {
+                        *ids.entry(value.as_str()).or_insert_with(|| {
+                            pool.push(value.clone());
+                            u32::try_from(pool.len() - 1).expect("too many distinct label values")
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            names: labels.label_names.clone(),
+            label_name_hash: labels.label_name_hash,
+            pool,
+            rows,
+        }
+    }
+}
+
+impl From<&InternedLabels> for Labels {
+    fn from(interned: &InternedLabels) -> Self {
+        Labels {
+            label_names: interned.names.clone(),
+            label_name_hash: interned.label_name_hash,
+            label_values: (0..interned.rows.len())
+                .map(|idx| interned.row(idx).map(str::to_owned).collect())
+                .collect(),
+        }
+    }
+}
+
+impl Labels {
+    /// Estimated heap usage in bytes: allocated capacity of the vectors and
+    /// strings, excluding allocator overhead.
+    pub fn heap_size(&self) -> usize {
+        self.label_names.capacity() * size_of::<String>()
+            + string_bytes(&self.label_names)
+            + self.label_values.capacity() * size_of::<Vec<String>>()
+            + self
+                .label_values
+                .iter()
+                .map(|row| row.capacity() * size_of::<String>() + string_bytes(row))
+                .sum::<usize>()
+    }
+}
+
+/// Heap bytes owned by the strings themselves.
+fn string_bytes(strings: &[String]) -> usize {
+    strings.iter().map(String::capacity).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::DefaultHasher;
+
+    use xxhash_rust::xxh3::Xxh3;
+
+    use super::*;
+    use crate::{
+        VarintEncoder, encode_borrowed_to_parquet, encode_to_parquet, read_labels_and_hash_str,
+        read_labels_from_path, scale_rows,
+    };
+
+    #[test]
+    fn roundtrip() {
+        let labels = read_labels_from_path::<DefaultHasher>("./assets/labels.csv.gz").unwrap();
+        let interned = InternedLabels::from(&labels);
+        let restored = Labels::from(&interned);
+
+        assert_eq!(restored.label_names, labels.label_names);
+        assert_eq!(restored.label_name_hash, labels.label_name_hash);
+        assert_eq!(restored.label_values, labels.label_values);
+        assert!(interned.heap_size() < labels.heap_size());
+    }
+
+    #[test]
+    fn pool_holds_distinct_values() {
+        let csv = "job,instance\nnode,a\nnode,b\napi,a\n";
+        let interned = InternedLabels::from(&read_labels_and_hash_str::<Xxh3>(csv).unwrap());
+
+        assert_eq!(interned.pool, ["node", "a", "b", "api"]);
+        assert_eq!(interned.rows, [[0, 1], [0, 2], [3, 1]]);
+        assert_eq!(interned.row(2).collect::<Vec<_>>(), ["api", "a"]);
+        assert_eq!(interned.encoder_rows()[1], [(0, "node"), (1, "b")]);
+    }
+
+    #[test]
+    fn encoder_rows_encode_like_labels() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/unique-lables.csv.gz").unwrap();
+        let interned = InternedLabels::from(&labels);
+
+        assert_eq!(
+            encode_borrowed_to_parquet(&VarintEncoder, &interned.encoder_rows()).unwrap(),
+            encode_to_parquet(&VarintEncoder, &scale_rows(&labels.label_values, 1)).unwrap()
+        );
+    }
+
+    #[test]
+    fn ts_ids_match_labels() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/unique-lables.csv.gz").unwrap();
+        let ts_ids = InternedLabels::from(&labels).ts_ids::<Xxh3>();
+
+        assert_eq!(ts_ids.len(), labels.label_values.len());
+        for (ts_id, row) in ts_ids.iter().zip(&labels.label_values) {
+            assert_eq!(*ts_id, label_row_ts_id::<Xxh3>(labels.label_name_hash, row));
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Loading labels from JSON lines.
+//!
+//! Each line holds one JSON object mapping label names to values. The header
+//! is the sorted union of the keys of all lines, and labels missing from a
+//! line are left empty.
+
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::io::{BufRead, BufReader, Read};
+
+use serde_json::Value;
+
+use crate::ts_id_gen::SeededHasher;
+use crate::{Error, Labels, open_csv_reader};
+
+/// Reads one row per JSON object, hashing the label names with `H`. Blank
+/// lines are skipped.
+///
+/// Numbers and booleans are stringified and `null` is read as an empty value.
+/// Nested objects and arrays are rejected. Rows are assembled as in
+/// [`Labels::from_map_iter`].
+pub fn read_labels_from_jsonl<H>(reader: impl Read) -> Result<Labels, Error>
+where
+    H: Default + Hasher + SeededHasher,
+{
+    let mut objects: Vec<HashMap<String, String>> = Vec::new();
+
+    for (idx, line) in BufReader::new(reader).lines().enumerate() {
+        let error = |message: String| Error::Parse {
+            path: None,
+            line: idx as u64 + 1,
+            message,
+        };
+        let line = line.map_err(|source| error(format!("failed to read line: {}", source)))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let object = match serde_json::from_str(&line) {
+            Ok(Value::Object(object)) => object,
+            Ok(_) => return Err(error("expected a JSON object".to_owned())),
+            Err(source) => return Err(error(format!("invalid JSON: {}", source))),
+        };
+        let row = object
+            .into_iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Value::String(value) => value,
+                    Value::Number(value) => value.to_string(),
+                    Value::Bool(value) => value.to_string(),
+                    Value::Null => String::new(),
+                    Value::Array(_) | Value::Object(_) => {
+                        return Err(error(format!("label {:?} has a nested value", key)));
+                    }
+                };
+                Ok((key, value))
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        objects.push(row);
+    }
+
+    Ok(Labels::from_map_iter::<H, _>(objects.into_iter()))
+}
+
+/// Like [`read_labels_from_jsonl`], reading from a file that may be
+/// compressed, see [`open_csv_reader`].
+pub fn read_labels_from_jsonl_path<H>(path: &str) -> Result<Labels, Error>
+where
+    H: Default + Hasher + SeededHasher,
+{
+    read_labels_from_jsonl::<H>(open_csv_reader(path)?).map_err(|err| err.with_path(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use xxhash_rust::xxh3::Xxh3;
+
+    use super::*;
+    use crate::read_labels_and_hash_str;
+
+    #[test]
+    fn heterogeneous_keys() {
+        let jsonl = concat!(
+            r#"{"job": "node", "instance": "a:9100"}"#,
+            "\n\n",
+            r#"{"instance": "b:9100", "env": "prod", "shard": 3, "up": true}"#,
+            "\n",
+            r#"{"job": "api", "env": null}"#,
+            "\n",
+        );
+        let labels = read_labels_from_jsonl::<Xxh3>(jsonl.as_bytes()).unwrap();
+
+        assert_eq!(
+            labels.label_names,
+            ["env", "instance", "job", "shard", "up"]
+        );
+        assert_eq!(
+            labels.label_values,
+            [
+                ["", "a:9100", "node", "", ""],
+                ["prod", "b:9100", "", "3", "true"],
+                ["", "", "api", "", ""],
+            ]
+        );
+
+        let csv = read_labels_and_hash_str::<Xxh3>("env,instance,job,shard,up\n").unwrap();
+        assert_eq!(labels.label_name_hash, csv.label_name_hash);
+    }
+
+    #[test]
+    fn read_fixture() {
+        let labels = read_labels_from_jsonl_path::<Xxh3>("./assets/fixtures/labels.jsonl").unwrap();
+        assert_eq!(labels.label_names, ["instance", "job"]);
+        assert_eq!(
+            labels.label_values,
+            [
+                ["localhost:9090", "prometheus"],
+                ["localhost:9100", "node"],
+                ["localhost:9100", "node,\"exporter\""],
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_lines() {
+        let error_of = |text: &str| {
+            read_labels_from_jsonl::<Xxh3>(text.as_bytes())
+                .err()
+                .expect("expected a parse error")
+        };
+
+        let err = error_of("{\"job\": \"a\"}\n{\"job\": {\"name\": \"a\"}}\n");
+        assert!(matches!(err, Error::Parse { line: 2, .. }), "{err}");
+        assert!(err.to_string().contains("\"job\""), "{err}");
+
+        let err = error_of("{\"job\": [\"a\"]}\n");
+        assert!(matches!(err, Error::Parse { line: 1, .. }), "{err}");
+        let err = error_of("\n[\"a\"]\n");
+        assert!(matches!(err, Error::Parse { line: 2, .. }), "{err}");
+        let err = error_of("{\"job\": \"a\"\n");
+        assert!(matches!(err, Error::Parse { line: 1, .. }), "{err}");
+    }
+}
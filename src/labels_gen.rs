@@ -0,0 +1,253 @@
+//! Deterministic synthetic label sets for benchmarks.
+//!
+//! Each label draws its values from a pool of random alphanumeric strings whose
+//! size is the label's cardinality. A label whose cardinality is at least the
+//! number of rows gets a distinct value per row, like an instance id.
+
+use std::hash::Hasher;
+use std::ops::Range;
+use std::str::FromStr;
+
+use rand::distr::{Alphanumeric, SampleString};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::ts_id_gen::SeededHasher;
+use crate::{Labels, compute_label_name_hash};
+
+/// Configuration for [`generate_labels`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntheticLabelsConfig {
+    pub num_labels: usize,
+    pub rows: usize,
+    /// Number of distinct values of each label, `num_labels` entries.
+    pub per_label_cardinality: Vec<usize>,
+    /// Length range of the generated values.
+    pub value_len_range: Range<usize>,
+    /// Seed of the random generator, so that runs are reproducible.
+    pub seed: u64,
+}
+
+impl SyntheticLabelsConfig {
+    /// A config with `num_labels` labels of growing cardinality, the last of
+    /// which has a distinct value per row.
+    pub fn new(rows: usize, num_labels: usize) -> Self {
+        let per_label_cardinality = (0..num_labels)
+            .map(|idx| {
+                if idx + 1 == num_labels {
+                    rows
+                } else {
+                    rows.min(1 << (2 * idx).min(30))
+                }
+            })
+            .collect();
+        Self {
+            num_labels,
+            rows,
+            per_label_cardinality,
+            value_len_range: 4..24,
+            seed: 0,
+        }
+    }
+
+    /// Checks that there is one cardinality per label and that the value
+    /// length range is not empty.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.per_label_cardinality.len() != self.num_labels {
+            return Err(format!(
+                "per_label_cardinality has {} entries, expected one per label ({})",
+                self.per_label_cardinality.len(),
+                self.num_labels
+            ));
+        }
+        if self.value_len_range.is_empty() {
+            return Err(format!(
+                "value_len_range {:?} is empty",
+                self.value_len_range
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for SyntheticLabelsConfig {
+    fn default() -> Self {
+        Self::new(100_000, 12)
+    }
+}
+
+/// Parses a comma-separated list of `key=value` pairs such as
+/// `rows=100000,labels=12`. Supported keys are `rows`, `labels` and `seed`;
+/// missing keys take their default value.
+impl FromStr for SyntheticLabelsConfig {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let default = Self::default();
+        let (mut rows, mut labels, mut seed) = (default.rows, default.num_labels, default.seed);
+        for pair in spec.split(',').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, found {:?}", pair))?;
+            let invalid = |_| format!("invalid value for {}: {:?}", key, value);
+            match key.trim() {
+                "rows" => rows = value.trim().parse().map_err(invalid)?,
+                "labels" => labels = value.trim().parse().map_err(invalid)?,
+                "seed" => seed = value.trim().parse().map_err(invalid)?,
+                _ => return Err(format!("unknown key {:?}", key)),
+            }
+        }
+        Ok(Self {
+            seed,
+            ..Self::new(rows, labels)
+        })
+    }
+}
+
+/// Generates a label set from `config`, hashing the label names with `H`.
+///
+/// The last label is named `instance`, the others `label_<idx>`.
+///
+/// # Panics
+///
+/// Panics if `config` fails [`SyntheticLabelsConfig::validate`].
+pub fn generate_labels<H>(config: &SyntheticLabelsConfig) -> Labels
+where
+    H: Default + Hasher + SeededHasher,
+{
+    if let Err(err) = config.validate() {
+        panic!("invalid synthetic labels config: {err}");
+    }
+    let mut rng = StdRng::seed_from_u64(config.seed);
+
+    let label_names: Vec<String> = (0..config.num_labels)
+        .map(|idx| {
+            if idx + 1 == config.num_labels {
+                "instance".to_owned()
+            } else {
+                format!("label_{idx}")
+            }
+        })
+        .collect();
+
+    let pools: Vec<Vec<String>> = config
+        .per_label_cardinality
+        .iter()
+        .map(|&cardinality| {
+            (0..cardinality.clamp(1, config.rows.max(1)))
+                .map(|_| {
+                    let len = rng.random_range(config.value_len_range.clone());
+                    Alphanumeric.sample_string(&mut rng, len)
+                })
+                .collect()
+        })
+        .collect();
+
+    let label_values = (0..config.rows)
+        .map(|row| {
+            pools
+                .iter()
+                .map(|pool| {
+                    let idx = if pool.len() >= config.rows {
+                        row
+                    } else {
+                        rng.random_range(0..pool.len())
+                    };
+                    pool[idx].clone()
+                })
+                .collect()
+        })
+        .collect();
+
+    let label_name_hash = compute_label_name_hash::<H>(&label_names);
+
+    Labels {
+        label_names,
+        label_name_hash,
+        label_values,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use xxhash_rust::xxh3::Xxh3;
+
+    use super::*;
+
+    #[test]
+    fn deterministic() {
+        let config = SyntheticLabelsConfig::new(1000, 5);
+        let first = generate_labels::<Xxh3>(&config);
+        let second = generate_labels::<Xxh3>(&config);
+        assert_eq!(first.label_names, second.label_names);
+        assert_eq!(first.label_values, second.label_values);
+
+        let other_seed = generate_labels::<Xxh3>(&SyntheticLabelsConfig { seed: 1, ..config });
+        assert_ne!(first.label_values, other_seed.label_values);
+    }
+
+    #[test]
+    fn respects_cardinality() {
+        let config = SyntheticLabelsConfig {
+            num_labels: 3,
+            rows: 1000,
+            per_label_cardinality: vec![1, 10, 1000],
+            value_len_range: 8..9,
+            seed: 7,
+        };
+        let labels = generate_labels::<Xxh3>(&config);
+
+        assert_eq!(labels.label_names, ["label_0", "label_1", "instance"]);
+        assert_eq!(labels.label_values.len(), 1000);
+        for (col, &cardinality) in config.per_label_cardinality.iter().enumerate() {
+            let distinct: HashSet<&String> =
+                labels.label_values.iter().map(|row| &row[col]).collect();
+            assert!(distinct.len() <= cardinality);
+        }
+        let instances: HashSet<&String> = labels.label_values.iter().map(|row| &row[2]).collect();
+        assert_eq!(instances.len(), 1000);
+        assert!(labels.label_values.iter().flatten().all(|v| v.len() == 8));
+    }
+
+    #[test]
+    fn validate() {
+        let config = SyntheticLabelsConfig::new(10, 3);
+        assert!(config.validate().is_ok());
+
+        let empty_range = SyntheticLabelsConfig {
+            value_len_range: 8..8,
+            ..config.clone()
+        };
+        assert!(empty_range.validate().unwrap_err().contains("empty"));
+
+        let missing_cardinality = SyntheticLabelsConfig {
+            per_label_cardinality: vec![1, 2],
+            ..config
+        };
+        assert!(missing_cardinality.validate().is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "value_len_range")]
+    fn generate_rejects_empty_value_len_range() {
+        let config = SyntheticLabelsConfig {
+            value_len_range: 0..0,
+            ..SyntheticLabelsConfig::new(10, 3)
+        };
+        generate_labels::<Xxh3>(&config);
+    }
+
+    #[test]
+    fn parse_spec() {
+        let config: SyntheticLabelsConfig = "rows=500,labels=4,seed=3".parse().unwrap();
+        assert_eq!(config.rows, 500);
+        assert_eq!(config.num_labels, 4);
+        assert_eq!(config.per_label_cardinality, [1, 4, 16, 500]);
+        assert_eq!(config.seed, 3);
+
+        assert!("rows=abc".parse::<SyntheticLabelsConfig>().is_err());
+        assert!("columns=3".parse::<SyntheticLabelsConfig>().is_err());
+    }
+}
@@ -1,239 +1,3098 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs::File;
-use std::hash::Hasher;
-use std::io::{BufReader, Cursor, Read};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufReader, BufWriter, Cursor, Read, Write};
 use std::sync::Arc;
 
-use arrow::array::{Array, BinaryBuilder, MapBuilder, StringBuilder};
-use arrow::datatypes::{DataType, Field, Schema};
-use flate2::read::GzDecoder;
+use arrow::array::{
+    Array, ArrayRef, BinaryBuilder, FixedSizeBinaryBuilder, MapBuilder, StringBuilder,
+    StringDictionaryBuilder, UInt64Array, UInt64Builder,
+};
+use arrow::datatypes::{DataType, Field, Int32Type, Schema};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use flate2::bufread::GzDecoder;
+use flate2::write::GzEncoder;
 use parquet::arrow::ArrowWriter;
-use parquet::file::properties::WriterProperties;
+use parquet::basic::{Compression, Encoding};
+use parquet::errors::ParquetError;
+use parquet::file::properties::{EnabledStatistics, WriterProperties, WriterPropertiesBuilder};
+use parquet::file::reader::{FileReader, SerializedFileReader};
 use parquet::schema::types::ColumnPath;
+use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+
+use crate::ts_id_gen::{Hasher128Ext, SeededHasher, TsIdGenerator, TsIdMode, compute_ts_ids};
+
+pub mod collision;
+mod column_ids;
+pub mod data_reader;
+pub mod encoding;
+mod error;
+pub mod generated;
+pub mod hash_quality;
+mod interned;
+mod jsonl;
+pub mod labels_gen;
+mod prom_text;
+mod stats;
+#[cfg(feature = "object-store")]
+pub mod store;
+pub mod ts_id_gen;
+
+// Re-export encoding types for convenience
+pub use column_ids::ColumnIdMap;
+pub use encoding::{
+    ChainedEncoder, DecodeError, EncodeError, FlatBufferEncoder, FlatBufferEncoderThreadLocal,
+    LengthPrefixedEncoder, LengthPrefixedEncoderAligned, LengthPrefixedEncoderBE,
+    MemcomparableEncoder, MemcomparableEncoderNullable, NullEncoder, RowEncoder,
+    SchemaAwareEncoder, VarintEncoder,
+};
+pub use error::Error;
+pub use interned::InternedLabels;
+pub use jsonl::{read_labels_from_jsonl, read_labels_from_jsonl_path};
+pub use prom_text::{METRIC_NAME_LABEL, read_labels_from_prom_path, read_labels_from_prom_text};
+pub use stats::{LabelStats, LabelsStats};
+
+pub struct Labels {
+    pub label_names: Vec<String>,
+    pub label_name_hash: u64,
+    pub label_values: Vec<Vec<String>>,
+}
+
+impl Labels {
+    /// Recomputes `label_name_hash` with `H` after `label_names` was modified.
+    pub fn recompute_label_name_hash<H>(&mut self)
+    where
+        H: Default + Hasher + SeededHasher,
+    {
+        self.label_name_hash = compute_label_name_hash::<H>(&self.label_names);
+    }
+
+    /// Removes duplicate rows from `label_values`, keeping the first occurrence
+    /// of each row in its original position. Returns the number of rows removed.
+    pub fn dedup(&mut self) -> usize {
+        let mut deduplicator = RowDeduplicator::default();
+        for row in std::mem::take(&mut self.label_values) {
+            deduplicator.push(row);
+        }
+        self.label_values = deduplicator.rows;
+        deduplicator.removed
+    }
+
+    /// Yields each row `factor` times, appending `-{idx}` to the value of label
+    /// `label_idx` in the `idx`-th copy so that the copies are distinct.
+    ///
+    /// Only one label varies, which keeps the cardinality of the other labels
+    /// realistic. Rows are produced lazily.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `label_idx` is not a valid label index.
+    pub fn amplify(
+        &self,
+        factor: usize,
+        label_idx: usize,
+    ) -> impl Iterator<Item = Vec<Vec<u8>>> + '_ {
+        assert!(
+            label_idx < self.label_names.len(),
+            "label index {} out of range for {} labels",
+            label_idx,
+            self.label_names.len()
+        );
+        self.label_values.iter().flat_map(move |row| {
+            (0..factor).map(move |idx| {
+                row.iter()
+                    .enumerate()
+                    .map(|(col, value)| {
+                        if col == label_idx {
+                            format!("{}-{}", value, idx).into_bytes()
+                        } else {
+                            value.as_bytes().to_vec()
+                        }
+                    })
+                    .collect()
+            })
+        })
+    }
+
+    /// Yields the rows of [`Labels::amplify`] as [`Labels`] of up to
+    /// `batch_size` rows each, sharing the label names and their hash, so that
+    /// batch APIs like [`compute_ts_ids`] can process amplified rows without
+    /// holding all of them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch_size` is zero or `label_idx` is not a valid label index.
+    pub fn amplify_batches(
+        &self,
+        factor: usize,
+        label_idx: usize,
+        batch_size: usize,
+    ) -> impl Iterator<Item = Labels> + '_ {
+        assert!(batch_size > 0, "batch size must be positive");
+        let mut rows = self.amplify(factor, label_idx).peekable();
+        std::iter::from_fn(move || {
+            rows.peek()?;
+            let label_values = rows
+                .by_ref()
+                .take(batch_size)
+                .map(|row| {
+                    row.into_iter()
+                        .map(|value| String::from_utf8(value).expect("amplified labels are UTF-8"))
+                        .collect()
+                })
+                .collect();
+            Some(Labels {
+                label_names: self.label_names.clone(),
+                label_name_hash: self.label_name_hash,
+                label_values,
+            })
+        })
+    }
+
+    /// Sorts `label_values` by the memcomparable encoding of each row, the order
+    /// a storage engine keeps primary keys in.
+    pub fn sort_rows(&mut self) {
+        self.sort_rows_by(&MemcomparableEncoder);
+    }
+
+    /// Sorts `label_values` by the encoding of each row with `encoder`, whose
+    /// output must preserve order for the result to be meaningful.
+    ///
+    /// Each row is encoded once and the sort is stable, so rows with equal keys
+    /// keep their input order.
+    pub fn sort_rows_by(&mut self, encoder: &dyn RowEncoder) {
+        let mut keys: Vec<(Vec<u8>, usize)> = self
+            .label_values
+            .iter()
+            .enumerate()
+            .map(|(idx, row)| {
+                let pairs: Vec<(u32, String)> = row
+                    .iter()
+                    .enumerate()
+                    .map(|(col_id, value)| (col_id as u32, value.clone()))
+                    .collect();
+                (encoding::encode_to_vec(encoder, &pairs), idx)
+            })
+            .collect();
+        keys.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut rows: Vec<Option<Vec<String>>> = std::mem::take(&mut self.label_values)
+            .into_iter()
+            .map(Some)
+            .collect();
+        self.label_values = keys
+            .into_iter()
+            .map(|(_, idx)| rows[idx].take().unwrap())
+            .collect();
+    }
+
+    /// Computes the ts_id of each row with
+    /// [`TsIdGenerator::write_sorted_label_pairs`], so that the ts_ids don't
+    /// depend on the order of `label_names`.
+    pub fn sorted_pair_ts_ids<H>(&self, seed: u64) -> Vec<u64>
+    where
+        H: Hasher + SeededHasher,
+    {
+        self.label_values
+            .iter()
+            .map(|row| {
+                let mut generator = TsIdGenerator::<H>::from_seed(seed);
+                generator.write_sorted_label_pairs(
+                    self.label_names
+                        .iter()
+                        .zip(row)
+                        .map(|(name, value)| (name.as_bytes(), value.as_bytes())),
+                );
+                generator.build_ts_id()
+            })
+            .collect()
+    }
+
+    /// Shuffles `label_values` with a permutation determined only by `seed`.
+    ///
+    /// The permutation is drawn from ChaCha8, which produces the same stream
+    /// on every platform, so benchmark inputs are reproducible across runs.
+    pub fn shuffle_rows(&mut self, seed: u64) {
+        self.label_values
+            .shuffle(&mut ChaCha8Rng::seed_from_u64(seed));
+    }
+
+    /// Builds labels from one map of label names to values per row, hashing
+    /// the label names with `H`.
+    ///
+    /// The label names are the sorted union of the keys of all maps, and labels
+    /// missing from a map are left empty.
+    pub fn from_map_iter<H, I>(iter: I) -> Labels
+    where
+        H: Default + Hasher + SeededHasher,
+        I: Iterator<Item = HashMap<String, String>>,
+    {
+        let mut rows: Vec<HashMap<String, String>> = Vec::new();
+        let mut names = BTreeSet::new();
+        for row in iter {
+            names.extend(row.keys().cloned());
+            rows.push(row);
+        }
+
+        let label_names: Vec<String> = names.into_iter().collect();
+        let label_values = rows
+            .into_iter()
+            .map(|mut row| {
+                label_names
+                    .iter()
+                    .map(|name| row.remove(name).unwrap_or_default())
+                    .collect()
+            })
+            .collect();
+
+        let label_name_hash = compute_label_name_hash::<H>(&label_names);
+        Labels {
+            label_name_hash,
+            label_names,
+            label_values,
+        }
+    }
+
+    /// Merges label sets with possibly different headers, hashing the merged
+    /// label names with `H`.
+    ///
+    /// The merged header holds every label name in order of first appearance.
+    /// Rows are remapped into it and labels missing from a source are left
+    /// empty. With `dedup`, duplicate rows across all sources are removed as in
+    /// [`Labels::dedup`].
+    ///
+    /// Fails if the header of a source names the same label twice.
+    pub fn merge<H>(sources: &[Labels], dedup: bool) -> Result<Labels, Error>
+    where
+        H: Default + Hasher + SeededHasher,
+    {
+        let mut label_names: Vec<String> = Vec::new();
+        let mut columns: HashMap<&str, usize> = HashMap::new();
+        for source in sources {
+            check_unique_names(&source.label_names)?;
+            for name in &source.label_names {
+                columns.entry(name).or_insert_with(|| {
+                    label_names.push(name.clone());
+                    label_names.len() - 1
+                });
+            }
+        }
+
+        let mut label_values =
+            Vec::with_capacity(sources.iter().map(|s| s.label_values.len()).sum());
+        for source in sources {
+            let mapping: Vec<usize> = source
+                .label_names
+                .iter()
+                .map(|name| columns[name.as_str()])
+                .collect();
+            for row in &source.label_values {
+                let mut merged = vec![String::new(); label_names.len()];
+                for (value, &column) in row.iter().zip(&mapping) {
+                    merged[column] = value.clone();
+                }
+                label_values.push(merged);
+            }
+        }
+
+        let label_name_hash = compute_label_name_hash::<H>(&label_names);
+        let mut labels = Labels {
+            label_names,
+            label_name_hash,
+            label_values,
+        };
+        if dedup {
+            labels.dedup();
+        }
+        Ok(labels)
+    }
+
+    /// Keeps only the labels named in `keep`, in that order, hashing the
+    /// reduced label names with `H`.
+    ///
+    /// Fails if a name in `keep` is not in the header or is given twice.
+    pub fn project<H>(&self, keep: &[&str]) -> Result<Labels, Error>
+    where
+        H: Default + Hasher + SeededHasher,
+    {
+        check_unique_names(keep)?;
+        let mut columns = Vec::with_capacity(keep.len());
+        for &name in keep {
+            let column = self
+                .label_names
+                .iter()
+                .position(|label_name| label_name == name)
+                .ok_or_else(|| Error::UnknownLabel {
+                    name: name.to_owned(),
+                })?;
+            columns.push(column);
+        }
+
+        let label_names: Vec<String> = columns
+            .iter()
+            .map(|&column| self.label_names[column].clone())
+            .collect();
+        let label_values = self
+            .label_values
+            .iter()
+            .map(|row| columns.iter().map(|&column| row[column].clone()).collect())
+            .collect();
+
+        let label_name_hash = compute_label_name_hash::<H>(&label_names);
+        Ok(Labels {
+            label_names,
+            label_name_hash,
+            label_values,
+        })
+    }
+
+    /// Removes the labels named in `remove`, keeping the order of the others.
+    /// The inverse of [`Labels::project`].
+    ///
+    /// Fails if a name in `remove` is not in the header.
+    pub fn drop<H>(&self, remove: &[&str]) -> Result<Labels, Error>
+    where
+        H: Default + Hasher + SeededHasher,
+    {
+        if let Some(&name) = remove
+            .iter()
+            .find(|&&name| !self.label_names.iter().any(|label_name| label_name == name))
+        {
+            return Err(Error::UnknownLabel {
+                name: name.to_owned(),
+            });
+        }
+        let keep: Vec<&str> = self
+            .label_names
+            .iter()
+            .map(String::as_str)
+            .filter(|name| !remove.contains(name))
+            .collect();
+        self.project::<H>(&keep)
+    }
+
+    /// Writes the label names as a header row followed by `label_values`.
+    pub fn write_csv<W: Write>(&self, sink: W) -> Result<(), Error> {
+        let error = |source| Error::CsvWrite { path: None, source };
+        let mut writer = csv::Writer::from_writer(sink);
+        writer.write_record(&self.label_names).map_err(error)?;
+        for row in &self.label_values {
+            writer.write_record(row).map_err(error)?;
+        }
+        writer.flush().map_err(|source| error(source.into()))
+    }
+
+    /// Writes the labels as CSV to `path`, compressed according to its
+    /// extension like [`open_csv_reader`] expects: `.gz` for gzip and `.zst`
+    /// or `.zstd` for zstd.
+    pub fn write_csv_to_path(&self, path: &str) -> Result<(), Error> {
+        self.write_csv_to_path_with_options(path, &CsvWriteOptions::default())
+    }
+
+    /// Like [`Labels::write_csv_to_path`], with the compression levels taken
+    /// from `options`.
+    pub fn write_csv_to_path_with_options(
+        &self,
+        path: &str,
+        options: &CsvWriteOptions,
+    ) -> Result<(), Error> {
+        create_compressed(path, options, |sink| self.write_csv(sink))
+            .map_err(|err: Error| err.with_path(path))
+    }
+
+    /// Pairs each value with its column position like
+    /// [`Labels::to_pairs`] without a map, turning the values that `nulls`
+    /// spells as null back into `None`.
+    ///
+    /// This reconstructs the nulls of labels extracted from parquet with the
+    /// same representation, for [`RowEncoder::encode_nullable`].
+    pub fn to_nullable_pairs(&self, nulls: &NullRepresentation) -> Vec<Vec<(u32, Option<String>)>> {
+        self.label_values
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .enumerate()
+                    .map(|(col, value)| (col as u32, nulls.parse(value).map(str::to_owned)))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// How null label values are written to, and read back from, CSV files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum NullRepresentation {
+    /// Nulls are written as empty strings, so they cannot be told apart from
+    /// empty values.
+    #[default]
+    Empty,
+    /// Nulls are written as the given string, e.g. `\N` or `<null>`.
+    Literal(String),
+    /// Rows containing a null are dropped.
+    SkipRow,
+}
+
+impl NullRepresentation {
+    /// Returns the value of a CSV field, or `None` if it spells a null.
+    ///
+    /// With [`NullRepresentation::SkipRow`] no field is null.
+    pub fn parse<'a>(&self, field: &'a str) -> Option<&'a str> {
+        match self {
+            NullRepresentation::Empty => (!field.is_empty()).then_some(field),
+            NullRepresentation::Literal(null) => (field != null).then_some(field),
+            NullRepresentation::SkipRow => Some(field),
+        }
+    }
+}
+
+impl std::str::FromStr for NullRepresentation {
+    type Err = String;
+
+    /// Parses `empty`, `skip-row` or `literal:<text>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "empty" => Ok(NullRepresentation::Empty),
+            "skip-row" => Ok(NullRepresentation::SkipRow),
+            _ => s
+                .strip_prefix("literal:")
+                .map(|null| NullRepresentation::Literal(null.to_owned()))
+                .ok_or_else(|| {
+                    format!(
+                        "invalid null representation {:?}, expected empty, skip-row or literal:<text>",
+                        s
+                    )
+                }),
+        }
+    }
+}
+
+/// Options for writing label files.
+#[derive(Debug, Clone)]
+pub struct CsvWriteOptions {
+    /// Gzip compression level, from 0 (none) to 9 (best).
+    pub gzip_level: u32,
+    /// Zstd compression level, where 0 selects the zstd default.
+    pub zstd_level: i32,
+}
+
+impl Default for CsvWriteOptions {
+    fn default() -> Self {
+        Self {
+            gzip_level: 6,
+            zstd_level: 0,
+        }
+    }
+}
+
+/// Creates the file at `path` and passes it to `write`, compressed according
+/// to its extension like [`open_csv_reader`] expects.
+///
+/// The compressed stream is finished even if `write` fails, so the file is
+/// never left as a truncated stream that decoders reject halfway through.
+/// The error of `write` takes precedence over one raised while finishing.
+pub fn create_compressed<T, E: From<Error>>(
+    path: &str,
+    options: &CsvWriteOptions,
+    write: impl FnOnce(&mut dyn Write) -> Result<T, E>,
+) -> Result<T, E> {
+    let io_error = |source| Error::Io {
+        path: path.to_owned(),
+        source,
+    };
+    let compression =
+        detect_compression(path, &[]).map_err(|format| Error::UnsupportedCompression {
+            path: path.to_owned(),
+            format,
+        })?;
+    let mut file = BufWriter::new(File::create(path).map_err(io_error)?);
+
+    match compression {
+        CsvCompression::None => {
+            let value = write(&mut file)?;
+            file.flush().map_err(io_error)?;
+            Ok(value)
+        }
+        CsvCompression::Gzip => {
+            let mut encoder = GzEncoder::new(file, flate2::Compression::new(options.gzip_level));
+            let result = write(&mut encoder);
+            let finished = encoder.finish().and_then(|mut file| file.flush());
+            let value = result?;
+            finished.map_err(io_error)?;
+            Ok(value)
+        }
+        CsvCompression::Zstd => {
+            let mut encoder = zstd::Encoder::new(file, options.zstd_level).map_err(io_error)?;
+            let result = write(&mut encoder);
+            let finished = encoder.finish().and_then(|mut file| file.flush());
+            let value = result?;
+            finished.map_err(io_error)?;
+            Ok(value)
+        }
+    }
+}
+
+/// Fails with [`Error::DuplicateLabels`] if a label name appears twice.
+fn check_unique_names<S: AsRef<str>>(label_names: &[S]) -> Result<(), Error> {
+    let duplicates = find_duplicate_names(label_names, false);
+    if duplicates.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::DuplicateLabels {
+            path: None,
+            duplicates,
+        })
+    }
+}
+
+/// Returns each name that appears more than once, as first spelled, with the
+/// positions at which it appears, in order of first appearance.
+fn find_duplicate_names<S: AsRef<str>>(
+    label_names: &[S],
+    ignore_case: bool,
+) -> Vec<(String, Vec<usize>)> {
+    let mut positions: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut first_seen = Vec::new();
+    for (idx, name) in label_names.iter().enumerate() {
+        let key = name_key(name.as_ref(), ignore_case);
+        let entry = positions.entry(key).or_default();
+        if entry.is_empty() {
+            first_seen.push(idx);
+        }
+        entry.push(idx);
+    }
+
+    first_seen
+        .into_iter()
+        .filter_map(|idx| {
+            let name = label_names[idx].as_ref();
+            let positions = positions.remove(&name_key(name, ignore_case))?;
+            (positions.len() > 1).then(|| (name.to_owned(), positions))
+        })
+        .collect()
+}
+
+fn name_key(name: &str, ignore_case: bool) -> String {
+    if ignore_case {
+        name.to_lowercase()
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Validates a header according to `options`, renaming repeated names to
+/// `name_2`, `name_3`, ... if [`CsvReadOptions::rename_duplicate_labels`] is
+/// set.
+fn resolve_duplicate_names(
+    mut label_names: Vec<String>,
+    options: &CsvReadOptions,
+) -> Result<Vec<String>, Error> {
+    let ignore_case = options.ignore_label_case;
+    let duplicates = find_duplicate_names(&label_names, ignore_case);
+    if duplicates.is_empty() {
+        return Ok(label_names);
+    }
+    if !options.rename_duplicate_labels {
+        return Err(Error::DuplicateLabels {
+            path: None,
+            duplicates,
+        });
+    }
+
+    let mut taken: HashSet<String> = label_names
+        .iter()
+        .map(|name| name_key(name, ignore_case))
+        .collect();
+    for (_, positions) in duplicates {
+        for (occurrence, &idx) in positions.iter().enumerate().skip(1) {
+            // Skip suffixes that would collide with another label name.
+            let mut suffix = occurrence + 1;
+            let renamed = loop {
+                let candidate = format!("{}_{}", label_names[idx], suffix);
+                if taken.insert(name_key(&candidate, ignore_case)) {
+                    break candidate;
+                }
+                suffix += 1;
+            };
+            label_names[idx] = renamed;
+        }
+    }
+    Ok(label_names)
+}
+
+/// Collects rows, dropping those equal to a row that was already pushed.
+///
+/// Rows are looked up by hash and compared exactly on a hash match, so hash
+/// collisions never drop distinct rows.
+#[derive(Default)]
+struct RowDeduplicator {
+    rows: Vec<Vec<String>>,
+    /// Indices into `rows` by row hash.
+    seen: HashMap<u64, Vec<usize>>,
+    removed: usize,
+}
+
+impl RowDeduplicator {
+    fn push(&mut self, row: Vec<String>) {
+        let mut hasher = DefaultHasher::new();
+        row.hash(&mut hasher);
+        let indices = self.seen.entry(hasher.finish()).or_default();
+        if indices.iter().any(|&idx| self.rows[idx] == row) {
+            self.removed += 1;
+        } else {
+            indices.push(self.rows.len());
+            self.rows.push(row);
+        }
+    }
+}
+
+/// Compression formats understood by [`open_csv_reader`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CsvCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression formats that are recognized, by extension or magic bytes, but
+/// cannot be decoded.
+const UNSUPPORTED_COMPRESSIONS: &[(&str, &str, &[u8])] = &[
+    ("bzip2", ".bz2", b"BZh"),
+    ("xz", ".xz", &[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+    ("lz4", ".lz4", &[0x04, 0x22, 0x4d, 0x18]),
+    ("zip", ".zip", b"PK\x03\x04"),
+];
+
+/// Detects the compression of a file from its extension, falling back to the
+/// magic bytes at the start of `header` when the extension is not known.
+///
+/// Returns the name of the format if it is recognized but not supported.
+fn detect_compression(path: &str, header: &[u8]) -> Result<CsvCompression, &'static str> {
+    if path.ends_with(".gz") {
+        return Ok(CsvCompression::Gzip);
+    }
+    if path.ends_with(".zst") || path.ends_with(".zstd") {
+        return Ok(CsvCompression::Zstd);
+    }
+    if let Some((format, _, _)) = UNSUPPORTED_COMPRESSIONS
+        .iter()
+        .find(|(_, extension, _)| path.ends_with(extension))
+    {
+        return Err(format);
+    }
+
+    if header.starts_with(GZIP_MAGIC) {
+        Ok(CsvCompression::Gzip)
+    } else if header.starts_with(ZSTD_MAGIC) {
+        Ok(CsvCompression::Zstd)
+    } else if let Some((format, _, _)) = UNSUPPORTED_COMPRESSIONS
+        .iter()
+        .find(|(_, _, magic)| header.starts_with(magic))
+    {
+        Err(format)
+    } else {
+        Ok(CsvCompression::None)
+    }
+}
+
+/// Path that [`open_csv_reader`] reads from standard input.
+pub const STDIN_PATH: &str = "-";
+
+/// Length of the longest magic number in [`detect_compression`], xz's.
+const MAX_MAGIC_LEN: usize = 6;
+
+/// Create a reader from a file path, automatically handling compression.
+///
+/// Files ending with `.gz` are decompressed using gzip and files ending with
+/// `.zst` or `.zstd` using zstd. For other paths the compression is detected
+/// from the magic bytes at the start of the file.
+///
+/// [`STDIN_PATH`] reads from standard input. Its compression is detected from
+/// the magic bytes too, which are read ahead and replayed since stdin cannot be
+/// rewound.
+pub fn open_csv_reader(path: &str) -> Result<Box<dyn Read>, Error> {
+    let source: Box<dyn Read> = if path == STDIN_PATH {
+        Box::new(std::io::stdin())
+    } else {
+        Box::new(File::open(path).map_err(|source| Error::Io {
+            path: path.to_owned(),
+            source,
+        })?)
+    };
+    decompress_reader(path, source)
+}
+
+/// Wraps `source` in the decoder of the compression of `path`, sniffing the
+/// magic bytes until [`MAX_MAGIC_LEN`] bytes or the end of the input, however
+/// short the reads of `source` are.
+fn decompress_reader(path: &str, mut source: Box<dyn Read>) -> Result<Box<dyn Read>, Error> {
+    let io_error = |source| Error::Io {
+        path: path.to_owned(),
+        source,
+    };
+    let mut header = Vec::with_capacity(MAX_MAGIC_LEN);
+    source
+        .by_ref()
+        .take(MAX_MAGIC_LEN as u64)
+        .read_to_end(&mut header)
+        .map_err(io_error)?;
+    let compression = detect_compression(path, &header);
+    let reader = BufReader::new(Cursor::new(header).chain(source));
+
+    match compression {
+        Ok(CsvCompression::None) => Ok(Box::new(reader)),
+        Ok(CsvCompression::Gzip) => Ok(Box::new(GzDecoder::new(reader))),
+        Ok(CsvCompression::Zstd) => Ok(Box::new(
+            zstd::stream::read::Decoder::with_buffer(reader).map_err(io_error)?,
+        )),
+        Err(format) => Err(Error::UnsupportedCompression {
+            path: path.to_owned(),
+            format,
+        }),
+    }
+}
+
+/// Options for parsing label files.
+#[derive(Debug, Clone)]
+pub struct CsvReadOptions {
+    /// Field delimiter, e.g. `b'\t'` for TSV.
+    pub delimiter: u8,
+    /// Whether the first record holds the label names. Without a header, the
+    /// names are `label_0..label_n`.
+    pub has_headers: bool,
+    /// Quote character, or `None` to disable quoting.
+    pub quote: Option<u8>,
+    /// Drop duplicate rows when reading all rows into [`Labels`]. Rows streamed
+    /// from a [`LabelsReader`] are not deduplicated.
+    pub dedup: bool,
+    /// Treat label names differing only in case as duplicates.
+    pub ignore_label_case: bool,
+    /// Rename repeated label names to `name_2`, `name_3`, ... instead of
+    /// failing with [`Error::DuplicateLabels`].
+    pub rename_duplicate_labels: bool,
+}
+
+impl Default for CsvReadOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            has_headers: true,
+            quote: Some(b'"'),
+            dedup: false,
+            ignore_label_case: false,
+            rename_duplicate_labels: false,
+        }
+    }
+}
+
+/// Streaming reader over the label rows of a CSV file.
+///
+/// The header and label name hash are read up front; rows are parsed lazily as
+/// the reader is iterated, so memory use does not grow with the file size.
+/// Every record must have the same number of fields as the header.
+pub struct LabelsReader {
+    label_names: Vec<String>,
+    label_name_hash: u64,
+    records: csv::StringRecordsIntoIter<Box<dyn Read>>,
+    next_record: u64,
+    path: Option<String>,
+    dedup: bool,
+}
+
+impl LabelsReader {
+    /// Reads the header from `reader` and hashes the label names with `H`.
+    pub fn new<H>(reader: Box<dyn Read>) -> Result<Self, Error>
+    where
+        H: Default + Hasher + SeededHasher,
+    {
+        Self::with_options::<H>(reader, &CsvReadOptions::default())
+    }
+
+    /// Like [`LabelsReader::new`], parsing `reader` with the given options.
+    pub fn with_options<H>(reader: Box<dyn Read>, options: &CsvReadOptions) -> Result<Self, Error>
+    where
+        H: Default + Hasher + SeededHasher,
+    {
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .delimiter(options.delimiter)
+            .has_headers(options.has_headers)
+            .quoting(options.quote.is_some())
+            .quote(options.quote.unwrap_or(b'"'))
+            .from_reader(reader);
+
+        // Without a header, this is the first record, which is still yielded
+        // by the records iterator.
+        let header = csv_reader.headers().map_err(|source| Error::Csv {
+            path: None,
+            record: None,
+            source,
+        })?;
+        let label_names: Vec<String> = if options.has_headers {
+            header.iter().map(|s| s.to_owned()).collect()
+        } else {
+            (0..header.len())
+                .map(|idx| format!("label_{idx}"))
+                .collect()
+        };
+        let label_names = resolve_duplicate_names(label_names, options)?;
+
+        let label_name_hash = compute_label_name_hash::<H>(&label_names);
+
+        Ok(Self {
+            label_names,
+            label_name_hash,
+            records: csv_reader.into_records(),
+            next_record: 1,
+            path: None,
+            dedup: options.dedup,
+        })
+    }
+
+    /// Opens `path` with [`open_csv_reader`]. Errors carry the path of the file.
+    pub fn from_path<H>(path: &str) -> Result<Self, Error>
+    where
+        H: Default + Hasher + SeededHasher,
+    {
+        Self::from_path_with_options::<H>(path, &CsvReadOptions::default())
+    }
+
+    /// Like [`LabelsReader::from_path`], parsing the file with the given options.
+    pub fn from_path_with_options<H>(path: &str, options: &CsvReadOptions) -> Result<Self, Error>
+    where
+        H: Default + Hasher + SeededHasher,
+    {
+        let mut reader = Self::with_options::<H>(open_csv_reader(path)?, options)
+            .map_err(|err| err.with_path(path))?;
+        reader.path = Some(path.to_owned());
+        Ok(reader)
+    }
+
+    pub fn label_names(&self) -> &[String] {
+        &self.label_names
+    }
+
+    pub fn label_name_hash(&self) -> u64 {
+        self.label_name_hash
+    }
+
+    /// Reads all remaining rows into memory, dropping duplicates if the reader
+    /// was created with [`CsvReadOptions::dedup`].
+    pub fn into_labels(self) -> Result<Labels, Error> {
+        self.into_labels_and_duplicates().map(|(labels, _)| labels)
+    }
+
+    /// Like [`LabelsReader::into_labels`], also returning the number of
+    /// duplicate rows dropped.
+    pub fn into_labels_and_duplicates(self) -> Result<(Labels, usize), Error> {
+        let label_names = self.label_names.clone();
+        let label_name_hash = self.label_name_hash;
+        let (label_values, duplicates_removed) = if self.dedup {
+            let mut deduplicator = RowDeduplicator::default();
+            for row in self {
+                deduplicator.push(row?);
+            }
+            (deduplicator.rows, deduplicator.removed)
+        } else {
+            (self.collect::<Result<_, _>>()?, 0)
+        };
+        Ok((
+            Labels {
+                label_names,
+                label_name_hash,
+                label_values,
+            },
+            duplicates_removed,
+        ))
+    }
+
+    fn read_row(&mut self, record: csv::Result<csv::StringRecord>) -> Result<Vec<String>, Error> {
+        let record = record.map_err(|source| Error::Csv {
+            path: None,
+            record: Some(self.next_record),
+            source,
+        })?;
+        self.next_record += 1;
+        if record.len() != self.label_names.len() {
+            return Err(Error::FieldCount {
+                path: None,
+                line: record.position().map_or(0, |pos| pos.line()),
+                expected: self.label_names.len(),
+                found: record.len(),
+            });
+        }
+        Ok(record.iter().map(|s| s.to_owned()).collect())
+    }
+}
+
+impl Iterator for LabelsReader {
+    type Item = Result<Vec<String>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.records.next()?;
+        Some(self.read_row(record).map_err(|err| match &self.path {
+            Some(path) => err.with_path(path),
+            None => err,
+        }))
+    }
+}
+
+/// Hashes `label_names` with `H`, as done for the header of every label file.
+///
+/// Each name is followed by a `0xff` separator, see
+/// [`TsIdGenerator::write_label_names`].
+pub fn compute_label_name_hash<H>(label_names: &[String]) -> u64
+where
+    H: Default + Hasher + SeededHasher,
+{
+    let mut generator = TsIdGenerator::<H>::default();
+    generator.write_label_names(label_names.iter().map(|s| s.as_bytes()));
+    generator.build_ts_id()
+}
+
+/// Read labels from a CSV reader and compute the label name hash.
+///
+/// Every record must have the same number of fields as the header.
+pub fn read_labels_and_hash<H>(reader: Box<dyn Read>) -> Result<Labels, Error>
+where
+    H: Default + Hasher + SeededHasher,
+{
+    LabelsReader::new::<H>(reader)?.into_labels()
+}
+
+/// Like [`read_labels_and_hash`], parsing `reader` with the given options.
+pub fn read_labels_and_hash_with_options<H>(
+    reader: Box<dyn Read>,
+    options: &CsvReadOptions,
+) -> Result<Labels, Error>
+where
+    H: Default + Hasher + SeededHasher,
+{
+    LabelsReader::with_options::<H>(reader, options)?.into_labels()
+}
+
+/// Read labels from an in-memory CSV string and compute the label name hash.
+///
+/// Useful for tests that build label data inline instead of reading a file.
+pub fn read_labels_and_hash_str<H>(csv_str: &str) -> Result<Labels, Error>
+where
+    H: Default + Hasher + SeededHasher,
+{
+    read_labels_and_hash::<H>(Box::new(Cursor::new(csv_str.as_bytes().to_vec())))
+}
+
+/// Read labels from a CSV file and compute the label name hash.
+///
+/// Compressed files are handled like in [`open_csv_reader`]. Errors carry
+/// the path of the file.
+pub fn read_labels_from_path<H>(path: &str) -> Result<Labels, Error>
+where
+    H: Default + Hasher + SeededHasher,
+{
+    LabelsReader::from_path::<H>(path)?.into_labels()
+}
+
+/// Like [`read_labels_from_path`], parsing the file with the given options.
+pub fn read_labels_from_path_with_options<H>(
+    path: &str,
+    options: &CsvReadOptions,
+) -> Result<Labels, Error>
+where
+    H: Default + Hasher + SeededHasher,
+{
+    LabelsReader::from_path_with_options::<H>(path, options)?.into_labels()
+}
+
+/// Reads several label files, e.g. per-day exports, and merges them with
+/// [`Labels::merge`] without removing duplicates.
+pub fn read_labels_from_paths<H>(paths: &[&str]) -> Result<Labels, Error>
+where
+    H: Default + Hasher + SeededHasher,
+{
+    let sources = paths
+        .iter()
+        .map(|path| read_labels_from_path::<H>(path))
+        .collect::<Result<Vec<_>, Error>>()?;
+    Labels::merge::<H>(&sources, false)
+}
+
+/// Pairs each value with its column index, as consumed by [`RowEncoder::encode`],
+/// repeating all rows `scale` times.
+pub fn scale_rows(label_values: &[Vec<String>], scale: usize) -> Vec<Vec<(u32, String)>> {
+    (0..scale)
+        .flat_map(|_| label_values)
+        .map(|row| {
+            row.iter()
+                .enumerate()
+                .map(|(idx, val)| (idx as u32, val.clone()))
+                .collect()
+        })
+        .collect()
+}
+
+/// Same as [`scale_rows`], with the scaled rows shuffled as in
+/// [`Labels::shuffle_rows`].
+pub fn scale_and_shuffle(
+    label_values: &[Vec<String>],
+    scale: usize,
+    seed: u64,
+) -> Vec<Vec<(u32, String)>> {
+    let mut rows = scale_rows(label_values, scale);
+    rows.shuffle(&mut ChaCha8Rng::seed_from_u64(seed));
+    rows
+}
+
+/// Repeats all rows `scale` times.
+pub fn scale_label_values(label_values: &[Vec<String>], scale: usize) -> Vec<Vec<String>> {
+    (0..scale).flat_map(|_| label_values).cloned().collect()
+}
+
+/// Returns the ratio of the concatenated encoded `rows` to their gzip
+/// compressed size, at the default compression level.
+///
+/// Estimates the on-disk size of the encoded rows in a compressed file, as
+/// opposed to the raw sizes reported by the benchmarks.
+pub fn compute_gzip_compression_ratio<E: RowEncoder + ?Sized>(
+    encoder: &E,
+    rows: &[Vec<(u32, String)>],
+) -> f64 {
+    let mut encoded = Vec::new();
+    for row in rows {
+        encoder.encode(&mut encoded, row);
+    }
+    let mut gzip = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(&encoded)
+        .expect("writing to a Vec cannot fail");
+    let compressed = gzip.finish().expect("writing to a Vec cannot fail");
+    encoded.len() as f64 / compressed.len() as f64
+}
+
+// ============================================================================
+// Parquet encoding functions
+// ============================================================================
+
+/// Default maximum number of rows per row group used by [`open_parquet_writer`].
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 1024;
+
+/// Writer properties shared by all benchmark parquet files.
+///
+/// Statistics are disabled to keep files small, and row groups hold
+/// [`DEFAULT_ROW_GROUP_SIZE`] rows. Callers can further customize the builder,
+/// e.g. with `set_max_row_group_size`.
+pub fn default_writer_properties(compression: Compression) -> WriterPropertiesBuilder {
+    WriterProperties::builder()
+        .set_compression(compression)
+        .set_statistics_enabled(EnabledStatistics::None)
+        .set_max_row_group_size(DEFAULT_ROW_GROUP_SIZE)
+}
+
+/// Open an [`ArrowWriter`] configured with [`default_writer_properties`].
+pub fn open_parquet_writer<W: Write + Send>(
+    sink: W,
+    schema: Arc<Schema>,
+    compression: Compression,
+) -> Result<ArrowWriter<W>, ParquetError> {
+    let props = default_writer_properties(compression).build();
+    ArrowWriter::try_new(sink, schema, Some(props))
+}
+
+/// Write a single record batch to an in-memory parquet file.
+fn write_batch(
+    batch: &RecordBatch,
+    props: WriterProperties,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut buffer = Vec::new();
+    let cursor = Cursor::new(&mut buffer);
+    let mut writer = ArrowWriter::try_new(cursor, batch.schema(), Some(props))?;
+    writer.write(batch)?;
+    writer.close()?;
+
+    Ok(buffer)
+}
+
+/// Options for writing encoded rows to parquet.
+#[derive(Debug, Clone)]
+pub struct ParquetWriteOptions {
+    /// Record chunk and page min/max statistics on the `primary_key` column.
+    pub statistics: bool,
+    /// Maximum number of rows in a row group.
+    pub max_row_group_size: usize,
+}
+
+impl Default for ParquetWriteOptions {
+    fn default() -> Self {
+        Self {
+            statistics: false,
+            max_row_group_size: DEFAULT_ROW_GROUP_SIZE,
+        }
+    }
+}
+
+/// Encode rows to parquet using any RowEncoder implementation.
+pub fn encode_to_parquet<E: RowEncoder + ?Sized>(
+    encoder: &E,
+    rows: &[Vec<(u32, String)>],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    write_primary_key_batch(&primary_key_batch(encoder, rows)?)
+}
+
+/// Same as [`encode_to_parquet`] for rows whose values are borrowed, e.g. from
+/// [`InternedLabels::encoder_rows`], encoded with
+/// [`RowEncoder::encode_borrowed`].
+pub fn encode_borrowed_to_parquet<E: RowEncoder + ?Sized>(
+    encoder: &E,
+    rows: &[Vec<(u32, &str)>],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let batch = encoded_batch(rows, |buffer, row| encoder.encode_borrowed(buffer, row))?;
+    write_primary_key_batch(&batch)
+}
+
+fn write_primary_key_batch(batch: &RecordBatch) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut buffer = Vec::new();
+    let cursor = Cursor::new(&mut buffer);
+    let mut writer = open_parquet_writer(cursor, batch.schema(), Compression::UNCOMPRESSED)?;
+    writer.write(batch)?;
+    writer.close()?;
+
+    Ok(buffer)
+}
+
+/// Same as [`encode_to_parquet`], with dictionary encoding explicitly enabled
+/// on the `primary_key` column.
+///
+/// Dictionary pages pay off when many rows share the same encoded bytes, which
+/// is more likely for encoders with a predictable layout. The parquet writer
+/// enables dictionaries by default, so this pins the setting rather than
+/// relying on that default.
+pub fn encode_to_parquet_with_dict<E: RowEncoder + ?Sized>(
+    encoder: &E,
+    rows: &[Vec<(u32, String)>],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let batch = primary_key_batch(encoder, rows)?;
+    let props = default_writer_properties(Compression::UNCOMPRESSED)
+        .set_dictionary_enabled(true)
+        .set_column_dictionary_enabled(ColumnPath::new(vec!["primary_key".to_owned()]), true)
+        .build();
+    write_batch(&batch, props)
+}
+
+/// Encode rows to parquet along with an auto-increment `row_id` column.
+///
+/// The `row_id` of each row is its index in `rows`, which allows joining the
+/// encoded labels back to metric values.
+pub fn encode_to_parquet_with_row_index<E: RowEncoder + ?Sized>(
+    encoder: &E,
+    rows: &[Vec<(u32, String)>],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let primary_keys = primary_key_batch(encoder, rows)?;
+
+    let mut row_ids = UInt64Builder::with_capacity(rows.len());
+    for row_id in 0..rows.len() as u64 {
+        row_ids.append_value(row_id);
+    }
+
+    let schema = Schema::new(vec![
+        Field::new("row_id", DataType::UInt64, false),
+        Field::new("primary_key", DataType::Binary, false),
+    ]);
+    let batch = RecordBatch::try_new(
+        Arc::new(schema),
+        vec![Arc::new(row_ids.finish()), primary_keys.column(0).clone()],
+    )?;
+
+    write_batch(
+        &batch,
+        default_writer_properties(Compression::UNCOMPRESSED).build(),
+    )
+}
+
+/// Encode rows to parquet along with the ts_id of each row.
+///
+/// Produces a `tsid: UInt64` column and a `primary_key: Binary` column. Ts_ids
+/// are computed with `TsIdGenerator::<H>::from_seed(label_name_hash)` followed
+/// by `write_label_values`.
+pub fn encode_to_parquet_with_tsids<E, H>(
+    encoder: &E,
+    label_name_hash: u64,
+    rows: &[Vec<(u32, String)>],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>>
+where
+    E: RowEncoder + ?Sized,
+    H: Hasher + SeededHasher,
+{
+    let primary_keys = primary_key_batch(encoder, rows)?;
+
+    let mut ts_ids = UInt64Builder::with_capacity(rows.len());
+    for row in rows {
+        let mut generator = TsIdGenerator::<H>::from_seed(label_name_hash);
+        generator.write_label_values(row.iter().map(|(_, value)| value.as_bytes()));
+        ts_ids.append_value(generator.build_ts_id());
+    }
+
+    let schema = Schema::new(vec![
+        Field::new("tsid", DataType::UInt64, false),
+        Field::new("primary_key", DataType::Binary, false),
+    ]);
+    let batch = RecordBatch::try_new(
+        Arc::new(schema),
+        vec![Arc::new(ts_ids.finish()), primary_keys.column(0).clone()],
+    )?;
+
+    write_batch(
+        &batch,
+        default_writer_properties(Compression::UNCOMPRESSED).build(),
+    )
+}
+
+/// Like [`encode_to_parquet_with_tsids`], with the rows sorted by ascending
+/// ts_id so that a ts_id can be looked up by binary search.
+///
+/// Rows with equal ts_ids keep their input order. Page statistics are
+/// recorded on the `tsid` column, so readers can also skip row groups and
+/// pages outside the looked up ts_id.
+pub fn encode_to_parquet_with_tsid_index<E, H>(
+    encoder: &E,
+    label_name_hash: u64,
+    rows: &[Vec<(u32, String)>],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>>
+where
+    E: RowEncoder + ?Sized,
+    H: Hasher + SeededHasher,
+{
+    let mut generator = TsIdGenerator::<H>::from_seed(label_name_hash);
+    let mut ts_ids: Vec<(u64, usize)> = rows
+        .iter()
+        .enumerate()
+        .map(|(idx, row)| {
+            generator.reset();
+            generator.write_label_values(row.iter().map(|(_, value)| value.as_bytes()));
+            (generator.finish(), idx)
+        })
+        .collect();
+    ts_ids.sort_by_key(|(ts_id, _)| *ts_id);
+
+    let mut ts_id_builder = UInt64Builder::with_capacity(rows.len());
+    let mut primary_keys = BinaryBuilder::new();
+    let mut encoded_row = Vec::new();
+    for (ts_id, idx) in ts_ids {
+        ts_id_builder.append_value(ts_id);
+        encoder.encode(&mut encoded_row, &rows[idx]);
+        primary_keys.append_value(&encoded_row);
+        encoded_row.clear();
+    }
+
+    let schema = Schema::new(vec![
+        Field::new("tsid", DataType::UInt64, false),
+        Field::new("primary_key", DataType::Binary, false),
+    ]);
+    let batch = RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            Arc::new(ts_id_builder.finish()),
+            Arc::new(primary_keys.finish()),
+        ],
+    )?;
+
+    let props = default_writer_properties(Compression::UNCOMPRESSED)
+        .set_column_statistics_enabled(
+            ColumnPath::new(vec!["tsid".to_owned()]),
+            EnabledStatistics::Page,
+        )
+        .build();
+    write_batch(&batch, props)
+}
+
+/// Encode rows to parquet with explicit writer options.
+pub fn encode_to_parquet_with_options<E: RowEncoder + ?Sized>(
+    encoder: &E,
+    rows: &[Vec<(u32, String)>],
+    options: &ParquetWriteOptions,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let props = primary_key_writer_properties(options);
+    write_batch(&primary_key_batch(encoder, rows)?, props)
+}
+
+/// Encode label rows to parquet in `sink`, one row group at a time.
+///
+/// Column ids are the positions of the values in each row. Only
+/// `options.max_row_group_size` rows are held in memory, so `rows` can be a
+/// [`LabelsReader`] over a file of any size. Returns the number of rows written.
+pub fn write_label_rows_to_parquet<E, W, I>(
+    encoder: &E,
+    rows: I,
+    sink: W,
+    options: &ParquetWriteOptions,
+) -> Result<u64, Box<dyn std::error::Error>>
+where
+    E: RowEncoder + ?Sized,
+    W: Write + Send,
+    I: IntoIterator<Item = Result<Vec<String>, Error>>,
+{
+    let schema = Arc::new(Schema::new(vec![Field::new(
+        "primary_key",
+        DataType::Binary,
+        false,
+    )]));
+    let mut writer =
+        ArrowWriter::try_new(sink, schema, Some(primary_key_writer_properties(options)))?;
+
+    let mut num_rows = 0;
+    let mut chunk = Vec::with_capacity(options.max_row_group_size);
+    let mut rows = rows.into_iter().peekable();
+    while rows.peek().is_some() {
+        chunk.clear();
+        for row in rows.by_ref().take(options.max_row_group_size) {
+            chunk.push(
+                row?.into_iter()
+                    .enumerate()
+                    .map(|(idx, value)| (idx as u32, value))
+                    .collect(),
+            );
+        }
+        writer.write(&primary_key_batch(encoder, &chunk)?)?;
+        num_rows += chunk.len() as u64;
+    }
+    writer.close()?;
+
+    Ok(num_rows)
+}
+
+/// Writer properties for a `primary_key` file with the given options.
+pub(crate) fn primary_key_writer_properties(options: &ParquetWriteOptions) -> WriterProperties {
+    let statistics = if options.statistics {
+        EnabledStatistics::Page
+    } else {
+        EnabledStatistics::None
+    };
+    default_writer_properties(Compression::UNCOMPRESSED)
+        .set_column_statistics_enabled(ColumnPath::new(vec!["primary_key".to_owned()]), statistics)
+        .set_max_row_group_size(options.max_row_group_size)
+        .build()
+}
+
+/// Build a single-column `primary_key` batch from encoded rows.
+pub(crate) fn primary_key_batch<E: RowEncoder + ?Sized>(
+    encoder: &E,
+    rows: &[Vec<(u32, String)>],
+) -> Result<RecordBatch, ArrowError> {
+    encoded_batch(rows, |buffer, row| encoder.encode(buffer, row))
+}
+
+/// Build a single-column `primary_key` batch from rows encoded by `encode`.
+fn encoded_batch<R>(
+    rows: &[R],
+    mut encode: impl FnMut(&mut Vec<u8>, &R),
+) -> Result<RecordBatch, ArrowError> {
+    let schema = Schema::new(vec![Field::new("primary_key", DataType::Binary, false)]);
+    let schema = Arc::new(schema);
+
+    let mut builder = BinaryBuilder::new();
+    let mut encoded_row = Vec::new();
+    for row in rows {
+        encode(&mut encoded_row, row);
+        builder.append_value(&encoded_row);
+        encoded_row.clear();
+    }
+
+    let array = Arc::new(builder.finish());
+    RecordBatch::try_new(schema, vec![array])
+}
+
+/// Encode using MapArray in Arrow (special case - uses label names as keys).
+pub fn encode_to_parquet_maparray(
+    label_names: &[String],
+    label_values: &[Vec<String>],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    write_maparray(label_names, label_values.iter())
+}
+
+/// Same as [`encode_to_parquet_maparray`], but groups rows sharing the same
+/// value of the first label so that equal strings are contiguous, which makes
+/// dictionary encoding more effective.
+///
+/// Rows are sorted stably by their first value; `label_values` is not modified.
+pub fn encode_to_parquet_maparray_sorted(
+    label_names: &[String],
+    label_values: &[Vec<String>],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut rows: Vec<&Vec<String>> = label_values.iter().collect();
+    rows.sort_by(|a, b| a.first().cmp(&b.first()));
+    write_maparray(label_names, rows.into_iter())
+}
+
+/// Streaming variant of [`encode_to_parquet_maparray`] for datasets that do
+/// not fit in memory.
+///
+/// Rows are buffered into a [`MapBuilder`] and written to `sink` as a record
+/// batch every `batch_size` rows, so at most one batch is held in memory.
+///
+/// # Panics
+///
+/// Panics if `batch_size` is zero.
+pub fn encode_to_parquet_maparray_streaming<R, W>(
+    label_names: &[String],
+    records: R,
+    sink: W,
+    batch_size: usize,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    R: Iterator<Item = Vec<String>>,
+    W: Write + Send,
+{
+    assert!(batch_size > 0, "batch_size must be positive");
+
+    let mut map_builder = MapBuilder::new(None, StringBuilder::new(), StringBuilder::new());
+    // Finishing the empty builder yields the map type without writing any rows.
+    let schema = maparray_schema(map_builder.finish().data_type());
+    let mut writer = ArrowWriter::try_new(sink, schema.clone(), Some(maparray_properties()))?;
+
+    let mut buffered = 0;
+    for row in records {
+        append_maparray_row(&mut map_builder, label_names, &row)?;
+        buffered += 1;
+        if buffered == batch_size {
+            let map_array = Arc::new(map_builder.finish());
+            writer.write(&RecordBatch::try_new(schema.clone(), vec![map_array])?)?;
+            buffered = 0;
+        }
+    }
+    if buffered > 0 {
+        let map_array = Arc::new(map_builder.finish());
+        writer.write(&RecordBatch::try_new(schema, vec![map_array])?)?;
+    }
+    writer.close()?;
+
+    Ok(())
+}
+
+/// Encode each label as its own `Dictionary(Int32, Utf8)` column, named after
+/// the label.
+///
+/// Unlike the single binary or map column of the other layouts, every label
+/// gets its own parquet dictionary, which pays off for low-cardinality labels.
+/// Values missing from a short row are written as nulls.
+pub fn encode_to_parquet_dict_string(
+    label_names: &[String],
+    label_values: &[Vec<String>],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut builders: Vec<StringDictionaryBuilder<Int32Type>> = label_names
+        .iter()
+        .map(|_| StringDictionaryBuilder::new())
+        .collect();
+    for row in label_values {
+        for (idx, builder) in builders.iter_mut().enumerate() {
+            builder.append_option(row.get(idx));
+        }
+    }
+
+    let columns: Vec<ArrayRef> = builders
+        .iter_mut()
+        .map(|builder| Arc::new(builder.finish()) as ArrayRef)
+        .collect();
+    let fields: Vec<Field> = label_names
+        .iter()
+        .zip(&columns)
+        .map(|(name, column)| Field::new(name, column.data_type().clone(), true))
+        .collect();
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?;
+
+    let props = default_writer_properties(Compression::UNCOMPRESSED)
+        .set_dictionary_enabled(true)
+        .build();
+    write_batch(&batch, props)
+}
+
+fn write_maparray<'a>(
+    label_names: &[String],
+    label_values: impl Iterator<Item = &'a Vec<String>>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let key_builder = StringBuilder::new();
+    let value_builder = StringBuilder::new();
+    let mut map_builder = MapBuilder::new(None, key_builder, value_builder);
+
+    for row in label_values {
+        append_maparray_row(&mut map_builder, label_names, row)?;
+    }
+
+    let map_array = map_builder.finish();
+    let schema = maparray_schema(map_array.data_type());
+
+    let map_array = Arc::new(map_array);
+    let batch = RecordBatch::try_new(schema, vec![map_array])?;
+
+    write_batch(&batch, maparray_properties())
+}
+
+fn append_maparray_row(
+    map_builder: &mut MapBuilder<StringBuilder, StringBuilder>,
+    label_names: &[String],
+    row: &[String],
+) -> Result<(), ArrowError> {
+    for (label_name, value) in label_names.iter().zip(row.iter()) {
+        map_builder.keys().append_value(label_name);
+        map_builder.values().append_value(value);
+    }
+    // `append` closes the map holding the entries pushed since the last call.
+    map_builder.append(true)
+}
+
+fn maparray_schema(map_type: &DataType) -> Arc<Schema> {
+    let map_field = Field::new("labels", map_type.clone(), false);
+    Arc::new(Schema::new(vec![map_field]))
+}
+
+fn maparray_properties() -> WriterProperties {
+    default_writer_properties(Compression::UNCOMPRESSED)
+        .set_dictionary_enabled(true)
+        .set_column_dictionary_enabled(
+            ColumnPath::new(vec![
+                "labels".to_owned(),
+                "entries".to_owned(),
+                "keys".to_owned(),
+            ]),
+            true,
+        )
+        .set_column_dictionary_enabled(
+            ColumnPath::new(vec![
+                "labels".to_owned(),
+                "entries".to_owned(),
+                "values".to_owned(),
+            ]),
+            true,
+        )
+        .build()
+}
+
+// ============================================================================
+// Ts_id column layouts
+// ============================================================================
+
+/// Physical layout of the `ts_id` column written by [`encode_tsids_to_parquet`].
+///
+/// 128-bit ts_ids are written by [`encode_tsids_128_to_parquet`] as
+/// `FixedSizeBinary(16)` instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TsIdLayout {
+    /// `UInt64` with `PLAIN` encoding. Ts_ids are mostly distinct, so the
+    /// column is written without a dictionary.
+    UInt64,
+    /// `UInt64` with `DELTA_BINARY_PACKED` encoding.
+    UInt64Delta,
+    /// `FixedSizeBinary(8)` holding the big-endian ts_id bytes.
+    FixedSizeBinary,
+}
+
+/// Options for [`encode_tsids_to_parquet`].
+#[derive(Debug, Clone, Copy)]
+pub struct TsIdParquetOptions {
+    pub layout: TsIdLayout,
+    /// Sort ts_ids ascending before writing.
+    pub sorted: bool,
+}
+
+/// Computes the ts_id of a label row, seeded with the label name hash.
+///
+/// Values may be owned or borrowed, e.g. from the pool of [`InternedLabels`].
+pub fn label_row_ts_id<H>(label_name_hash: u64, row: &[impl AsRef<str>]) -> u64
+where
+    H: Hasher + SeededHasher,
+{
+    let mut generator = TsIdGenerator::<H>::from_seed(label_name_hash);
+    generator.write_label_values(row.iter().map(|s| s.as_ref().as_bytes()));
+    generator.build_ts_id()
+}
+
+/// Like [`label_row_ts_id`], building a 128-bit ts_id.
+pub fn label_row_ts_id_128<H>(label_name_hash: u64, row: &[String]) -> u128
+where
+    H: Hasher128Ext + SeededHasher,
+{
+    let mut generator = TsIdGenerator::<H>::from_seed(label_name_hash);
+    generator.write_label_values(row.iter().map(|s| s.as_bytes()));
+    generator.build_ts_id_128()
+}
+
+/// Lazily computes the ts_id of each row, e.g. as produced by a [`LabelsReader`].
+pub fn generate_ts_ids<H, I>(
+    label_name_hash: u64,
+    rows: I,
+) -> impl Iterator<Item = Result<u64, Error>>
+where
+    H: Hasher + SeededHasher,
+    I: IntoIterator<Item = Result<Vec<String>, Error>>,
+{
+    rows.into_iter()
+        .map(move |row| row.map(|row| label_row_ts_id::<H>(label_name_hash, &row)))
+}
+
+/// Write only the ts_id of each label row to parquet, using the given layout.
+///
+/// Ts_ids are computed with `TsIdGenerator::<H>::from_seed(labels.label_name_hash)`
+/// followed by `write_label_values`.
+pub fn encode_tsids_to_parquet<H>(
+    labels: &Labels,
+    options: TsIdParquetOptions,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>>
+where
+    H: Hasher + SeededHasher,
+{
+    let mut ts_ids = compute_ts_ids::<H>(labels, TsIdMode::Seeded);
+    if options.sorted {
+        ts_ids.sort_unstable();
+    }
+
+    let mut props = default_writer_properties(Compression::UNCOMPRESSED);
+    let column = ColumnPath::new(vec!["ts_id".to_owned()]);
+    let array: ArrayRef = match options.layout {
+        TsIdLayout::UInt64 => {
+            props = props.set_column_dictionary_enabled(column, false);
+            Arc::new(UInt64Array::from(ts_ids))
+        }
+        TsIdLayout::UInt64Delta => {
+            props = props
+                .set_column_dictionary_enabled(column.clone(), false)
+                .set_column_encoding(column, Encoding::DELTA_BINARY_PACKED);
+            Arc::new(UInt64Array::from(ts_ids))
+        }
+        TsIdLayout::FixedSizeBinary => {
+            let mut builder = FixedSizeBinaryBuilder::with_capacity(ts_ids.len(), 8);
+            for ts_id in ts_ids {
+                builder.append_value(ts_id.to_be_bytes())?;
+            }
+            Arc::new(builder.finish())
+        }
+    };
+
+    write_tsid_column(array, props.build())
+}
+
+/// Write the 128-bit ts_id of each label row to parquet, as a
+/// `FixedSizeBinary(16)` column holding the big-endian ts_id bytes.
+///
+/// Ts_ids are computed like in [`encode_tsids_to_parquet`], with
+/// [`TsIdGenerator::build_ts_id_128`].
+pub fn encode_tsids_128_to_parquet<H>(
+    labels: &Labels,
+    sorted: bool,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>>
+where
+    H: Hasher128Ext + SeededHasher,
+{
+    let mut ts_ids: Vec<u128> = labels
+        .label_values
+        .iter()
+        .map(|row| label_row_ts_id_128::<H>(labels.label_name_hash, row))
+        .collect();
+    if sorted {
+        ts_ids.sort_unstable();
+    }
+
+    let mut builder = FixedSizeBinaryBuilder::with_capacity(ts_ids.len(), 16);
+    for ts_id in ts_ids {
+        builder.append_value(ts_id.to_be_bytes())?;
+    }
+    write_tsid_column(
+        Arc::new(builder.finish()),
+        default_writer_properties(Compression::UNCOMPRESSED).build(),
+    )
+}
+
+fn write_tsid_column(
+    array: ArrayRef,
+    props: WriterProperties,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let schema = Arc::new(Schema::new(vec![Field::new(
+        "ts_id",
+        array.data_type().clone(),
+        false,
+    )]));
+    let batch = RecordBatch::try_new(schema, vec![array])?;
+    write_batch(&batch, props)
+}
+
+// ============================================================================
+// Row group pruning
+// ============================================================================
+
+/// Returns the indices of row groups whose `primary_key` min/max statistics
+/// overlap the key range `[lower, upper)`.
+///
+/// Row groups without statistics are always kept, since they cannot be ruled out.
+pub fn prune_row_groups(
+    bytes: &[u8],
+    lower: &[u8],
+    upper: &[u8],
+) -> Result<Vec<usize>, Box<dyn std::error::Error>> {
+    let reader = SerializedFileReader::new(bytes::Bytes::copy_from_slice(bytes))?;
+    let metadata = reader.metadata();
+    let column_idx = metadata
+        .file_metadata()
+        .schema_descr()
+        .columns()
+        .iter()
+        .position(|column| column.name() == "primary_key")
+        .ok_or("primary_key column not found")?;
+
+    let kept = metadata
+        .row_groups()
+        .iter()
+        .enumerate()
+        .filter(|(_, row_group)| {
+            let Some(stats) = row_group.column(column_idx).statistics() else {
+                return true;
+            };
+            match (stats.min_bytes_opt(), stats.max_bytes_opt()) {
+                (Some(min), Some(max)) => min < upper && max >= lower,
+                _ => true,
+            }
+        })
+        .map(|(idx, _)| idx)
+        .collect();
+
+    Ok(kept)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::hash::DefaultHasher;
+
+    use ahash::AHasher;
+    use arrow::array::{AsArray, FixedSizeBinaryArray};
+    use arrow::datatypes::UInt64Type;
+    use fxhash::FxHasher64;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use rand::RngCore;
+    use siphasher::sip::{SipHasher13, SipHasher24};
+    use wyhash::WyHash;
+    use xxhash_rust::xxh3::Xxh3;
+    use xxhash_rust::xxh64::Xxh64;
+
+    use super::*;
+
+    /// Checks that `total_rows` amplified label rows have distinct ts_ids.
+    fn test_hasher<H: Hasher + Default + SeededHasher>(total_rows: usize) {
+        let mut labels = read_labels_from_path::<H>("./assets/labels.csv.gz").unwrap();
+        labels.dedup();
+        let amp = total_rows.div_ceil(labels.label_values.len());
+        let instance_idx = labels
+            .label_names
+            .iter()
+            .position(|name| name == "instance")
+            .unwrap();
+        let mut all_hash_codes = HashSet::with_capacity(labels.label_values.len() * amp);
+
+        for batch in labels.amplify_batches(amp, instance_idx, 1 << 16) {
+            for ts_id in compute_ts_ids::<H>(&batch, TsIdMode::Seeded) {
+                assert!(all_hash_codes.insert(ts_id));
+            }
+        }
+    }
+
+    fn test_hasher_128<H: Hasher128Ext + SeededHasher>(total_rows: usize) {
+        let mut labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        labels.dedup();
+        let amp = total_rows.div_ceil(labels.label_values.len());
+        let instance_idx = labels
+            .label_names
+            .iter()
+            .position(|name| name == "instance")
+            .unwrap();
+        let mut all_hash_codes = HashSet::with_capacity(labels.label_values.len() * amp);
+
+        for label_values in labels.amplify(amp, instance_idx) {
+            let mut ts_id_gen = TsIdGenerator::<H>::from_seed(labels.label_name_hash);
+            ts_id_gen.write_label_values(label_values.iter().map(|a| a.as_slice()));
+            assert!(all_hash_codes.insert(ts_id_gen.build_ts_id_128()));
+        }
+    }
+
+    #[test]
+    fn parse_null_representation() {
+        assert_eq!("empty".parse(), Ok(NullRepresentation::Empty));
+        assert_eq!("skip-row".parse(), Ok(NullRepresentation::SkipRow));
+        assert_eq!(
+            "literal:<null>".parse(),
+            Ok(NullRepresentation::Literal("<null>".to_owned()))
+        );
+        assert!("null".parse::<NullRepresentation>().is_err());
+
+        assert_eq!(NullRepresentation::Empty.parse(""), None);
+        assert_eq!(NullRepresentation::Empty.parse("up"), Some("up"));
+        let literal = NullRepresentation::Literal("<null>".to_owned());
+        assert_eq!(literal.parse("<null>"), None);
+        assert_eq!(literal.parse(""), Some(""));
+        assert_eq!(NullRepresentation::SkipRow.parse(""), Some(""));
+    }
+
+    #[test]
+    fn test_from_map_iter() {
+        let maps = [
+            vec![("job", "node"), ("instance", "a:9100")],
+            vec![("instance", "b:9100"), ("env", "prod")],
+            vec![("job", "api")],
+            vec![],
+            vec![("region", "eu"), ("job", "node"), ("env", "dev")],
+        ]
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                .collect::<HashMap<_, _>>()
+        });
+        let labels = Labels::from_map_iter::<Xxh3, _>(maps);
+
+        assert_eq!(labels.label_names, ["env", "instance", "job", "region"]);
+        assert_eq!(
+            labels.label_values,
+            [
+                ["", "a:9100", "node", ""],
+                ["prod", "b:9100", "", ""],
+                ["", "", "api", ""],
+                ["", "", "", ""],
+                ["dev", "", "node", "eu"],
+            ]
+        );
+        let expected = read_labels_and_hash_str::<Xxh3>("env,instance,job,region\n").unwrap();
+        assert_eq!(labels.label_name_hash, expected.label_name_hash);
+    }
+
+    #[test]
+    fn test_merge_fixtures() {
+        let labels = read_labels_from_paths::<Xxh3>(&[
+            "./assets/fixtures/merge/day1.csv",
+            "./assets/fixtures/merge/day2.csv",
+            "./assets/fixtures/merge/day3.csv",
+        ])
+        .unwrap();
+        let row = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(labels.label_names, ["job", "instance", "env", "region"]);
+        assert_eq!(
+            labels.label_values,
+            [
+                row(&["node", "a:9100", "", ""]),
+                row(&["api", "b:8080", "", ""]),
+                row(&["node", "c:9100", "prod", ""]),
+                row(&["node", "a:9100", "", ""]),
+            ]
+        );
+        let expected = read_labels_and_hash_str::<Xxh3>("job,instance,env,region\n").unwrap();
+        assert_eq!(labels.label_name_hash, expected.label_name_hash);
+    }
+
+    #[test]
+    fn test_project() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/fixtures/merge/day2.csv").unwrap();
+
+        let projected = labels.project::<Xxh3>(&["job", "instance"]).unwrap();
+        assert_eq!(projected.label_names, ["job", "instance"]);
+        assert_eq!(
+            projected.label_values,
+            [["node", "c:9100"], ["node", "a:9100"]]
+        );
+        let expected = read_labels_and_hash_str::<Xxh3>("job,instance\n").unwrap();
+        assert_eq!(projected.label_name_hash, expected.label_name_hash);
+
+        let dropped = labels.drop::<Xxh3>(&["instance"]).unwrap();
+        assert_eq!(dropped.label_names, ["job", "env"]);
+        assert_eq!(dropped.label_values, [["node", "prod"], ["node", ""]]);
+        let expected = read_labels_and_hash_str::<Xxh3>("job,env\n").unwrap();
+        assert_eq!(dropped.label_name_hash, expected.label_name_hash);
+
+        assert!(matches!(
+            labels.project::<Xxh3>(&["job", "pod"]),
+            Err(Error::UnknownLabel { name }) if name == "pod"
+        ));
+        assert!(matches!(
+            labels.drop::<Xxh3>(&["pod"]),
+            Err(Error::UnknownLabel { name }) if name == "pod"
+        ));
+        assert!(matches!(
+            labels.project::<Xxh3>(&["job", "env", "job"]),
+            Err(Error::DuplicateLabels { duplicates, .. }) if duplicates == [("job".to_owned(), vec![0, 2])]
+        ));
+    }
+
+    #[test]
+    fn test_sorted_pair_ts_ids() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/fixtures/merge/day2.csv").unwrap();
+        let reordered = labels.project::<Xxh3>(&["env", "job", "instance"]).unwrap();
+        assert_ne!(reordered.label_names, labels.label_names);
+
+        let ts_ids = labels.sorted_pair_ts_ids::<Xxh3>(0);
+        assert_eq!(ts_ids, reordered.sorted_pair_ts_ids::<Xxh3>(0));
+        assert_eq!(ts_ids.len(), 2);
+        assert_ne!(ts_ids[0], ts_ids[1]);
+    }
+
+    #[test]
+    fn test_compute_gzip_compression_ratio() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        let rows = scale_rows(&labels.label_values, 1);
+        let ratio = compute_gzip_compression_ratio(&VarintEncoder, &rows);
+        // Label values repeat a lot across series.
+        assert!(ratio > 2.0, "ratio {ratio}");
+
+        let doubled = scale_rows(&labels.label_values, 2);
+        assert!(compute_gzip_compression_ratio(&VarintEncoder, &doubled) > ratio);
+
+        // Random bytes don't compress, gzip only adds its framing.
+        let ratio = compute_gzip_compression_ratio(&RandomBytesEncoder, &rows[..100]);
+        assert!((0.95..=1.0).contains(&ratio), "ratio {ratio}");
+    }
+
+    /// Encodes every row as 1 KiB of random bytes.
+    struct RandomBytesEncoder;
+
+    impl RowEncoder for RandomBytesEncoder {
+        fn name(&self) -> &'static str {
+            "random_bytes"
+        }
+
+        fn encode(&self, buffer: &mut Vec<u8>, _row: &[(u32, String)]) {
+            let mut bytes = [0; 1024];
+            rand::rng().fill_bytes(&mut bytes);
+            buffer.extend_from_slice(&bytes);
+        }
+
+        fn decode(&self, _data: &[u8]) -> Result<Vec<(u32, String)>, DecodeError> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn test_merge_dedup() {
+        let day1 = read_labels_from_path::<Xxh3>("./assets/fixtures/merge/day1.csv").unwrap();
+        let day2 = read_labels_from_path::<Xxh3>("./assets/fixtures/merge/day2.csv").unwrap();
+        let empty = read_labels_from_path::<Xxh3>("./assets/fixtures/merge/day3.csv").unwrap();
+
+        let sources = [day1, empty, day2];
+        let merged = Labels::merge::<Xxh3>(&sources, true).unwrap();
+        assert_eq!(merged.label_names, ["job", "instance", "env", "region"]);
+        assert_eq!(merged.label_values.len(), 3);
+        let mut undeduplicated = Labels::merge::<Xxh3>(&sources, false).unwrap();
+        assert_eq!(undeduplicated.dedup(), 1);
+        assert_eq!(undeduplicated.label_values, merged.label_values);
+
+        let only_empty = read_labels_from_path::<Xxh3>("./assets/fixtures/merge/day3.csv").unwrap();
+        let merged = Labels::merge::<Xxh3>(&[only_empty], false).unwrap();
+        assert_eq!(merged.label_names, ["env", "region"]);
+        assert!(merged.label_values.is_empty());
+        assert!(
+            Labels::merge::<Xxh3>(&[], false)
+                .unwrap()
+                .label_names
+                .is_empty()
+        );
+
+        let duplicate = Labels {
+            label_names: vec!["job".to_owned(), "job".to_owned()],
+            label_name_hash: 0,
+            label_values: vec![vec!["a".to_owned(), "b".to_owned()]],
+        };
+        assert!(matches!(
+            Labels::merge::<Xxh3>(&[duplicate], false),
+            Err(Error::DuplicateLabels { duplicates, .. }) if duplicates[0].0 == "job"
+        ));
+    }
+
+    #[test]
+    fn test_shuffle_rows() {
+        let csv = (0..10).fold("idx\n".to_owned(), |csv, idx| csv + &format!("{idx}\n"));
+        let mut labels = read_labels_and_hash_str::<Xxh3>(&csv).unwrap();
+        labels.shuffle_rows(42);
+        let order: Vec<&str> = labels
+            .label_values
+            .iter()
+            .map(|row| row[0].as_str())
+            .collect();
+        assert_eq!(order, ["7", "1", "5", "4", "6", "9", "3", "8", "2", "0"]);
+
+        let shuffled = scale_and_shuffle(&labels.label_values, 2, 7);
+        let mut expected = scale_rows(&labels.label_values, 2);
+        assert_ne!(shuffled, expected);
+        let mut sorted = shuffled.clone();
+        sorted.sort();
+        expected.sort();
+        assert_eq!(sorted, expected);
+        assert_eq!(shuffled, scale_and_shuffle(&labels.label_values, 2, 7));
+    }
+
+    #[test]
+    fn test_scale() {
+        let csv = "job,instance\nnode,a\napi,b\n";
+        let labels = read_labels_and_hash_str::<Xxh3>(csv).unwrap();
+
+        let rows = scale_rows(&labels.label_values, 3);
+        assert_eq!(rows.len(), 3 * labels.label_values.len());
+        assert_eq!(rows[3], [(0, "api".to_owned()), (1, "b".to_owned())]);
+
+        let values = scale_label_values(&labels.label_values, 3);
+        assert_eq!(values.len(), 3 * labels.label_values.len());
+        assert_eq!(values[2], labels.label_values[0]);
+        assert!(scale_rows(&labels.label_values, 0).is_empty());
+    }
+
+    #[test]
+    fn test_amplify() {
+        let csv = "job,instance\nnode,a\napi,a\n";
+        let labels = read_labels_and_hash_str::<Xxh3>(csv).unwrap();
+
+        let rows: Vec<Vec<Vec<u8>>> = labels.amplify(3, 1).collect();
+        assert_eq!(rows.len(), 6);
+        assert_eq!(rows.iter().collect::<HashSet<_>>().len(), 6);
+        assert_eq!(rows[0], [b"node".to_vec(), b"a-0".to_vec()]);
+        assert_eq!(rows[2], [b"node".to_vec(), b"a-2".to_vec()]);
+        assert_eq!(rows[3], [b"api".to_vec(), b"a-0".to_vec()]);
+        assert_eq!(labels.amplify(0, 0).count(), 0);
+    }
+
+    #[test]
+    fn compile_time_label_name_hash() {
+        let csv = "job,instance,env\nnode,localhost:9090,prod\n";
+        let labels = read_labels_and_hash::<Xxh3>(Box::new(csv.as_bytes())).unwrap();
+        assert_eq!(
+            tsid_bench_macros::tsid_label_name_hash!(["job", "instance", "env"]),
+            labels.label_name_hash
+        );
+        assert_ne!(
+            tsid_bench_macros::tsid_label_name_hash!(["instance", "job", "env"]),
+            labels.label_name_hash
+        );
+    }
+
+    #[ignore]
+    #[test]
+    fn check_collisions() {
+        let total_rows = 100_000_000;
+        test_hasher::<Xxh3>(total_rows);
+        test_hasher::<Xxh64>(total_rows);
+        test_hasher::<FxHasher64>(total_rows);
+        test_hasher::<DefaultHasher>(total_rows);
+        test_hasher::<SipHasher13>(total_rows);
+        test_hasher::<SipHasher24>(total_rows);
+        test_hasher::<AHasher>(total_rows);
+        test_hasher::<WyHash>(total_rows);
+        test_hasher_128::<Xxh3>(total_rows);
+        test_hasher_128::<mur3::Hasher128>(total_rows);
+    }
+
+    #[test]
+    fn test_read_compressed_fixtures() {
+        let expected = read_labels_from_path::<Xxh3>("./assets/fixtures/labels.csv").unwrap();
+        assert_eq!(expected.label_values.len(), 3);
+
+        for path in [
+            "./assets/fixtures/labels.csv.gz",
+            "./assets/fixtures/labels.csv.zst",
+            // zstd content without an extension, detected from the magic bytes.
+            "./assets/fixtures/labels-zstd",
+        ] {
+            let labels = read_labels_from_path::<Xxh3>(path).unwrap();
+            assert_eq!(labels.label_names, expected.label_names, "{path}");
+            assert_eq!(labels.label_name_hash, expected.label_name_hash, "{path}");
+            assert_eq!(labels.label_values, expected.label_values, "{path}");
+        }
+    }
+
+    #[test]
+    fn test_read_delimited_fixtures() {
+        let expected = read_labels_from_path::<Xxh3>("./assets/fixtures/labels.csv").unwrap();
+
+        for (path, delimiter) in [
+            ("./assets/fixtures/labels.tsv", b'\t'),
+            ("./assets/fixtures/labels.psv", b'|'),
+        ] {
+            let options = CsvReadOptions {
+                delimiter,
+                ..Default::default()
+            };
+            let labels = read_labels_from_path_with_options::<Xxh3>(path, &options).unwrap();
+            assert_eq!(labels.label_names, expected.label_names, "{path}");
+            assert_eq!(labels.label_name_hash, expected.label_name_hash, "{path}");
+            assert_eq!(labels.label_values, expected.label_values, "{path}");
+        }
+    }
+
+    #[test]
+    fn test_read_without_headers() {
+        let expected = read_labels_from_path::<Xxh3>("./assets/fixtures/labels.csv").unwrap();
+        let options = CsvReadOptions {
+            has_headers: false,
+            ..Default::default()
+        };
+        let labels = read_labels_from_path_with_options::<Xxh3>(
+            "./assets/fixtures/labels-no-header.csv",
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(labels.label_names, ["label_0", "label_1"]);
+        assert_eq!(labels.label_values, expected.label_values);
+        let mut generator = TsIdGenerator::<Xxh3>::default();
+        generator.write_label_names([b"label_0".as_slice(), b"label_1".as_slice()].into_iter());
+        assert_eq!(labels.label_name_hash, generator.build_ts_id());
+    }
+
+    #[test]
+    fn test_recompute_label_name_hash() {
+        let mut labels = read_labels_from_path::<Xxh3>("./assets/fixtures/merge/day1.csv").unwrap();
+        let original = labels.label_name_hash;
+        assert_eq!(
+            original,
+            compute_label_name_hash::<Xxh3>(&labels.label_names)
+        );
+
+        labels.label_names.push("env".to_owned());
+        for row in &mut labels.label_values {
+            row.push("prod".to_owned());
+        }
+        labels.recompute_label_name_hash::<Xxh3>();
+        assert_ne!(labels.label_name_hash, original);
+        let expected = read_labels_and_hash_str::<Xxh3>("job,instance,env\n").unwrap();
+        assert_eq!(labels.label_name_hash, expected.label_name_hash);
+    }
+
+    #[test]
+    fn test_dedup_preserves_first_seen_order() {
+        let csv = "job,instance\na,1\nb,2\na,1\nc,3\nb,2\na,1\n";
+        let expected = [["a", "1"], ["b", "2"], ["c", "3"]];
+
+        let mut labels = read_labels_and_hash_str::<Xxh3>(csv).unwrap();
+        assert_eq!(labels.dedup(), 3);
+        assert_eq!(labels.label_values, expected);
+        assert_eq!(labels.dedup(), 0);
+
+        let options = CsvReadOptions {
+            dedup: true,
+            ..Default::default()
+        };
+        let labels = read_labels_and_hash_with_options::<Xxh3>(
+            Box::new(std::io::Cursor::new(csv.as_bytes())),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(labels.label_values, expected);
+
+        let reader = LabelsReader::with_options::<Xxh3>(
+            Box::new(std::io::Cursor::new(csv.as_bytes())),
+            &options,
+        )
+        .unwrap();
+        let (labels, duplicates_removed) = reader.into_labels_and_duplicates().unwrap();
+        assert_eq!(labels.label_values, expected);
+        assert_eq!(duplicates_removed, 3);
+    }
+
+    #[test]
+    fn test_sort_rows() {
+        let mut labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        let mut expected = scale_rows(&labels.label_values, 1);
+        expected.sort();
+
+        labels.sort_rows();
+        assert_eq!(scale_rows(&labels.label_values, 1), expected);
+    }
+
+    /// Encodes only the first value of a row, so rows sharing it compare equal.
+    struct FirstValueEncoder;
+
+    impl RowEncoder for FirstValueEncoder {
+        fn name(&self) -> &'static str {
+            "first_value"
+        }
+
+        fn encode(&self, buffer: &mut Vec<u8>, row: &[(u32, String)]) {
+            buffer.extend_from_slice(row[0].1.as_bytes());
+        }
 
-use crate::ts_id_gen::{SeededHasher, TsIdGenerator};
+        /// Decodes the first value back as column 0; the rest of the row is
+        /// lost.
+        fn decode(&self, data: &[u8]) -> Result<Vec<(u32, String)>, DecodeError> {
+            let value = String::from_utf8(data.to_vec()).map_err(DecodeError::InvalidUtf8)?;
+            Ok(vec![(0, value)])
+        }
+    }
 
-pub mod data_reader;
-pub mod encoding;
-pub mod generated;
-pub mod ts_id_gen;
+    #[test]
+    fn test_sort_rows_by_is_stable() {
+        let csv = "job,instance\nnode,2\napi,1\nnode,1\napi,3\napi,2\n";
+        let mut labels = read_labels_and_hash_str::<Xxh3>(csv).unwrap();
 
-// Re-export encoding types for convenience
-pub use encoding::{
-    FlatBufferEncoder, LengthPrefixedEncoder, MemcomparableEncoder, RowEncoder, VarintEncoder,
-};
+        labels.sort_rows_by(&FirstValueEncoder);
+        assert_eq!(
+            labels.label_values,
+            [
+                ["api", "1"],
+                ["api", "3"],
+                ["api", "2"],
+                ["node", "2"],
+                ["node", "1"],
+            ]
+        );
+    }
 
-pub struct Labels {
-    pub label_names: Vec<String>,
-    pub label_name_hash: u64,
-    pub label_values: Vec<Vec<String>>,
-}
+    #[test]
+    fn test_duplicate_label_names() {
+        let read = |csv: &str, options: &CsvReadOptions| {
+            read_labels_and_hash_with_options::<Xxh3>(
+                Box::new(std::io::Cursor::new(csv.as_bytes().to_vec())),
+                options,
+            )
+        };
+        let csv = "job,env,Job,job,env\na,b,c,d,e\n";
 
-/// Create a reader from a file path, automatically handling gzip compression.
-///
-/// If the path ends with `.gz`, the file is decompressed using gzip.
-pub fn open_csv_reader(path: &str) -> Box<dyn Read> {
-    let file = File::open(path).expect("Failed to open file");
-    if path.ends_with(".gz") {
-        Box::new(BufReader::new(GzDecoder::new(file)))
-    } else {
-        Box::new(BufReader::new(file))
+        let err = read(csv, &CsvReadOptions::default()).err().unwrap();
+        let Error::DuplicateLabels { duplicates, .. } = &err else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(
+            duplicates,
+            &[
+                ("job".to_owned(), vec![0, 3]),
+                ("env".to_owned(), vec![1, 4])
+            ]
+        );
+        assert_eq!(
+            err.to_string(),
+            "duplicate label names: \"job\" at positions [0, 3]; \"env\" at positions [1, 4]"
+        );
+
+        let case_insensitive = CsvReadOptions {
+            ignore_label_case: true,
+            ..Default::default()
+        };
+        let err = read(csv, &case_insensitive).err().unwrap();
+        assert!(
+            matches!(&err, Error::DuplicateLabels { duplicates, .. } if duplicates[0] == ("job".to_owned(), vec![0, 2, 3])),
+            "{err}"
+        );
     }
-}
 
-/// Read labels from a CSV reader and compute the label name hash.
-pub fn read_labels_and_hash<H>(reader: Box<dyn Read>) -> Labels
-where
-    H: Default + Hasher + SeededHasher,
-{
-    let mut csv_reader = csv::ReaderBuilder::new().from_reader(reader);
+    #[test]
+    fn test_rename_duplicate_label_names() {
+        let read = |csv: &str, options: &CsvReadOptions| {
+            read_labels_and_hash_with_options::<Xxh3>(
+                Box::new(std::io::Cursor::new(csv.as_bytes().to_vec())),
+                options,
+            )
+            .unwrap()
+        };
+        let rename = CsvReadOptions {
+            rename_duplicate_labels: true,
+            ..Default::default()
+        };
 
-    let label_names: Vec<String> = csv_reader
-        .headers()
-        .expect("Failed to read headers")
-        .iter()
-        .map(|s| s.to_owned())
-        .collect();
+        let labels = read("job,env,job,job_2,job\na,b,c,d,e\n", &rename);
+        assert_eq!(
+            labels.label_names,
+            ["job", "env", "job_3", "job_2", "job_4"]
+        );
+        assert_eq!(labels.label_values, [["a", "b", "c", "d", "e"]]);
 
-    let mut generator = TsIdGenerator::<H>::default();
-    generator.write_label_names(label_names.iter().map(|s| s.as_bytes()));
-    let label_name_hash = generator.build_ts_id();
+        // The hash covers the rewritten names, not the original header.
+        let renamed = read("job,env,job_3,job_2,job_4\n", &CsvReadOptions::default());
+        assert_eq!(labels.label_name_hash, renamed.label_name_hash);
+        let mut generator = TsIdGenerator::<Xxh3>::default();
+        generator.write_label_names(
+            ["job", "env", "job", "job_2", "job"]
+                .map(str::as_bytes)
+                .into_iter(),
+        );
+        assert_ne!(labels.label_name_hash, generator.build_ts_id());
 
-    let label_values: Vec<Vec<String>> = csv_reader
-        .records()
-        .map(|record| {
-            record
-                .expect("Failed to read record")
-                .iter()
-                .map(|s| s.to_owned())
-                .collect()
-        })
-        .collect();
+        let labels = read(
+            "Job,job\na,b\n",
+            &CsvReadOptions {
+                ignore_label_case: true,
+                ..rename
+            },
+        );
+        assert_eq!(labels.label_names, ["Job", "job_2"]);
+    }
 
-    Labels {
-        label_names,
-        label_name_hash,
-        label_values,
+    #[test]
+    fn test_read_without_quoting() {
+        let csv = "job,instance\n\"prometheus,localhost:9090\n";
+        let options = CsvReadOptions {
+            quote: None,
+            ..Default::default()
+        };
+        let labels = read_labels_and_hash_with_options::<Xxh3>(
+            Box::new(std::io::Cursor::new(csv.as_bytes())),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(labels.label_values, [["\"prometheus", "localhost:9090"]]);
     }
-}
 
-// ============================================================================
-// Parquet encoding functions
-// ============================================================================
+    #[test]
+    fn test_open_unsupported_compression() {
+        let err = open_csv_reader("./assets/fixtures/labels.csv.xz")
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            Error::UnsupportedCompression { format: "xz", .. }
+        ));
+    }
 
-/// Encode rows to parquet using any RowEncoder implementation.
-pub fn encode_to_parquet<E: RowEncoder + ?Sized>(
-    encoder: &E,
-    rows: &[Vec<(u32, String)>],
-) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let schema = Schema::new(vec![Field::new("primary_key", DataType::Binary, false)]);
-    let schema = Arc::new(schema);
+    #[test]
+    fn test_detect_compression_from_magic() {
+        assert_eq!(
+            detect_compression("labels", &[0x1f, 0x8b, 0x08]),
+            Ok(CsvCompression::Gzip)
+        );
+        assert_eq!(
+            detect_compression("labels", &[0x28, 0xb5, 0x2f, 0xfd]),
+            Ok(CsvCompression::Zstd)
+        );
+        assert_eq!(detect_compression("labels", b"BZh91AY"), Err("bzip2"));
+        assert_eq!(
+            detect_compression("labels", b"job,instance"),
+            Ok(CsvCompression::None)
+        );
+        assert_eq!(detect_compression("labels", b""), Ok(CsvCompression::None));
+    }
 
-    let mut builder = BinaryBuilder::new();
-    let mut encoded_row = Vec::new();
-    for row in rows {
-        encoder.encode(&mut encoded_row, row);
-        builder.append_value(&encoded_row);
-        encoded_row.clear();
+    /// Yields one byte per read, like a slow pipe.
+    struct ByteAtATime<R>(R);
+
+    impl<R: Read> Read for ByteAtATime<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
     }
 
-    let array = Arc::new(builder.finish());
-    let batch = arrow::record_batch::RecordBatch::try_new(schema.clone(), vec![array])?;
+    #[test]
+    fn test_decompress_short_reads() {
+        let read = |data: Vec<u8>| {
+            let mut reader =
+                decompress_reader(STDIN_PATH, Box::new(ByteAtATime(Cursor::new(data))))?;
+            let mut text = String::new();
+            reader.read_to_string(&mut text).unwrap();
+            Ok::<_, Error>(text)
+        };
+        let csv = "job,instance\nnode,a:9100\n";
 
-    let mut buffer = Vec::new();
-    let cursor = Cursor::new(&mut buffer);
-    let props = WriterProperties::builder().build();
-    let mut writer = ArrowWriter::try_new(cursor, schema, Some(props))?;
-    writer.write(&batch)?;
-    writer.close()?;
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(csv.as_bytes()).unwrap();
+        assert_eq!(read(encoder.finish().unwrap()).unwrap(), csv);
+        let zstd = zstd::stream::encode_all(csv.as_bytes(), 0).unwrap();
+        assert_eq!(read(zstd).unwrap(), csv);
+        assert_eq!(read(csv.as_bytes().to_vec()).unwrap(), csv);
+        // Inputs shorter than a magic number are read as they are.
+        assert_eq!(read(b"j".to_vec()).unwrap(), "j");
+        assert_eq!(read(Vec::new()).unwrap(), "");
 
-    Ok(buffer)
-}
+        let xz = std::fs::read("./assets/fixtures/labels.csv.xz").unwrap();
+        assert!(matches!(
+            read(xz),
+            Err(Error::UnsupportedCompression { format: "xz", .. })
+        ));
+    }
 
-/// Encode using MapArray in Arrow (special case - uses label names as keys).
-pub fn encode_to_parquet_maparray(
-    label_names: &[String],
-    label_values: &[Vec<String>],
-) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let key_builder = StringBuilder::new();
-    let value_builder = StringBuilder::new();
-    let mut map_builder = MapBuilder::new(None, key_builder, value_builder);
+    #[test]
+    fn test_max_magic_len() {
+        let longest = UNSUPPORTED_COMPRESSIONS
+            .iter()
+            .map(|(_, _, magic)| magic.len())
+            .chain([GZIP_MAGIC.len(), ZSTD_MAGIC.len()])
+            .max();
+        assert_eq!(longest, Some(MAX_MAGIC_LEN));
+    }
 
-    for row in label_values {
-        map_builder.append(true)?;
-        for (label_name, value) in label_names.iter().zip(row.iter()) {
-            map_builder.keys().append_value(label_name);
-            map_builder.values().append_value(value);
-        }
+    #[test]
+    fn test_open_missing_file() {
+        let err = open_csv_reader("./assets/missing.csv").err().unwrap();
+        assert!(matches!(&err, Error::Io { path, .. } if path == "./assets/missing.csv"));
+        assert!(
+            err.to_string()
+                .starts_with("failed to read ./assets/missing.csv")
+        );
     }
 
-    let map_array = map_builder.finish();
-    let map_field = Field::new("labels", map_array.data_type().clone(), false);
-    let schema = Schema::new(vec![map_field]);
-    let schema = Arc::new(schema);
+    #[test]
+    fn test_read_labels_from_missing_path() {
+        let err = read_labels_from_path::<DefaultHasher>("./assets/missing.csv.gz")
+            .err()
+            .unwrap();
+        assert!(matches!(&err, Error::Io { path, .. } if path == "./assets/missing.csv.gz"));
+    }
 
-    let map_array = Arc::new(map_array);
-    let batch = arrow::record_batch::RecordBatch::try_new(schema.clone(), vec![map_array])?;
+    #[test]
+    fn test_read_labels_from_str() {
+        let csv =
+            "job,instance\nprometheus,localhost:9090\nnode,localhost:9100\napi,localhost:8080\n";
+        let labels = read_labels_and_hash_str::<Xxh3>(csv).unwrap();
 
-    let mut buffer = Vec::new();
-    let cursor = Cursor::new(&mut buffer);
-    let props = WriterProperties::builder()
-        .set_dictionary_enabled(true)
-        .set_column_dictionary_enabled(
-            ColumnPath::new(vec![
-                "labels".to_owned(),
-                "entries".to_owned(),
-                "keys".to_owned(),
-            ]),
-            true,
-        )
-        .set_column_dictionary_enabled(
-            ColumnPath::new(vec![
-                "labels".to_owned(),
-                "entries".to_owned(),
-                "values".to_owned(),
-            ]),
-            true,
+        assert_eq!(labels.label_names, ["job", "instance"]);
+        assert_eq!(
+            labels.label_values,
+            [
+                ["prometheus", "localhost:9090"],
+                ["node", "localhost:9100"],
+                ["api", "localhost:8080"],
+            ]
+        );
+
+        let mut generator = TsIdGenerator::<Xxh3>::default();
+        generator.write_label_names([b"job".as_slice(), b"instance".as_slice()].into_iter());
+        assert_eq!(labels.label_name_hash, generator.build_ts_id());
+    }
+
+    #[test]
+    fn test_labels_reader_streams_rows() {
+        let csv = "job,instance\nprometheus,localhost:9090\nnode,localhost:9100\n";
+        let labels = read_labels_and_hash_str::<Xxh3>(csv).unwrap();
+
+        let reader =
+            LabelsReader::new::<Xxh3>(Box::new(std::io::Cursor::new(csv.as_bytes()))).unwrap();
+        assert_eq!(reader.label_names(), labels.label_names);
+        assert_eq!(reader.label_name_hash(), labels.label_name_hash);
+
+        let label_name_hash = reader.label_name_hash();
+        let ts_ids: Vec<u64> = generate_ts_ids::<Xxh3, _>(label_name_hash, reader)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let expected: Vec<u64> = labels
+            .label_values
+            .iter()
+            .map(|row| label_row_ts_id::<Xxh3>(labels.label_name_hash, row))
+            .collect();
+        assert_eq!(ts_ids, expected);
+    }
+
+    #[test]
+    fn test_write_csv_roundtrip() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        let path = std::env::temp_dir().join(format!("tsid-bench-{}.csv.gz", std::process::id()));
+        let path = path.to_str().unwrap();
+        labels.write_csv_to_path(path).unwrap();
+        let reloaded = read_labels_from_path::<Xxh3>(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(reloaded.label_names, labels.label_names);
+        assert_eq!(reloaded.label_name_hash, labels.label_name_hash);
+        assert_eq!(reloaded.label_values, labels.label_values);
+
+        // Same through an in-memory buffer and `read_labels_and_hash`.
+        let mut buffer = Vec::new();
+        labels.write_csv(&mut buffer).unwrap();
+        let reloaded = read_labels_and_hash::<Xxh3>(Box::new(Cursor::new(buffer))).unwrap();
+        assert_eq!(reloaded.label_name_hash, labels.label_name_hash);
+        assert_eq!(reloaded.label_values, labels.label_values);
+    }
+
+    #[test]
+    fn test_write_csv_special_chars() {
+        let labels = Labels {
+            label_names: vec!["job".to_owned(), "path, \"quoted\"".to_owned()],
+            label_name_hash: 0,
+            label_values: vec![
+                vec!["a,b".to_owned(), "say \"hi\"".to_owned()],
+                vec!["line\nbreak".to_owned(), "crlf\r\n".to_owned()],
+                vec![String::new(), " padded ".to_owned()],
+            ],
+        };
+        let mut buffer = Vec::new();
+        labels.write_csv(&mut buffer).unwrap();
+        let reloaded =
+            read_labels_and_hash_str::<Xxh3>(std::str::from_utf8(&buffer).unwrap()).unwrap();
+
+        assert_eq!(reloaded.label_names, labels.label_names);
+        assert_eq!(reloaded.label_values, labels.label_values);
+
+        let path = std::env::temp_dir().join(format!("tsid-bench-{}.csv.xz", std::process::id()));
+        assert!(matches!(
+            labels.write_csv_to_path(path.to_str().unwrap()),
+            Err(Error::UnsupportedCompression { format: "xz", .. })
+        ));
+    }
+
+    #[test]
+    fn test_stream_rows_to_parquet() {
+        const NUM_ROWS: u64 = 20_000;
+        let path = std::env::temp_dir().join(format!("tsid-bench-{}.csv", std::process::id()));
+        let _guard = RemoveOnDrop(path.clone());
+        let path = path.to_str().unwrap();
+        {
+            let mut file = std::io::BufWriter::new(File::create(path).unwrap());
+            writeln!(file, "job,instance").unwrap();
+            for idx in 0..NUM_ROWS {
+                writeln!(file, "job-{},instance-{}", idx % 100, idx).unwrap();
+            }
+        }
+
+        // Rows are counted as they pass through and never collected, across
+        // several row groups.
+        let mut seen = 0u64;
+        let reader = LabelsReader::from_path::<Xxh3>(path).unwrap();
+        let rows = reader.inspect(|_| seen += 1);
+        let written = write_label_rows_to_parquet(
+            &VarintEncoder,
+            rows,
+            std::io::sink(),
+            &ParquetWriteOptions {
+                max_row_group_size: 1024,
+                ..ParquetWriteOptions::default()
+            },
         )
-        .build();
-    let mut writer = ArrowWriter::try_new(cursor, schema, Some(props))?;
-    writer.write(&batch)?;
-    writer.close()?;
+        .unwrap();
+        assert_eq!(written, NUM_ROWS);
+        assert_eq!(seen, NUM_ROWS);
 
-    Ok(buffer)
-}
+        let reader = LabelsReader::from_path::<Xxh3>(path).unwrap();
+        let num_ts_ids = generate_ts_ids::<Xxh3, _>(reader.label_name_hash(), reader)
+            .map(Result::unwrap)
+            .count();
+        assert_eq!(num_ts_ids as u64, NUM_ROWS);
+    }
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashSet;
-    use std::hash::DefaultHasher;
+    /// Removes a temporary file when the test ends, even if it panics.
+    struct RemoveOnDrop(std::path::PathBuf);
 
-    use fxhash::FxHasher64;
-    use xxhash_rust::xxh3::Xxh3;
-    use xxhash_rust::xxh64::Xxh64;
+    impl Drop for RemoveOnDrop {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
 
-    use super::*;
+    #[test]
+    fn test_read_ragged_csv() {
+        let csv = "job,instance\nprometheus,localhost:9090\nnode\n";
+        let err = read_labels_and_hash_str::<DefaultHasher>(csv)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            Error::FieldCount {
+                path: None,
+                line: 3,
+                expected: 2,
+                found: 1,
+            }
+        ));
+        assert_eq!(
+            err.with_path("labels.csv").to_string(),
+            "labels.csv: line 3 has 1 fields, expected 2 from the header"
+        );
+    }
+
+    #[test]
+    fn check_collisions_synthetic() {
+        let config = labels_gen::SyntheticLabelsConfig::new(5_000, 12);
+        let labels = labels_gen::generate_labels::<Xxh3>(&config);
+        fn count_distinct<H: Hasher + SeededHasher>(labels: &Labels) -> usize {
+            compute_ts_ids::<H>(labels, TsIdMode::Seeded)
+                .into_iter()
+                .collect::<HashSet<_>>()
+                .len()
+        }
+
+        // The instance label makes every row distinct.
+        assert_eq!(count_distinct::<Xxh3>(&labels), config.rows);
+        assert_eq!(count_distinct::<Xxh64>(&labels), config.rows);
+        assert_eq!(count_distinct::<FxHasher64>(&labels), config.rows);
+        assert_eq!(count_distinct::<DefaultHasher>(&labels), config.rows);
+        assert_eq!(count_distinct::<SipHasher13>(&labels), config.rows);
+        assert_eq!(count_distinct::<SipHasher24>(&labels), config.rows);
+        assert_eq!(count_distinct::<AHasher>(&labels), config.rows);
+        assert_eq!(count_distinct::<WyHash>(&labels), config.rows);
+
+        fn count_distinct_128<H: Hasher128Ext + SeededHasher>(labels: &Labels) -> usize {
+            labels
+                .label_values
+                .iter()
+                .map(|row| label_row_ts_id_128::<H>(labels.label_name_hash, row))
+                .collect::<HashSet<_>>()
+                .len()
+        }
+        assert_eq!(count_distinct_128::<Xxh3>(&labels), config.rows);
+        assert_eq!(count_distinct_128::<mur3::Hasher128>(&labels), config.rows);
+    }
+
+    /// 20 groups of 10 rows each, sorted by the first label value.
+    fn sorted_prefix_rows() -> Vec<Vec<(u32, String)>> {
+        let mut rows: Vec<Vec<(u32, String)>> = (b'a'..=b't')
+            .flat_map(|first| {
+                (0..10).map(move |idx| {
+                    vec![
+                        (0, (first as char).to_string()),
+                        (1, format!("value-{idx}")),
+                    ]
+                })
+            })
+            .collect();
+        rows.sort();
+        rows
+    }
+
+    #[test]
+    fn test_prune_row_groups() {
+        let rows = sorted_prefix_rows();
+        let options = ParquetWriteOptions {
+            statistics: true,
+            max_row_group_size: 10,
+        };
+        let encoded =
+            encode_to_parquet_with_options(&MemcomparableEncoder, &rows, &options).unwrap();
+
+        let (lower, upper) = MemcomparableEncoder::prefix_range(&[(0, "c".to_owned())]);
+        let kept = prune_row_groups(&encoded, &lower, &upper).unwrap();
+        assert_eq!(kept, vec![2]);
+
+        let (lower, _) = MemcomparableEncoder::prefix_range(&[(0, "c".to_owned())]);
+        let (_, upper) = MemcomparableEncoder::prefix_range(&[(0, "e".to_owned())]);
+        let kept = prune_row_groups(&encoded, &lower, &upper).unwrap();
+        assert_eq!(kept, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_prune_row_groups_without_statistics() {
+        let rows = sorted_prefix_rows();
+        let options = ParquetWriteOptions {
+            statistics: false,
+            max_row_group_size: 10,
+        };
+        let encoded =
+            encode_to_parquet_with_options(&MemcomparableEncoder, &rows, &options).unwrap();
+
+        let (lower, upper) = MemcomparableEncoder::prefix_range(&[(0, "c".to_owned())]);
+        let kept = prune_row_groups(&encoded, &lower, &upper).unwrap();
+        assert_eq!(kept.len(), 20);
+    }
+
+    fn read_batches(data: Vec<u8>) -> Vec<RecordBatch> {
+        ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(data))
+            .unwrap()
+            .build()
+            .unwrap()
+            .map(|batch| batch.unwrap())
+            .collect()
+    }
 
-    fn test_hasher<H: Hasher + Default + SeededHasher>(amp: usize) {
-        let labels = read_labels_and_hash::<H>(open_csv_reader("./assets/labels.csv.gz"));
-        let mut all_hash_codes = HashSet::with_capacity(labels.label_values.len());
+    fn read_u64_column(data: Vec<u8>, name: &str) -> Vec<u64> {
+        read_batches(data)
+            .iter()
+            .flat_map(|batch| {
+                let column = batch
+                    .column_by_name(name)
+                    .unwrap()
+                    .as_primitive::<UInt64Type>();
+                column.values().to_vec()
+            })
+            .collect()
+    }
 
-        for label in labels.label_values.iter() {
-            for idx in 0..amp {
-                let mut ts_id_gen = TsIdGenerator::<H>::from_seed(labels.label_name_hash);
-                let label_values: Vec<_> = label
+    fn read_binary_column(data: Vec<u8>, name: &str) -> Vec<Vec<u8>> {
+        read_batches(data)
+            .iter()
+            .flat_map(|batch| {
+                let column = batch.column_by_name(name).unwrap().as_binary::<i32>();
+                column
                     .iter()
-                    .map(|s| format!("{}-{}", s, idx).into_bytes())
-                    .collect();
-                ts_id_gen.write_label_values(label_values.iter().map(|a| a.as_slice()));
-                let i = ts_id_gen.build_ts_id();
-                assert!(!all_hash_codes.contains(&i));
-                all_hash_codes.insert(i);
+                    .map(|v| v.unwrap().to_vec())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    fn read_tsids(data: Vec<u8>) -> Vec<u64> {
+        let mut ts_ids = Vec::new();
+        for batch in read_batches(data) {
+            let column = batch.column(0).clone();
+            match column.data_type() {
+                DataType::UInt64 => {
+                    ts_ids.extend(column.as_primitive::<UInt64Type>().values().iter().copied())
+                }
+                DataType::FixedSizeBinary(8) => {
+                    let array = column
+                        .as_any()
+                        .downcast_ref::<FixedSizeBinaryArray>()
+                        .unwrap();
+                    ts_ids.extend(
+                        array
+                            .iter()
+                            .map(|v| u64::from_be_bytes(v.unwrap().try_into().unwrap())),
+                    );
+                }
+                other => panic!("unexpected ts_id column type: {other:?}"),
             }
         }
+        ts_ids
     }
 
-    #[ignore]
     #[test]
-    fn check_collisions() {
-        let amp = 100_000_000usize / 660;
-        test_hasher::<Xxh3>(amp);
-        test_hasher::<Xxh64>(amp);
-        test_hasher::<FxHasher64>(amp);
-        test_hasher::<DefaultHasher>(amp);
+    fn test_encode_with_row_index() {
+        let labels = read_labels_from_path::<DefaultHasher>("./assets/labels.csv.gz").unwrap();
+        let rows = scale_rows(&labels.label_values, 1);
+
+        let with_index = encode_to_parquet_with_row_index(&VarintEncoder, &rows).unwrap();
+        let plain = encode_to_parquet(&VarintEncoder, &rows).unwrap();
+
+        let row_ids = read_u64_column(with_index.clone(), "row_id");
+        assert_eq!(row_ids, (0..rows.len() as u64).collect::<Vec<_>>());
+        assert_eq!(
+            read_binary_column(with_index, "primary_key"),
+            read_binary_column(plain, "primary_key")
+        );
+    }
+
+    #[test]
+    fn test_encode_with_tsids() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/unique-lables.csv.gz").unwrap();
+        let rows = scale_rows(&labels.label_values, 1);
+
+        let data =
+            encode_to_parquet_with_tsids::<_, Xxh3>(&VarintEncoder, labels.label_name_hash, &rows)
+                .unwrap();
+
+        let ts_ids = read_u64_column(data.clone(), "tsid");
+        assert_eq!(ts_ids.len(), rows.len());
+        assert_eq!(ts_ids.iter().collect::<HashSet<_>>().len(), rows.len());
+        assert_eq!(
+            ts_ids[0],
+            label_row_ts_id::<Xxh3>(labels.label_name_hash, &labels.label_values[0])
+        );
+        assert_eq!(
+            read_binary_column(data, "primary_key"),
+            read_binary_column(
+                encode_to_parquet(&VarintEncoder, &rows).unwrap(),
+                "primary_key"
+            )
+        );
+    }
+
+    #[test]
+    fn test_encode_with_tsid_index() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        let rows = scale_rows(&labels.label_values, 2);
+
+        let data = encode_to_parquet_with_tsid_index::<_, Xxh3>(
+            &VarintEncoder,
+            labels.label_name_hash,
+            &rows,
+        )
+        .unwrap();
+
+        let ts_ids = read_u64_column(data.clone(), "tsid");
+        assert_eq!(ts_ids.len(), rows.len());
+        assert!(ts_ids.is_sorted());
+        // Each primary key stays next to the ts_id of its row.
+        let primary_keys = read_binary_column(data.clone(), "primary_key");
+        for (ts_id, primary_key) in ts_ids.iter().zip(&primary_keys) {
+            let values: Vec<String> = VarintEncoder
+                .decode(primary_key)
+                .unwrap()
+                .into_iter()
+                .map(|(_, value)| value)
+                .collect();
+            assert_eq!(
+                *ts_id,
+                label_row_ts_id::<Xxh3>(labels.label_name_hash, &values)
+            );
+        }
+
+        let reader = SerializedFileReader::new(bytes::Bytes::from(data)).unwrap();
+        let statistics = reader.metadata().row_group(0).column(0).statistics();
+        assert!(statistics.is_some());
+        assert!(encode_to_parquet_with_tsid_index::<_, Xxh3>(&VarintEncoder, 0, &[]).is_ok());
     }
 
-    fn to_pairs(label_values: &[Vec<String>]) -> Vec<Vec<(u32, String)>> {
-        label_values
+    #[test]
+    fn test_encode_tsids_layouts() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        let expected: Vec<u64> = labels
+            .label_values
             .iter()
             .map(|row| {
-                row.iter()
-                    .enumerate()
-                    .map(|(idx, val)| (idx as u32, val.clone()))
-                    .collect()
+                let mut generator = TsIdGenerator::<Xxh3>::from_seed(labels.label_name_hash);
+                generator.write_label_values(row.iter().map(|s| s.as_bytes()));
+                generator.build_ts_id()
             })
-            .collect()
+            .collect();
+        let mut expected_sorted = expected.clone();
+        expected_sorted.sort_unstable();
+
+        for layout in [
+            TsIdLayout::UInt64,
+            TsIdLayout::UInt64Delta,
+            TsIdLayout::FixedSizeBinary,
+        ] {
+            for sorted in [false, true] {
+                let options = TsIdParquetOptions { layout, sorted };
+                let data = encode_tsids_to_parquet::<Xxh3>(&labels, options).unwrap();
+                let ts_ids = read_tsids(data);
+                if sorted {
+                    assert_eq!(ts_ids, expected_sorted, "{options:?}");
+                } else {
+                    assert_eq!(ts_ids, expected, "{options:?}");
+                }
+            }
+        }
+
+        // Neither UInt64 layout writes a dictionary page.
+        for layout in [TsIdLayout::UInt64, TsIdLayout::UInt64Delta] {
+            let options = TsIdParquetOptions {
+                layout,
+                sorted: false,
+            };
+            let data = encode_tsids_to_parquet::<Xxh3>(&labels, options).unwrap();
+            let reader = SerializedFileReader::new(bytes::Bytes::from(data)).unwrap();
+            let column = reader.metadata().row_group(0).column(0);
+            assert!(column.dictionary_page_offset().is_none(), "{layout:?}");
+        }
+    }
+
+    #[test]
+    fn test_encode_to_parquet_dict_string() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        let data =
+            encode_to_parquet_dict_string(&labels.label_names, &labels.label_values).unwrap();
+
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        for batch in read_batches(data) {
+            let schema = batch.schema();
+            let names: Vec<&String> = schema.fields().iter().map(|field| field.name()).collect();
+            assert_eq!(names, labels.label_names.iter().collect::<Vec<_>>());
+            let columns: Vec<_> = batch
+                .columns()
+                .iter()
+                .map(|column| {
+                    assert_eq!(
+                        column.data_type(),
+                        &DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8))
+                    );
+                    column.as_dictionary::<Int32Type>()
+                })
+                .collect();
+            for row in 0..batch.num_rows() {
+                rows.push(
+                    columns
+                        .iter()
+                        .map(|column| {
+                            let values = column.values().as_string::<i32>();
+                            values.value(column.keys().value(row) as usize).to_owned()
+                        })
+                        .collect(),
+                );
+            }
+        }
+        assert_eq!(rows, labels.label_values);
+    }
+
+    #[test]
+    fn test_encode_tsids_128() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        let expected: Vec<u128> = labels
+            .label_values
+            .iter()
+            .map(|row| label_row_ts_id_128::<Xxh3>(labels.label_name_hash, row))
+            .collect();
+        let mut expected_sorted = expected.clone();
+        expected_sorted.sort_unstable();
+
+        for sorted in [false, true] {
+            let data = encode_tsids_128_to_parquet::<Xxh3>(&labels, sorted).unwrap();
+            let mut ts_ids = Vec::new();
+            for batch in read_batches(data) {
+                assert_eq!(batch.column(0).data_type(), &DataType::FixedSizeBinary(16));
+                let array = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<FixedSizeBinaryArray>()
+                    .unwrap();
+                ts_ids.extend(
+                    array
+                        .iter()
+                        .map(|v| u128::from_be_bytes(v.unwrap().try_into().unwrap())),
+                );
+            }
+            if sorted {
+                assert_eq!(ts_ids, expected_sorted);
+            } else {
+                assert_eq!(ts_ids, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_open_parquet_writer_matches_manual_setup() {
+        let labels = read_labels_from_path::<DefaultHasher>("./assets/labels.csv.gz").unwrap();
+        let rows = scale_rows(&labels.label_values, 1);
+        let batch = primary_key_batch(&VarintEncoder, &rows).unwrap();
+
+        let mut helper_output = Vec::new();
+        let mut writer =
+            open_parquet_writer(&mut helper_output, batch.schema(), Compression::SNAPPY).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let mut manual_output = Vec::new();
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_statistics_enabled(EnabledStatistics::None)
+            .set_max_row_group_size(1024)
+            .build();
+        let mut writer =
+            ArrowWriter::try_new(&mut manual_output, batch.schema(), Some(props)).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        assert_eq!(helper_output, manual_output);
     }
 
     #[test]
     fn test_encode_maparray() {
-        let labels = read_labels_and_hash::<DefaultHasher>(open_csv_reader("./assets/labels.csv.gz"));
+        let labels = read_labels_from_path::<DefaultHasher>("./assets/labels.csv.gz").unwrap();
         let encoded =
             encode_to_parquet_maparray(&labels.label_names, &labels.label_values).unwrap();
         println!("maparray size: {:.2}k", encoded.len() as f64 / 1024.0);
         assert!(!encoded.is_empty());
+
+        // Each map holds the entries of its own row, from the first row to the
+        // last.
+        let label_names = vec!["job".to_owned(), "instance".to_owned()];
+        let rows = vec![
+            vec!["node".to_owned(), "a:9100".to_owned()],
+            vec!["api".to_owned(), "b:8080".to_owned()],
+        ];
+        let batches = read_batches(encode_to_parquet_maparray(&label_names, &rows).unwrap());
+        let maps = batches[0].column(0).as_map();
+        assert_eq!(maps.len(), rows.len());
+        for (idx, row) in rows.iter().enumerate() {
+            let entries = maps.value(idx);
+            let strings = |column: usize| -> Vec<&str> {
+                entries
+                    .column(column)
+                    .as_string::<i32>()
+                    .iter()
+                    .flatten()
+                    .collect()
+            };
+            assert_eq!(strings(0), label_names);
+            assert_eq!(strings(1), *row);
+        }
+    }
+
+    #[test]
+    fn test_encode_maparray_sorted() {
+        let labels = read_labels_from_path::<DefaultHasher>("./assets/labels.csv.gz").unwrap();
+        let unsorted =
+            encode_to_parquet_maparray(&labels.label_names, &labels.label_values).unwrap();
+        let sorted =
+            encode_to_parquet_maparray_sorted(&labels.label_names, &labels.label_values).unwrap();
+        assert!(sorted.len() <= unsorted.len());
+
+        // Same output as encoding rows sorted stably by their first value.
+        let mut rows = labels.label_values.clone();
+        rows.sort_by(|a, b| a[0].cmp(&b[0]));
+        let expected = encode_to_parquet_maparray(&labels.label_names, &rows).unwrap();
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn test_encode_maparray_streaming() {
+        let label_names = vec!["job".to_owned(), "instance".to_owned()];
+        let records =
+            (0..5000).map(|idx| vec![format!("job-{}", idx % 7), format!("host-{}", idx)]);
+
+        let mut sink = Cursor::new(Vec::new());
+        encode_to_parquet_maparray_streaming(&label_names, records, &mut sink, 1024).unwrap();
+
+        let batches = read_batches(sink.into_inner());
+        assert_eq!(
+            batches.iter().map(RecordBatch::num_rows).sum::<usize>(),
+            5000
+        );
+        let entries_of = |batch: &RecordBatch, row: usize| {
+            let entries = batch.column(0).as_map().value(row);
+            let strings = |idx: usize| {
+                let column = entries.column(idx).as_string::<i32>();
+                column
+                    .iter()
+                    .flatten()
+                    .map(str::to_owned)
+                    .collect::<Vec<_>>()
+            };
+            (strings(0), strings(1))
+        };
+        let first = entries_of(&batches[0], 0);
+        assert_eq!(
+            first,
+            (
+                vec!["job".into(), "instance".into()],
+                vec!["job-0".into(), "host-0".into()]
+            )
+        );
+        let last_batch = batches.last().unwrap();
+        let last = entries_of(last_batch, last_batch.num_rows() - 1);
+        assert_eq!(
+            last,
+            (
+                vec!["job".into(), "instance".into()],
+                vec!["job-1".into(), "host-4999".into()]
+            )
+        );
+
+        // An empty input still produces a readable file.
+        let mut sink = Cursor::new(Vec::new());
+        encode_to_parquet_maparray_streaming(&label_names, std::iter::empty(), &mut sink, 1024)
+            .unwrap();
+        assert!(read_batches(sink.into_inner()).is_empty());
+    }
+
+    #[test]
+    fn test_encode_with_dict() {
+        let rows: Vec<Vec<(u32, String)>> = (0..300)
+            .map(|idx| vec![(0, "node".to_owned()), (1, format!("host-{}", idx % 3))])
+            .collect();
+        let plain = encode_to_parquet(&MemcomparableEncoder, &rows).unwrap();
+        let dict = encode_to_parquet_with_dict(&MemcomparableEncoder, &rows).unwrap();
+
+        let primary_keys = |data: Vec<u8>| -> Vec<Vec<u8>> {
+            read_batches(data)
+                .iter()
+                .flat_map(|batch| {
+                    let column = batch
+                        .column_by_name("primary_key")
+                        .unwrap()
+                        .as_binary::<i32>();
+                    column
+                        .iter()
+                        .map(|key| key.unwrap().to_vec())
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        };
+        assert_eq!(primary_keys(dict.clone()), primary_keys(plain));
+
+        let reader = SerializedFileReader::new(bytes::Bytes::from(dict)).unwrap();
+        let column = reader.metadata().row_group(0).column(0);
+        assert!(column.dictionary_page_offset().is_some());
     }
 
     #[test]
     fn test_encode_with_trait() {
-        let labels = read_labels_and_hash::<DefaultHasher>(open_csv_reader("./assets/labels.csv.gz"));
-        let rows = to_pairs(&labels.label_values);
+        let labels = read_labels_from_path::<DefaultHasher>("./assets/labels.csv.gz").unwrap();
+        let rows = scale_rows(&labels.label_values, 1);
 
         // Test all encoders using the trait
         let encoders: Vec<Box<dyn RowEncoder>> = vec![
             Box::new(LengthPrefixedEncoder),
+            Box::new(LengthPrefixedEncoderBE),
             Box::new(VarintEncoder),
             Box::new(MemcomparableEncoder),
             Box::new(FlatBufferEncoder),
+            Box::new(NullEncoder),
         ];
 
         for encoder in &encoders {
@@ -245,5 +3104,9 @@ mod tests {
             );
             assert!(!encoded.is_empty());
         }
+
+        let encoded = encoding::encode_to_vec(&NullEncoder, &rows[0]);
+        assert!(encoded.is_empty());
+        assert!(NullEncoder.decode(&encoded).unwrap().is_empty());
     }
 }
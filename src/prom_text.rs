@@ -0,0 +1,255 @@
+//! Loading labels from the Prometheus text exposition format.
+//!
+//! Every sample line becomes one row. The metric name is stored in the
+//! `__name__` column, the first of the header, followed by the other label
+//! names in order of first appearance. Labels missing from a sample are left
+//! empty.
+
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::io::{BufRead, BufReader, Read};
+
+use crate::ts_id_gen::SeededHasher;
+use crate::{Error, Labels, compute_label_name_hash, open_csv_reader};
+
+/// Name of the column holding the metric name.
+pub const METRIC_NAME_LABEL: &str = "__name__";
+
+/// Reads the samples of a Prometheus text exposition, hashing the label names
+/// with `H`. Comment and blank lines are skipped.
+pub fn read_labels_from_prom_text<H>(reader: impl Read) -> Result<Labels, Error>
+where
+    H: Default + Hasher + SeededHasher,
+{
+    let mut label_names = vec![METRIC_NAME_LABEL.to_owned()];
+    let mut columns: HashMap<String, usize> = HashMap::from([(METRIC_NAME_LABEL.to_owned(), 0)]);
+    let mut samples: Vec<Vec<(usize, String)>> = Vec::new();
+
+    for (idx, line) in BufReader::new(reader).lines().enumerate() {
+        let line_number = idx as u64 + 1;
+        let error = |message: String| Error::Parse {
+            path: None,
+            line: line_number,
+            message,
+        };
+        let line = line.map_err(|source| error(format!("failed to read line: {}", source)))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let sample = parse_sample(line)
+            .map_err(error)?
+            .into_iter()
+            .map(|(name, value)| {
+                let next_column = label_names.len();
+                let column = *columns.entry(name).or_insert_with_key(|name| {
+                    label_names.push(name.clone());
+                    next_column
+                });
+                (column, value)
+            })
+            .collect();
+        samples.push(sample);
+    }
+
+    let label_values = samples
+        .into_iter()
+        .map(|sample| {
+            let mut row = vec![String::new(); label_names.len()];
+            for (column, value) in sample {
+                row[column] = value;
+            }
+            row
+        })
+        .collect();
+
+    let label_name_hash = compute_label_name_hash::<H>(&label_names);
+
+    Ok(Labels {
+        label_name_hash,
+        label_names,
+        label_values,
+    })
+}
+
+/// Like [`read_labels_from_prom_text`], reading from a file that may be
+/// compressed, see [`open_csv_reader`].
+pub fn read_labels_from_prom_path<H>(path: &str) -> Result<Labels, Error>
+where
+    H: Default + Hasher + SeededHasher,
+{
+    read_labels_from_prom_text::<H>(open_csv_reader(path)?).map_err(|err| err.with_path(path))
+}
+
+/// Parses a sample line such as `http_requests_total{code="200"} 1027 1395066363000`
+/// into its `(label name, value)` pairs, the metric name first.
+fn parse_sample(line: &str) -> Result<Vec<(String, String)>, String> {
+    let mut cursor = Cursor { rest: line };
+
+    let metric_name = cursor.take_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':');
+    if metric_name.is_empty() || metric_name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(format!("expected a metric name, found {:?}", line));
+    }
+    let mut labels = vec![(METRIC_NAME_LABEL.to_owned(), metric_name.to_owned())];
+
+    cursor.skip_whitespace();
+    if cursor.eat('{') {
+        loop {
+            cursor.skip_whitespace();
+            if cursor.eat('}') {
+                break;
+            }
+            let name = cursor.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+            if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+                return Err(format!("expected a label name, found {:?}", cursor.rest));
+            }
+            if labels.iter().any(|(existing, _)| existing == name) {
+                return Err(format!("duplicate label {:?}", name));
+            }
+            cursor.skip_whitespace();
+            if !cursor.eat('=') {
+                return Err(format!("expected '=' after label {:?}", name));
+            }
+            cursor.skip_whitespace();
+            let value = cursor.label_value()?;
+            labels.push((name.to_owned(), value));
+
+            cursor.skip_whitespace();
+            if !cursor.eat(',') {
+                cursor.skip_whitespace();
+                if !cursor.eat('}') {
+                    return Err(format!("expected ',' or '}}', found {:?}", cursor.rest));
+                }
+                break;
+            }
+        }
+    }
+
+    let mut fields = cursor.rest.split_whitespace();
+    match fields.next() {
+        Some(value) if value.parse::<f64>().is_ok() => {}
+        Some(value) => return Err(format!("invalid sample value {:?}", value)),
+        None => return Err("missing sample value".to_owned()),
+    }
+    if let Some(timestamp) = fields.next()
+        && timestamp.parse::<i64>().is_err()
+    {
+        return Err(format!("invalid timestamp {:?}", timestamp));
+    }
+    if let Some(extra) = fields.next() {
+        return Err(format!("unexpected {:?} after the sample", extra));
+    }
+    Ok(labels)
+}
+
+/// The unparsed remainder of a line.
+struct Cursor<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> &'a str {
+        let end = self.rest.find(|c| !predicate(c)).unwrap_or(self.rest.len());
+        let (taken, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        taken
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        match self.rest.strip_prefix(expected) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Parses a double-quoted label value, unescaping `\\`, `\"` and `\n`.
+    fn label_value(&mut self) -> Result<String, String> {
+        if !self.eat('"') {
+            return Err(format!("expected '\"', found {:?}", self.rest));
+        }
+        let mut value = String::new();
+        let mut chars = self.rest.char_indices();
+        while let Some((idx, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &self.rest[idx + 1..];
+                    return Ok(value);
+                }
+                '\\' => match chars.next() {
+                    Some((_, '\\')) => value.push('\\'),
+                    Some((_, '"')) => value.push('"'),
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, other)) => return Err(format!("invalid escape '\\{}'", other)),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        Err("unterminated label value".to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xxhash_rust::xxh3::Xxh3;
+
+    use super::*;
+    use crate::read_labels_and_hash_str;
+
+    #[test]
+    fn read_fixture() {
+        let labels = read_labels_from_prom_path::<Xxh3>("./assets/fixtures/metrics.prom").unwrap();
+        let row = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            labels.label_names,
+            ["__name__", "method", "code", "path", "le"]
+        );
+        assert_eq!(
+            labels.label_values,
+            [
+                row(&["http_requests_total", "post", "200", "", ""]),
+                row(&["http_requests_total", "get", "400", "/api/\"v1\"", ""]),
+                row(&[
+                    "http_request_duration_seconds_bucket",
+                    "",
+                    "",
+                    "C:\\tmp\nlog",
+                    "0.1"
+                ]),
+                row(&["http_request_duration_seconds_bucket", "", "", "", "+Inf"]),
+                row(&["http_request_duration_seconds_count", "", "", "", ""]),
+                row(&["process_start_time_seconds", "", "", "", ""]),
+            ]
+        );
+
+        // Same label name hash as a CSV file with the same header.
+        let csv = read_labels_and_hash_str::<Xxh3>("__name__,method,code,path,le\n").unwrap();
+        assert_eq!(labels.label_name_hash, csv.label_name_hash);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let line_of = |text: &str| match read_labels_from_prom_text::<Xxh3>(text.as_bytes()) {
+            Err(Error::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+        };
+
+        assert_eq!(line_of("# HELP up\nup 1\nup{job=\"a\" 1\n"), 3);
+        assert_eq!(line_of("up{job=\"a} 1\n"), 1);
+        assert_eq!(line_of("\nup{job=\"\\x\"} 1\n"), 2);
+        assert_eq!(line_of("up{job=\"a\",job=\"b\"} 1\n"), 1);
+        assert_eq!(line_of("up\n"), 1);
+        assert_eq!(line_of("up one\n"), 1);
+        assert_eq!(line_of("up 1 2 3\n"), 1);
+        assert_eq!(line_of("{job=\"a\"} 1\n"), 1);
+    }
+}
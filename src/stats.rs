@@ -0,0 +1,182 @@
+//! Per-label cardinality and size statistics.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::Labels;
+
+/// Statistics of the values of a single label.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelStats {
+    pub name: String,
+    /// Exact number of distinct values.
+    pub distinct: usize,
+    /// Minimum value length in bytes.
+    pub min_len: usize,
+    /// Mean value length in bytes.
+    pub mean_len: f64,
+    /// Maximum value length in bytes.
+    pub max_len: usize,
+    /// Share of empty values, between 0 and 1.
+    pub empty_share: f64,
+    /// Total bytes of all values.
+    pub total_bytes: usize,
+}
+
+/// Statistics of a [`Labels`] dataset, see [`Labels::stats`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelsStats {
+    pub rows: usize,
+    /// Number of distinct rows.
+    pub distinct_rows: usize,
+    /// Total bytes of all values.
+    pub total_bytes: usize,
+    /// One entry per label, in label order.
+    pub labels: Vec<LabelStats>,
+}
+
+impl Labels {
+    /// Computes per-label and dataset-level statistics.
+    pub fn stats(&self) -> LabelsStats {
+        let rows = self.label_values.len();
+        let labels: Vec<LabelStats> = self
+            .label_names
+            .iter()
+            .enumerate()
+            .map(|(col, name)| {
+                let mut distinct = HashSet::new();
+                let (mut min_len, mut max_len) = (usize::MAX, 0);
+                let (mut total_bytes, mut empty) = (0, 0);
+                for value in self.label_values.iter().map(|row| &row[col]) {
+                    distinct.insert(value.as_str());
+                    min_len = min_len.min(value.len());
+                    max_len = max_len.max(value.len());
+                    total_bytes += value.len();
+                    if value.is_empty() {
+                        empty += 1;
+                    }
+                }
+                LabelStats {
+                    name: name.clone(),
+                    distinct: distinct.len(),
+                    min_len: if rows == 0 { 0 } else { min_len },
+                    mean_len: ratio(total_bytes, rows),
+                    max_len,
+                    empty_share: ratio(empty, rows),
+                    total_bytes,
+                }
+            })
+            .collect();
+
+        LabelsStats {
+            rows,
+            distinct_rows: self.label_values.iter().collect::<HashSet<_>>().len(),
+            total_bytes: labels.iter().map(|label| label.total_bytes).sum(),
+            labels,
+        }
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+impl fmt::Display for LabelsStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name_width = self
+            .labels
+            .iter()
+            .map(|label| label.name.len())
+            .chain(["label".len()])
+            .max()
+            .unwrap();
+
+        writeln!(
+            f,
+            "{:<name_width$}  {:>10}  {:>7}  {:>9}  {:>7}  {:>7}  {:>12}",
+            "label", "distinct", "min_len", "mean_len", "max_len", "empty", "bytes"
+        )?;
+        for label in &self.labels {
+            writeln!(
+                f,
+                "{:<name_width$}  {:>10}  {:>7}  {:>9.1}  {:>7}  {:>6.1}%  {:>12}",
+                label.name,
+                label.distinct,
+                label.min_len,
+                label.mean_len,
+                label.max_len,
+                label.empty_share * 100.0,
+                label.total_bytes
+            )?;
+        }
+        write!(
+            f,
+            "{} rows ({} distinct), {} labels, {} bytes",
+            self.rows,
+            self.distinct_rows,
+            self.labels.len(),
+            self.total_bytes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xxhash_rust::xxh3::Xxh3;
+
+    use super::*;
+    use crate::read_labels_and_hash_str;
+
+    #[test]
+    fn stats_on_small_dataset() {
+        let csv = "job,instance\nnode,a\nnode,\napi,abcd\nnode,a\n";
+        let stats = read_labels_and_hash_str::<Xxh3>(csv).unwrap().stats();
+
+        assert_eq!(stats.rows, 4);
+        assert_eq!(stats.distinct_rows, 3);
+        assert_eq!(stats.total_bytes, 15 + 6);
+        assert_eq!(
+            stats.labels[0],
+            LabelStats {
+                name: "job".to_owned(),
+                distinct: 2,
+                min_len: 3,
+                mean_len: 3.75,
+                max_len: 4,
+                empty_share: 0.0,
+                total_bytes: 15,
+            }
+        );
+        assert_eq!(
+            stats.labels[1],
+            LabelStats {
+                name: "instance".to_owned(),
+                distinct: 3,
+                min_len: 0,
+                mean_len: 1.5,
+                max_len: 4,
+                empty_share: 0.25,
+                total_bytes: 6,
+            }
+        );
+
+        let table = stats.to_string();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("label       distinct"));
+        assert!(lines[2].starts_with("instance"));
+        assert_eq!(lines[3], "4 rows (3 distinct), 2 labels, 21 bytes");
+    }
+
+    #[test]
+    fn stats_on_empty_dataset() {
+        let stats = read_labels_and_hash_str::<Xxh3>("job\n").unwrap().stats();
+        assert_eq!(stats.rows, 0);
+        assert_eq!(stats.labels[0].min_len, 0);
+        assert_eq!(stats.labels[0].mean_len, 0.0);
+    }
+}
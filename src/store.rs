@@ -0,0 +1,76 @@
+//! Reading and writing benchmark parquet files through an [`ObjectStore`].
+//!
+//! These mirror the local-file helpers in the crate root and in
+//! [`crate::data_reader`], so datasets living in S3 or MinIO can be used
+//! without downloading them first.
+
+use std::hash::Hasher;
+use std::sync::Arc;
+
+use futures::TryStreamExt;
+use object_store::ObjectStore;
+use object_store::path::Path;
+use parquet::arrow::ParquetRecordBatchStreamBuilder;
+use parquet::arrow::async_reader::ParquetObjectReader;
+use parquet::arrow::async_writer::{AsyncArrowWriter, ParquetObjectWriter};
+
+use crate::data_reader::{EXCLUDED_COLUMNS, batch_rows, label_columns};
+use crate::ts_id_gen::SeededHasher;
+use crate::{
+    Labels, NullRepresentation, ParquetWriteOptions, RowEncoder, compute_label_name_hash,
+    primary_key_batch, primary_key_writer_properties,
+};
+
+/// Encode rows and write them as a `primary_key` parquet file to `path` in `store`.
+///
+/// Produces the same file as [`crate::encode_to_parquet_with_options`].
+pub async fn write_parquet_to_store<E: RowEncoder + ?Sized>(
+    store: Arc<dyn ObjectStore>,
+    path: &Path,
+    encoder: &E,
+    rows: &[Vec<(u32, String)>],
+    options: &ParquetWriteOptions,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let batch = primary_key_batch(encoder, rows)?;
+    let props = primary_key_writer_properties(options);
+
+    let sink = ParquetObjectWriter::new(store, path.clone());
+    let mut writer = AsyncArrowWriter::try_new(sink, batch.schema(), Some(props))?;
+    writer.write(&batch).await?;
+    writer.close().await?;
+    Ok(())
+}
+
+/// Read the label columns of a parquet file in `store` and compute the label name hash.
+///
+/// Column selection and value conversion follow [`crate::data_reader::read_parquet_files`].
+pub async fn read_labels_from_store<H>(
+    store: Arc<dyn ObjectStore>,
+    path: &Path,
+) -> Result<Labels, Box<dyn std::error::Error + Send + Sync>>
+where
+    H: Default + Hasher + SeededHasher,
+{
+    let reader = ParquetObjectReader::new(store, path.clone());
+    let builder = ParquetRecordBatchStreamBuilder::new(reader).await?;
+    let (column_indices, label_names) = label_columns(builder.schema(), &EXCLUDED_COLUMNS);
+    let mut stream = builder.build()?;
+
+    let mut label_values = Vec::new();
+    while let Some(batch) = stream.try_next().await? {
+        label_values.extend(batch_rows(
+            &batch,
+            &column_indices,
+            &label_names,
+            &NullRepresentation::Empty,
+        )?);
+    }
+
+    let label_name_hash = compute_label_name_hash::<H>(&label_names);
+
+    Ok(Labels {
+        label_names,
+        label_name_hash,
+        label_values,
+    })
+}
@@ -1,13 +1,86 @@
-use std::hash::{DefaultHasher, Hasher};
+use std::hash::{BuildHasher, DefaultHasher, Hasher};
+use std::marker::PhantomData;
+use std::num::NonZeroUsize;
 
+use ahash::AHasher;
 use cityhash_sys::{CityHash32Hasher, CityHash64Hasher};
 use fxhash::FxHasher64;
+use lru::LruCache;
 use mur3::Hasher128;
+use rayon::prelude::*;
+use siphasher::sip::{SipHasher13, SipHasher24};
+use wyhash::WyHash;
 use xxhash_rust::xxh3::{Xxh3, Xxh3Builder};
-use xxhash_rust::xxh64::Xxh64;
+use xxhash_rust::xxh64::{Xxh64, xxh64};
+
+use crate::Labels;
+
+/// How [`TsIdGenerator`] delimits the label names and values it hashes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FramingMode {
+    /// Each item is followed by a separator byte, `0xff` unless set with
+    /// [`TsIdGenerator::with_separator`].
+    ///
+    /// Valid UTF-8 never contains `0xff`, so the default separator is
+    /// unambiguous for string labels, but raw byte items containing it can
+    /// move the boundary: `[b"a\xff", b"b"]` and `[b"a", b"\xffb"]` hash the
+    /// same with streaming hashers such as xxh3.
+    #[default]
+    Separator,
+    /// Each item is preceded by its length as a LEB128 varint, which keeps
+    /// the boundaries unambiguous for any bytes.
+    LengthPrefixed,
+}
 
 pub struct TsIdGenerator<H> {
     hasher: H,
+    framing: FramingMode,
+    /// Byte following each item with [`FramingMode::Separator`].
+    separator: u8,
+    /// Seed given to `from_seed` or the last `reset_with_seed`, restored by
+    /// [`reset`](TsIdGenerator::reset).
+    seed: Option<u64>,
+    /// When the generator was created or last reset, for the generation
+    /// latency metric.
+    #[cfg(feature = "metrics")]
+    created: std::time::Instant,
+}
+
+impl<H> TsIdGenerator<H> {
+    fn with_hasher(hasher: H, seed: Option<u64>) -> Self {
+        Self {
+            hasher,
+            framing: FramingMode::Separator,
+            separator: 0xff,
+            seed,
+            #[cfg(feature = "metrics")]
+            created: std::time::Instant::now(),
+        }
+    }
+
+    /// Records a generated ts_id in the `tsid.generated` counter, and the time
+    /// since the generator was created or reset in the
+    /// `tsid.generation_latency_ns` histogram, which covers writing the labels
+    /// as well as finishing the hash.
+    ///
+    /// Compiles to nothing without the `metrics` feature.
+    #[inline]
+    fn record_generated(&self) {
+        #[cfg(feature = "metrics")]
+        {
+            metrics::counter!("tsid.generated").increment(1);
+            metrics::histogram!("tsid.generation_latency_ns")
+                .record(self.created.elapsed().as_nanos() as f64);
+        }
+    }
+
+    #[inline]
+    fn restart_clock(&mut self) {
+        #[cfg(feature = "metrics")]
+        {
+            self.created = std::time::Instant::now();
+        }
+    }
 }
 
 impl<H> Default for TsIdGenerator<H>
@@ -15,9 +88,7 @@ where
     H: Default + Hasher,
 {
     fn default() -> Self {
-        Self {
-            hasher: Default::default(),
-        }
+        Self::with_hasher(Default::default(), None)
     }
 }
 
@@ -26,67 +97,665 @@ where
     H: Hasher + SeededHasher,
 {
     pub fn new(hasher: H) -> Self {
-        Self { hasher }
+        Self::with_hasher(hasher, None)
     }
 
     pub fn from_seed(seed: u64) -> Self {
-        let h = H::from_seed(seed);
-        Self { hasher: h }
+        Self::with_hasher(H::from_seed(seed), Some(seed))
+    }
+
+    /// Resets the generator to its state right after
+    /// [`from_seed`](Self::from_seed), so that it can be reused for the next
+    /// series instead of constructing a new one.
+    ///
+    /// Framing and separator are kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the generator was not created with `from_seed` nor reset with
+    /// [`reset_with_seed`](Self::reset_with_seed), since its seed is unknown.
+    pub fn reset(&mut self) {
+        let seed = self
+            .seed
+            .expect("reset requires a generator created with from_seed");
+        self.hasher.reset_same_seed(seed);
+        self.restart_clock();
+    }
+
+    /// Resets the generator to the state of `from_seed(seed)`, keeping the
+    /// framing and separator.
+    pub fn reset_with_seed(&mut self, seed: u64) {
+        if self.seed == Some(seed) {
+            self.hasher.reset_same_seed(seed);
+        } else {
+            self.hasher.reset_with_seed(seed);
+            self.seed = Some(seed);
+        }
+        self.restart_clock();
+    }
+
+    /// Sets how label names and values are delimited, [`FramingMode::Separator`]
+    /// by default. Both modes produce different ts_ids for the same labels.
+    pub fn with_framing(mut self, framing: FramingMode) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    pub fn framing(&self) -> FramingMode {
+        self.framing
+    }
+
+    /// Sets the byte following each label name and value with
+    /// [`FramingMode::Separator`], `0xff` by default.
+    ///
+    /// Only bytes that never occur in UTF-8, `0xc0`, `0xc1` and `0xf5..=0xff`,
+    /// keep string labels unambiguous. With any other byte, e.g. an ASCII
+    /// `,`, the labels `["a,", "b"]` and `["a", ",b"]` can collide; use
+    /// [`FramingMode::LengthPrefixed`] for such labels instead.
+    pub fn with_separator(mut self, separator: u8) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    pub fn separator(&self) -> u8 {
+        self.separator
     }
 
     pub fn write_label_names<'a>(&mut self, label_names: impl Iterator<Item = &'a [u8]>) {
+        for label in label_names {
+            self.write_item(label);
+        }
+    }
+
+    /// Like [`write_label_names`](Self::write_label_names), with `separator`
+    /// following each name instead of the framing of the generator.
+    ///
+    /// The hash differs from `write_label_names` for the same names. A
+    /// multi-byte separator makes boundaries harder to forge with binary
+    /// names: with `b"\x00\xff\x00"`, distinct lists of names without NUL
+    /// bytes always hash distinct byte streams, even if the names contain
+    /// `0xff`.
+    pub fn write_label_names_with_separator<'a>(
+        &mut self,
+        label_names: impl Iterator<Item = &'a [u8]>,
+        separator: &[u8],
+    ) {
         for label in label_names {
             self.hasher.write(label);
-            self.hasher.write_u8(0xff);
+            self.hasher.write(separator);
         }
     }
 
+    /// Like [`write_label_names`](Self::write_label_names), but hashes the
+    /// names in lexicographic byte order, so the result does not depend on
+    /// the order in which they are given.
+    ///
+    /// Sorted and ordered hashes of the same names differ, so a dataset must
+    /// use one of the two consistently; mixing them splits the same label set
+    /// into distinct hashes.
+    pub fn write_label_names_sorted<'a>(&mut self, label_names: impl Iterator<Item = &'a [u8]>) {
+        let mut label_names: Vec<&[u8]> = label_names.collect();
+        label_names.sort_unstable();
+        self.write_label_names(label_names.into_iter());
+    }
+
     pub fn write_label_values<'a>(&mut self, label_values: impl Iterator<Item = &'a [u8]>) {
         for value in label_values {
-            self.hasher.write(value);
-            self.hasher.write_u8(0xff);
+            self.write_item(value);
+        }
+    }
+
+    /// Like [`write_label_values`](Self::write_label_values), for values
+    /// already collected in a slice.
+    ///
+    /// Both compile to the same loop and hash at the same speed, as the
+    /// `write_label_values_slice` benchmark shows, so this is only a
+    /// convenience: there is no point collecting values into a slice to call
+    /// it.
+    pub fn write_label_values_slice(&mut self, label_values: &[&[u8]]) {
+        for value in label_values {
+            self.write_item(value);
+        }
+    }
+
+    /// Hashes `(name, value)` pairs in name byte order, each as the name
+    /// followed by the value, so the result does not depend on the order of
+    /// the pairs, e.g. for OTLP attributes.
+    ///
+    /// Pairs sharing a name are ordered by value. The result differs from
+    /// hashing all names before all values, so a dataset must use one of the
+    /// two consistently.
+    pub fn write_sorted_label_pairs<'a>(
+        &mut self,
+        pairs: impl Iterator<Item = (&'a [u8], &'a [u8])>,
+    ) {
+        let mut pairs: Vec<(&[u8], &[u8])> = pairs.collect();
+        pairs.sort_unstable();
+        for (name, value) in pairs {
+            self.write_item(name);
+            self.write_item(value);
         }
     }
 
-    pub fn build_ts_id(self) -> u64 {
+    #[inline]
+    fn write_item(&mut self, item: &[u8]) {
+        match self.framing {
+            FramingMode::Separator => {
+                self.hasher.write(item);
+                self.hasher.write_u8(self.separator);
+            }
+            FramingMode::LengthPrefixed => {
+                let mut prefix = [0u8; 10];
+                let mut len = item.len() as u64;
+                let mut prefix_len = 0;
+                loop {
+                    let byte = (len & 0x7f) as u8;
+                    len >>= 7;
+                    if len == 0 {
+                        prefix[prefix_len] = byte;
+                        prefix_len += 1;
+                        break;
+                    }
+                    prefix[prefix_len] = byte | 0x80;
+                    prefix_len += 1;
+                }
+                self.hasher.write(&prefix[..prefix_len]);
+                self.hasher.write(item);
+            }
+        }
+    }
+
+    pub fn build_ts_id(mut self) -> u64 {
+        self.finish()
+    }
+
+    /// Like [`build_ts_id`](Self::build_ts_id), without consuming the
+    /// generator, which can then be [`reset`](Self::reset) for the next
+    /// series.
+    pub fn finish(&mut self) -> u64 {
+        self.record_generated();
         self.hasher.finish()
     }
 }
 
+impl<H> TsIdGenerator<H>
+where
+    H: Hasher128Ext + SeededHasher,
+{
+    /// Builds a 128-bit ts_id from the native 128-bit output of the hasher.
+    ///
+    /// With 64-bit ts_ids, a collision becomes likely at a few billion
+    /// series; 128 bits move that bound to about 2^64. Hashers without a
+    /// 128-bit output don't implement [`Hasher128Ext`], see
+    /// [`TwoHashTsIdGenerator`] to combine two of them instead.
+    pub fn build_ts_id_128(self) -> u128 {
+        self.record_generated();
+        self.hasher.finish_u128()
+    }
+}
+
+/// Hasher state of a [`TsIdGenerator`] after writing the label names, from
+/// which a generator per row resumes with [`TsIdGenerator::resume`].
+///
+/// Unlike seeding each row with the label name hash, the resumed ts_id is
+/// exactly the one-pass hash of the names followed by the values.
+#[derive(Clone)]
+pub struct TsIdSnapshot<H> {
+    hasher: H,
+    framing: FramingMode,
+    separator: u8,
+}
+
+/// Snapshots need a hasher whose state can be cloned mid-stream. Hashers that
+/// don't implement [`Clone`], like murmur3's, can only be reused through seeds.
+impl<H> TsIdGenerator<H>
+where
+    H: Hasher + Clone,
+{
+    /// Captures the state after the label names written so far.
+    pub fn snapshot(&self) -> TsIdSnapshot<H> {
+        TsIdSnapshot {
+            hasher: self.hasher.clone(),
+            framing: self.framing,
+            separator: self.separator,
+        }
+    }
+
+    /// Creates a generator continuing from `snapshot`, with its framing and
+    /// separator.
+    ///
+    /// The resumed generator has no seed, so it cannot be
+    /// [`reset`](TsIdGenerator::reset); resume the snapshot again instead.
+    pub fn resume(snapshot: &TsIdSnapshot<H>) -> Self {
+        let mut generator = Self::with_hasher(snapshot.hasher.clone(), None);
+        generator.framing = snapshot.framing;
+        generator.separator = snapshot.separator;
+        generator
+    }
+}
+
+/// How [`compute_ts_ids`] hashes the label names of each row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TsIdMode {
+    /// Hashes the label names and then the values of each row, starting from
+    /// seed 0.
+    Full,
+    /// Seeds each row with the label name hash and hashes only the values,
+    /// like [`label_row_ts_id`](crate::label_row_ts_id).
+    Seeded,
+    /// Hashes the label names once and resumes each row from a
+    /// [`TsIdSnapshot`], producing the same ts_ids as [`TsIdMode::Full`].
+    ///
+    /// Hashers that cannot be cloned mid-stream, see
+    /// [`SeededHasher::try_clone`], hash the names of every row as in `Full`.
+    Snapshot,
+}
+
+/// Computes the ts_id of every row of `labels`, reusing a single generator
+/// across rows.
+pub fn compute_ts_ids<H>(labels: &Labels, mode: TsIdMode) -> Vec<u64>
+where
+    H: Hasher + SeededHasher,
+{
+    let mut ts_ids = vec![0; labels.label_values.len()];
+    compute_ts_ids_into::<H>(labels, &labels.label_values, mode, &mut ts_ids);
+    ts_ids
+}
+
+/// Like [`compute_ts_ids`], but splits the rows into one chunk per thread of
+/// the current rayon pool.
+///
+/// Each chunk sets up its own generator once and reuses it for all of its
+/// rows. The ts_ids are returned in row order and are identical to those of
+/// [`compute_ts_ids`]. Run it inside
+/// [`ThreadPool::install`](rayon::ThreadPool::install) to limit the number of
+/// threads.
+pub fn compute_ts_ids_parallel<H>(labels: &Labels, mode: TsIdMode) -> Vec<u64>
+where
+    H: Hasher + SeededHasher,
+{
+    par_compute_chunks(&labels.label_values, |rows, ts_ids| {
+        compute_ts_ids_into::<H>(labels, rows, mode, ts_ids)
+    })
+}
+
+/// Splits `rows` into one chunk per thread of the current rayon pool and
+/// fills the ts_ids of each chunk with `compute`.
+fn par_compute_chunks<F>(rows: &[Vec<String>], compute: F) -> Vec<u64>
+where
+    F: Fn(&[Vec<String>], &mut [u64]) + Sync,
+{
+    let chunk_size = rows.len().div_ceil(rayon::current_num_threads()).max(1);
+    let mut ts_ids = vec![0; rows.len()];
+    ts_ids
+        .par_chunks_mut(chunk_size)
+        .zip(rows.par_chunks(chunk_size))
+        .for_each(|(ts_ids, rows)| compute(rows, ts_ids));
+    ts_ids
+}
+
+/// Writes the ts_ids of `rows`, a slice of `labels.label_values`, to `ts_ids`.
+fn compute_ts_ids_into<H>(labels: &Labels, rows: &[Vec<String>], mode: TsIdMode, ts_ids: &mut [u64])
+where
+    H: Hasher + SeededHasher,
+{
+    let seed = match mode {
+        TsIdMode::Full | TsIdMode::Snapshot => 0,
+        TsIdMode::Seeded => labels.label_name_hash,
+    };
+    let mut generator = TsIdGenerator::<H>::from_seed(seed);
+    // Hasher state after the label names, resumed by every row
+    let names = match mode {
+        TsIdMode::Snapshot => {
+            generator.write_label_names(labels.label_names.iter().map(String::as_bytes));
+            generator.hasher.try_clone()
+        }
+        TsIdMode::Full | TsIdMode::Seeded => None,
+    };
+    for (row, ts_id) in rows.iter().zip(ts_ids) {
+        match names.as_ref().and_then(SeededHasher::try_clone) {
+            Some(hasher) => {
+                generator.hasher = hasher;
+                generator.restart_clock();
+            }
+            None => {
+                generator.reset();
+                if mode != TsIdMode::Seeded {
+                    generator.write_label_names(labels.label_names.iter().map(String::as_bytes));
+                }
+            }
+        }
+        generator.write_label_values(row.iter().map(String::as_bytes));
+        *ts_id = generator.finish();
+    }
+}
+
+/// Memoizes the ts_ids of recently seen label sets, for ingest pipelines where
+/// the same series arrives over and over.
+///
+/// Ts_ids are computed like [`label_row_ts_id`](crate::label_row_ts_id),
+/// seeded with the label name hash. A hit costs hashing the values once with
+/// the `HashMap` hasher plus a comparison, so it only pays off for hashers
+/// slower than that or for long label sets. With cheap hashers such as xxh3
+/// the cache may not beat computing every ts_id.
+///
+/// Size the cache to the number of active series sent by a scrape target or
+/// remote-write client: a typical Prometheus target exposes a few hundred to a
+/// few thousand series, so 1000 to 10,000 entries keep a steady stream of
+/// samples almost always hitting.
+pub struct TsIdCache<H: Hasher + SeededHasher> {
+    label_name_hash: u64,
+    cache: LruCache<Vec<Vec<u8>>, u64>,
+    hits: u64,
+    misses: u64,
+    _hasher: PhantomData<H>,
+}
+
+impl<H: Hasher + SeededHasher> TsIdCache<H> {
+    /// Creates a cache of at most `capacity` ts_ids for label sets whose names
+    /// hash to `label_name_hash`.
+    pub fn new(label_name_hash: u64, capacity: NonZeroUsize) -> Self {
+        Self {
+            label_name_hash,
+            cache: LruCache::new(capacity),
+            hits: 0,
+            misses: 0,
+            _hasher: PhantomData,
+        }
+    }
+
+    /// Returns the ts_id of `label_values`, computing and caching it if it is
+    /// not cached yet. The least recently used entry is evicted when full.
+    pub fn get_or_compute(&mut self, label_values: &[Vec<u8>]) -> u64 {
+        if let Some(&ts_id) = self.cache.get(label_values) {
+            self.hits += 1;
+            return ts_id;
+        }
+        self.misses += 1;
+        let mut generator = TsIdGenerator::<H>::from_seed(self.label_name_hash);
+        generator.write_label_values(label_values.iter().map(Vec::as_slice));
+        let ts_id = generator.build_ts_id();
+        self.cache.put(label_values.to_vec(), ts_id);
+        ts_id
+    }
+
+    /// Returns the number of lookups answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Returns the number of lookups that computed the ts_id.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Returns the fraction of lookups answered from the cache, or 0 if there
+    /// were none.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / lookups as f64
+    }
+}
+
 pub type DefaultTsIdGenerator = TsIdGenerator<DefaultHasher>;
 pub type FxTsIdGenerator = TsIdGenerator<FxHasher64>;
 pub type Mur3TsIdGenerator = TsIdGenerator<Hasher128>;
 pub type Xx3TsIdGenerator = TsIdGenerator<Xxh3>;
 pub type Xx64TsIdGenerator = TsIdGenerator<Xxh64>;
 pub type CityHashTsIdGenerator = TsIdGenerator<CityHash32Hasher>;
+/// Ts_id generator using SipHash-1-3, the hash of Rust's `HashMap`.
+///
+/// SipHash is keyed, so it only resists crafted collisions from untrusted
+/// label names and values when its key is secret. The seeds used in this
+/// crate are not: ts_ids are seeded with the label name hash, which anyone
+/// who knows the label names can compute. Callers that need collision
+/// resistance must mix a secret key into the seed, or build the generator
+/// with [`TsIdGenerator::new`] from `SipHasher13::new_with_keys`.
+pub type Sip13TsIdGenerator = TsIdGenerator<SipHasher13>;
+/// Ts_id generator using SipHash-2-4, the more conservative SipHash variant.
+///
+/// Like [`Sip13TsIdGenerator`], it only resists hash flooding from untrusted
+/// label names and values when keyed with a secret, which the label name hash
+/// seed is not. It costs more rounds per block.
+pub type Sip24TsIdGenerator = TsIdGenerator<SipHasher24>;
+/// Keyed ts_id generator for label sets from untrusted tenants.
+///
+/// It only keeps tenants from crafting label values with colliding ts_ids
+/// when keyed with a secret, see [`Sip13TsIdGenerator`]. Seeding it with the
+/// label name hash, as the rest of this crate does, gives no such protection.
+pub type SipTsIdGenerator = Sip13TsIdGenerator;
+/// Ts_id generator using aHash.
+///
+/// aHash uses AES instructions when the target enables them and a fallback
+/// algorithm otherwise, so the same labels can get different ts_ids on
+/// different builds. It suits benchmarks more than persisted ts_ids.
+pub type AHashTsIdGenerator = TsIdGenerator<AHasher>;
+pub type WyHashTsIdGenerator = TsIdGenerator<WyHash>;
+
+/// A full 128-bit ts_id produced by [`Mur3TsIdGenerator::build_ts_id_128_native`].
+pub type Mur3TsId = u128;
+
+impl Mur3TsIdGenerator {
+    /// Builds a 128-bit ts_id from both halves of the murmur3 hash.
+    ///
+    /// Unlike [`build_ts_id`](TsIdGenerator::build_ts_id), which goes through
+    /// [`Hasher::finish`] and keeps only 64 bits, this uses `finish128`.
+    pub fn build_ts_id_128_native(&mut self) -> Mur3TsId {
+        self.record_generated();
+        self.hasher.finish_u128()
+    }
+}
 
 impl Xx3TsIdGenerator {
     pub fn write_label_names_and_finish<'a>(
         &mut self,
         label_names: impl Iterator<Item = &'a [u8]>,
     ) -> u64 {
-        for label in label_names {
-            self.hasher.write(label);
-            self.hasher.write_u8(0xff);
-        }
+        self.write_label_names(label_names);
         self.hasher.finish()
     }
 }
 
+/// Ts_id generator that feeds the same writes to two independent hashers and
+/// concatenates their outputs into a 128-bit ts_id.
+///
+/// XOR-combining two 64-bit hashes would still leave 64 bits, with a birthday
+/// collision at about 2^32 ts_ids. Concatenating them moves that bound to about
+/// 2^64, provided the two hash functions are unrelated.
+///
+/// Items are always followed by a `0xff` separator, like the default
+/// [`FramingMode::Separator`] of [`TsIdGenerator`].
+pub struct TwoHashTsIdGenerator<A, B> {
+    a: A,
+    b: B,
+}
+
+impl<A, B> Default for TwoHashTsIdGenerator<A, B>
+where
+    A: Default + Hasher,
+    B: Default + Hasher,
+{
+    fn default() -> Self {
+        Self {
+            a: Default::default(),
+            b: Default::default(),
+        }
+    }
+}
+
+impl<A, B> TwoHashTsIdGenerator<A, B>
+where
+    A: Hasher + SeededHasher,
+    B: Hasher + SeededHasher,
+{
+    pub fn new(a: A, b: B) -> Self {
+        Self { a, b }
+    }
+
+    /// Seeds both hashers with `seed`.
+    pub fn from_seed(seed: u64) -> Self {
+        Self {
+            a: A::from_seed(seed),
+            b: B::from_seed(seed),
+        }
+    }
+
+    pub fn write_label_names<'a>(&mut self, label_names: impl Iterator<Item = &'a [u8]>) {
+        self.write_all(label_names);
+    }
+
+    pub fn write_label_values<'a>(&mut self, label_values: impl Iterator<Item = &'a [u8]>) {
+        self.write_all(label_values);
+    }
+
+    /// Builds a 128-bit ts_id with the hash of `A` in the high and the hash of
+    /// `B` in the low 64 bits.
+    pub fn build_ts_id_128(&self) -> u128 {
+        ((self.a.finish() as u128) << 64) | (self.b.finish() as u128)
+    }
+
+    fn write_all<'a>(&mut self, items: impl Iterator<Item = &'a [u8]>) {
+        for item in items {
+            self.a.write(item);
+            self.a.write_u8(0xff);
+            self.b.write(item);
+            self.b.write_u8(0xff);
+        }
+    }
+}
+
+/// 128-bit ts_id generator combining xxh3 and FxHash.
+pub type Xx3FxTsIdGenerator = TwoHashTsIdGenerator<Xxh3, FxHasher64>;
+
+/// Size of the stack buffer of [`prometheus_series_hash`], matching the
+/// 1KiB buffer of Prometheus' `labels.Hash()`.
+const PROMETHEUS_HASH_BUFFER: usize = 1024;
+
+/// Hashes a label set the way Prometheus' `labels.Hash()` does: XXH64 with
+/// seed 0 over `name 0xff value 0xff` for each label, in name order.
+///
+/// Like Prometheus, label sets fitting in a 1KiB buffer are hashed in one
+/// shot, and larger ones are streamed into the hasher from the first label
+/// that doesn't fit. Both paths produce the same hash. Pairs that are not
+/// sorted by name are sorted first, as `labels.New` would.
+pub fn prometheus_series_hash(pairs: &[(&str, &str)]) -> u64 {
+    if !pairs.is_sorted() {
+        let mut sorted = pairs.to_vec();
+        sorted.sort_unstable();
+        return prometheus_series_hash(&sorted);
+    }
+
+    let mut buffer = [0u8; PROMETHEUS_HASH_BUFFER];
+    let mut len = 0;
+    for (idx, (name, value)) in pairs.iter().enumerate() {
+        if len + name.len() + value.len() + 2 >= PROMETHEUS_HASH_BUFFER {
+            let mut hasher = Xxh64::new(0);
+            hasher.update(&buffer[..len]);
+            for (name, value) in &pairs[idx..] {
+                hasher.update(name.as_bytes());
+                hasher.update(&[0xff]);
+                hasher.update(value.as_bytes());
+                hasher.update(&[0xff]);
+            }
+            return hasher.digest();
+        }
+        for item in [name.as_bytes(), value.as_bytes()] {
+            buffer[len..len + item.len()].copy_from_slice(item);
+            buffer[len + item.len()] = 0xff;
+            len += item.len() + 1;
+        }
+    }
+    xxh64(&buffer[..len], 0)
+}
+
 pub trait SeededHasher {
     fn from_seed(seed: u64) -> Self;
+
+    /// Resets the hasher to the state of `from_seed(seed)`.
+    ///
+    /// The default constructs a new hasher; hashers able to reseed in place
+    /// override it.
+    fn reset_with_seed(&mut self, seed: u64)
+    where
+        Self: Sized,
+    {
+        *self = Self::from_seed(seed);
+    }
+
+    /// Like [`reset_with_seed`](SeededHasher::reset_with_seed), for a hasher
+    /// already seeded with `seed`.
+    ///
+    /// Hashers keeping state derived from the seed, like the xxh3 secret,
+    /// override it to only clear the hashed input.
+    fn reset_same_seed(&mut self, seed: u64)
+    where
+        Self: Sized,
+    {
+        self.reset_with_seed(seed);
+    }
+
+    /// Clones the hasher mid-stream, for [`TsIdMode::Snapshot`].
+    ///
+    /// Returns `None` by default; hashers implementing [`Clone`] override it.
+    fn try_clone(&self) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+}
+
+/// Hashers with a native 128-bit output, for
+/// [`TsIdGenerator::build_ts_id_128`].
+pub trait Hasher128Ext: Hasher {
+    /// Returns the 128-bit hash of the bytes written so far.
+    fn finish_u128(&self) -> u128;
+}
+
+impl Hasher128Ext for Xxh3 {
+    fn finish_u128(&self) -> u128 {
+        self.digest128()
+    }
+}
+
+/// The two halves of `finish128` are the high and low 64 bits, so the high
+/// half is the [`Hasher::finish`] value.
+impl Hasher128Ext for Hasher128 {
+    fn finish_u128(&self) -> u128 {
+        let (h1, h2) = self.finish128();
+        ((h1 as u128) << 64) | h2 as u128
+    }
 }
 
 impl SeededHasher for Xxh3 {
     fn from_seed(seed: u64) -> Self {
         Xxh3Builder::new().with_seed(seed).build()
     }
+
+    fn reset_same_seed(&mut self, _seed: u64) {
+        self.reset();
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
 }
 
 impl SeededHasher for Xxh64 {
     fn from_seed(seed: u64) -> Self {
         Xxh64::new(seed)
     }
+
+    fn reset_with_seed(&mut self, seed: u64) {
+        self.reset(seed);
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
 }
 
 impl SeededHasher for FxHasher64 {
@@ -95,6 +764,10 @@ impl SeededHasher for FxHasher64 {
         hasher.write_u64(seed);
         hasher
     }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
 }
 
 impl SeededHasher for DefaultHasher {
@@ -103,6 +776,10 @@ impl SeededHasher for DefaultHasher {
         hasher.write_u64(seed);
         hasher
     }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
 }
 
 impl SeededHasher for CityHash64Hasher {
@@ -111,8 +788,687 @@ impl SeededHasher for CityHash64Hasher {
     }
 }
 
+/// The seed fills the two 64-bit keys as `(seed, seed.rotate_left(32))`.
+impl SeededHasher for SipHasher13 {
+    fn from_seed(seed: u64) -> Self {
+        SipHasher13::new_with_keys(seed, seed.rotate_left(32))
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
+}
+
+/// The seed fills the two 64-bit keys as `(seed, seed.rotate_left(32))`.
+impl SeededHasher for SipHasher24 {
+    fn from_seed(seed: u64) -> Self {
+        SipHasher24::new_with_keys(seed, seed.rotate_left(32))
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
+}
+
 impl SeededHasher for Hasher128 {
     fn from_seed(seed: u64) -> Self {
         Hasher128::with_seed(seed as u32)
     }
 }
+
+/// The seed fills the four 64-bit keys as `seed` rotated by 0, 16, 32 and 48
+/// bits.
+impl SeededHasher for AHasher {
+    fn from_seed(seed: u64) -> Self {
+        ahash::RandomState::with_seeds(
+            seed,
+            seed.rotate_left(16),
+            seed.rotate_left(32),
+            seed.rotate_left(48),
+        )
+        .build_hasher()
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
+
+impl SeededHasher for WyHash {
+    fn from_seed(seed: u64) -> Self {
+        WyHash::with_seed(seed)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::read_labels_from_path;
+
+    #[test]
+    fn ts_id_cache_matches_uncached() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/unique-lables.csv.gz").unwrap();
+        let rows: Vec<Vec<Vec<u8>>> = labels
+            .label_values
+            .iter()
+            .map(|row| row.iter().map(|value| value.clone().into_bytes()).collect())
+            .collect();
+
+        let mut cache =
+            TsIdCache::<Xxh3>::new(labels.label_name_hash, NonZeroUsize::new(100).unwrap());
+        for pass in 0..2 {
+            for (row, values) in rows.iter().zip(&labels.label_values) {
+                let expected = crate::label_row_ts_id::<Xxh3>(labels.label_name_hash, values);
+                assert_eq!(cache.get_or_compute(row), expected, "pass {pass}");
+                // Repeats of the row just computed hit.
+                assert_eq!(cache.get_or_compute(row), expected);
+            }
+        }
+        // Every row is evicted before it is seen again in the second pass.
+        assert_eq!(cache.misses(), 2 * rows.len() as u64);
+        assert_eq!(cache.hits(), 2 * rows.len() as u64);
+        assert_eq!(cache.hit_rate(), 0.5);
+    }
+
+    #[test]
+    fn ts_id_cache_evicts_least_recently_used() {
+        let row = |value: &str| vec![value.as_bytes().to_vec()];
+        let mut cache = TsIdCache::<Xxh3>::new(7, NonZeroUsize::new(2).unwrap());
+        assert_eq!(cache.hit_rate(), 0.0);
+        for value in ["a", "b", "a", "c", "a", "b"] {
+            cache.get_or_compute(&row(value));
+        }
+        // "b" was evicted by "c", as "a" was used more recently.
+        assert_eq!((cache.hits(), cache.misses()), (2, 4));
+    }
+
+    #[test]
+    fn sorted_label_names_ignore_order() {
+        let hash = |names: &[&str]| {
+            let mut generator = Xx3TsIdGenerator::default();
+            generator.write_label_names_sorted(names.iter().map(|name| name.as_bytes()));
+            generator.build_ts_id()
+        };
+        let expected = hash(&["env", "instance", "job"]);
+        assert_eq!(hash(&["job", "env", "instance"]), expected);
+        assert_eq!(hash(&["instance", "job", "env"]), expected);
+
+        let mut ordered = Xx3TsIdGenerator::default();
+        ordered.write_label_names(
+            ["env", "instance", "job"]
+                .iter()
+                .map(|name| name.as_bytes()),
+        );
+        assert_eq!(ordered.build_ts_id(), expected);
+        assert_ne!(hash(&["env", "instance"]), expected);
+    }
+
+    #[test]
+    fn sorted_label_pairs_ignore_order() {
+        let hash = |pairs: &[(&str, &str)]| {
+            let mut generator = Xx3TsIdGenerator::default();
+            generator.write_sorted_label_pairs(
+                pairs
+                    .iter()
+                    .map(|(name, value)| (name.as_bytes(), value.as_bytes())),
+            );
+            generator.build_ts_id()
+        };
+        let expected = hash(&[("env", "prod"), ("instance", "host:9100"), ("job", "node")]);
+        assert_eq!(
+            hash(&[("job", "node"), ("env", "prod"), ("instance", "host:9100")]),
+            expected
+        );
+        assert_eq!(
+            hash(&[("instance", "host:9100"), ("job", "node"), ("env", "prod")]),
+            expected
+        );
+
+        assert_ne!(
+            hash(&[("env", "dev"), ("instance", "host:9100"), ("job", "node")]),
+            expected
+        );
+        // Swapping values between names is a different series.
+        assert_ne!(
+            hash(&[("env", "node"), ("instance", "host:9100"), ("job", "prod")]),
+            expected
+        );
+        assert_ne!(hash(&[("env", "prod"), ("job", "node")]), expected);
+    }
+
+    /// Expected values of Prometheus' `labels.Hash()` for each label set.
+    /// `scripts/prometheus_hash_golden` prints them from the Go implementation;
+    /// rerun it and update these constants when adding or changing a case.
+    #[test]
+    fn prometheus_series_hash_golden() {
+        assert_eq!(prometheus_series_hash(&[]), 0xef46db3751d8e999);
+        assert_eq!(prometheus_series_hash(&[("a", "b")]), 0x608891b6a4fd3588);
+        assert_eq!(
+            prometheus_series_hash(&[
+                ("__name__", "up"),
+                ("instance", "localhost:9090"),
+                ("job", "prometheus"),
+            ]),
+            0xf28c8afcd224e55f
+        );
+        assert_eq!(
+            prometheus_series_hash(&[
+                ("__name__", "http_requests_total"),
+                ("code", "200"),
+                ("handler", "/api/v1/query"),
+                ("method", "GET"),
+            ]),
+            0x47d3f1e7d3f25bef
+        );
+
+        // Unsorted input is hashed as the sorted label set.
+        assert_eq!(
+            prometheus_series_hash(&[
+                ("job", "prometheus"),
+                ("__name__", "up"),
+                ("instance", "localhost:9090"),
+            ]),
+            0xf28c8afcd224e55f
+        );
+    }
+
+    /// The 2000-byte value is the `long` case of
+    /// `scripts/prometheus_hash_golden`.
+    #[test]
+    fn prometheus_series_hash_streams_large_label_sets() {
+        let long = "x".repeat(2000);
+        assert_eq!(
+            prometheus_series_hash(&[("__name__", "long"), ("value", &long)]),
+            0x955f6885a9a98a52
+        );
+
+        // Label sets right around the buffer size hash like the one-shot path.
+        for len in 1000..1030 {
+            let value = "y".repeat(len);
+            let pairs = [("__name__", "edge"), ("value", value.as_str())];
+            let mut bytes = Vec::new();
+            for (name, value) in pairs {
+                bytes.extend_from_slice(name.as_bytes());
+                bytes.push(0xff);
+                bytes.extend_from_slice(value.as_bytes());
+                bytes.push(0xff);
+            }
+            assert_eq!(
+                prometheus_series_hash(&pairs),
+                xxh64(&bytes, 0),
+                "len {len}"
+            );
+        }
+    }
+
+    fn assert_reuse_matches_fresh<H: Hasher + SeededHasher>(labels: &crate::Labels) {
+        let seeds = [labels.label_name_hash, 0, labels.label_name_hash];
+        let mut reused = TsIdGenerator::<H>::from_seed(seeds[0]);
+        for seed in seeds {
+            reused.reset_with_seed(seed);
+            for row in labels.label_values.iter().take(100) {
+                reused.reset();
+                reused.write_label_values(row.iter().map(|s| s.as_bytes()));
+                let mut fresh = TsIdGenerator::<H>::from_seed(seed);
+                fresh.write_label_values(row.iter().map(|s| s.as_bytes()));
+                assert_eq!(
+                    reused.finish(),
+                    fresh.build_ts_id(),
+                    "seed {seed}, row {row:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reset_matches_fresh_generator() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        assert_reuse_matches_fresh::<Xxh3>(&labels);
+        assert_reuse_matches_fresh::<Xxh64>(&labels);
+        assert_reuse_matches_fresh::<FxHasher64>(&labels);
+        assert_reuse_matches_fresh::<SipHasher13>(&labels);
+        assert_reuse_matches_fresh::<Hasher128>(&labels);
+
+        // Framing survives a reset.
+        let mut generator =
+            Xx3TsIdGenerator::from_seed(7).with_framing(FramingMode::LengthPrefixed);
+        generator.write_label_values([b"stale".as_slice()].into_iter());
+        generator.reset();
+        generator.write_label_values([b"up".as_slice()].into_iter());
+        let mut fresh = Xx3TsIdGenerator::from_seed(7).with_framing(FramingMode::LengthPrefixed);
+        fresh.write_label_values([b"up".as_slice()].into_iter());
+        assert_eq!(generator.finish(), fresh.build_ts_id());
+    }
+
+    #[test]
+    #[should_panic(expected = "reset requires a generator created with from_seed")]
+    fn reset_requires_seed() {
+        Xx3TsIdGenerator::default().reset();
+    }
+
+    #[test]
+    fn multi_byte_separator_separates_names() {
+        const SEPARATOR: &[u8] = b"\x00\xff\x00";
+        let hash = |names: &[Vec<u8>]| {
+            let mut generator = Xx3TsIdGenerator::from_seed(0);
+            generator.write_label_names_with_separator(names.iter().map(Vec::as_slice), SEPARATOR);
+            generator.build_ts_id()
+        };
+
+        // Collides with the single 0xff separator, see `FramingMode::Separator`.
+        let left = [b"a\xff".to_vec(), b"b".to_vec()];
+        let right = [b"a".to_vec(), b"\xffb".to_vec()];
+        assert_ne!(hash(&left), hash(&right));
+
+        // Random name lists over a small alphabet including 0xff, so that
+        // lists often share names and boundaries.
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let alphabet = [b'a', b'b', b'_', 0xff];
+        let mut seen: HashMap<u64, Vec<Vec<u8>>> = HashMap::new();
+        for _ in 0..20_000 {
+            let names: Vec<Vec<u8>> = (0..rng.random_range(0..4))
+                .map(|_| {
+                    (0..rng.random_range(0..4))
+                        .map(|_| alphabet[rng.random_range(0..alphabet.len())])
+                        .collect()
+                })
+                .collect();
+            let previous = seen.entry(hash(&names)).or_insert_with(|| names.clone());
+            assert_eq!(*previous, names, "distinct names hash the same");
+        }
+    }
+
+    fn assert_snapshot_matches_one_pass<H: Hasher + SeededHasher + Clone>(labels: &crate::Labels) {
+        let names = || labels.label_names.iter().map(|s| s.as_bytes());
+        let mut generator = TsIdGenerator::<H>::from_seed(3).with_separator(0xfe);
+        generator.write_label_names(names());
+        let snapshot = generator.snapshot();
+
+        for row in labels.label_values.iter().take(100) {
+            let mut resumed = TsIdGenerator::resume(&snapshot);
+            resumed.write_label_values(row.iter().map(|s| s.as_bytes()));
+
+            let mut one_pass = TsIdGenerator::<H>::from_seed(3).with_separator(0xfe);
+            one_pass.write_label_names(names());
+            one_pass.write_label_values(row.iter().map(|s| s.as_bytes()));
+            assert_eq!(resumed.build_ts_id(), one_pass.build_ts_id(), "row {row:?}");
+        }
+    }
+
+    #[test]
+    fn snapshot_matches_one_pass() {
+        let labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        assert_snapshot_matches_one_pass::<DefaultHasher>(&labels);
+        assert_snapshot_matches_one_pass::<FxHasher64>(&labels);
+        assert_snapshot_matches_one_pass::<Xxh3>(&labels);
+        assert_snapshot_matches_one_pass::<Xxh64>(&labels);
+        assert_snapshot_matches_one_pass::<SipHasher13>(&labels);
+        assert_snapshot_matches_one_pass::<SipHasher24>(&labels);
+        assert_snapshot_matches_one_pass::<AHasher>(&labels);
+        assert_snapshot_matches_one_pass::<WyHash>(&labels);
+    }
+
+    #[test]
+    fn compute_ts_ids_modes() {
+        let mut labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        labels.label_values.truncate(5);
+        let names = || labels.label_names.iter().map(|s| s.as_bytes());
+
+        let full: Vec<u64> = labels
+            .label_values
+            .iter()
+            .map(|row| {
+                let mut generator = Xx3TsIdGenerator::from_seed(0);
+                generator.write_label_names(names());
+                generator.write_label_values(row.iter().map(|s| s.as_bytes()));
+                generator.build_ts_id()
+            })
+            .collect();
+        let seeded: Vec<u64> = labels
+            .label_values
+            .iter()
+            .map(|row| {
+                let mut generator = Xx3TsIdGenerator::from_seed(labels.label_name_hash);
+                generator.write_label_values(row.iter().map(|s| s.as_bytes()));
+                generator.build_ts_id()
+            })
+            .collect();
+
+        assert_eq!(compute_ts_ids::<Xxh3>(&labels, TsIdMode::Full), full);
+        assert_eq!(compute_ts_ids::<Xxh3>(&labels, TsIdMode::Snapshot), full);
+        assert_eq!(compute_ts_ids::<Xxh3>(&labels, TsIdMode::Seeded), seeded);
+        assert_ne!(full, seeded);
+    }
+
+    #[test]
+    fn write_label_values_slice_matches_iter() {
+        let values: [&[u8]; 4] = [b"up", b"", b"node\xff", b"localhost:9100"];
+        for framing in [FramingMode::Separator, FramingMode::LengthPrefixed] {
+            let mut from_slice = Xx3TsIdGenerator::from_seed(7).with_framing(framing);
+            from_slice.write_label_values_slice(&values);
+            let mut from_iter = Xx3TsIdGenerator::from_seed(7).with_framing(framing);
+            from_iter.write_label_values(values.into_iter());
+            assert_eq!(from_slice.build_ts_id(), from_iter.build_ts_id());
+        }
+    }
+
+    /// Runs `compute` on pools of 1, 3 and 8 threads, checking it matches
+    /// `serial` every time.
+    fn assert_matches_on_pools<H>(
+        serial: &[u64],
+        what: &str,
+        compute: impl Fn() -> Vec<u64> + Sync,
+    ) {
+        for num_threads in [1, 3, 8] {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(num_threads)
+                .build()
+                .unwrap();
+            assert_eq!(
+                pool.install(&compute),
+                serial,
+                "{} with {what} on {num_threads} threads",
+                std::any::type_name::<H>()
+            );
+        }
+    }
+
+    fn assert_parallel_matches_serial<H: Hasher + SeededHasher>(labels: &crate::Labels) {
+        for mode in [TsIdMode::Full, TsIdMode::Seeded, TsIdMode::Snapshot] {
+            let serial = compute_ts_ids::<H>(labels, mode);
+            assert_matches_on_pools::<H>(&serial, &format!("{mode:?}"), || {
+                compute_ts_ids_parallel::<H>(labels, mode)
+            });
+        }
+        assert_eq!(
+            compute_ts_ids::<H>(labels, TsIdMode::Snapshot),
+            compute_ts_ids::<H>(labels, TsIdMode::Full),
+            "{}",
+            std::any::type_name::<H>()
+        );
+    }
+
+    #[test]
+    fn compute_ts_ids_parallel_matches_serial() {
+        let mut labels = read_labels_from_path::<Xxh3>("./assets/labels.csv.gz").unwrap();
+        labels.label_values.truncate(1000);
+        assert_parallel_matches_serial::<DefaultHasher>(&labels);
+        assert_parallel_matches_serial::<FxHasher64>(&labels);
+        assert_parallel_matches_serial::<Xxh3>(&labels);
+        assert_parallel_matches_serial::<Xxh64>(&labels);
+        assert_parallel_matches_serial::<SipHasher13>(&labels);
+        assert_parallel_matches_serial::<SipHasher24>(&labels);
+        assert_parallel_matches_serial::<AHasher>(&labels);
+        assert_parallel_matches_serial::<WyHash>(&labels);
+        assert_parallel_matches_serial::<CityHash64Hasher>(&labels);
+        // Without a clone, snapshot mode hashes the names of every row.
+        assert!(Hasher128::from_seed(0).try_clone().is_none());
+        assert_parallel_matches_serial::<Hasher128>(&labels);
+
+        // Fewer rows than threads, and no rows at all.
+        labels.label_values.truncate(2);
+        assert_parallel_matches_serial::<Xxh3>(&labels);
+        labels.label_values.clear();
+        assert_parallel_matches_serial::<Xxh3>(&labels);
+    }
+
+    #[test]
+    fn mur3_128_no_collisions() {
+        let labels =
+            read_labels_from_path::<DefaultHasher>("./assets/unique-lables.csv.gz").unwrap();
+        let mut ts_ids = HashSet::with_capacity(labels.label_values.len());
+
+        for row in &labels.label_values {
+            let mut generator = Mur3TsIdGenerator::from_seed(0);
+            generator.write_label_names(labels.label_names.iter().map(|s| s.as_bytes()));
+            generator.write_label_values(row.iter().map(|s| s.as_bytes()));
+            let ts_id = generator.build_ts_id_128_native();
+            assert!(ts_ids.insert(ts_id), "collision on {:?}", row);
+        }
+    }
+
+    #[test]
+    fn two_hash_128_no_collisions() {
+        let labels =
+            read_labels_from_path::<DefaultHasher>("./assets/unique-lables.csv.gz").unwrap();
+        let mut ts_ids = HashSet::with_capacity(labels.label_values.len());
+
+        for row in &labels.label_values {
+            let mut generator = Xx3FxTsIdGenerator::from_seed(0);
+            generator.write_label_names(labels.label_names.iter().map(|s| s.as_bytes()));
+            generator.write_label_values(row.iter().map(|s| s.as_bytes()));
+            let ts_id = generator.build_ts_id_128();
+            assert!(ts_ids.insert(ts_id), "collision on {:?}", row);
+
+            // Each half is the ts_id of the corresponding single hasher.
+            assert_eq!(
+                (ts_id >> 64) as u64,
+                single_ts_id::<Xxh3>(&labels.label_names, row)
+            );
+            assert_eq!(
+                ts_id as u64,
+                single_ts_id::<FxHasher64>(&labels.label_names, row)
+            );
+        }
+    }
+
+    fn single_ts_id<H: Hasher + SeededHasher>(names: &[String], row: &[String]) -> u64 {
+        let mut generator = TsIdGenerator::<H>::from_seed(0);
+        generator.write_label_names(names.iter().map(|s| s.as_bytes()));
+        generator.write_label_values(row.iter().map(|s| s.as_bytes()));
+        generator.build_ts_id()
+    }
+
+    #[test]
+    fn sip_seed_fills_both_keys() {
+        let seed = 0x0123_4567_89ab_cdef;
+        let ts_id = |mut generator: Sip13TsIdGenerator| {
+            generator.write_label_values([b"up".as_slice()].into_iter());
+            generator.build_ts_id()
+        };
+        let keyed = SipHasher13::new_with_keys(0x0123_4567_89ab_cdef, 0x89ab_cdef_0123_4567);
+        assert_eq!(
+            ts_id(Sip13TsIdGenerator::from_seed(seed)),
+            ts_id(Sip13TsIdGenerator::new(keyed))
+        );
+        assert_ne!(
+            ts_id(Sip13TsIdGenerator::from_seed(seed)),
+            ts_id(Sip13TsIdGenerator::from_seed(seed + 1))
+        );
+
+        let mut sip24 = Sip24TsIdGenerator::from_seed(seed);
+        sip24.write_label_values([b"up".as_slice()].into_iter());
+        let mut keyed = SipHasher24::new_with_keys(0x0123_4567_89ab_cdef, 0x89ab_cdef_0123_4567);
+        keyed.write(b"up");
+        keyed.write_u8(0xff);
+        assert_eq!(sip24.build_ts_id(), keyed.finish());
+    }
+
+    /// Crafts two 16-byte label values that collide under fxhash: the second
+    /// 8-byte word of one cancels the difference left by the first.
+    #[test]
+    fn keyed_sip_resists_crafted_fxhash_collision() {
+        let names = [b"tenant".as_slice()];
+        let mut prefix = FxTsIdGenerator::from_seed(0);
+        prefix.write_label_names(names.into_iter());
+        let state_after = |word: &[u8]| {
+            let mut hasher = prefix.hasher.clone();
+            hasher.write(word);
+            hasher.finish()
+        };
+
+        let tail = u64::from_ne_bytes(*b"/pod-001");
+        let crafted_tail = tail
+            ^ state_after(b"tenant-a").rotate_left(5)
+            ^ state_after(b"tenant-b").rotate_left(5);
+        let value_a = [b"tenant-a".as_slice(), &tail.to_ne_bytes()].concat();
+        let value_b = [b"tenant-b".as_slice(), &crafted_tail.to_ne_bytes()].concat();
+        assert_ne!(value_a, value_b);
+
+        fn ts_id<H: Hasher + SeededHasher>(seed: u64, value: &[u8]) -> u64 {
+            let mut generator = TsIdGenerator::<H>::from_seed(seed);
+            generator.write_label_names([b"tenant".as_slice()].into_iter());
+            generator.write_label_values([value].into_iter());
+            generator.build_ts_id()
+        }
+        assert_eq!(
+            ts_id::<FxHasher64>(0, &value_a),
+            ts_id::<FxHasher64>(0, &value_b)
+        );
+
+        let key = 0x5eed_f00d_7e4a_a7c5;
+        assert_ne!(
+            ts_id::<SipHasher13>(key, &value_a),
+            ts_id::<SipHasher13>(key, &value_b)
+        );
+        assert_ne!(
+            ts_id::<SipHasher24>(key, &value_a),
+            ts_id::<SipHasher24>(key, &value_b)
+        );
+    }
+
+    #[test]
+    fn length_prefixed_framing_separates_0xff() {
+        fn ts_id<H: Hasher + SeededHasher>(framing: FramingMode, values: [&[u8]; 2]) -> u64 {
+            let mut generator = TsIdGenerator::<H>::from_seed(0).with_framing(framing);
+            generator.write_label_names([b"a".as_slice(), b"b".as_slice()].into_iter());
+            generator.write_label_values(values.into_iter());
+            generator.build_ts_id()
+        }
+        let left: [&[u8]; 2] = [b"a\xff", b"b"];
+        let right: [&[u8]; 2] = [b"a", b"\xffb"];
+
+        // The collision is in the framing, whatever the hash function.
+        assert_eq!(
+            ts_id::<Xxh3>(FramingMode::Separator, left),
+            ts_id::<Xxh3>(FramingMode::Separator, right)
+        );
+        assert_eq!(
+            ts_id::<FxHasher64>(FramingMode::Separator, left),
+            ts_id::<FxHasher64>(FramingMode::Separator, right)
+        );
+        assert_ne!(
+            ts_id::<Xxh3>(FramingMode::LengthPrefixed, left),
+            ts_id::<Xxh3>(FramingMode::LengthPrefixed, right)
+        );
+        assert_ne!(
+            ts_id::<FxHasher64>(FramingMode::LengthPrefixed, left),
+            ts_id::<FxHasher64>(FramingMode::LengthPrefixed, right)
+        );
+    }
+
+    #[test]
+    fn separator_byte_changes_ts_id() {
+        let ts_id = |mut generator: Xx3TsIdGenerator| {
+            generator.write_label_names([b"job".as_slice(), b"instance".as_slice()].into_iter());
+            generator.write_label_values([b"node".as_slice(), b"host:9100".as_slice()].into_iter());
+            generator.build_ts_id()
+        };
+        let default = ts_id(Xx3TsIdGenerator::from_seed(0));
+        assert_eq!(
+            ts_id(Xx3TsIdGenerator::from_seed(0).with_separator(0xff)),
+            default
+        );
+        assert_ne!(
+            ts_id(Xx3TsIdGenerator::from_seed(0).with_separator(0xfe)),
+            default
+        );
+        assert_eq!(Xx3TsIdGenerator::default().separator(), 0xff);
+    }
+
+    #[test]
+    fn length_prefix_is_leb128() {
+        let value = vec![b'x'; 300];
+        let mut generator =
+            Xx3TsIdGenerator::from_seed(0).with_framing(FramingMode::LengthPrefixed);
+        generator.write_label_values([value.as_slice()].into_iter());
+
+        let mut hasher = Xxh3::from_seed(0);
+        hasher.write(&[0xac, 0x02]);
+        hasher.write(&value);
+        assert_eq!(generator.build_ts_id(), hasher.finish());
+    }
+
+    #[test]
+    fn build_ts_id_128_known_values() {
+        fn ts_id_128<H: Hasher128Ext + SeededHasher>(seed: u64) -> u128 {
+            let mut generator = TsIdGenerator::<H>::from_seed(seed);
+            generator.write_label_names([b"job".as_slice(), b"instance".as_slice()].into_iter());
+            generator
+                .write_label_values([b"node".as_slice(), b"localhost:9100".as_slice()].into_iter());
+            generator.build_ts_id_128()
+        }
+
+        assert_eq!(ts_id_128::<Xxh3>(0), 0x06b755a05b71922e73b885213cee5399);
+        assert_eq!(
+            ts_id_128::<Hasher128>(0),
+            0xc206e3174759d6a4f8cc7fe0d7d0ae32
+        );
+        assert_ne!(ts_id_128::<Xxh3>(1), 0x06b755a05b71922e73b885213cee5399);
+
+        // The native murmur3 path builds the same id.
+        let mut generator = Mur3TsIdGenerator::from_seed(0);
+        generator.write_label_names([b"job".as_slice(), b"instance".as_slice()].into_iter());
+        generator
+            .write_label_values([b"node".as_slice(), b"localhost:9100".as_slice()].into_iter());
+        assert_eq!(
+            generator.build_ts_id_128_native(),
+            0xc206e3174759d6a4f8cc7fe0d7d0ae32
+        );
+    }
+
+    #[test]
+    fn xxh3_128_no_collisions() {
+        let labels =
+            read_labels_from_path::<DefaultHasher>("./assets/unique-lables.csv.gz").unwrap();
+        let mut ts_ids = HashSet::with_capacity(labels.label_values.len());
+
+        for row in &labels.label_values {
+            let mut generator = Xx3TsIdGenerator::from_seed(0);
+            generator.write_label_names(labels.label_names.iter().map(|s| s.as_bytes()));
+            generator.write_label_values(row.iter().map(|s| s.as_bytes()));
+            let ts_id = generator.build_ts_id_128();
+            assert!(ts_ids.insert(ts_id), "collision on {:?}", row);
+        }
+    }
+
+    #[test]
+    fn mur3_128_high_half_matches_finish() {
+        let mut generator = Mur3TsIdGenerator::from_seed(0);
+        generator.write_label_values([b"up".as_slice(), b"localhost:9090".as_slice()].into_iter());
+        let ts_id = generator.build_ts_id_128_native();
+        assert_eq!((ts_id >> 64) as u64, generator.build_ts_id());
+    }
+
+    #[test]
+    fn write_label_names_and_finish_matches_write_label_names() {
+        let names = [b"job".as_slice(), b"instance".as_slice()];
+        let generators = [
+            Xx3TsIdGenerator::from_seed(0),
+            Xx3TsIdGenerator::from_seed(0).with_separator(0xfe),
+            Xx3TsIdGenerator::from_seed(0).with_framing(FramingMode::LengthPrefixed),
+        ];
+        for generator in generators {
+            let (framing, separator) = (generator.framing(), generator.separator());
+            let mut one_call = Xx3TsIdGenerator::from_seed(0)
+                .with_framing(framing)
+                .with_separator(separator);
+            let mut streamed = generator;
+            streamed.write_label_names(names.into_iter());
+            assert_eq!(
+                one_call.write_label_names_and_finish(names.into_iter()),
+                streamed.build_ts_id(),
+                "{framing:?} {separator:#x}"
+            );
+        }
+    }
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "collision-check")]
+
+use assert_cmd::Command;
+use tsid_bench::read_labels_from_path;
+use xxhash_rust::xxh3::Xxh3;
+
+const COLLISION_CHECK: &str = env!("CARGO_BIN_EXE_collision-check");
+
+const LABELS: &str = "assets/labels.csv.gz";
+
+/// Returns the value of the `name:` line of the report.
+fn field<'a>(stdout: &'a str, name: &str) -> &'a str {
+    stdout
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+        .unwrap_or_else(|| panic!("no {name} in {stdout}"))
+        .trim()
+}
+
+fn run(args: &[&str]) -> String {
+    let output = Command::new(COLLISION_CHECK)
+        .args(args)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn reports_collisions_with_tiny_amp() {
+    let mut labels = read_labels_from_path::<Xxh3>(LABELS).unwrap();
+    labels.dedup();
+    let rows = labels.label_values.len() as u64 * 2;
+
+    for hasher in ["xxh3", "mur3"] {
+        let stdout = run(&[LABELS, hasher, "2", "3"]);
+        assert_eq!(field(&stdout, "inserted").parse::<u64>().unwrap(), rows);
+        assert_eq!(field(&stdout, "collisions").parse::<u64>().unwrap(), 0);
+        let expected: f64 = field(&stdout, "expected collisions").parse().unwrap();
+        assert!(expected > 0.0 && expected < 1e-6, "{stdout}");
+        assert!(!stdout.contains("interrupted"), "{stdout}");
+    }
+}
+
+#[test]
+fn rejects_unknown_hasher() {
+    Command::new(COLLISION_CHECK)
+        .args([LABELS, "md5", "2"])
+        .assert()
+        .failure();
+}
@@ -0,0 +1,213 @@
+//! Golden ts_ids of every hasher with a stable algorithm.
+//!
+//! Ts_ids are persisted, so these constants must never change: a failure
+//! means a hasher upgrade, a platform difference or a change to the
+//! [`TsIdGenerator`] framing altered stored ts_ids.
+//!
+//! `DefaultHasher` and `AHasher` are only checked for self-consistency, since
+//! std doesn't specify the algorithm of `DefaultHasher` and aHash picks one
+//! depending on the target CPU features. The golden values of
+//! `CityHash64Hasher` are still to be recorded, see [`cityhash64`].
+
+use std::hash::{DefaultHasher, Hasher};
+
+use ahash::AHasher;
+use cityhash_sys::CityHash64Hasher;
+use fxhash::FxHasher64;
+use mur3::Hasher128;
+use siphasher::sip::{SipHasher13, SipHasher24};
+use tsid_bench::ts_id_gen::{SeededHasher, TsIdGenerator};
+use wyhash::WyHash;
+use xxhash_rust::xxh3::Xxh3;
+use xxhash_rust::xxh64::Xxh64;
+
+const LABEL_NAMES: [&[u8]; 3] = [b"job", b"instance", b"env"];
+
+/// Label values, including non-ASCII UTF-8, values containing the `0xff`
+/// separator and empty values.
+///
+/// Rows 2 and 3 differ only in the position of a `0xff` byte relative to the
+/// separator, so they get the same ts_id from streaming hashers. WyHash and
+/// aHash hash every write on its own and tell them apart.
+const ROWS: [[&[u8]; 3]; 5] = [
+    [b"node", b"localhost:9100", b"prod"],
+    [
+        "ノード".as_bytes(),
+        "сервер:9100".as_bytes(),
+        "🚀".as_bytes(),
+    ],
+    [b"a\xff", b"b", b""],
+    [b"a", b"\xffb", b""],
+    [b"", b"", b""],
+];
+
+/// Returns the `(unseeded, seeded)` ts_ids of each of [`ROWS`].
+///
+/// Unseeded ts_ids hash the label names and values from seed 0. Seeded ts_ids
+/// hash the values only, from the unseeded hash of the label names.
+fn ts_ids<H>() -> Vec<(u64, u64)>
+where
+    H: Hasher + SeededHasher,
+{
+    let mut generator = TsIdGenerator::<H>::from_seed(0);
+    generator.write_label_names(LABEL_NAMES.into_iter());
+    let label_name_hash = generator.build_ts_id();
+
+    ROWS.iter()
+        .map(|row| {
+            let mut generator = TsIdGenerator::<H>::from_seed(0);
+            generator.write_label_names(LABEL_NAMES.into_iter());
+            generator.write_label_values(row.iter().copied());
+            let unseeded = generator.build_ts_id();
+
+            let mut generator = TsIdGenerator::<H>::from_seed(label_name_hash);
+            generator.write_label_values(row.iter().copied());
+            (unseeded, generator.build_ts_id())
+        })
+        .collect()
+}
+
+#[test]
+fn xxh3() {
+    assert_eq!(
+        ts_ids::<Xxh3>(),
+        [
+            (0x46a99ca0edda4660, 0xfa437adcffd16d19),
+            (0x29155febfa74a539, 0x1a01cc5dd1489594),
+            (0x7e1f5e6028e4986e, 0x648f74e3e8ca2557),
+            (0x7e1f5e6028e4986e, 0x648f74e3e8ca2557),
+            (0x2dba4042b21b2876, 0xf828bde7b0093425),
+        ]
+    );
+}
+
+#[test]
+fn xxh64() {
+    assert_eq!(
+        ts_ids::<Xxh64>(),
+        [
+            (0xa63e9bbe54731e55, 0x81ac56c0965963e0),
+            (0x3fe519aae5f141ba, 0xe27ec9abe9a22d66),
+            (0x0df3b5301f37633c, 0xdb3fdd4c9741e9fd),
+            (0x0df3b5301f37633c, 0xdb3fdd4c9741e9fd),
+            (0x8cd4904452fb144f, 0xa44d568ecee18994),
+        ]
+    );
+}
+
+/// fxhash reads its input in native byte order, so ts_ids differ on
+/// big-endian targets.
+#[test]
+#[cfg(target_endian = "little")]
+fn fxhash() {
+    assert_eq!(
+        ts_ids::<FxHasher64>(),
+        [
+            (0xa4ecd68a487fe5c2, 0x671a7dc6828f169c),
+            (0x73b1631f5a24c78a, 0x2a9fe373e557266e),
+            (0xbddbaaa4bb8eaf76, 0x3b5060a5070776bd),
+            (0xbddbaaa4bb8eaf76, 0x3b5060a5070776bd),
+            (0xe858e7db25eb2545, 0x6c1a95935b2fb650),
+        ]
+    );
+}
+
+#[test]
+fn sip13() {
+    assert_eq!(
+        ts_ids::<SipHasher13>(),
+        [
+            (0x0d02baa51a6adb94, 0x2c1f0c65fb59bc34),
+            (0x70fb8ea9be80d1e8, 0x8da9bd2a506325c8),
+            (0xfa4eaab70ae04355, 0x329b2bf522407923),
+            (0xfa4eaab70ae04355, 0x329b2bf522407923),
+            (0xed3c8e80441d5104, 0x8c57012a3f81e9dc),
+        ]
+    );
+}
+
+#[test]
+fn sip24() {
+    assert_eq!(
+        ts_ids::<SipHasher24>(),
+        [
+            (0x2fd525baf696534d, 0xfa82a9fa2d769fd1),
+            (0x50b37d2df5fed19a, 0x600666820360b90b),
+            (0x79a09c364da13f95, 0x0671b06a376e7c57),
+            (0x79a09c364da13f95, 0x0671b06a376e7c57),
+            (0x345517bdada3de5c, 0x19b46f6708ad0da9),
+        ]
+    );
+}
+
+#[test]
+fn mur3() {
+    assert_eq!(
+        ts_ids::<Hasher128>(),
+        [
+            (0xd65b9585d0effbb1, 0x2ea13d493e8ce7d7),
+            (0x61a3d1f7decd0167, 0xbdf0994b14780f53),
+            (0x20cafd4296b822ae, 0x9872f856c97f4b9e),
+            (0x20cafd4296b822ae, 0x9872f856c97f4b9e),
+            (0x94cc9be97945987b, 0x3d039adeeebc27f6),
+        ]
+    );
+}
+
+#[test]
+fn wyhash() {
+    assert_eq!(
+        ts_ids::<WyHash>(),
+        [
+            (0xad3bf57d604a6d36, 0xffe209c726172cb5),
+            (0xd4101058d7f07b7d, 0xa40caa6d4902f999),
+            (0xeda6870457661f6b, 0x2631aa679ef314ac),
+            (0x815e965ccd95be40, 0xeb858d67d2020bde),
+            (0x20de08744a5e6f63, 0x827ba97fbd2a51a1),
+        ]
+    );
+}
+
+/// Tracks the missing CityHash64 golden values: run it with `--ignored` to
+/// print the ts_ids, check them against the reference CityHash64 with seed,
+/// then turn it into an assertion like the tests above.
+#[test]
+#[ignore = "CityHash64 golden values are not recorded yet"]
+fn cityhash64() {
+    for (unseeded, seeded) in ts_ids::<CityHash64Hasher>() {
+        println!("({unseeded:#018x}, {seeded:#018x}),");
+    }
+    panic!("record the CityHash64 ts_ids printed above as golden values");
+}
+
+/// Checks that `H` is deterministic within the process and tells the rows
+/// apart, except rows 2 and 3 which only per-write hashers tell apart.
+fn assert_self_consistent<H>()
+where
+    H: Hasher + SeededHasher,
+{
+    let expected = ts_ids::<H>();
+    assert_eq!(ts_ids::<H>(), expected);
+    for (idx, ts_id) in expected.iter().enumerate() {
+        for (other_idx, other) in expected.iter().enumerate().skip(idx + 1) {
+            if (idx, other_idx) != (2, 3) {
+                assert_ne!(ts_id.0, other.0, "rows {idx} and {other_idx}");
+                assert_ne!(ts_id.1, other.1, "rows {idx} and {other_idx}");
+            }
+        }
+    }
+}
+
+/// `DefaultHasher` ts_ids can change with any Rust release: its algorithm is
+/// unspecified, so they must not be persisted.
+#[test]
+fn default_hasher_is_only_self_consistent() {
+    assert_self_consistent::<DefaultHasher>();
+}
+
+/// `AHasher` ts_ids can differ between builds for different CPUs, and
+/// between aHash releases.
+#[test]
+fn ahash_is_only_self_consistent() {
+    assert_self_consistent::<AHasher>();
+}
@@ -0,0 +1,31 @@
+#![cfg(feature = "metrics")]
+
+use metrics_exporter_prometheus::PrometheusBuilder;
+use tsid_bench::ts_id_gen::Xx3TsIdGenerator;
+
+#[test]
+fn counts_generated_ts_ids() {
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+
+    metrics::with_local_recorder(&recorder, || {
+        for idx in 0..100 {
+            let mut generator = Xx3TsIdGenerator::default();
+            generator.write_label_names(["job", "instance"].iter().map(|name| name.as_bytes()));
+            generator.write_label_values(["node", &idx.to_string()].iter().map(|v| v.as_bytes()));
+            generator.build_ts_id();
+        }
+    });
+
+    let rendered = handle.render();
+    assert!(
+        rendered.lines().any(|line| line == "tsid_generated 100"),
+        "{rendered}"
+    );
+    assert!(
+        rendered
+            .lines()
+            .any(|line| line == "tsid_generation_latency_ns_count 100"),
+        "{rendered}"
+    );
+}
@@ -0,0 +1,81 @@
+#![cfg(feature = "object-store")]
+
+use std::hash::DefaultHasher;
+use std::sync::Arc;
+
+use arrow::array::StringArray;
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use object_store::memory::InMemory;
+use object_store::path::Path;
+use object_store::{ObjectStore, PutPayload};
+use parquet::arrow::ArrowWriter;
+use tsid_bench::store::{read_labels_from_store, write_parquet_to_store};
+use tsid_bench::{
+    ParquetWriteOptions, VarintEncoder, encode_to_parquet_with_options, read_labels_from_path,
+};
+
+#[tokio::test]
+async fn write_parquet_roundtrip() {
+    let labels = read_labels_from_path::<DefaultHasher>("./assets/unique-lables.csv.gz").unwrap();
+    let rows: Vec<Vec<(u32, String)>> = labels
+        .label_values
+        .iter()
+        .map(|row| {
+            row.iter()
+                .enumerate()
+                .map(|(idx, val)| (idx as u32, val.clone()))
+                .collect()
+        })
+        .collect();
+    let options = ParquetWriteOptions::default();
+
+    let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let path = Path::from("bench/primary_keys.parquet");
+    write_parquet_to_store(store.clone(), &path, &VarintEncoder, &rows, &options)
+        .await
+        .unwrap();
+
+    let stored = store.get(&path).await.unwrap().bytes().await.unwrap();
+    let expected = encode_to_parquet_with_options(&VarintEncoder, &rows, &options).unwrap();
+    assert_eq!(stored.as_ref(), expected.as_slice());
+}
+
+#[tokio::test]
+async fn read_labels_roundtrip() {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("job", DataType::Utf8, true),
+        Field::new("greptime_timestamp", DataType::Utf8, true),
+        Field::new("instance", DataType::Utf8, true),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(StringArray::from(vec![Some("node"), Some("node"), None])),
+            Arc::new(StringArray::from(vec!["1", "2", "3"])),
+            Arc::new(StringArray::from(vec!["a:9100", "b:9100", "c:9100"])),
+        ],
+    )
+    .unwrap();
+    let mut data = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut data, schema, None).unwrap();
+    writer.write(&batch).unwrap();
+    writer.close().unwrap();
+
+    let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let path = Path::from("labels.parquet");
+    store.put(&path, PutPayload::from(data)).await.unwrap();
+
+    let labels = read_labels_from_store::<DefaultHasher>(store, &path)
+        .await
+        .unwrap();
+    assert_eq!(labels.label_names, vec!["job", "instance"]);
+    assert_eq!(
+        labels.label_values,
+        vec![
+            vec!["node", "a:9100"],
+            vec!["node", "b:9100"],
+            vec!["", "c:9100"],
+        ]
+    );
+}
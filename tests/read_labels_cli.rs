@@ -0,0 +1,97 @@
+use std::io::Write;
+
+use assert_cmd::Command;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+const READ_LABELS: &str = env!("CARGO_BIN_EXE_read-labels");
+
+const CSV: &str = "job,instance\nnode,a:9100\nnode,b:9100\napi,a:9100\n";
+
+fn stats_from_stdin(input: Vec<u8>) -> String {
+    let output = Command::new(READ_LABELS)
+        .args(["--stats", "-"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn stats_from_plain_stdin() {
+    let stdout = stats_from_stdin(CSV.as_bytes().to_vec());
+    assert!(stdout.starts_with("label     "), "{stdout}");
+    assert!(stdout.contains("\njob  "), "{stdout}");
+    assert!(stdout.contains("\ninstance  "), "{stdout}");
+    assert!(stdout.contains("3 rows (3 distinct), 2 labels"), "{stdout}");
+}
+
+#[test]
+fn stats_from_gzipped_stdin() {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(CSV.as_bytes()).unwrap();
+    let gzipped = encoder.finish().unwrap();
+
+    assert_eq!(
+        stats_from_stdin(gzipped),
+        stats_from_stdin(CSV.as_bytes().to_vec())
+    );
+}
+
+#[test]
+fn invalid_stdin_fails() {
+    Command::new(READ_LABELS)
+        .args(["--stats", "-"])
+        .write_stdin("job,instance\nnode\n")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn invalid_capacity_fails() {
+    let output = Command::new(READ_LABELS)
+        .args([
+            "--dedup",
+            "--capacity",
+            "many",
+            "data.parquet",
+            "labels.csv",
+        ])
+        .assert()
+        .failure()
+        .get_output()
+        .stderr
+        .clone();
+    let stderr = String::from_utf8(output).unwrap();
+    assert!(stderr.contains("invalid --capacity \"many\""), "{stderr}");
+}
+
+#[test]
+fn invalid_filter_fails() {
+    let output = Command::new(READ_LABELS)
+        .args(["--filter", "__name__", "data.parquet", "labels.csv"])
+        .assert()
+        .failure()
+        .get_output()
+        .stderr
+        .clone();
+    let stderr = String::from_utf8(output).unwrap();
+    assert!(stderr.contains("expected column=value"), "{stderr}");
+}
+
+#[test]
+fn stdin_without_stats_fails() {
+    let output = Command::new(READ_LABELS)
+        .args(["-", "labels.csv"])
+        .write_stdin(CSV)
+        .assert()
+        .failure()
+        .get_output()
+        .stderr
+        .clone();
+    let stderr = String::from_utf8(output).unwrap();
+    assert!(stderr.contains("only --stats reads stdin"), "{stderr}");
+}
@@ -0,0 +1,37 @@
+//! Compile-time helpers for `tsid-bench`.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::{LitStr, Token, bracketed, parse_macro_input};
+use xxhash_rust::xxh3::Xxh3;
+
+/// A bracketed list of string literals: `["a", "b", "c"]`.
+struct LabelNames(Punctuated<LitStr, Token![,]>);
+
+impl syn::parse::Parse for LabelNames {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let content;
+        bracketed!(content in input);
+        Ok(Self(Punctuated::parse_terminated(&content)?))
+    }
+}
+
+/// Computes the `Xxh3` label name hash of a fixed label set at compile time.
+///
+/// `tsid_label_name_hash!(["a", "b", "c"])` expands to the `u64` that
+/// `TsIdGenerator::<Xxh3>::write_label_names` followed by `build_ts_id` yields
+/// for the same names: each name is hashed followed by a `0xff` separator.
+#[proc_macro]
+pub fn tsid_label_name_hash(input: TokenStream) -> TokenStream {
+    let names = parse_macro_input!(input as LabelNames);
+
+    let mut hasher = Xxh3::default();
+    for name in &names.0 {
+        hasher.update(name.value().as_bytes());
+        hasher.update(&[0xff]);
+    }
+    let hash = hasher.digest();
+
+    quote!(#hash).into()
+}